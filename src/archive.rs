@@ -0,0 +1,182 @@
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Error, ErrorKind};
+use std::io::prelude::*;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use crate::file_io::RetryReader;
+
+use flate2::read::DeflateDecoder;
+
+// Separator between an archive and one of its members in input names
+// (e.g. `poses.tar::run1/poses.mol2.gz`)
+pub const MEMBER_SEPARATOR: &str = "::";
+
+// Compression applied by the archive itself to a member
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemberCompression {
+    Stored,
+    Deflated
+}
+
+// Struct describing where the bytes of an archive member are stored
+#[derive(Clone, Debug)]
+pub struct ArchiveMember {
+    pub name: String,
+    pub offset: u64,
+    pub length: u64,
+    pub compression: MemberCompression
+}
+
+// Struct describing the members of an archive in archive order
+pub struct ArchiveListing {
+    pub members: Vec<ArchiveMember>,
+    positions: HashMap<String, usize>
+}
+impl ArchiveListing {
+    fn new(members: Vec<ArchiveMember>) -> Self {
+        let positions = members
+            .iter()
+            .enumerate()
+            .map(|(idx, m)| (m.name.clone(), idx))
+            .collect();
+        ArchiveListing { members, positions }
+    }
+
+    // Returns a member by name
+    pub fn get(&self, name: &str) -> Option<&ArchiveMember> {
+        self.positions.get(name).map(|&idx| &self.members[idx])
+    }
+}
+
+// Listings of every archive read so far, so that opening a member does not
+// read the archive headers again
+static LISTINGS: OnceLock<Mutex<HashMap<String, Arc<ArchiveListing>>>> = OnceLock::new();
+
+// Returns true if an input is an archive whose members are read as inputs
+pub fn is_archive(filename: &str) -> bool {
+    filename.ends_with(".tar") || filename.ends_with(".zip")
+}
+
+// Splits an input name into its archive and member, if it names an archive member
+pub fn split_member_name(filename: &str) -> Option<(&str, &str)> {
+    filename
+        .split_once(MEMBER_SEPARATOR)
+        .filter(|(archive, _)| is_archive(archive))
+}
+
+// Lists the regular file members of an uncompressed tar archive
+fn list_tar(filename: &str) -> Result<Vec<ArchiveMember>, Error> {
+    // headers are skipped over by seeking past the member contents
+    let mut archive = tar::Archive::new(io::BufReader::new(File::open(filename)?));
+    let mut members = Vec::new();
+    for entry in archive.entries_with_seek()? {
+        let entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        members.push(ArchiveMember {
+            name: entry.path()?.to_string_lossy().into_owned(),
+            offset: entry.raw_file_position(),
+            length: entry.size(),
+            compression: MemberCompression::Stored
+        });
+    }
+    Ok(members)
+}
+
+// Lists the file members of a zip archive
+fn list_zip(filename: &str) -> Result<Vec<ArchiveMember>, Error> {
+    let mut archive = zip::ZipArchive::new(io::BufReader::new(File::open(filename)?))?;
+    let mut members = Vec::with_capacity(archive.len());
+    for idx in 0..archive.len() {
+        let entry = archive.by_index_raw(idx)?;
+        if !entry.is_file() {
+            continue;
+        }
+        let compression = match entry.compression() {
+            zip::CompressionMethod::Stored => MemberCompression::Stored,
+            zip::CompressionMethod::Deflated => MemberCompression::Deflated,
+            method => return Err(Error::new(
+                ErrorKind::Unsupported,
+                format!("{} member {} uses unsupported compression {}", filename, entry.name(), method)
+            ))
+        };
+        members.push(ArchiveMember {
+            name: entry.name().to_string(),
+            offset: entry.data_start(),
+            length: entry.compressed_size(),
+            compression
+        });
+    }
+    Ok(members)
+}
+
+// Returns the members of an archive, listing it on first use
+pub fn list_members(filename: &str) -> Result<Arc<ArchiveListing>, Error> {
+    let cache = LISTINGS.get_or_init(|| Mutex::new(HashMap::new()));
+    if let Some(listing) = cache.lock().unwrap().get(filename) {
+        return Ok(listing.clone())
+    }
+    let members = match filename.ends_with(".zip") {
+        true => list_zip(filename)?,
+        false => list_tar(filename)?
+    };
+    let listing = Arc::new(ArchiveListing::new(members));
+    cache.lock().unwrap().insert(filename.to_string(), listing.clone());
+    Ok(listing)
+}
+
+// Finds a member of an archive by name
+fn find_member(filename: &str) -> Result<ArchiveMember, Error> {
+    let (archive, name) = split_member_name(filename)
+        .ok_or_else(|| Error::new(ErrorKind::InvalidInput, format!("{} is not an archive member", filename)))?;
+    list_members(archive)?
+        .get(name)
+        .cloned()
+        .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("{} has no member {}", archive, name)))
+}
+
+// Replaces every archive among the inputs by its mol2 members, in archive order
+pub fn expand_archives(input_files: Vec<String>) -> Result<Vec<String>, Error> {
+    let mut expanded = Vec::with_capacity(input_files.len());
+    for x in input_files {
+        if !is_archive(&x) {
+            expanded.push(x);
+            continue;
+        }
+        let listing = list_members(&x)?;
+        let num_expanded = expanded.len();
+        expanded.extend(
+            listing
+                .members
+                .iter()
+                .filter(|m| m.name.contains(".mol2"))
+                .map(|m| format!("{}{}{}", x, MEMBER_SEPARATOR, m.name))
+        );
+        if expanded.len() == num_expanded {
+            return Err(Error::new(ErrorKind::NotFound, format!("{} contains no mol2 files", x)))
+        }
+    }
+    Ok(expanded)
+}
+
+// Opens the bytes of an archive member, undoing the compression of the archive
+// (the member itself may still be a compressed mol2 file)
+pub fn open_member(filename: &str) -> Result<Box<dyn Read + Send>, Error> {
+    let member = find_member(filename)?;
+    let (archive, _) = split_member_name(filename).unwrap();
+    let mut file = File::open(archive)?;
+    file.seek(io::SeekFrom::Start(member.offset))?;
+    let stored = RetryReader::new(file).take(member.length);
+    match member.compression {
+        MemberCompression::Stored => Ok(Box::new(stored)),
+        MemberCompression::Deflated => Ok(Box::new(DeflateDecoder::new(stored)))
+    }
+}
+
+// Returns the stored size of an archive member
+pub fn member_size(filename: &str) -> Option<u64> {
+    find_member(filename).ok().map(|m| m.length)
+}
@@ -0,0 +1,83 @@
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::Instant;
+
+// Struct describing limits on the work of a run
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Budget {
+    pub max_seconds: Option<f64>,
+    pub max_molecules: Option<u64>
+}
+impl Budget {
+
+    // Describes the limits of the budget (or none)
+    pub fn describe(&self) -> String {
+        let limits: Vec<String> = self.max_seconds
+            .map(|x| format!("{} seconds", x))
+            .into_iter()
+            .chain(self.max_molecules.map(|x| format!("{} molecules", x)))
+            .collect();
+        match limits.is_empty() {
+            true => "none".to_string(),
+            false => limits.join(", ")
+        }
+    }
+}
+
+// Struct tracking the work of a run against its budget
+//
+// The budget is only checked before a file is started: files in flight are
+// always finished so the outputs hold whole files.
+pub struct BudgetTracker {
+    budget: Budget,
+    start: Instant,
+    molecules: AtomicU64,
+    skipped: AtomicUsize
+}
+impl BudgetTracker {
+
+    // Instantiate a new BudgetTracker starting the clock now
+    pub fn new(budget: Budget) -> Self {
+        BudgetTracker {
+            budget,
+            start: Instant::now(),
+            molecules: AtomicU64::new(0),
+            skipped: AtomicUsize::new(0)
+        }
+    }
+
+    // Returns true if the budget is spent, counting the file that is not started
+    pub fn skip_file(&self) -> bool {
+        let out_of_time = self.budget.max_seconds
+            .is_some_and(|s| self.start.elapsed().as_secs_f64() >= s);
+        let out_of_molecules = self.budget.max_molecules
+            .is_some_and(|m| self.molecules.load(Ordering::Relaxed) >= m);
+
+        if out_of_time || out_of_molecules {
+            self.skipped.fetch_add(1, Ordering::Relaxed);
+            return true
+        }
+        false
+    }
+
+    // Records the molecules read from a finished file
+    pub fn add_molecules(&self, num_molecules: u64) {
+        self.molecules.fetch_add(num_molecules, Ordering::Relaxed);
+    }
+
+    // Returns the number of files not started because the budget was spent
+    pub fn num_skipped(&self) -> usize {
+        self.skipped.load(Ordering::Relaxed)
+    }
+
+    // Prints a notice marking the results as partial if any file was skipped
+    pub fn report(&self, num_files: usize) {
+        let num_skipped = self.num_skipped();
+        if num_skipped > 0 {
+            println!(
+                ">>> Partial Results: budget exhausted, {} of {} files not processed",
+                num_skipped, num_files
+            );
+        }
+    }
+}
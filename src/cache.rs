@@ -0,0 +1,232 @@
+
+use std::collections::{BTreeSet, HashMap};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Error};
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use crate::mol2::Mol2Reader;
+
+use rayon::prelude::*;
+
+// First line of every cache file
+pub const CACHE_MAGIC: &str = "#mol2grep-cache\t1";
+
+// Struct describing the contents of a single input file
+#[derive(Clone, Debug, PartialEq)]
+pub struct FileSummary {
+    pub molecules: u64,
+    // header fields found in any molecule of the file
+    pub fields: Vec<String>
+}
+
+// Struct identifying a version of a file on disk
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct FileStamp {
+    size: u64,
+    mtime: u128
+}
+impl FileStamp {
+    fn of(path: &Path) -> Result<Self, Error> {
+        let metadata = fs::metadata(path)?;
+        let mtime = metadata.modified()?
+            .duration_since(UNIX_EPOCH)
+            .map(|x| x.as_nanos())
+            .unwrap_or(0);
+        Ok(FileStamp { size: metadata.len(), mtime })
+    }
+}
+
+// Struct describing a cache of file summaries keyed by path, size, and modification time
+//
+// Stored as a tab separated file: `path size mtime molecules fields` where
+// fields are comma separated; a stale or unreadable entry is simply recomputed.
+pub struct MetadataCache {
+    path: PathBuf,
+    entries: HashMap<PathBuf, (FileStamp, FileSummary)>,
+    num_hits: usize,
+    modified: bool
+}
+impl MetadataCache {
+
+    // Loads a cache file, starting empty if it does not exist or is malformed
+    pub fn load(path: &Path) -> Self {
+        let mut cache = MetadataCache {
+            path: path.to_path_buf(),
+            entries: HashMap::new(),
+            num_hits: 0,
+            modified: false
+        };
+
+        let mut lines = match File::open(path) {
+            Ok(f) => BufReader::new(f).lines(),
+            Err(_) => return cache
+        };
+        if lines.next().and_then(|x| x.ok()).as_deref() != Some(CACHE_MAGIC) {
+            return cache
+        }
+        for line in lines.map_while(|x| x.ok()) {
+            if let Some((p, stamp, summary)) = parse_line(&line) {
+                cache.entries.insert(p, (stamp, summary));
+            }
+        }
+        cache
+    }
+
+    // Returns the default cache location:
+    // $MOL2GREP_CACHE, $XDG_CACHE_HOME/mol2grep/files.tsv, or ~/.cache/mol2grep/files.tsv
+    pub fn default_path() -> Option<PathBuf> {
+        if let Some(x) = std::env::var_os("MOL2GREP_CACHE") {
+            return Some(PathBuf::from(x))
+        }
+        std::env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|x| Path::new(&x).join(".cache")))
+            .map(|x| x.join("mol2grep").join("files.tsv"))
+    }
+
+    // Returns the cached summary of a file if it is unchanged since it was cached
+    pub fn get(&mut self, filename: &str) -> Option<FileSummary> {
+        let path = fs::canonicalize(filename).ok()?;
+        let stamp = FileStamp::of(&path).ok()?;
+        match self.entries.get(&path) {
+            Some((s, summary)) if *s == stamp => {
+                self.num_hits += 1;
+                Some(summary.clone())
+            },
+            _ => None
+        }
+    }
+
+    // Stores the summary of a file along with its current size and modification time
+    pub fn insert(&mut self, filename: &str, summary: FileSummary) {
+        let path = match fs::canonicalize(filename) {
+            Ok(x) => x,
+            Err(_) => return
+        };
+        if let Ok(stamp) = FileStamp::of(&path) {
+            self.entries.insert(path, (stamp, summary));
+            self.modified = true;
+        }
+    }
+
+    // Returns the number of summaries served from the cache
+    pub fn num_hits(&self) -> usize {
+        self.num_hits
+    }
+
+    // Writes the cache back to disk if any entry changed
+    pub fn save(&self) -> Result<(), Error> {
+        if !self.modified {
+            return Ok(())
+        }
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        // written aside and renamed so concurrent runs never read a partial cache
+        let tmp = self.path.with_extension(format!("tmp.{}", std::process::id()));
+        let mut writer = BufWriter::new(File::create(&tmp)?);
+        writeln!(writer, "{}", CACHE_MAGIC)?;
+        for (p, (stamp, summary)) in self.entries.iter() {
+            writeln!(
+                writer,
+                "{}\t{}\t{}\t{}\t{}",
+                p.display(), stamp.size, stamp.mtime, summary.molecules, summary.fields.join(",")
+            )?;
+        }
+        writer.flush()?;
+        drop(writer);
+        fs::rename(&tmp, &self.path)
+    }
+}
+
+// Parses a single cache line
+fn parse_line(line: &str) -> Option<(PathBuf, FileStamp, FileSummary)> {
+    let items: Vec<&str> = line.split('\t').collect();
+    if items.len() != 5 {
+        return None
+    }
+    let fields = match items[4] {
+        "" => Vec::new(),
+        x => x.split(',').map(|f| f.to_string()).collect()
+    };
+    Some((
+        PathBuf::from(items[0]),
+        FileStamp { size: items[1].parse().ok()?, mtime: items[2].parse().ok()? },
+        FileSummary { molecules: items[3].parse().ok()?, fields }
+    ))
+}
+
+// Reads a file to count its molecules and collect its header fields
+pub fn summarize_file(filename: &str) -> Result<FileSummary, Error> {
+    let mut molecules = 0;
+    let mut fields = BTreeSet::new();
+    for mol in Mol2Reader::new(filename)? {
+        molecules += 1;
+        for (key, _) in mol.get_properties() {
+            if !fields.contains(key) {
+                fields.insert(key.clone());
+            }
+        }
+    }
+    Ok(FileSummary { molecules, fields: fields.into_iter().collect() })
+}
+
+// Summarizes every input, reading in parallel only the files missing from the cache
+pub fn summarize_files(
+        input_files: &[String],
+        mut cache: Option<&mut MetadataCache>) -> Result<Vec<FileSummary>, Error> {
+
+    let cached: Vec<Option<FileSummary>> = input_files
+        .iter()
+        .map(|x| cache.as_mut().and_then(|c| c.get(x)))
+        .collect();
+
+    let summaries = input_files
+        .par_iter()
+        .zip(cached.par_iter())
+        .map(|(x, c)| match c {
+            Some(s) => Ok(s.clone()),
+            None => summarize_file(x)
+        })
+        .collect::<Result<Vec<FileSummary>, Error>>()?;
+
+    if let Some(cache) = cache {
+        for ((x, c), s) in input_files.iter().zip(cached.iter()).zip(summaries.iter()) {
+            if c.is_none() {
+                cache.insert(x, s.clone());
+            }
+        }
+    }
+    Ok(summaries)
+}
+
+// implements count subcommand: molecule counts and header fields of every input
+// returns the total number of molecules
+pub fn count(
+        input_files: Vec<String>,
+        output_filename: &str,
+        cache_path: Option<&Path>) -> Result<u64, Error> {
+
+    let mut cache = cache_path.map(MetadataCache::load);
+    let summaries = summarize_files(&input_files, cache.as_mut())?;
+
+    let mut writer = BufWriter::new(File::create(output_filename)?);
+    writer.write_all(b"file\tmolecules\tfields\n")?;
+    for (x, s) in input_files.iter().zip(summaries.iter()) {
+        writeln!(writer, "{}\t{}\t{}", x, s.molecules, s.fields.join(","))?;
+    }
+    let total: u64 = summaries.iter().map(|s| s.molecules).sum();
+
+    println!(">>> Number of Files: {}", input_files.len());
+    println!(">>> Number of Molecules: {}", total);
+    if let Some(c) = cache {
+        println!(">>> Number of Files Served from Cache: {}", c.num_hits());
+        c.save()?;
+    }
+    println!(">>> Counts Written to: {}", output_filename);
+
+    Ok(total)
+}
@@ -0,0 +1,183 @@
+
+use std::collections::{BTreeSet, HashSet};
+use std::fs::File;
+use std::io::{BufWriter, Error};
+use std::io::prelude::*;
+use std::sync::Arc;
+
+use crate::mol2::Mol2Reader;
+use crate::mol2utils::par_stream_filtered;
+use crate::file_io::writer;
+use crate::provenance::Provenance;
+use crate::stats::per_ligand_stats;
+
+use rayon::prelude::*;
+
+// Struct describing the differences between two pose collections
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DiffSummary {
+    pub shared: usize,
+    pub only_a: usize,
+    pub only_b: usize,
+    pub changed: usize
+}
+
+// implements diff subcommand: compares two pose collections by name
+// returns the number of names in each category
+//
+// Every name is compared by its best energy in each collection; with a
+// tolerance, shared names whose best energies differ by more are reported
+// as changed. Names only in one collection and changed names are written as
+// `name status energy_a energy_b delta` sorted by name, delta being b - a.
+pub fn diff(
+        input_a: &[String],
+        input_b: &[String],
+        output_filename: &str,
+        tolerance: Option<f64>) -> Result<DiffSummary, Error> {
+
+    let a = per_ligand_stats(input_a);
+    let b = per_ligand_stats(input_b);
+    let names: BTreeSet<&String> = a.keys().chain(b.keys()).collect();
+
+    let mut summary = DiffSummary::default();
+    let mut writer = BufWriter::new(File::create(output_filename)?);
+    writer.write_all(b"name\tstatus\tenergy_a\tenergy_b\tdelta\n")?;
+    for name in names {
+        match (a.get(name), b.get(name)) {
+            (Some(x), None) => {
+                summary.only_a += 1;
+                writeln!(writer, "{}\tonly_a\t{}\t\t", name, x.best)?;
+            },
+            (None, Some(y)) => {
+                summary.only_b += 1;
+                writeln!(writer, "{}\tonly_b\t\t{}\t", name, y.best)?;
+            },
+            (Some(x), Some(y)) => {
+                summary.shared += 1;
+                let delta = y.best - x.best;
+                if tolerance.is_some_and(|t| delta.abs() > t) {
+                    summary.changed += 1;
+                    writeln!(writer, "{}\tchanged\t{}\t{}\t{}", name, x.best, y.best, delta)?;
+                }
+            },
+            (None, None) => unreachable!()
+        }
+    }
+    writer.flush()?;
+
+    println!(">>> Number of Shared Names: {}", summary.shared);
+    println!(">>> Number of Names Only in A: {}", summary.only_a);
+    println!(">>> Number of Names Only in B: {}", summary.only_b);
+    if let Some(t) = tolerance {
+        println!(">>> Number of Names With Energies Changed Beyond {}: {}", t, summary.changed);
+    }
+    println!(">>> Differences Written to: {}", output_filename);
+
+    Ok(summary)
+}
+
+// Enumerate describing a name-based set operation between two pose collections
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SetOperation {
+    // poses of A whose name is in B
+    Intersect,
+    // poses of A, then poses of B whose name is not in A
+    Union,
+    // poses of A whose name is not in B
+    Subtract
+}
+impl SetOperation {
+
+    // Parses a set operation from its CLI representation
+    pub fn from_name(op: &str) -> Option<Self> {
+        match op {
+            "intersect" => Some(SetOperation::Intersect),
+            "union" => Some(SetOperation::Union),
+            "subtract" => Some(SetOperation::Subtract),
+            _ => None
+        }
+    }
+
+    // Returns the name the operation is selected by
+    pub fn name(&self) -> &'static str {
+        match self {
+            SetOperation::Intersect => "intersect",
+            SetOperation::Union => "union",
+            SetOperation::Subtract => "subtract"
+        }
+    }
+}
+
+// Reads the names of every pose of a collection in parallel
+fn collect_names(input_files: &[String]) -> HashSet<String> {
+    input_files
+        .par_iter()
+        .map(|x| {
+            Mol2Reader::new(x)
+                .unwrap()
+                .map(|mol| mol.get_name().to_string())
+                .collect::<HashSet<String>>()
+        })
+        .reduce(HashSet::new, |mut a, b| {
+            a.extend(b);
+            a
+        })
+}
+
+// Writes the poses of a collection whose name is (or is not) in a set
+// returns the number of molecules written
+fn write_by_membership(
+        input_files: Vec<String>,
+        names: Arc<HashSet<String>>,
+        member: bool,
+        writer: &mut dyn Write) -> u32 {
+
+    let channel_recv = par_stream_filtered(input_files, move |_, _, mol| {
+        names.contains(mol.get_name()) == member
+    });
+    let mut num_written = 0;
+    for mol in channel_recv {
+        writer
+            .write_all(mol.get_lines().as_bytes())
+            .expect("Error: Error writing to output file");
+        num_written += 1;
+    }
+    num_written
+}
+
+// implements set subcommand: name-based set algebra on whole pose records
+// returns the number of molecules written
+//
+// Only names are held in memory: those of B for intersect and subtract, and
+// those of A for union, while the poses themselves are streamed.
+pub fn set_operation(
+        input_a: Vec<String>,
+        input_b: Vec<String>,
+        op: SetOperation,
+        output_filename: &str) -> Result<u32, Error> {
+
+    let inputs: Vec<String> = input_a.iter().chain(input_b.iter()).cloned().collect();
+    let provenance = Provenance::new(&inputs)?;
+    let mut writer = writer(output_filename)?;
+    provenance.write(&mut writer)?;
+
+    let num_written = match op {
+        SetOperation::Intersect | SetOperation::Subtract => {
+            let names = Arc::new(collect_names(&input_b));
+            println!(">>> Number of Names in B: {}", names.len());
+            write_by_membership(input_a, names, op == SetOperation::Intersect, &mut writer)
+        },
+        SetOperation::Union => {
+            let names = Arc::new(collect_names(&input_a));
+            println!(">>> Number of Names in A: {}", names.len());
+            let everything = Arc::new(HashSet::new());
+            write_by_membership(input_a, everything, false, &mut writer) +
+                write_by_membership(input_b, names, false, &mut writer)
+        }
+    };
+    writer.flush()?;
+
+    println!(">>> Number of Molecules Written: {}", num_written);
+
+    Ok(num_written)
+}
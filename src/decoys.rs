@@ -0,0 +1,208 @@
+
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufWriter, Error, ErrorKind};
+use std::io::prelude::*;
+
+use crate::mol2::{Mol2, Mol2Reader};
+use crate::mol2utils::par_stream_filtered;
+use crate::file_io::{is_stdin, writer};
+use crate::provenance::Provenance;
+
+use rayon::prelude::*;
+
+// Molecular weight difference weighing as much as one heavy atom in the match distance
+const MW_PER_HEAVY_ATOM: f64 = 12.0;
+
+// Struct describing the properties decoys are matched on
+#[derive(Clone, Debug)]
+pub struct Descriptors {
+    pub mw: f64,
+    pub net_charge: i64,
+    pub heavy_atoms: u32
+}
+impl Descriptors {
+
+    // Computes the descriptors of a molecule from its atoms
+    pub fn from_mol(mol: &Mol2) -> Self {
+        let atoms = mol.atoms();
+        Descriptors {
+            mw: atoms.iter().map(|a| a.mass()).sum(),
+            net_charge: atoms.iter().map(|a| a.charge).sum::<f64>().round() as i64,
+            heavy_atoms: atoms.iter().filter(|a| a.is_heavy()).count() as u32
+        }
+    }
+
+    // Returns the distance between two sets of descriptors
+    // (None if they differ in net charge, which must match exactly)
+    pub fn distance(&self, other: &Descriptors) -> Option<f64> {
+        if self.net_charge != other.net_charge {
+            return None
+        }
+        let d_mw = (self.mw - other.mw).abs() / MW_PER_HEAVY_ATOM;
+        let d_heavy = (self.heavy_atoms as f64 - other.heavy_atoms as f64).abs();
+        Some(d_mw + d_heavy)
+    }
+}
+
+// Struct describing the first pose of a named library molecule
+struct Candidate {
+    name: String,
+    file_index: usize,
+    record_index: usize,
+    descriptors: Descriptors
+}
+
+// Struct describing the source of the actives
+pub enum Actives {
+    Names(Vec<String>),
+    Molecules(String)
+}
+
+// Reads the descriptors of the first pose of every name across the inputs
+fn library_candidates(input_files: &[String]) -> Vec<Candidate> {
+    let records: Vec<Candidate> = input_files
+        .par_iter()
+        .enumerate()
+        .flat_map_iter(|(file_index, x)| {
+            let mut seen = HashSet::new();
+            Mol2Reader::new(x)
+                .unwrap()
+                .enumerate()
+                .filter(move |(_, mol)| seen.insert(mol.get_name().to_string()))
+                .map(move |(record_index, mol)| Candidate {
+                    name: mol.get_name().to_string(),
+                    file_index,
+                    record_index,
+                    descriptors: Descriptors::from_mol(&mol)
+                })
+        })
+        .collect();
+
+    // keep the first pose of names spread across files
+    let mut seen = HashSet::new();
+    records
+        .into_iter()
+        .filter(|c| seen.insert(c.name.clone()))
+        .collect()
+}
+
+// Resolves the names and descriptors of the actives
+fn active_descriptors(actives: &Actives, library: &[Candidate]) -> Result<Vec<(String, Descriptors)>, Error> {
+    match actives {
+        Actives::Names(names) => {
+            let by_name: HashMap<&str, &Descriptors> = library
+                .iter()
+                .map(|c| (c.name.as_str(), &c.descriptors))
+                .collect();
+            let mut seen = HashSet::new();
+            Ok(names
+                .iter()
+                .filter(|x| seen.insert(x.as_str()))
+                .filter_map(|x| by_name.get(x.as_str()).map(|d| (x.clone(), (*d).clone())))
+                .collect())
+        },
+        Actives::Molecules(filename) => {
+            let mut seen = HashSet::new();
+            Ok(Mol2Reader::new(filename)?
+                .filter(|mol| seen.insert(mol.get_name().to_string()))
+                .map(|mol| (mol.get_name().to_string(), Descriptors::from_mol(&mol)))
+                .collect())
+        }
+    }
+}
+
+// implements decoys subcommand: selects property-matched decoys for every active
+// returns the number of decoys written
+//
+// Decoys share the net charge of their active and are the closest library
+// molecules in molecular weight and heavy atom count. Every library molecule is
+// assigned to at most one active and never shares a name with an active.
+pub fn decoys(
+        input_files: Vec<String>,
+        actives: &Actives,
+        per_active: usize,
+        output_filename: &str,
+        pairs_filename: &str) -> Result<usize, Error> {
+
+    // candidates are collected in a first pass and written in a second
+    if input_files.iter().any(|x| is_stdin(x)) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "decoys reads the library twice and cannot read the standard input"
+        ))
+    }
+
+    let library = library_candidates(&input_files);
+    let active_set = active_descriptors(actives, &library)?;
+
+    let active_names: HashSet<&str> = active_set
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect();
+    let mut used = vec![false; library.len()];
+
+    // greedy assignment of the nearest unused candidates in active order
+    let mut pairs: Vec<(usize, usize, f64)> = Vec::new();
+    for (active_index, (_, descriptors)) in active_set.iter().enumerate() {
+        let mut ranked: Vec<(usize, f64)> = library
+            .par_iter()
+            .enumerate()
+            .filter(|(idx, c)| !used[*idx] && !active_names.contains(c.name.as_str()))
+            .filter_map(|(idx, c)| descriptors.distance(&c.descriptors).map(|d| (idx, d)))
+            .collect();
+        ranked.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+
+        for (idx, d) in ranked.into_iter().take(per_active) {
+            used[idx] = true;
+            pairs.push((active_index, idx, d));
+        }
+    }
+
+    // pairing table
+    let mut pair_writer = BufWriter::new(File::create(pairs_filename)?);
+    pair_writer.write_all(b"active\tdecoy\tdistance\tactive_mw\tdecoy_mw\tnet_charge\tactive_heavy_atoms\tdecoy_heavy_atoms\n")?;
+    for (active_index, idx, d) in pairs.iter() {
+        let (name, a) = &active_set[*active_index];
+        let c = &library[*idx];
+        writeln!(
+            pair_writer,
+            "{}\t{}\t{:.3}\t{:.3}\t{:.3}\t{}\t{}\t{}",
+            name, c.name, d, a.mw, c.descriptors.mw, a.net_charge, a.heavy_atoms, c.descriptors.heavy_atoms
+        )?;
+    }
+
+    // second pass writing the selected poses
+    let selected: HashSet<(usize, usize)> = pairs
+        .iter()
+        .map(|(_, idx, _)| (library[*idx].file_index, library[*idx].record_index))
+        .collect();
+    let provenance = Provenance::new(&input_files)?;
+    let mut writer = writer(output_filename)?;
+    provenance.write(&mut writer)?;
+    let channel_recv = par_stream_filtered(input_files, move |file_index, record_index, _| {
+        selected.contains(&(file_index, record_index))
+    });
+    for mol in channel_recv {
+        writer
+            .write_all(mol.get_lines().as_bytes())
+            .expect("Error: Error writing to output file");
+    }
+
+    let num_short = active_set
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| pairs.iter().filter(|p| p.0 == *i).count() < per_active)
+        .count();
+
+    println!(">>> Number of Library Molecules: {}", library.len());
+    println!(">>> Number of Actives: {}", active_set.len());
+    println!(">>> Number of Decoys Selected: {}", pairs.len());
+    if num_short > 0 {
+        println!(">>> Number of Actives With Fewer Than {} Decoys: {}", per_active, num_short);
+    }
+    println!(">>> Decoys Written to: {}", output_filename);
+    println!(">>> Pairs Written to: {}", pairs_filename);
+
+    Ok(pairs.len())
+}
@@ -0,0 +1,111 @@
+
+use std::collections::{HashMap, HashSet};
+use std::io::{Error, ErrorKind};
+use std::io::prelude::*;
+use std::sync::Arc;
+
+use crate::mol2::Mol2Reader;
+use crate::mol2utils::{par_stream_filtered, stream_molecules};
+use crate::file_io::{writer, is_stdin};
+use crate::provenance::Provenance;
+
+use rayon::prelude::*;
+
+// Best pose of every name as (energy, file index, record index)
+type BestPoses = HashMap<String, (f64, usize, usize)>;
+
+// Finds the best pose of every name (the earliest pose wins ties)
+// returns the best poses and the number of molecules read
+fn best_poses(input_files: &[String]) -> (BestPoses, u32) {
+    input_files
+        .par_iter()
+        .enumerate()
+        .map(|(file_index, x)| {
+            let mut best = BestPoses::new();
+            let mut num_molecules = 0;
+            for (record_index, mol) in Mol2Reader::new(x).unwrap().enumerate() {
+                num_molecules += 1;
+                let energy = mol.get_energy();
+                match best.get_mut(mol.get_name()) {
+                    Some(b) if energy < b.0 => *b = (energy, file_index, record_index),
+                    Some(_) => {},
+                    None => { best.insert(mol.get_name().to_string(), (energy, file_index, record_index)); }
+                }
+            }
+            (best, num_molecules)
+        })
+        .reduce(|| (BestPoses::new(), 0), |(mut a, na), (b, nb)| {
+            for (name, candidate) in b {
+                let entry = a.entry(name).or_insert(candidate);
+                let better = candidate.0
+                    .total_cmp(&entry.0)
+                    .then((candidate.1, candidate.2).cmp(&(entry.1, entry.2)))
+                    .is_lt();
+                if better {
+                    *entry = candidate;
+                }
+            }
+            (a, na + nb)
+        })
+}
+
+// implements dedup subcommand: writes a single pose per name
+// returns the number of molecules written
+//
+// The lowest energy pose is found in a first pass over the inputs and written
+// in a second; with `keep_first` the first pose of every name in input order is
+// written in a single streaming pass instead.
+pub fn dedup(
+        input_files: Vec<String>,
+        output_filename: &str,
+        keep_first: bool) -> Result<u32, Error> {
+
+    if !keep_first && input_files.iter().any(|x| is_stdin(x)) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "keeping the best pose reads the inputs twice and cannot read the standard input (use --keep-first)"
+        ))
+    }
+
+    let provenance = Provenance::new(&input_files)?;
+    let mut writer = writer(output_filename)?;
+    provenance.write(&mut writer)?;
+
+    let mut num_molecules = 0;
+    let mut num_written = 0;
+    if keep_first {
+        let mut seen: HashSet<String> = HashSet::new();
+        for mol in stream_molecules(input_files) {
+            num_molecules += 1;
+            if seen.contains(mol.get_name()) {
+                continue;
+            }
+            seen.insert(mol.get_name().to_string());
+            writer
+                .write_all(mol.get_lines().as_bytes())
+                .expect("Error: Error writing to output file");
+            num_written += 1;
+        }
+    } else {
+        let (best, n) = best_poses(&input_files);
+        num_molecules = n;
+        let winners = Arc::new(best);
+        let channel_recv = par_stream_filtered(input_files, move |file_index, record_index, mol| {
+            winners
+                .get(mol.get_name())
+                .is_some_and(|b| b.1 == file_index && b.2 == record_index)
+        });
+        for mol in channel_recv {
+            writer
+                .write_all(mol.get_lines().as_bytes())
+                .expect("Error: Error writing to output file");
+            num_written += 1;
+        }
+    }
+    writer.flush()?;
+
+    println!(">>> Number of Molecules Processed: {}", num_molecules);
+    println!(">>> Number of Unique Names Written: {}", num_written);
+
+    Ok(num_written)
+}
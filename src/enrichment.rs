@@ -0,0 +1,172 @@
+
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Error};
+use std::io::prelude::*;
+
+use crate::mol2::Mol2Reader;
+
+use rayon::prelude::*;
+
+// Lowest false positive rate considered by the logAUC
+pub const LOG_AUC_LAMBDA: f64 = 0.001;
+
+// Struct describing the retrospective enrichment of a ranked set of molecules
+pub struct Enrichment {
+    pub num_actives: usize,
+    pub num_decoys: usize,
+    pub auc: f64,
+    pub log_auc: f64,
+    pub enrichment_factors: Vec<(f64, f64)>,
+    pub roc: Vec<(f64, f64, f64)>
+}
+impl Enrichment {
+
+    // Returns the logAUC minus that of a random ranking
+    pub fn adjusted_log_auc(&self) -> f64 {
+        let random = (1.0 - LOG_AUC_LAMBDA) / (std::f64::consts::LN_10 * (1.0 / LOG_AUC_LAMBDA).log10());
+        self.log_auc - random
+    }
+
+    // Writes the summary metrics as rows of: metric, value
+    pub fn write_summary(&self, filename: &str) -> Result<(), Error> {
+        let mut writer = BufWriter::new(File::create(filename)?);
+        writer.write_all(b"metric\tvalue\n")?;
+        writeln!(writer, "num_actives\t{}", self.num_actives)?;
+        writeln!(writer, "num_decoys\t{}", self.num_decoys)?;
+        writeln!(writer, "auc\t{:.6}", self.auc)?;
+        writeln!(writer, "log_auc\t{:.6}", self.log_auc)?;
+        writeln!(writer, "adjusted_log_auc\t{:.6}", self.adjusted_log_auc())?;
+        for (percent, ef) in self.enrichment_factors.iter() {
+            writeln!(writer, "ef_{}\t{:.6}", percent, ef)?;
+        }
+        Ok(())
+    }
+
+    // Writes the ROC curve as rows of: fpr, tpr, score threshold
+    pub fn write_roc(&self, filename: &str) -> Result<(), Error> {
+        let mut writer = BufWriter::new(File::create(filename)?);
+        writer.write_all(b"fpr\ttpr\tthreshold\n")?;
+        for (fpr, tpr, threshold) in self.roc.iter() {
+            writeln!(writer, "{:.6}\t{:.6}\t{}", fpr, tpr, threshold)?;
+        }
+        Ok(())
+    }
+}
+
+// Returns the best (lowest) energy of every name across the inputs
+pub fn best_scores(input_files: &[String]) -> HashMap<String, f64> {
+    input_files
+        .par_iter()
+        .map(|x| {
+            let mut scores: HashMap<String, f64> = HashMap::new();
+            for mol in Mol2Reader::new(x).unwrap() {
+                let best = scores.entry(mol.get_name().to_string()).or_insert(f64::INFINITY);
+                *best = best.min(mol.get_energy());
+            }
+            scores
+        })
+        .reduce(HashMap::new, |mut a, b| {
+            for (k, v) in b {
+                let best = a.entry(k).or_insert(f64::INFINITY);
+                *best = best.min(v);
+            }
+            a
+        })
+}
+
+// Reads a two column (name, score) table keeping the best score of every name
+// (lines whose score does not parse, such as a header, are skipped)
+pub fn read_score_table(filename: &str) -> Result<HashMap<String, f64>, Error> {
+    let reader = BufReader::new(File::open(filename)?);
+    let mut scores: HashMap<String, f64> = HashMap::new();
+    for line in reader.lines() {
+        let line = line?;
+        let items: Vec<&str> = line.split_whitespace().collect();
+        if items.len() < 2 {
+            continue;
+        }
+        if let Ok(score) = items[1].parse::<f64>() {
+            let best = scores.entry(items[0].to_string()).or_insert(f64::INFINITY);
+            *best = best.min(score);
+        }
+    }
+    Ok(scores)
+}
+
+// Integrates the ROC curve over a log10 scaled false positive rate from LOG_AUC_LAMBDA to 1
+fn log_auc(roc: &[(f64, f64, f64)]) -> f64 {
+    let lambda = LOG_AUC_LAMBDA;
+    let mut area = 0.0;
+    for w in roc.windows(2) {
+        let (x0, y0, _) = w[0];
+        let (x1, y1, _) = w[1];
+        if x1 <= lambda || x1 <= x0 {
+            continue;
+        }
+        // clip the segment to the lower bound of the integration
+        let (x0, y0) = if x0 < lambda {
+            (lambda, y0 + (y1 - y0) * (lambda - x0) / (x1 - x0))
+        } else {
+            (x0, y0)
+        };
+        area += (y0 + y1) / 2.0 * (x1.log10() - x0.log10());
+    }
+    area / (1.0 / lambda).log10()
+}
+
+// Computes ROC and enrichment metrics ranking molecules by ascending score
+// (None if there are no actives or no decoys among the scored molecules)
+pub fn enrichment(
+        scores: &HashMap<String, f64>,
+        actives: &HashSet<String>,
+        ef_percents: &[f64]) -> Option<Enrichment> {
+
+    // rank by score, breaking ties by name for reproducibility
+    let mut ranked: Vec<(&str, f64, bool)> = scores
+        .iter()
+        .map(|(k, v)| (k.as_str(), *v, actives.contains(k)))
+        .collect();
+    ranked.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(b.0)));
+
+    let num_total = ranked.len();
+    let num_actives = ranked.iter().filter(|x| x.2).count();
+    let num_decoys = num_total - num_actives;
+    if num_actives == 0 || num_decoys == 0 {
+        return None
+    }
+
+    // one ROC point per distinct score so ties form a diagonal step
+    let mut roc = vec![(0.0, 0.0, f64::NEG_INFINITY)];
+    let (mut tp, mut fp) = (0, 0);
+    for (i, (_, score, is_active)) in ranked.iter().enumerate() {
+        if *is_active { tp += 1 } else { fp += 1 }
+        if ranked.get(i + 1).is_none_or(|next| next.1 != *score) {
+            roc.push((fp as f64 / num_decoys as f64, tp as f64 / num_actives as f64, *score));
+        }
+    }
+
+    let auc = roc
+        .windows(2)
+        .map(|w| (w[1].0 - w[0].0) * (w[0].1 + w[1].1) / 2.0)
+        .sum();
+
+    let enrichment_factors = ef_percents
+        .iter()
+        .map(|p| {
+            let n_top = ((num_total as f64 * p / 100.0).ceil() as usize).clamp(1, num_total);
+            let hits = ranked.iter().take(n_top).filter(|x| x.2).count();
+            let ef = (hits as f64 / n_top as f64) / (num_actives as f64 / num_total as f64);
+            (*p, ef)
+        })
+        .collect();
+
+    Some(Enrichment {
+        num_actives,
+        num_decoys,
+        auc,
+        log_auc: log_auc(&roc),
+        enrichment_factors,
+        roc
+    })
+}
@@ -0,0 +1,118 @@
+
+use std::fs::File;
+use std::io::{BufWriter, Error, ErrorKind};
+use std::io::prelude::*;
+use std::process::{Command, Stdio};
+
+use crate::mol2::Mol2;
+
+// Options of a command run on the accepted molecules
+#[derive(Clone, Debug)]
+pub struct ExecOptions {
+    pub command: String,
+    pub batch_size: usize,
+    pub report: String
+}
+impl ExecOptions {
+
+    // Describes the command and its batching
+    pub fn describe(&self) -> String {
+        format!("{} (batches of {}, report {})", self.command, self.batch_size, self.report)
+    }
+}
+
+// Struct describing the outcome of running the command on one batch
+pub struct ExecResult {
+    pub batch: usize,
+    pub molecules: usize,
+    pub exit_code: Option<i32>
+}
+
+// Struct describing a command fed batches of molecules on its stdin
+//
+// The command runs through `sh -c` once per batch with the batch index in
+// MOL2GREP_BATCH; its stdout and stderr are passed through.
+pub struct ExecHook {
+    options: ExecOptions,
+    buffer: String,
+    num_buffered: usize,
+    results: Vec<ExecResult>
+}
+impl ExecHook {
+
+    // Instantiate a new ExecHook
+    pub fn new(options: ExecOptions) -> Self {
+        ExecHook {
+            options,
+            buffer: String::new(),
+            num_buffered: 0,
+            results: Vec::new()
+        }
+    }
+
+    // Adds a molecule to the current batch, running the command once the batch is full
+    pub fn push(&mut self, mol: &Mol2) -> Result<(), Error> {
+        self.buffer.push_str(mol.get_lines());
+        self.num_buffered += 1;
+        if self.num_buffered >= self.options.batch_size.max(1) {
+            self.run_batch()?;
+        }
+        Ok(())
+    }
+
+    // Runs the command on the buffered molecules
+    fn run_batch(&mut self) -> Result<(), Error> {
+        let batch = self.results.len();
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(&self.options.command)
+            .env("MOL2GREP_BATCH", batch.to_string())
+            .stdin(Stdio::piped())
+            .spawn()?;
+
+        // a command is free not to read all of its input
+        let written = child.stdin
+            .take()
+            .unwrap()
+            .write_all(self.buffer.as_bytes());
+        match written {
+            Err(e) if e.kind() != ErrorKind::BrokenPipe => return Err(e),
+            _ => {}
+        }
+        let status = child.wait()?;
+
+        self.results.push(ExecResult {
+            batch,
+            molecules: self.num_buffered,
+            exit_code: status.code()
+        });
+        self.buffer.clear();
+        self.num_buffered = 0;
+        Ok(())
+    }
+
+    // Runs the command on the last partial batch and writes the report
+    // returns the number of batches whose command failed
+    pub fn finish(mut self) -> Result<usize, Error> {
+        if self.num_buffered > 0 {
+            self.run_batch()?;
+        }
+
+        let mut writer = BufWriter::new(File::create(&self.options.report)?);
+        writer.write_all(b"batch\tmolecules\texit_code\n")?;
+        for r in self.results.iter() {
+            // a command killed by a signal has no exit code
+            let code = r.exit_code.map(|x| x.to_string()).unwrap_or_else(|| "signal".to_string());
+            writeln!(writer, "{}\t{}\t{}", r.batch, r.molecules, code)?;
+        }
+
+        let num_failed = self.results
+            .iter()
+            .filter(|r| r.exit_code != Some(0))
+            .count();
+        println!(">>> Number of Command Batches: {} ({} failed)", self.results.len(), num_failed);
+        println!(">>> Command Report Written to: {}", self.options.report);
+
+        Ok(num_failed)
+    }
+}
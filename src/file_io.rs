@@ -3,21 +3,555 @@ use std::fs::File;
 use std::io::BufWriter;
 use std::io::prelude::*;
 use std::path::Path;
+use crate::remote::is_remote;
 use flate2::write::GzEncoder;
 use flate2::Compression;
 use std::io;
+use std::io::Cursor;
+use std::thread;
+use std::time::{Duration, Instant};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::sync::mpsc::{self, Receiver, SyncSender};
 
+// Number of times a failed read is attempted before giving up
+const READ_ATTEMPTS: u32 = 4;
 
-// Public writer function to write to gzip
-pub fn writer(filename: &str) -> Box<dyn Write> {
+// Delay before the first retry of a failed read (doubled on each attempt)
+const RETRY_DELAY_MS: u64 = 100;
+
+
+// Compression level requested from the CLI (unset for each format's default)
+static COMPRESSION_LEVEL: OnceLock<i32> = OnceLock::new();
+
+// Sets the compression level of all subsequently created writers
+pub fn set_compression_level(level: i32) {
+    COMPRESSION_LEVEL.set(level).ok();
+}
+
+// Whether gzip outputs are written as BGZF (set from the CLI)
+static BGZF: OnceLock<bool> = OnceLock::new();
+
+// Writes all subsequently created gzip outputs as BGZF
+pub fn set_bgzf(bgzf: bool) {
+    BGZF.set(bgzf).ok();
+}
+
+// Struct describing the compression of an output chosen by its filename suffix
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutputFormat {
+    Gzip,
+    Zstd,
+    Plain
+}
+impl OutputFormat {
+
+    // Returns the format of a filename: `.gz` is gzip, `.zst` is zstd, anything else is plain text
+    pub fn from_filename(filename: &str) -> Self {
+        if filename.ends_with(".gz") {
+            OutputFormat::Gzip
+        } else if filename.ends_with(".zst") {
+            OutputFormat::Zstd
+        } else {
+            OutputFormat::Plain
+        }
+    }
+}
+
+// Public writer function to write to a file compressed according to its suffix
+// (`-` writes plain text to the standard output)
+pub fn writer(filename: &str) -> io::Result<Box<dyn Write>> {
+    if is_stdout(filename) {
+        return Ok(Box::new(BufWriter::with_capacity(128 * 1024, io::stdout())))
+    }
     let path = Path::new(filename);
-    let file = File::create(&path).unwrap();
+    let file = File::create(path)?;
+    encoder(file, filename)
+}
+
+// Wraps a sink in the encoder matching the suffix of a filename
+fn encoder<W: Write + Send + 'static>(sink: W, filename: &str) -> io::Result<Box<dyn Write>> {
+    let level = COMPRESSION_LEVEL.get().copied();
+
+    match OutputFormat::from_filename(filename) {
+        OutputFormat::Gzip => {
+            let compression = level
+                .map(|x| Compression::new(x.clamp(0, 9) as u32))
+                .unwrap_or_default();
+            if BGZF.get().copied().unwrap_or(false) {
+                return Ok(Box::new(BgzfWriter::new(sink, compression)))
+            }
+            Ok(Box::new(BufWriter::with_capacity(
+                128 * 1024,
+                GzEncoder::new(sink, compression),
+            )))
+        },
+        OutputFormat::Zstd => zstd_writer(sink, level),
+        OutputFormat::Plain => Ok(Box::new(BufWriter::with_capacity(128 * 1024, sink)))
+    }
+
+}
+
+// Opens a zstd encoder finishing the frame when dropped
+#[cfg(feature = "zstd")]
+fn zstd_writer<W: Write + Send + 'static>(sink: W, level: Option<i32>) -> io::Result<Box<dyn Write>> {
+    let encoder = zstd::stream::write::Encoder::new(sink, level.unwrap_or(zstd::DEFAULT_COMPRESSION_LEVEL))?
+        .auto_finish();
+    Ok(Box::new(BufWriter::with_capacity(128 * 1024, encoder)))
+}
+
+#[cfg(not(feature = "zstd"))]
+fn zstd_writer<W: Write + Send + 'static>(_sink: W, _level: Option<i32>) -> io::Result<Box<dyn Write>> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "writing .zst files requires the zstd feature"))
+}
+
+// Largest number of uncompressed bytes in a BGZF block
+const BGZF_BLOCK_SIZE: usize = 0xff00;
+
+// Empty BGZF block marking the end of a file
+const BGZF_EOF: [u8; 28] = [
+    0x1f, 0x8b, 0x08, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x06, 0x00, 0x42, 0x43,
+    0x02, 0x00, 0x1b, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00
+];
+
+// Struct describing a BGZF (blocked gzip) encoder
+//
+// Every block is a gzip member of at most 64 KB recording its compressed size,
+// so readers can seek to any block and decompress blocks in parallel. A block
+// is ended before a write that would overflow it rather than within it: as
+// molecules are written one per call, blocks hold whole molecules and the
+// index locates each molecule within a single small block.
+pub struct BgzfWriter<W: Write> {
+    inner: W,
+    buffer: Vec<u8>,
+    compression: Compression,
+    finished: bool
+}
+impl<W: Write> BgzfWriter<W> {
+
+    pub fn new(inner: W, compression: Compression) -> Self {
+        BgzfWriter {
+            inner,
+            buffer: Vec::with_capacity(BGZF_BLOCK_SIZE),
+            compression,
+            finished: false
+        }
+    }
+
+    // Compresses and writes the first `length` buffered bytes as one block
+    fn write_block(&mut self, length: usize) -> io::Result<()> {
+        let data = &self.buffer[..length];
+        let mut deflate = flate2::write::DeflateEncoder::new(Vec::new(), self.compression);
+        deflate.write_all(data)?;
+        let compressed = deflate.finish()?;
+        let mut crc = flate2::Crc::new();
+        crc.update(data);
+
+        // header with the BC extra subfield holding the block size minus one
+        let block_size = (compressed.len() + 26 - 1) as u16;
+        let mut header = [
+            0x1f, 0x8b, 0x08, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff,
+            0x06, 0x00, 0x42, 0x43, 0x02, 0x00, 0x00, 0x00
+        ];
+        header[16..18].copy_from_slice(&block_size.to_le_bytes());
+        self.inner.write_all(&header)?;
+        self.inner.write_all(&compressed)?;
+        self.inner.write_all(&crc.sum().to_le_bytes())?;
+        self.inner.write_all(&(length as u32).to_le_bytes())?;
+        self.buffer.drain(..length);
+        Ok(())
+    }
+
+    // Writes the buffered bytes and the end of file marker
+    pub fn finish(&mut self) -> io::Result<()> {
+        if self.finished {
+            return Ok(())
+        }
+        if !self.buffer.is_empty() {
+            self.write_block(self.buffer.len())?;
+        }
+        self.inner.write_all(&BGZF_EOF)?;
+        self.finished = true;
+        self.inner.flush()
+    }
+}
+impl<W: Write> Write for BgzfWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !self.buffer.is_empty() && self.buffer.len() + buf.len() > BGZF_BLOCK_SIZE {
+            self.write_block(self.buffer.len())?;
+        }
+        self.buffer.extend_from_slice(buf);
+
+        // writes larger than a block are split over full blocks
+        while self.buffer.len() > BGZF_BLOCK_SIZE {
+            self.write_block(BGZF_BLOCK_SIZE)?;
+        }
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+impl<W: Write> Drop for BgzfWriter<W> {
+    fn drop(&mut self) {
+        self.finish().ok();
+    }
+}
+
+// Struct describing a set of named outputs written either as files or as the
+// entries of a single tar archive (gzip compressed if named .tar.gz or .tgz)
+//
+// Archive entries are encoded into spill files next to the archive and streamed
+// into it, in the order of their names, once their writers are finished; the
+// spill files are removed as they are appended.
+pub struct OutputSet {
+    names: Vec<String>,
+    spills: Vec<String>,
+    builder: Option<tar::Builder<Box<dyn Write>>>
+}
+impl OutputSet {
+
+    // Opens a writer for every name, compressed according to its suffix
+    pub fn open(names: &[String], archive: Option<&str>) -> io::Result<(Self, Vec<Box<dyn Write>>)> {
+        let archive = match archive {
+            Some(x) => x,
+            None => {
+                let writers = names
+                    .iter()
+                    .map(|x| writer(x))
+                    .collect::<io::Result<Vec<Box<dyn Write>>>>()?;
+                let outputs = OutputSet { names: names.to_vec(), spills: Vec::new(), builder: None };
+                return Ok((outputs, writers))
+            }
+        };
+
+        let file = File::create(archive)?;
+        let sink: Box<dyn Write> = if archive.ends_with(".tar.gz") || archive.ends_with(".tgz") {
+            Box::new(GzEncoder::new(BufWriter::new(file), Compression::default()))
+        } else {
+            Box::new(BufWriter::new(file))
+        };
+        let mut outputs = OutputSet {
+            names: names.to_vec(),
+            spills: Vec::new(),
+            builder: Some(tar::Builder::new(sink))
+        };
+        let mut writers = Vec::with_capacity(names.len());
+        for (idx, name) in names.iter().enumerate() {
+            let spill = format!("{}.{}.part", archive, idx);
+            let file = File::create(&spill)?;
+            outputs.spills.push(spill);
+            writers.push(encoder(file, name)?);
+        }
+        Ok((outputs, writers))
+    }
+
+    // Finishes the writers and, when archiving, appends every output as an archive entry
+    pub fn finish(mut self, writers: Vec<Box<dyn Write>>) -> io::Result<()> {
+        // flushing surfaces write errors; dropping finalizes the encoders
+        for mut w in writers {
+            w.flush()?;
+        }
+
+        let mut builder = match self.builder.take() {
+            Some(x) => x,
+            None => return Ok(())
+        };
+        let mtime = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        for (name, spill) in self.names.iter().zip(self.spills.iter()) {
+            let file = File::open(spill)?;
+            let mut header = tar::Header::new_gnu();
+            header.set_size(file.metadata()?.len());
+            header.set_mode(0o644);
+            header.set_mtime(mtime);
+            builder.append_data(&mut header, name, io::BufReader::new(file))?;
+            std::fs::remove_file(spill)?;
+        }
+        self.spills.clear();
+        builder.into_inner()?.flush()
+    }
+}
+impl Drop for OutputSet {
+    // removes the spill files of an archive that was never finished
+    fn drop(&mut self) {
+        for spill in self.spills.iter() {
+            std::fs::remove_file(spill).ok();
+        }
+    }
+}
+
+// Name standing for the standard input in input lists
+pub const STDIN_NAME: &str = "-";
+
+// Returns true if an input name stands for the standard input
+pub fn is_stdin(filename: &str) -> bool {
+    filename == STDIN_NAME
+}
+
+// Output name standing for the standard output
+pub const STDOUT_NAME: &str = "-";
+
+// Returns true if an output name stands for the standard output
+pub fn is_stdout(filename: &str) -> bool {
+    filename == STDOUT_NAME
+}
+
+// Struct describing a reader that retries failed reads with exponential backoff
+//
+// Network filesystems occasionally fail a read transiently; a failed read does
+// not advance the file position, so the same read can simply be repeated.
+pub struct RetryReader<R: Read> {
+    inner: R
+}
+impl<R: Read> RetryReader<R> {
+
+    // Instantiate a new RetryReader
+    pub fn new(inner: R) -> Self {
+        RetryReader { inner }
+    }
+}
+impl<R: Read> Read for RetryReader<R> {
+
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut attempt = 0;
+        loop {
+            match self.inner.read(buf) {
+                Ok(n) => {
+                    throttle(n);
+                    return Ok(n)
+                },
+                Err(e) if e.kind() != io::ErrorKind::Interrupted && attempt + 1 < READ_ATTEMPTS => {
+                    thread::sleep(Duration::from_millis(RETRY_DELAY_MS << attempt));
+                    attempt += 1;
+                },
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {},
+                result => return result
+            }
+        }
+    }
+}
+
+// Struct describing a token bucket limiting the rate of bytes read
+//
+// Readers take tokens for every byte read and sleep off any debt, so the rate
+// holds across all threads sharing the bucket. Unused tokens accumulate for at
+// most one second.
+pub struct RateLimiter {
+    bytes_per_second: f64,
+    state: Mutex<(f64, Instant)>
+}
+impl RateLimiter {
+
+    // Instantiate a new RateLimiter with an empty bucket
+    pub fn new(megabytes_per_second: f64) -> Self {
+        RateLimiter {
+            bytes_per_second: megabytes_per_second * 1e6,
+            state: Mutex::new((0.0, Instant::now()))
+        }
+    }
+
+    // Takes tokens for a number of bytes, sleeping until the rate allows them
+    pub fn acquire(&self, num_bytes: usize) {
+        let wait = {
+            let mut state = self.state.lock().unwrap();
+            let (tokens, last) = *state;
+            let now = Instant::now();
+            let tokens = (tokens + now.duration_since(last).as_secs_f64() * self.bytes_per_second)
+                .min(self.bytes_per_second) - num_bytes as f64;
+            *state = (tokens, now);
+            -tokens / self.bytes_per_second
+        };
+        if wait > 0.0 {
+            thread::sleep(Duration::from_secs_f64(wait));
+        }
+    }
+}
+
+// Limit on the rate of reads of every input (unset to read unthrottled)
+static READ_LIMIT: OnceLock<RateLimiter> = OnceLock::new();
+
+// Sets the maximum rate of reads across all readers in megabytes per second
+pub fn set_max_read_mbps(megabytes_per_second: f64) {
+    READ_LIMIT.set(RateLimiter::new(megabytes_per_second)).ok();
+}
+
+// Waits until the read limit allows a number of bytes to be read
+fn throttle(num_bytes: usize) {
+    if let Some(limiter) = READ_LIMIT.get() {
+        limiter.acquire(num_bytes);
+    }
+}
+
+// Lowers the IO scheduling priority of the process to the lowest best-effort level
+// returns false where the platform does not support it
+#[cfg(target_os = "linux")]
+pub fn lower_io_priority() -> bool {
+    const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+    const IOPRIO_CLASS_BE: libc::c_int = 2;
+    const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+    const IOPRIO_LOWEST: libc::c_int = 7;
+
+    // pid 0 is the calling process
+    let priority = (IOPRIO_CLASS_BE << IOPRIO_CLASS_SHIFT) | IOPRIO_LOWEST;
+    unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, priority) == 0 }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn lower_io_priority() -> bool {
+    false
+}
+
+// Decompressed bytes fetched by each IO task
+const READ_AHEAD_BYTES: usize = 1024 * 1024;
+
+// Number of IO threads requested from the CLI (0 to size automatically)
+static IO_THREADS: OnceLock<usize> = OnceLock::new();
+
+// Pool performing file reads and decompression separately from the parsing workers
+static IO_POOL: OnceLock<rayon::ThreadPool> = OnceLock::new();
+
+// Sets the number of IO threads (must be called before the first input is opened)
+pub fn set_io_threads(num_threads: usize) {
+    IO_THREADS.set(num_threads).ok();
+}
+
+// Returns the number of IO threads used when none are requested
+fn default_io_threads() -> usize {
+    let num_cores = thread::available_parallelism()
+        .map(|x| x.get())
+        .unwrap_or(1);
+    (num_cores / 4).max(2)
+}
+
+// Returns the IO pool, building it on first use
+fn io_pool() -> &'static rayon::ThreadPool {
+    IO_POOL.get_or_init(|| {
+        let num_threads = match IO_THREADS.get() {
+            Some(&n) if n > 0 => n,
+            _ => default_io_threads()
+        };
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(num_threads)
+            .thread_name(|i| format!("mol2grep-io-{}", i))
+            .build()
+            .expect("Error : Failed to build IO thread pool")
+    })
+}
+
+// Returns the number of threads in the IO pool
+pub fn io_threads() -> usize {
+    io_pool().current_num_threads()
+}
+
+// Struct describing a reader whose underlying reads run on the IO pool
+//
+// One chunk is prefetched while the previous one is parsed. Only a single task
+// per reader is ever queued and tasks never wait on the consumer, so readers
+// consumed in any interleaving cannot starve the pool.
+pub struct ReadAhead {
+    inner: Arc<Mutex<Box<dyn Read + Send>>>,
+    pending: Option<Receiver<(Vec<u8>, Option<io::Error>)>>,
+    current: Cursor<Vec<u8>>,
+    error: Option<io::Error>
+}
+impl ReadAhead {
 
-    Box::new(BufWriter::with_capacity(
-        128 * 1024,
-        GzEncoder::new(file, Compression::default()),
-    ))
+    // Instantiate a new ReadAhead and schedule its first read
+    pub fn new(inner: Box<dyn Read + Send>) -> Self {
+        let mut reader = ReadAhead {
+            inner: Arc::new(Mutex::new(inner)),
+            pending: None,
+            current: Cursor::new(Vec::new()),
+            error: None
+        };
+        reader.prefetch();
+        reader
+    }
 
+    // Schedules the next chunk to be read on the IO pool
+    //
+    // Bytes read before an error are delivered ahead of the error itself.
+    fn prefetch(&mut self) {
+        let (sender, receiver): (SyncSender<(Vec<u8>, Option<io::Error>)>, _) = mpsc::sync_channel(1);
+        let inner = self.inner.clone();
+        io_pool().spawn(move || {
+            let mut chunk = Vec::with_capacity(READ_AHEAD_BYTES);
+            let error = inner
+                .lock()
+                .unwrap()
+                .by_ref()
+                .take(READ_AHEAD_BYTES as u64)
+                .read_to_end(&mut chunk)
+                .err();
+            sender.send((chunk, error)).ok();
+        });
+        self.pending = Some(receiver);
+    }
+}
+impl Read for ReadAhead {
+
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let n = self.current.read(buf)?;
+            if n > 0 {
+                return Ok(n)
+            }
+
+            if let Some(e) = self.error.take() {
+                return Err(e)
+            }
+
+            let receiver = match self.pending.take() {
+                Some(r) => r,
+                None => return Ok(0)
+            };
+            let (chunk, error) = receiver
+                .recv()
+                .map_err(|_| io::Error::other("IO task terminated"))?;
+
+            // a short chunk marks the end of the input
+            if error.is_none() && chunk.len() == READ_AHEAD_BYTES {
+                self.prefetch();
+            }
+            self.current = Cursor::new(chunk);
+            self.error = error;
+        }
+    }
+}
+
+// Returns true if an input name contains glob wildcards
+pub fn is_glob_pattern(filename: &str) -> bool {
+    filename.contains(['*', '?', '['])
+}
+
+// Expands glob patterns among the inputs into the paths they match, in sorted order
+//
+// Patterns are expanded here rather than by the shell so that hundreds of
+// thousands of files do not exceed the argument length limit. Names that exist
+// as given and URLs are kept verbatim, and a pattern matching no file is an error.
+pub fn expand_input_patterns(input_files: Vec<String>) -> Result<Vec<String>, io::Error> {
+    let mut expanded = Vec::with_capacity(input_files.len());
+    for x in input_files {
+        if !is_glob_pattern(&x) || is_remote(&x) || Path::new(&x).exists() {
+            expanded.push(x);
+            continue;
+        }
+        let pattern = glob::glob(&x)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, format!("{}: {}", x, e)))?;
+        let num_expanded = expanded.len();
+        for path in pattern {
+            let path = path.map_err(io::Error::from)?;
+            if path.is_file() {
+                expanded.push(path.to_string_lossy().into_owned());
+            }
+        }
+        if expanded.len() == num_expanded {
+            return Err(io::Error::new(io::ErrorKind::NotFound, format!("no input matches {}", x)))
+        }
+    }
+    Ok(expanded)
 }
 
 // Reads in an input list of paths
@@ -34,3 +568,49 @@ pub fn read_input_list(filename: &str) -> Result<Vec<String>, io::Error>{
 
     Ok(content)
 }
+
+// Reads in an ordered list of names (the first column of every non-empty line)
+pub fn read_name_list(filename: &str) -> Result<Vec<String>, io::Error> {
+
+    let mut file = File::open(filename)?;
+    let mut list = String::new();
+    file.read_to_string(&mut list)?;
+
+    let content: Vec<String> = list
+        .lines()
+        .filter_map(|x| x.split_whitespace().next())
+        .map(|x| x.to_string())
+        .collect();
+
+    Ok(content)
+}
+
+// Restricts an input list to a range of files
+pub fn select_input_range(
+        input_files: Vec<String>,
+        resume_from: Option<&str>,
+        skip: usize,
+        limit: Option<usize>) -> Result<Vec<String>, io::Error> {
+
+    // start at the position of the given path if resuming
+    let start = match resume_from {
+        Some(path) => {
+            input_files
+                .iter()
+                .position(|x| x == path)
+                .ok_or_else(|| io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("resume path not found in input list: {}", path)
+                ))?
+        },
+        None => 0
+    };
+
+    let selection = input_files
+        .into_iter()
+        .skip(start + skip)
+        .take(limit.unwrap_or(usize::MAX))
+        .collect();
+
+    Ok(selection)
+}
@@ -0,0 +1,230 @@
+
+use std::collections::{HashMap, HashSet};
+use std::io::{Error, ErrorKind};
+use std::sync::{Arc, Mutex};
+use std::io::prelude::*;
+
+use crate::mol2::{Mol2, Mol2Reader};
+use crate::mol2utils::par_stream_filtered;
+use crate::file_io::{writer, is_stdin};
+use crate::sample::{Sampler, sequence_id};
+use crate::plugin::MoleculeFilter;
+use crate::exec::{ExecHook, ExecOptions};
+
+use rayon::prelude::*;
+
+// Options modifying the behavior of the filter subcommand
+#[derive(Default)]
+pub struct FilterOptions {
+    pub max_energy: Option<f64>,
+    pub min_energy: Option<f64>,
+    pub top_percent: Option<f64>,
+    pub sample: Option<Sampler>,
+    pub plugin: Option<Arc<dyn MoleculeFilter>>,
+    pub max_radius_of_gyration: Option<f64>,
+    pub pocket: Option<Pocket>,
+    pub min_poses: Option<usize>,
+    pub max_poses: Option<usize>,
+    pub exec: Option<ExecOptions>
+}
+
+// Struct describing a sphere pose centroids are required to fall within
+#[derive(Clone, Debug)]
+pub struct Pocket {
+    pub center: (f64, f64, f64),
+    pub max_distance: f64
+}
+impl Pocket {
+
+    // Parses a pocket from a comma separated center (`x,y,z`) and a radius
+    pub fn from_args(center: &str, max_distance: f64) -> Option<Self> {
+        let coords: Vec<f64> = center
+            .split(',')
+            .map(|x| x.trim().parse::<f64>())
+            .collect::<Result<_, _>>()
+            .ok()?;
+        if coords.len() != 3 {
+            return None
+        }
+        Some(Pocket {
+            center: (coords[0], coords[1], coords[2]),
+            max_distance
+        })
+    }
+
+    // Describes the center and radius of the pocket
+    pub fn describe(&self) -> String {
+        let (x, y, z) = self.center;
+        format!("{} from {},{},{}", self.max_distance, x, y, z)
+    }
+}
+
+// Returns true if the molecule passes the geometric criteria
+// (molecules without atoms fail any geometric criterion)
+fn passes_geometry(mol: &Mol2, max_rg: Option<f64>, pocket: Option<&Pocket>) -> bool {
+    if max_rg.is_none() && pocket.is_none() {
+        return true
+    }
+    match mol.geometry() {
+        Some(g) => {
+            max_rg.is_none_or(|r| g.radius_of_gyration <= r) &&
+                pocket.is_none_or(|p| g.distance_to(p.center) <= p.max_distance)
+        },
+        None => false
+    }
+}
+
+// Reads the energies of all molecules across the inputs in parallel
+fn collect_energies(input_files: &[String]) -> Vec<f64> {
+    input_files
+        .par_iter()
+        .flat_map_iter(|x| {
+            Mol2Reader::new(x)
+                .unwrap()
+                .map(|mol| mol.get_energy())
+        })
+        .collect()
+}
+
+// Counts the poses of every ligand across the inputs in parallel
+fn count_poses(input_files: &[String]) -> HashMap<String, usize> {
+    input_files
+        .par_iter()
+        .map(|x| {
+            let mut counts: HashMap<String, usize> = HashMap::new();
+            for mol in Mol2Reader::new(x).unwrap() {
+                *counts.entry(mol.get_name().to_string()).or_default() += 1;
+            }
+            counts
+        })
+        .reduce(HashMap::new, |mut a, b| {
+            for (k, v) in b {
+                *a.entry(k).or_default() += v;
+            }
+            a
+        })
+}
+
+// Returns the names of ligands whose number of poses lies within the bounds
+fn ligands_within_pose_counts(
+        input_files: &[String],
+        min_poses: Option<usize>,
+        max_poses: Option<usize>) -> HashSet<String> {
+    count_poses(input_files)
+        .into_iter()
+        .filter(|(_, n)| min_poses.is_none_or(|m| *n >= m) && max_poses.is_none_or(|m| *n <= m))
+        .map(|(name, _)| name)
+        .collect()
+}
+
+// Computes the energy below which the best `percent` of molecules fall
+pub fn percentile_cutoff(mut energies: Vec<f64>, percent: f64) -> Option<f64> {
+    if energies.is_empty() {
+        return None
+    }
+
+    // number of molecules to keep (at least one)
+    let k = ((energies.len() as f64 * percent / 100.0).ceil() as usize)
+        .clamp(1, energies.len());
+
+    let (_, cutoff, _) = energies.select_nth_unstable_by(k - 1, |a, b| a.total_cmp(b));
+    Some(*cutoff)
+}
+
+// Returns true if the plugin keeps the molecule
+// the first plugin error is recorded and every later molecule is rejected
+fn plugin_keeps(plugin: &dyn MoleculeFilter, mol: &Mol2, error: &Mutex<Option<Error>>) -> bool {
+    if error.lock().unwrap().is_some() {
+        return false
+    }
+    match plugin.keep(mol) {
+        Ok(keep) => keep,
+        Err(e) => {
+            error.lock().unwrap().get_or_insert(e);
+            false
+        }
+    }
+}
+
+// implements filter subcommand
+pub fn filter(
+        input_files: Vec<String>,
+        output_filename: &str,
+        options: &FilterOptions) -> Result<u32, Error> {
+
+    // two pass criteria cannot read the standard input a second time
+    let two_pass = options.top_percent.is_some() || options.min_poses.is_some() || options.max_poses.is_some();
+    if two_pass && input_files.iter().any(|x| is_stdin(x)) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "--top-percent, --min-poses, and --max-poses read the inputs twice and cannot read the standard input"
+        ))
+    }
+
+    // first pass computing the energy cutoff of the requested quantile
+    let cutoff = match options.top_percent {
+        Some(percent) => {
+            let energies = collect_energies(&input_files);
+            let cutoff = percentile_cutoff(energies, percent).unwrap_or(f64::NEG_INFINITY);
+            println!(">>> Energy Cutoff (top {}%): {}", percent, cutoff);
+            cutoff
+        },
+        None => f64::INFINITY
+    };
+    let max_energy = options.max_energy.map_or(cutoff, |x| x.min(cutoff));
+    let min_energy = options.min_energy.unwrap_or(f64::NEG_INFINITY);
+
+    // first pass counting poses per ligand
+    let ligands = match (options.min_poses, options.max_poses) {
+        (None, None) => None,
+        (min_poses, max_poses) => {
+            let ligands = ligands_within_pose_counts(&input_files, min_poses, max_poses);
+            println!(">>> Number of Ligands Within Pose Counts: {}", ligands.len());
+            Some(ligands)
+        }
+    };
+
+    let sample = options.sample.clone();
+    let plugin = options.plugin.clone();
+    let plugin_error: Arc<Mutex<Option<Error>>> = Arc::new(Mutex::new(None));
+    let plugin_error_fmt = Arc::clone(&plugin_error);
+    let max_rg = options.max_radius_of_gyration;
+    let pocket = options.pocket.clone();
+    let channel_recv = par_stream_filtered(input_files, move |file_index, record_index, mol| {
+        (min_energy..=max_energy).contains(&mol.get_energy()) &&
+            ligands
+                .as_ref()
+                .is_none_or(|x| x.contains(mol.get_name())) &&
+            sample
+                .as_ref()
+                .is_none_or(|s| s.keep(mol.get_name(), sequence_id(file_index, record_index))) &&
+            passes_geometry(mol, max_rg, pocket.as_ref()) &&
+            plugin
+                .as_ref()
+                .is_none_or(|p| plugin_keeps(p.as_ref(), mol, &plugin_error_fmt))
+    });
+
+    // writes passing molecules to file
+    let mut writer = writer(output_filename)?;
+    let mut num_passing = 0;
+    let mut hook = options.exec.clone().map(ExecHook::new);
+    for mol in channel_recv {
+        writer
+            .write_all(mol.get_lines().as_bytes())
+            .expect("Error: Error writing to output file");
+        if let Some(ref mut h) = hook {
+            h.push(&mol)?;
+        }
+        num_passing += 1;
+    }
+    if let Some(e) = plugin_error.lock().unwrap().take() {
+        return Err(e)
+    }
+    if let Some(h) = hook {
+        h.finish()?;
+    }
+
+    println!(">>> Number of Molecules Accepted: {}", num_passing);
+
+    Ok(num_passing)
+}
@@ -0,0 +1,387 @@
+
+use std::fs::File;
+use std::io;
+use std::io::{BufReader, Cursor};
+use std::io::prelude::*;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use flate2::bufread::GzDecoder;
+use flate2::read::MultiGzDecoder;
+use rayon::prelude::*;
+
+use crate::file_io::{ReadAhead, RetryReader, io_threads, is_stdin};
+use crate::archive;
+use crate::remote;
+
+// Files smaller than this are decompressed on a single thread
+pub const PARALLEL_MIN_BYTES: u64 = 32 * 1024 * 1024;
+
+// Compressed bytes decompressed per thread in each parallel batch
+const BATCH_BYTES_PER_THREAD: usize = 4 * 1024 * 1024;
+
+// Largest window of compressed bytes read ahead regardless of the number of threads
+const MAX_WINDOW_BYTES: usize = 64 * 1024 * 1024;
+
+// Set while a file is decompressed in parallel
+//
+// The IO pool is already saturated by a single file, so further large files are
+// decompressed sequentially and only one set of windows is held at once.
+static PARALLEL_IN_USE: AtomicBool = AtomicBool::new(false);
+
+// Returns the offsets of every plausible gzip member header in a buffer
+//
+// A candidate is the gzip magic, the deflate method, and a flag byte with the
+// reserved bits unset. False positives within compressed data are possible and
+// are detected when the surrounding member fails to decode.
+pub fn member_offsets(data: &[u8]) -> Vec<usize> {
+    data.windows(4)
+        .enumerate()
+        .filter(|(_, w)| w[0] == 0x1f && w[1] == 0x8b && w[2] == 0x08 && w[3] & 0xe0 == 0)
+        .map(|(i, _)| i)
+        .collect()
+}
+
+// Windows of compressed bytes a single member may span before the rest of the
+// file is decompressed sequentially (such a member cannot be split between threads)
+const MAX_MEMBER_WINDOWS: usize = 4;
+
+// Decompresses a single complete gzip member
+fn decode_member(member: &[u8]) -> io::Result<Vec<u8>> {
+    let mut decoder = GzDecoder::new(member);
+    let mut buffer = Vec::with_capacity(member.len() * 6);
+    decoder.read_to_end(&mut buffer)?;
+
+    // a truncated member (the next candidate was a false positive) reads short
+    if decoder.into_inner().is_empty() {
+        Ok(buffer)
+    } else {
+        Err(io::Error::new(io::ErrorKind::InvalidData, "trailing data after gzip member"))
+    }
+}
+
+// Decompresses the gzip member at the start of a buffer
+// returns its contents and compressed length (none if it does not end within the buffer)
+fn decode_leading_member(data: &[u8]) -> Option<(Vec<u8>, usize)> {
+    let mut decoder = GzDecoder::new(data);
+    let mut buffer = Vec::new();
+    decoder.read_to_end(&mut buffer).ok()?;
+    Some((buffer, data.len() - decoder.into_inner().len()))
+}
+
+// Struct describing a reader decompressing the members of a multi-member gzip
+// file concurrently while yielding their contents in order
+//
+// Compressed bytes are read ahead one window at a time and the members complete
+// within the window are decompressed in parallel; the member continuing past the
+// window is kept for the next one. A member split by a false candidate header is
+// decompressed on its own and the following members are decompressed in parallel
+// again.
+pub struct ParallelGzDecoder<R: Read> {
+    source: Option<R>,
+    eof: bool,
+    window: Vec<u8>,
+    window_bytes: usize,
+    peak_window: usize,
+    num_members: usize,
+    buffer: Cursor<Vec<u8>>,
+    fallback: Option<MultiGzDecoder<io::Chain<Cursor<Vec<u8>>, R>>>,
+    exclusive: bool
+}
+impl<R: Read> ParallelGzDecoder<R> {
+
+    // Instantiate a new ParallelGzDecoder reading a window per IO pool batch
+    // (members are decompressed by the IO pool the reads are scheduled on)
+    pub fn new(source: R) -> Self {
+        Self::with_window(source, (BATCH_BYTES_PER_THREAD * io_threads()).min(MAX_WINDOW_BYTES))
+    }
+
+    // Instantiate a new ParallelGzDecoder unless another file is already being
+    // decompressed in parallel, in which case the source is returned
+    pub fn exclusive(source: R) -> Result<Self, R> {
+        if PARALLEL_IN_USE.compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire).is_err() {
+            return Err(source)
+        }
+        let mut decoder = Self::new(source);
+        decoder.exclusive = true;
+        Ok(decoder)
+    }
+
+    // Instantiate a new ParallelGzDecoder reading ahead windows of the given size
+    pub fn with_window(source: R, window_bytes: usize) -> Self {
+        ParallelGzDecoder {
+            source: Some(source),
+            eof: false,
+            window: Vec::new(),
+            window_bytes: window_bytes.max(1),
+            peak_window: 0,
+            num_members: 0,
+            buffer: Cursor::new(Vec::new()),
+            fallback: None,
+            exclusive: false
+        }
+    }
+
+    // Returns the number of members decompressed without falling back
+    pub fn num_members(&self) -> usize {
+        self.num_members
+    }
+
+    // Returns the largest number of compressed bytes held at once
+    pub fn peak_window(&self) -> usize {
+        self.peak_window
+    }
+
+    // Reads the next window and decompresses its complete members in parallel into the buffer
+    //
+    // If no member ends within a window several times the window size, or the
+    // remaining bytes at the end of the file do not decode, the rest of the file
+    // is decompressed sequentially instead.
+    fn fill_batch(&mut self) -> io::Result<()> {
+        if let Some(source) = self.source.as_mut() {
+            let wanted = self.window_bytes as u64;
+            let n = source.take(wanted).read_to_end(&mut self.window)?;
+            self.eof = (n as u64) < wanted;
+        }
+        self.peak_window = self.peak_window.max(self.window.len());
+
+        // candidate members, the last continuing past the window unless it is the end of the file
+        let mut offsets = member_offsets(&self.window);
+        if offsets.first() != Some(&0) {
+            offsets.insert(0, 0);
+        }
+        let mut ends = offsets[1..].to_vec();
+        if self.eof {
+            ends.push(self.window.len());
+        }
+        let window = &self.window;
+        let decoded: Vec<io::Result<Vec<u8>>> = offsets
+            .par_iter()
+            .zip(ends.par_iter())
+            .map(|(a, b)| decode_member(&window[*a..*b]))
+            .collect();
+
+        // members are taken in order from the last member boundary
+        let mut pos = 0;
+        let mut members = Vec::new();
+        for ((a, b), member) in offsets.iter().zip(ends.iter()).zip(decoded) {
+            if *a < pos {
+                continue;
+            }
+            match member {
+                Ok(x) if *a == pos => {
+                    members.push(x);
+                    pos = *b;
+                },
+                // a false candidate split the member at the boundary
+                _ => match decode_leading_member(&window[pos..]) {
+                    Some((x, length)) => {
+                        members.push(x);
+                        pos += length;
+                    },
+                    None => break
+                }
+            }
+            self.num_members += 1;
+        }
+        self.window.drain(..pos);
+        self.buffer = Cursor::new(members.concat());
+
+        let stalled = pos == 0 && (self.eof || self.window.len() >= MAX_MEMBER_WINDOWS * self.window_bytes);
+        if stalled && !self.window.is_empty() {
+            let remainder = Cursor::new(std::mem::take(&mut self.window));
+            let source = self.source.take().expect("source is only taken by the fallback");
+            self.fallback = Some(MultiGzDecoder::new(remainder.chain(source)));
+        }
+        Ok(())
+    }
+}
+impl<R: Read> Read for ParallelGzDecoder<R> {
+
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if let Some(decoder) = self.fallback.as_mut() {
+                return decoder.read(buf)
+            }
+
+            let n = self.buffer.read(buf)?;
+            if n > 0 || (self.eof && self.window.is_empty()) {
+                return Ok(n)
+            }
+            self.fill_batch()?;
+        }
+    }
+}
+
+impl<R: Read> Drop for ParallelGzDecoder<R> {
+
+    fn drop(&mut self) {
+        if self.exclusive {
+            PARALLEL_IN_USE.store(false, Ordering::Release);
+        }
+    }
+}
+
+// Struct describing a BufRead counting the bytes consumed from it
+struct CountingReader<R: BufRead> {
+    inner: R,
+    consumed: u64
+}
+impl<R: BufRead> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.consumed += n as u64;
+        Ok(n)
+    }
+}
+impl<R: BufRead> BufRead for CountingReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+    fn consume(&mut self, amt: usize) {
+        self.inner.consume(amt);
+        self.consumed += amt as u64;
+    }
+}
+
+// Struct describing the location of a single gzip member
+#[derive(Clone, Debug)]
+pub struct Member {
+    pub offset: u64,
+    pub length: u64,
+    pub decompressed_offset: u64
+}
+
+// Struct describing a reader decompressing a gzip file one member at a time,
+// recording the compressed range and decompressed offset of every member
+pub struct MemberReader {
+    decoder: Option<GzDecoder<CountingReader<BufReader<RetryReader<File>>>>>,
+    members: Arc<Mutex<Vec<Member>>>,
+    member_start: u64,
+    member_decompressed: u64,
+    decompressed: u64
+}
+impl MemberReader {
+
+    // Opens a gzip file recording its members into a shared list as they are read
+    pub fn open(filename: &str, members: Arc<Mutex<Vec<Member>>>) -> io::Result<Self> {
+        let inner = CountingReader {
+            inner: BufReader::new(RetryReader::new(File::open(filename)?)),
+            consumed: 0
+        };
+        Ok(MemberReader {
+            decoder: Some(GzDecoder::new(inner)),
+            members,
+            member_start: 0,
+            member_decompressed: 0,
+            decompressed: 0
+        })
+    }
+}
+impl Read for MemberReader {
+
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let decoder = match self.decoder.as_mut() {
+                Some(d) => d,
+                None => return Ok(0)
+            };
+            let n = decoder.read(buf)?;
+            if n > 0 || buf.is_empty() {
+                self.decompressed += n as u64;
+                return Ok(n)
+            }
+
+            // the member is complete: record it and continue with the next one
+            let mut inner = self.decoder.take().unwrap().into_inner();
+            self.members.lock().unwrap().push(Member {
+                offset: self.member_start,
+                length: inner.consumed - self.member_start,
+                decompressed_offset: self.member_decompressed
+            });
+            if inner.fill_buf()?.is_empty() {
+                return Ok(0)
+            }
+            self.member_start = inner.consumed;
+            self.member_decompressed = self.decompressed;
+            self.decoder = Some(GzDecoder::new(inner));
+        }
+    }
+}
+
+// Decompresses the members within a compressed byte range of a gzip file
+pub fn read_range(filename: &str, offset: u64, length: u64) -> io::Result<Vec<u8>> {
+    let mut file = File::open(filename)?;
+    file.seek(io::SeekFrom::Start(offset))?;
+    let mut buffer = Vec::new();
+    MultiGzDecoder::new(RetryReader::new(file).take(length)).read_to_end(&mut buffer)?;
+    Ok(buffer)
+}
+
+// Opens a zstd compressed stream for reading
+#[cfg(feature = "zstd")]
+fn open_zstd<R: Read + Send + 'static>(reader: R) -> io::Result<Box<dyn Read + Send>> {
+    let decoder = zstd::stream::read::Decoder::new(reader)?;
+    Ok(Box::new(ReadAhead::new(Box::new(decoder))))
+}
+
+#[cfg(not(feature = "zstd"))]
+fn open_zstd<R: Read + Send + 'static>(_reader: R) -> io::Result<Box<dyn Read + Send>> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "reading .zst files requires the zstd feature"))
+}
+
+// Opens a stream for reading, detecting its compression from its leading bytes
+fn open_stream<R: BufRead + Send + 'static>(mut reader: R) -> io::Result<Box<dyn Read + Send>> {
+    let magic = reader.fill_buf()?;
+    if magic.starts_with(&[0x1f, 0x8b]) {
+        Ok(Box::new(ReadAhead::new(Box::new(MultiGzDecoder::new(reader)))))
+    } else if magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+        open_zstd(reader)
+    } else {
+        Ok(Box::new(ReadAhead::new(Box::new(reader))))
+    }
+}
+
+// Opens a gzip file for reading, decompressing members in parallel when the
+// file is large enough to benefit, more than one IO thread is available, and no
+// other file is being decompressed in parallel (reads of the underlying file are
+// retried on failure and decompression runs on the IO pool)
+//
+// Inputs that are not gzip compressed are recognized by their leading bytes and
+// read as zstd or as plain text; the standard input (-), URLs, and archive
+// members (archive.tar::member) are streamed.
+pub fn open(filename: &str) -> io::Result<Box<dyn Read + Send>> {
+    if is_stdin(filename) {
+        return open_stream(BufReader::with_capacity(1 << 20, io::stdin()))
+    }
+    if remote::is_remote(filename) {
+        return open_stream(BufReader::with_capacity(1 << 20, remote::open_remote(filename)?))
+    }
+    if archive::split_member_name(filename).is_some() {
+        return open_stream(BufReader::with_capacity(1 << 20, archive::open_member(filename)?))
+    }
+    let mut file = File::open(filename)?;
+    let size = file.metadata()?.len();
+
+    let mut magic = [0u8; 4];
+    let num_magic = file.read(&mut magic)?;
+    file.seek(io::SeekFrom::Start(0))?;
+    match &magic[..num_magic] {
+        [0x1f, 0x8b, ..] => {},
+        [0x28, 0xb5, 0x2f, 0xfd] => return open_zstd(RetryReader::new(file)),
+        _ => return Ok(Box::new(ReadAhead::new(Box::new(RetryReader::new(file)))))
+    }
+
+    let file = RetryReader::new(file);
+
+    let file = if size >= PARALLEL_MIN_BYTES && io_threads() > 1 {
+        match ParallelGzDecoder::exclusive(file) {
+            Ok(decoder) => return Ok(Box::new(ReadAhead::new(Box::new(decoder)))),
+            Err(file) => file
+        }
+    } else {
+        file
+    };
+
+    Ok(Box::new(ReadAhead::new(Box::new(MultiGzDecoder::new(file)))))
+}
@@ -0,0 +1,597 @@
+
+use std::collections::{HashMap, HashSet};
+use std::convert::TryInto;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Error, ErrorKind};
+use std::io::prelude::*;
+use std::sync::{Arc, Mutex};
+
+use crate::mol2::{Mol2, Mol2Reader};
+use crate::gzip::{self, Member, MemberReader};
+use crate::file_io::{writer, is_stdout};
+use crate::provenance::Provenance;
+use crate::query::{QueryColumns, QueryFormat, QueryReader, Tolerance};
+
+use memmap2::Mmap;
+use rayon::prelude::*;
+
+// First line of every index file
+pub const INDEX_MAGIC: &str = "#mol2grep-index\t1";
+
+// Leading bytes of every name table
+const NAME_TABLE_MAGIC: &[u8; 8] = b"m2gnames";
+
+// Bytes of the name table header (magic, number of records, length of the index it belongs to)
+const NAME_TABLE_HEADER: usize = 24;
+
+// Bytes of a single name table record
+const NAME_RECORD_BYTES: usize = 56;
+
+// Struct describing an input file covered by an index
+#[derive(Clone, Debug)]
+pub struct IndexedFile {
+    pub path: String,
+    pub size: u64
+}
+
+// Struct describing the location of a single molecule
+//
+// A block is a compressed byte range made of whole gzip members that no molecule
+// crosses; a molecule is found by decompressing its block only and slicing
+// `record_length` bytes at `record_offset` of the decompressed block.
+#[derive(Clone, Debug)]
+pub struct IndexEntry {
+    pub name: String,
+    pub energy: f64,
+    pub file_index: usize,
+    pub block_offset: u64,
+    pub block_length: u64,
+    pub record_offset: u64,
+    pub record_length: u64
+}
+impl IndexEntry {
+
+    // Parses an entry from a tab separated index line
+    fn from_line(line: &str) -> Option<Self> {
+        let items: Vec<&str> = line.split('\t').collect();
+        if items.len() != 7 {
+            return None
+        }
+        Some(IndexEntry {
+            energy: items[0].parse().ok()?,
+            name: items[1].to_string(),
+            file_index: items[2].parse().ok()?,
+            block_offset: items[3].parse().ok()?,
+            block_length: items[4].parse().ok()?,
+            record_offset: items[5].parse().ok()?,
+            record_length: items[6].parse().ok()?
+        })
+    }
+}
+
+// Struct describing a compressed block and where its contents start once decompressed
+struct Block {
+    offset: u64,
+    length: u64,
+    decompressed_offset: u64
+}
+
+// Groups gzip members into blocks so that no molecule spans two blocks
+fn group_members(members: &[Member], spans: &[(u64, u64)]) -> Vec<Block> {
+    let mut blocks: Vec<Block> = Vec::new();
+    for member in members {
+        // a boundary inside a molecule joins the member to the previous block
+        let d = member.decompressed_offset;
+        let idx = spans.partition_point(|(start, _)| *start < d);
+        let crossed = idx > 0 && spans[idx - 1].1 > d;
+
+        match blocks.last_mut() {
+            Some(block) if crossed => block.length = member.offset + member.length - block.offset,
+            _ => blocks.push(Block {
+                offset: member.offset,
+                length: member.length,
+                decompressed_offset: d
+            })
+        }
+    }
+    blocks
+}
+
+// Indexes the molecules of a single file
+fn index_file(file_index: usize, filename: &str) -> Result<Vec<IndexEntry>, Error> {
+    let members = Arc::new(Mutex::new(Vec::new()));
+    let reader = MemberReader::open(filename, Arc::clone(&members))?;
+    let mut mol2_reader = Mol2Reader::from_reader(filename, Box::new(reader));
+
+    let mut records = Vec::new();
+    while let Some(mol) = mol2_reader.next() {
+        records.push((mol.get_name().to_string(), mol.get_energy(), mol2_reader.last_span()));
+    }
+    if let Some(e) = mol2_reader.error() {
+        return Err(Error::new(e.kind(), format!("Failed reading {}: {}", filename, e)))
+    }
+    if mol2_reader.is_truncated() {
+        return Err(Error::new(ErrorKind::InvalidData, format!("{} is truncated", filename)))
+    }
+
+    let spans: Vec<(u64, u64)> = records.iter().map(|(_, _, s)| *s).collect();
+    let blocks = group_members(&members.lock().unwrap(), &spans);
+
+    Ok(records
+        .into_iter()
+        .map(|(name, energy, (start, end))| {
+            let idx = blocks.partition_point(|b| b.decompressed_offset <= start) - 1;
+            let block = &blocks[idx];
+            IndexEntry {
+                name,
+                energy,
+                file_index,
+                block_offset: block.offset,
+                block_length: block.length,
+                record_offset: start - block.decompressed_offset,
+                record_length: end - start
+            }
+        })
+        .collect())
+}
+
+// implements index subcommand: writes an energy sorted index of every molecule
+// returns the number of molecules indexed
+pub fn build_index(input_files: Vec<String>, index_filename: &str) -> Result<usize, Error> {
+
+    let files = input_files
+        .iter()
+        .map(|x| Ok(IndexedFile { path: x.clone(), size: std::fs::metadata(x)?.len() }))
+        .collect::<Result<Vec<IndexedFile>, Error>>()?;
+
+    let mut entries: Vec<IndexEntry> = input_files
+        .par_iter()
+        .enumerate()
+        .map(|(file_index, x)| index_file(file_index, x))
+        .collect::<Result<Vec<Vec<IndexEntry>>, Error>>()?
+        .into_iter()
+        .flatten()
+        .collect();
+
+    // best poses first, in input order on ties
+    entries.sort_by(|a, b| {
+        a.energy.total_cmp(&b.energy)
+            .then(a.file_index.cmp(&b.file_index))
+            .then(a.block_offset.cmp(&b.block_offset))
+            .then(a.record_offset.cmp(&b.record_offset))
+    });
+
+    let mut writer = BufWriter::new(File::create(index_filename)?);
+    writeln!(writer, "{}", INDEX_MAGIC)?;
+    for (idx, f) in files.iter().enumerate() {
+        writeln!(writer, "#file\t{}\t{}\t{}", idx, f.size, f.path)?;
+    }
+    writer.write_all(b"energy\tname\tfile\tblock_offset\tblock_length\trecord_offset\trecord_length\n")?;
+    for e in entries.iter() {
+        writeln!(
+            writer,
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            e.energy, e.name, e.file_index, e.block_offset, e.block_length, e.record_offset, e.record_length
+        )?;
+    }
+
+    writer.flush()?;
+    drop(writer);
+    let index_len = std::fs::metadata(index_filename)?.len();
+    write_name_table(&name_table_filename(index_filename), &mut entries, index_len)?;
+
+    println!(">>> Number of Files Indexed: {}", files.len());
+    println!(">>> Number of Molecules Indexed: {}", entries.len());
+    println!(">>> Index Written to: {}", index_filename);
+
+    Ok(entries.len())
+}
+
+// Returns the filename of the name table written next to an index
+pub fn name_table_filename(index_filename: &str) -> String {
+    format!("{}.names", index_filename)
+}
+
+// Writes the binary name table of an index (reorders the entries by name)
+//
+// The table holds fixed-width records sorted by name followed by the names
+// themselves, so the poses of a name are found by binary search without reading
+// the index. A record is the offset and length of its name, the file index, the
+// energy, and the block and record offsets and lengths, all little endian.
+fn write_name_table(filename: &str, entries: &mut [IndexEntry], index_len: u64) -> Result<(), Error> {
+    entries.sort_by(|a, b| {
+        a.name.cmp(&b.name)
+            .then(a.file_index.cmp(&b.file_index))
+            .then(a.block_offset.cmp(&b.block_offset))
+            .then(a.record_offset.cmp(&b.record_offset))
+    });
+
+    let mut writer = BufWriter::new(File::create(filename)?);
+    writer.write_all(NAME_TABLE_MAGIC)?;
+    writer.write_all(&(entries.len() as u64).to_le_bytes())?;
+    writer.write_all(&index_len.to_le_bytes())?;
+
+    let mut name_offset: u64 = 0;
+    for e in entries.iter() {
+        writer.write_all(&name_offset.to_le_bytes())?;
+        writer.write_all(&(e.name.len() as u32).to_le_bytes())?;
+        writer.write_all(&(e.file_index as u32).to_le_bytes())?;
+        writer.write_all(&e.energy.to_le_bytes())?;
+        writer.write_all(&e.block_offset.to_le_bytes())?;
+        writer.write_all(&e.block_length.to_le_bytes())?;
+        writer.write_all(&e.record_offset.to_le_bytes())?;
+        writer.write_all(&e.record_length.to_le_bytes())?;
+        name_offset += e.name.len() as u64;
+    }
+    for e in entries.iter() {
+        writer.write_all(e.name.as_bytes())?;
+    }
+    writer.flush()
+}
+
+// Struct describing a memory mapped name table
+struct NameTable {
+    filename: String,
+    data: Mmap,
+    num_records: usize
+}
+impl NameTable {
+
+    // Opens the name table of an index
+    // returns none if there is none or it was written for a different index
+    fn open(index_filename: &str) -> Result<Option<Self>, Error> {
+        let filename = name_table_filename(index_filename);
+        let file = match File::open(&filename) {
+            Ok(x) => x,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e)
+        };
+
+        // the name table must not change while it is mapped
+        let data = unsafe { Mmap::map(&file)? };
+        if data.len() < NAME_TABLE_HEADER || &data[..8] != NAME_TABLE_MAGIC {
+            return Err(malformed(&filename, "missing header"))
+        }
+        let num_records = u64::from_le_bytes(data[8..16].try_into().unwrap()) as usize;
+        let index_len = u64::from_le_bytes(data[16..24].try_into().unwrap());
+        if index_len != std::fs::metadata(index_filename)?.len() {
+            return Ok(None)
+        }
+        if num_records.checked_mul(NAME_RECORD_BYTES).is_none_or(|x| x > data.len() - NAME_TABLE_HEADER) {
+            return Err(malformed(&filename, "truncated records"))
+        }
+        Ok(Some(NameTable { filename, data, num_records }))
+    }
+
+    // Returns the bytes of a record
+    fn record(&self, idx: usize) -> &[u8] {
+        let start = NAME_TABLE_HEADER + idx * NAME_RECORD_BYTES;
+        &self.data[start..start + NAME_RECORD_BYTES]
+    }
+
+    // Reads the little endian integer at a position of a record
+    fn field(record: &[u8], at: usize) -> u64 {
+        u64::from_le_bytes(record[at..at + 8].try_into().unwrap())
+    }
+
+    // Returns the name of a record
+    fn name(&self, idx: usize) -> Result<&[u8], Error> {
+        let record = self.record(idx);
+        let pool = NAME_TABLE_HEADER + self.num_records * NAME_RECORD_BYTES;
+        let start = pool as u64 + Self::field(record, 0);
+        let length = u32::from_le_bytes(record[8..12].try_into().unwrap()) as u64;
+        self.data
+            .get(start as usize..(start + length) as usize)
+            .ok_or_else(|| malformed(&self.filename, "name beyond the end of the table"))
+    }
+
+    // Returns the index entry of a record
+    fn entry(&self, idx: usize) -> Result<IndexEntry, Error> {
+        let record = self.record(idx);
+        Ok(IndexEntry {
+            name: String::from_utf8_lossy(self.name(idx)?).into_owned(),
+            energy: f64::from_le_bytes(record[16..24].try_into().unwrap()),
+            file_index: u32::from_le_bytes(record[12..16].try_into().unwrap()) as usize,
+            block_offset: Self::field(record, 24),
+            block_length: Self::field(record, 32),
+            record_offset: Self::field(record, 40),
+            record_length: Self::field(record, 48)
+        })
+    }
+
+    // Returns the entries of every pose of a name
+    fn find(&self, name: &str) -> Result<Vec<IndexEntry>, Error> {
+
+        // binary search for the first record not ordered before the name
+        let (mut lo, mut hi) = (0, self.num_records);
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            if self.name(mid)? < name.as_bytes() {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+
+        let mut entries = Vec::new();
+        while lo < self.num_records && self.name(lo)? == name.as_bytes() {
+            entries.push(self.entry(lo)?);
+            lo += 1;
+        }
+        Ok(entries)
+    }
+}
+
+// Opens an index and reads its header, returning the indexed files and the
+// remaining lines holding one entry each
+fn open_index(index_filename: &str) -> Result<(Vec<IndexedFile>, io::Lines<BufReader<File>>), Error> {
+    let mut lines = BufReader::new(File::open(index_filename)?).lines();
+    if lines.next().transpose()?.as_deref() != Some(INDEX_MAGIC) {
+        return Err(malformed(index_filename, "missing header"))
+    }
+
+    let mut files = Vec::new();
+    for line in lines.by_ref() {
+        let line = line?;
+        let items: Vec<&str> = match line.strip_prefix("#file\t") {
+            Some(x) => x.splitn(3, '\t').collect(),
+            None => break
+        };
+        if items.len() != 3 {
+            return Err(malformed(index_filename, &line))
+        }
+        files.push(IndexedFile {
+            path: items[2].to_string(),
+            size: items[1].parse().map_err(|_| malformed(index_filename, &line))?
+        });
+    }
+    Ok((files, lines))
+}
+
+// Error describing a malformed line of an index
+fn malformed(index_filename: &str, line: &str) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("Malformed index {}: {}", index_filename, line)
+    )
+}
+
+// Parses an index entry, checking it refers to an indexed file
+fn parse_entry(index_filename: &str, line: &str, num_files: usize) -> Result<IndexEntry, Error> {
+    IndexEntry::from_line(line)
+        .filter(|e| e.file_index < num_files)
+        .ok_or_else(|| malformed(index_filename, line))
+}
+
+// Reads the entries of an index from the best energy onwards, stopping at the
+// first entry beyond `max_energy` or once `top` entries are read
+pub fn read_index(
+        index_filename: &str,
+        top: Option<usize>,
+        max_energy: Option<f64>) -> Result<(Vec<IndexedFile>, Vec<IndexEntry>), Error> {
+
+    let (files, lines) = open_index(index_filename)?;
+
+    let mut entries = Vec::new();
+    for line in lines {
+        if top.is_some_and(|n| entries.len() >= n) {
+            break;
+        }
+        let entry = parse_entry(index_filename, &line?, files.len())?;
+        if max_energy.is_some_and(|e| entry.energy > e) {
+            break;
+        }
+        entries.push(entry);
+    }
+
+    Ok((files, entries))
+}
+
+// Reads the entries of every pose of the given names, in input order
+//
+// Names are looked up by binary search in the name table of the index; the
+// whole index is scanned instead if its name table is missing or out of date.
+// No input is read either way.
+pub fn find_names(
+        index_filename: &str,
+        names: &HashSet<String>) -> Result<(Vec<IndexedFile>, Vec<IndexEntry>), Error> {
+
+    let (files, lines) = open_index(index_filename)?;
+
+    let mut entries = Vec::new();
+    if let Some(table) = NameTable::open(index_filename)? {
+        for name in names.iter() {
+            entries.extend(table.find(name)?);
+        }
+        if let Some(e) = entries.iter().find(|e| e.file_index >= files.len()) {
+            return Err(malformed(&table.filename, &e.name))
+        }
+        entries.sort_by_key(|e| (e.file_index, e.block_offset, e.record_offset));
+        return Ok((files, entries))
+    }
+    for line in lines {
+        let line = line?;
+
+        // names are the second column and are checked before parsing the whole line
+        let name = line.split('\t').nth(1).unwrap_or_default();
+        if !names.contains(name) {
+            continue;
+        }
+        entries.push(parse_entry(index_filename, &line, files.len())?);
+    }
+    entries.sort_by_key(|e| (e.file_index, e.block_offset, e.record_offset));
+
+    Ok((files, entries))
+}
+
+// Reads the text of indexed molecules, decompressing every needed block once
+//
+// Returns the molecules in the order of the entries.
+pub fn fetch_entries(files: &[IndexedFile], entries: &[IndexEntry]) -> Result<Vec<String>, Error> {
+
+    // a changed input would make every offset meaningless
+    for f in files.iter() {
+        if std::fs::metadata(&f.path)?.len() != f.size {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("{} changed since it was indexed; rebuild the index", f.path)
+            ))
+        }
+    }
+
+    let mut blocks: HashMap<(usize, u64, u64), Vec<usize>> = HashMap::new();
+    for (idx, e) in entries.iter().enumerate() {
+        blocks
+            .entry((e.file_index, e.block_offset, e.block_length))
+            .or_default()
+            .push(idx);
+    }
+
+    let mut records: Vec<(usize, String)> = blocks
+        .into_par_iter()
+        .map(|((file_index, offset, length), members)| {
+            let data = gzip::read_range(&files[file_index].path, offset, length)?;
+            members
+                .into_iter()
+                .map(|idx| {
+                    let e = &entries[idx];
+                    let start = e.record_offset as usize;
+                    let end = start + e.record_length as usize;
+                    data.get(start..end)
+                        .map(|x| (idx, String::from_utf8_lossy(x).into_owned()))
+                        .ok_or_else(|| Error::new(
+                            ErrorKind::InvalidData,
+                            format!("{} is shorter than indexed", files[file_index].path)
+                        ))
+                })
+                .collect::<Result<Vec<(usize, String)>, Error>>()
+        })
+        .collect::<Result<Vec<Vec<(usize, String)>>, Error>>()?
+        .into_iter()
+        .flatten()
+        .collect();
+    records.sort_unstable_by_key(|(idx, _)| *idx);
+
+    Ok(records.into_iter().map(|(_, x)| x).collect())
+}
+
+// implements top subcommand: writes the best poses of an index in energy order
+// returns the number of molecules written
+pub fn top(
+        index_filename: &str,
+        top: Option<usize>,
+        max_energy: Option<f64>,
+        output_filename: &str) -> Result<usize, Error> {
+
+    let (files, entries) = read_index(index_filename, top, max_energy)?;
+    let records = fetch_entries(&files, &entries)?;
+
+    // the index stands in for the inputs, which are only partially read
+    let provenance = Provenance::new(&[index_filename.to_string()])?;
+    let mut writer = writer(output_filename)?;
+    provenance.write(&mut writer)?;
+    for x in records.iter() {
+        writer
+            .write_all(x.as_bytes())
+            .expect("Error: Error writing to output file");
+    }
+
+    let num_blocks = entries
+        .iter()
+        .map(|e| (e.file_index, e.block_offset))
+        .collect::<HashSet<(usize, u64)>>()
+        .len();
+    println!(">>> Number of Blocks Read: {}", num_blocks);
+    println!(">>> Number of Molecules Written: {}", records.len());
+
+    Ok(records.len())
+}
+
+// implements fetch subcommand: writes every pose of the given names from an index
+// returns the number of molecules written
+pub fn fetch(
+        index_filename: &str,
+        names: &HashSet<String>,
+        output_filename: &str) -> Result<usize, Error> {
+
+    let (files, entries) = find_names(index_filename, names)?;
+    let records = fetch_entries(&files, &entries)?;
+
+    let mut writer = writer(output_filename)?;
+    if !is_stdout(output_filename) {
+        Provenance::new(&[index_filename.to_string()])?.write(&mut writer)?;
+    }
+    for x in records.iter() {
+        writer
+            .write_all(x.as_bytes())
+            .expect("Error: Error writing to output file");
+    }
+    writer.flush()?;
+
+    let found: HashSet<&str> = entries.iter().map(|e| e.name.as_str()).collect();
+    let mut missing: Vec<&String> = names.iter().filter(|x| !found.contains(x.as_str())).collect();
+    missing.sort();
+    for x in missing.iter() {
+        eprintln!("Warning: {} is not in the index", x);
+    }
+
+    // summaries would be mixed into molecules printed to the standard output
+    if !is_stdout(output_filename) {
+        println!(">>> Number of Names Found: {}", found.len());
+        println!(">>> Number of Molecules Written: {}", records.len());
+    }
+
+    Ok(records.len())
+}
+
+// implements grep through an index: writes the queried poses without reading
+// the inputs beyond the blocks holding them
+// returns the number of molecules written
+//
+// Names and energies are read from the index, so queries with expected scores
+// are matched within `tol` exactly as when scanning the inputs.
+pub fn grep_index(
+        index_filename: &str,
+        query_filenames: &[&str],
+        columns: &QueryColumns,
+        output_filename: &str,
+        tol: Tolerance) -> Result<usize, Error> {
+
+    let tables = query_filenames
+        .iter()
+        .map(|x| QueryReader::new(x)?.load_queries_with(columns))
+        .collect::<Result<Vec<QueryFormat>, Error>>()?;
+    if tables.iter().any(|t| t.has_patterns()) {
+        return Err(Error::new(ErrorKind::InvalidInput, "query patterns cannot be looked up in an index"))
+    }
+    let names: HashSet<String> = tables
+        .iter()
+        .flat_map(|t| t.names())
+        .map(|x| x.to_string())
+        .collect();
+
+    let (files, mut entries) = find_names(index_filename, &names)?;
+    entries.retain(|e| {
+        let mut mol = Mol2::new();
+        mol.add_name(e.name.clone());
+        mol.add_energy(e.energy);
+        tables.iter().any(|t| t.matches(&mol, &tol))
+    });
+    let records = fetch_entries(&files, &entries)?;
+
+    let mut inputs = vec![index_filename.to_string()];
+    inputs.extend(query_filenames.iter().map(|x| x.to_string()));
+    let mut writer = writer(output_filename)?;
+    Provenance::new(&inputs)?.write(&mut writer)?;
+    for x in records.iter() {
+        writer
+            .write_all(x.as_bytes())
+            .expect("Error: Error writing to output file");
+    }
+    writer.flush()?;
+
+    println!(">>> Number of Queries: {}", names.len());
+    println!(">>> Number of Matches: {}", records.len());
+
+    Ok(records.len())
+}
@@ -0,0 +1,42 @@
+
+// mol2grep: parsing, searching, and processing of DOCK mol2 pose files
+//
+// The command line tool is built on these modules; `process_files` runs a
+// per-molecule closure over many files in parallel for embedding applications.
+
+mod test;
+pub mod mol2;
+pub mod query;
+pub mod mol2utils;
+pub mod file_io;
+pub mod gzip;
+pub mod archive;
+pub mod remote;
+pub mod filter;
+pub mod plugin;
+#[cfg(feature = "plot")]
+pub mod plot;
+pub mod provenance;
+pub mod budget;
+pub mod exec;
+pub mod memory;
+pub mod decoys;
+pub mod enrichment;
+pub mod qc;
+pub mod reorder;
+pub mod merge;
+pub mod sort;
+pub mod dedup;
+pub mod slice;
+pub mod compare;
+pub mod index;
+pub mod report;
+pub mod sample;
+pub mod stats;
+pub mod cache;
+pub mod zinc;
+pub mod transform;
+pub mod process;
+
+pub use mol2::{Mol2, Mol2Reader};
+pub use process::{process_files, Action, ProcessOptions, ProcessSummary};
@@ -1,13 +1,19 @@
 
 use clap::{Arg, App, ArgMatches, SubCommand, AppSettings};
-use std::io::Error;
+use std::io::{Error, ErrorKind};
+use std::collections::HashSet;
 
-mod test;
-mod mol2;
-mod query;
-mod mol2utils;
-mod file_io;
-use file_io::read_input_list;
+use mol2grep::{
+    mol2, query, mol2utils, file_io, archive, filter, plugin, budget, exec, cache, decoys,
+    enrichment, qc, reorder, merge, sort, dedup, slice, compare, index, report, sample, stats, transform, zinc,
+    provenance
+};
+#[cfg(feature = "plot")]
+use mol2grep::plot;
+use file_io::{read_input_list, read_name_list, select_input_range};
+use mol2::{NameScheme, ReaderConfig};
+use report::{list_setting, optional_setting, print_dry_run};
+use sample::{Sampler, SampleKey};
 
 // builds the global threadpool for rayon parallel processing
 fn build_threadpool(num_threads: usize) {
@@ -20,14 +26,264 @@ fn build_threadpool(num_threads: usize) {
 
 }
 
+// collects input files from either the CLI or a file listing their paths
+fn collect_input_files(matches: &ArgMatches) -> Vec<String> {
+    let input_files = match matches.values_of("mol2") {
+
+        // case where one or multiple mol2 are given at CLI
+        Some(f) => {
+            f.map(|x| x.to_string())
+                .collect()
+        },
+
+        // case where a single input file containing mol2 paths is given at CLI
+        None => {
+            read_input_list(matches.value_of("input_files").unwrap()).unwrap()
+        }
+
+    };
+
+    // wildcards are expanded here when quoted to avoid the shell argument limit
+    let input_files = file_io::expand_input_patterns(input_files)
+        .expect("Error: Failed to expand input patterns");
+
+    // tar and zip archives are read member by member
+    let input_files = archive::expand_archives(input_files)
+        .expect("Error: Failed to list archive members");
+
+    let skip = matches.value_of("skip_files")
+        .map(|x| x.parse::<usize>().expect("Malformed input: skip-files"))
+        .unwrap_or(0);
+
+    let limit = matches.value_of("limit_files")
+        .map(|x| x.parse::<usize>().expect("Malformed input: limit-files"));
+
+    let input_files = select_input_range(
+        input_files,
+        matches.value_of("resume_from"),
+        skip,
+        limit
+    ).expect("Error: Failed to select input files");
+
+    // the standard input can only be read once
+    if input_files.iter().filter(|x| file_io::is_stdin(x)).count() > 1 {
+        panic!("Malformed input: the standard input (-) is given more than once");
+    }
+    input_files
+}
+
+// builds a deterministic sampler if a sampling fraction is given
+fn parse_sampler(matches: &ArgMatches) -> Option<Sampler> {
+    let frac = matches.value_of("sample_frac")?
+        .parse::<f64>()
+        .expect("Malformed input: sample-frac");
+
+    let seed = matches.value_of("seed")
+        .unwrap()
+        .parse::<u64>()
+        .expect("Malformed input: seed");
+
+    let key = SampleKey::from_name(matches.value_of("sample_by").unwrap())
+        .expect("Malformed input: sample-by");
+
+    Some(Sampler::new(frac, seed, key))
+}
+
+// arguments selecting a deterministic subsample of molecules
+fn sample_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("sample_frac")
+            .long("sample-frac")
+            .value_name("FRAC")
+            .help("Keep a deterministic fraction of molecules (e.g. 0.01)")
+            .takes_value(true)
+            .required(false),
+        Arg::with_name("seed")
+            .long("seed")
+            .help("Seed used for deterministic subsampling")
+            .takes_value(true)
+            .required(false)
+            .default_value("0"),
+        Arg::with_name("sample_by")
+            .long("sample-by")
+            .help("Molecule attribute hashed to decide sample inclusion (index: position of the file and of the molecule within it)")
+            .takes_value(true)
+            .possible_values(&["name", "index"])
+            .default_value("name")
+    ]
+}
+
+// optional figure arguments (only available with the `plot` feature)
+fn plot_args() -> Vec<Arg<'static, 'static>> {
+    if cfg!(feature = "plot") {
+        vec![
+            Arg::with_name("plot")
+                .long("plot")
+                .value_name("roc.svg")
+                .help("SVG filename to draw the ROC curve to")
+                .takes_value(true)
+        ]
+    } else {
+        Vec::new()
+    }
+}
+
+// arguments describing the input files (shared across subcommands)
+fn input_args() -> Vec<Arg<'static, 'static>> {
+    input_args_unless(&["input_files"])
+}
+
+// input arguments where mol2 inputs may be replaced by any of the given arguments
+fn input_args_unless(alternatives: &'static [&'static str]) -> Vec<Arg<'static, 'static>> {
+    let mut args = vec![
+        Arg::with_name("mol2")
+            .short("i")
+            .long("input")
+            .value_name("*.mol2.gz")
+            .help("mol2.gz formatted files to process (can take multiple inputs)")
+            .takes_value(true)
+            .required(true)
+            .min_values(1)
+            .required_unless_one(alternatives),
+        Arg::with_name("input_files")
+            .short("f")
+            .long("files")
+            .value_name("<files>.txt")
+            .help("a list of filenames to process")
+            .takes_value(true)
+            .required(false)
+    ];
+    args.extend(input_range_args());
+    args
+}
+
+// arguments selecting a range of the input file list
+fn input_range_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("skip_files")
+            .long("skip-files")
+            .value_name("N")
+            .help("Skip the first N input files")
+            .takes_value(true)
+            .required(false),
+        Arg::with_name("limit_files")
+            .long("limit-files")
+            .value_name("M")
+            .help("Process at most M input files")
+            .takes_value(true)
+            .required(false),
+        Arg::with_name("resume_from")
+            .long("resume-from")
+            .value_name("<path>")
+            .help("Start processing at the given input file (inclusive)")
+            .takes_value(true)
+            .required(false)
+    ]
+}
+
+// arguments piping accepted molecules into a command
+fn exec_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("exec")
+            .long("exec")
+            .value_name("COMMAND")
+            .help("Pipe accepted molecules into COMMAND (run with sh -c, once per batch) on stdin")
+            .takes_value(true)
+            .required(false),
+        Arg::with_name("exec_batch")
+            .long("exec-batch")
+            .value_name("N")
+            .help("Number of molecules fed to each run of the --exec command")
+            .takes_value(true)
+            .default_value("1"),
+        Arg::with_name("exec_report")
+            .long("exec-report")
+            .value_name("exec.tsv")
+            .help("tab-separated filename to write the exit code of every --exec batch to")
+            .takes_value(true)
+            .default_value("exec.tsv")
+    ]
+}
+
+// Parses the --exec arguments
+fn parse_exec(matches: &ArgMatches) -> Option<exec::ExecOptions> {
+    matches.value_of("exec").map(|x| exec::ExecOptions {
+        command: x.to_string(),
+        batch_size: matches.value_of("exec_batch")
+            .unwrap()
+            .parse::<usize>()
+            .expect("Malformed input: exec-batch"),
+        report: matches.value_of("exec_report").unwrap().to_string()
+    })
+}
+
+// argument writing every output as an entry of a single archive
+fn archive_arg() -> Arg<'static, 'static> {
+    Arg::with_name("archive")
+        .long("archive")
+        .value_name("outputs.tar")
+        .help("Write the outputs as entries of a single tar archive (gzip compressed if named .tar.gz or .tgz) instead of separate files")
+        .takes_value(true)
+        .required(false)
+}
+
+// arguments limiting the time or number of molecules of a run
+fn budget_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("max_seconds")
+            .long("max-seconds")
+            .value_name("SECONDS")
+            .help("Stop starting new files after SECONDS; files in flight are finished and outputs finalized")
+            .takes_value(true)
+            .required(false),
+        Arg::with_name("max_molecules")
+            .long("max-molecules")
+            .value_name("N")
+            .help("Stop starting new files once N molecules were read; files in flight are finished")
+            .takes_value(true)
+            .required(false)
+    ]
+}
+
+// Parses the run budget from the budget arguments
+fn parse_budget(matches: &ArgMatches) -> budget::Budget {
+    budget::Budget {
+        max_seconds: matches.value_of("max_seconds")
+            .map(|x| x.parse::<f64>().expect("Malformed input: max-seconds")),
+        max_molecules: matches.value_of("max_molecules")
+            .map(|x| x.parse::<u64>().expect("Malformed input: max-molecules"))
+    }
+}
+
+// Options of grep honored when queries are looked up in an index, with the global options
+const INDEX_GREP_ARGS: &[&str] = &[
+    "index", "query", "output", "tolerance", "rel_tol", "tol_mode", "id_col", "score_col", "tol_col", "query_format", "num_threads",
+    "dry_run", "fallback_names", "io_threads", "compression_level", "bgzf", "hash_inputs", "max_read_mbps", "low_io_priority", "strict"
+];
+
+// Returns an error listing the options given to grep that an index lookup does not honor
+fn check_index_grep_args(matches: &ArgMatches) -> Result<(), Error> {
+    let mut unsupported: Vec<String> = matches.args
+        .keys()
+        .filter(|x| !INDEX_GREP_ARGS.contains(x) && matches.occurrences_of(x) > 0)
+        .map(|x| x.replace('_', "-"))
+        .collect();
+    unsupported.sort_unstable();
+    match unsupported.is_empty() {
+        true => Ok(()),
+        false => Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("options not supported with --index: {}", unsupported.join(", "))
+        ))
+    }
+}
+
 // runs grep subcommand
 fn subcommand_grep(matches: &ArgMatches) -> Result<(), Error> {
 
     // Assign Variables
-    let input_mol2s = matches.values_of("mol2");
-    let input_filelist = matches.value_of("input_files");
 
-    let query_filename = matches.value_of("query").unwrap();
+    let query_filenames: Vec<&str> = matches.values_of("query").unwrap().collect();
     let output_filename = matches.value_of("output").unwrap();
     let tol = matches.value_of("tolerance")
         .unwrap()
@@ -39,31 +295,115 @@ fn subcommand_grep(matches: &ArgMatches) -> Result<(), Error> {
         .parse::<usize>()
         .expect("Malformed input: num_threads");
 
-    // Instantiate Input File List
-    let input_files: Vec<String>;
-    match input_mol2s {
-
-        // case where one or multiple mol2 are given at CLI
-        Some(f) => {
-            input_files = f.into_iter()
-                .map(|x| x.to_string())
-                .collect()
+    let options = mol2utils::GrepOptions {
+        unique: matches.is_present("unique"),
+        file_report: matches.value_of("file_report").map(|x| x.to_string()),
+        group_output: matches.is_present("group_output"),
+        per_query_output: matches.is_present("per_query_output"),
+        alias: matches.value_of("alias").map(|x| x.to_string()),
+        budget: parse_budget(matches),
+        archive: matches.value_of("archive").map(|x| x.to_string()),
+        exec: parse_exec(matches),
+        stats_memory: matches.is_present("stats_memory"),
+        regex: matches.is_present("regex"),
+        ignore_case: matches.is_present("ignore_case"),
+        query_columns: query::QueryColumns {
+            id: matches.value_of("id_col").map(query::ColumnSpec::parse),
+            score: matches.value_of("score_col").map(query::ColumnSpec::parse),
+            tol: matches.value_of("tol_col").map(query::ColumnSpec::parse),
+            group: matches.value_of("group_col").map(query::ColumnSpec::parse),
+            csv: matches.value_of("query_format") == Some("csv"),
+            smiles: matches.value_of("query_format") == Some("smi")
         },
+        rel_tol: matches.value_of("rel_tol")
+            .map_or(0.0, |x| x.parse::<f64>().expect("Malformed input: rel_tol")),
+        tol_mode: matches.value_of("tol_mode")
+            .map_or(query::ToleranceMode::default(), |x| query::ToleranceMode::from_name(x).expect("Malformed input: tol_mode")),
+        invert: matches.is_present("invert"),
+        max_count: matches.value_of("max_count")
+            .map(|x| x.parse::<u64>().expect("Malformed input: max_count")),
+        missing_out: matches.value_of("missing_out").map(|x| x.to_string()),
+        query_counts: matches.value_of("query_counts").map(|x| x.to_string()),
+        exclude: matches.value_of("exclude").map(|x| x.to_string()),
+        annotate_source: matches.is_present("annotate_source"),
+        names_only: matches.is_present("names_only"),
+        with_energy: matches.is_present("with_energy"),
+        count_only: matches.is_present("count"),
+        files_with_matches: matches.is_present("files_with_matches"),
+        rest_out: matches.value_of("rest_out").map(|x| x.to_string()),
+        per_input_output: matches.is_present("per_input_output"),
+        split_by_query: matches.is_present("split_by_query"),
+        ordered: matches.is_present("ordered"),
+        max_per_file: matches.value_of("max_per_file")
+            .map(|x| x.parse::<u64>().expect("Malformed input: max_per_file")),
+        best_per_id: matches.is_present("best_per_id")
+    };
 
-        // case where a single input file containing mol2 paths is given at CLI
-        None => {
-            input_files = read_input_list(input_filelist.unwrap()).unwrap()
+    // an index stands in for the inputs, which are then never scanned
+    if let Some(index_filename) = matches.value_of("index") {
+        check_index_grep_args(matches)?;
+        let tolerance = query::Tolerance { absolute: tol, relative: options.rel_tol, mode: options.tol_mode };
+        if matches.is_present("dry_run") {
+            print_dry_run(
+                "grep",
+                &[index_filename.to_string()],
+                &[output_filename.to_string()],
+                &[
+                    ("query", query_filenames.join(", ")),
+                    ("tolerance", tol.to_string()),
+                    ("rel_tol", options.rel_tol.to_string()),
+                    ("tol_mode", options.tol_mode.name().to_string())
+                ]
+            );
+            return Ok(())
         }
+        build_threadpool(num_threads);
+        index::grep_index(index_filename, &query_filenames, &options.query_columns, output_filename, tolerance)?;
+        return Ok(())
+    }
 
-    };
+    // Instantiate Input File List
+    let input_files = collect_input_files(matches);
+
+    if matches.is_present("dry_run") {
+        let (queries, outputs) = mol2utils::grep_dry_run(&input_files, &query_filenames, output_filename, &options)?;
+        print_dry_run(
+            "grep",
+            &input_files,
+            &outputs,
+            &[
+                ("query", queries.join(", ")),
+                ("tolerance", tol.to_string()),
+                ("rel_tol", options.rel_tol.to_string()),
+                ("tol_mode", options.tol_mode.name().to_string()),
+                ("threads", num_threads.to_string()),
+                ("unique", options.unique.to_string()),
+                ("invert", options.invert.to_string()),
+                ("exclude", optional_setting(options.exclude.as_ref())),
+                ("max_count", optional_setting(options.max_count)),
+                ("ignore_case", options.ignore_case.to_string()),
+                ("ordered", options.ordered.to_string()),
+                ("best_per_id", options.best_per_id.to_string()),
+                ("max_per_file", optional_setting(options.max_per_file)),
+                ("group_output", options.group_output.to_string()),
+                ("file_report", optional_setting(options.file_report.as_ref())),
+                ("alias", optional_setting(options.alias.as_ref())),
+                ("budget", options.budget.describe()),
+                ("archive", optional_setting(options.archive.as_ref())),
+                ("exec", optional_setting(options.exec.as_ref().map(|x| x.describe())))
+            ]
+        );
+        return Ok(())
+    }
 
     build_threadpool(num_threads);
 
     mol2utils::grep(
         input_files,
-        query_filename,
+        &query_filenames,
         output_filename,
-        tol
+        tol,
+        &options
     ).expect("Error: Failed to grep");
 
     Ok(())
@@ -74,8 +414,6 @@ fn subcommand_grep(matches: &ArgMatches) -> Result<(), Error> {
 fn subcommand_split(matches: &ArgMatches) -> Result<(), Error> {
 
     // assign variables
-    let input_mol2s = matches.values_of("mol2");
-    let input_filelist = matches.value_of("input_files");
     let prefix = matches.value_of("prefix").unwrap();
 
     let num_files = matches.value_of("num_files")
@@ -88,30 +426,41 @@ fn subcommand_split(matches: &ArgMatches) -> Result<(), Error> {
         .parse::<usize>()
         .expect("Malformed input: num_threads");
 
-    // Instantiate Input File List
-    let input_files: Vec<String>;
-    match input_mol2s {
+    let options = mol2utils::SplitOptions {
+        budget: parse_budget(matches),
+        archive: matches.value_of("archive").map(|x| x.to_string())
+    };
 
-        // case where one or multiple mol2 are given at CLI
-        Some(f) => {
-            input_files = f.into_iter()
-                .map(|x| x.to_string())
-                .collect()
-        },
+    // Instantiate Input File List
+    let input_files = collect_input_files(matches);
 
-        // case where a single input file containing mol2 paths is given at CLI
-        None => {
-            input_files = read_input_list(input_filelist.unwrap()).unwrap()
+    if matches.is_present("dry_run") {
+        let mut outputs: Vec<String> = (0..num_files)
+            .map(|i| mol2utils::split_filename(prefix, i))
+            .collect();
+        if let Some(ref archive) = options.archive {
+            outputs = vec![format!("{} ({} entries)", archive, outputs.len())];
         }
-
-    };
+        print_dry_run(
+            "split",
+            &input_files,
+            &outputs,
+            &[
+                ("num_files", num_files.to_string()),
+                ("threads", num_threads.to_string()),
+                ("budget", options.budget.describe())
+            ]
+        );
+        return Ok(())
+    }
 
     build_threadpool(num_threads);
 
     mol2utils::split(
         input_files,
         prefix,
-        num_files
+        num_files,
+        &options
     ).expect("Error: Failed to split");
 
     Ok(())
@@ -122,219 +471,2968 @@ fn subcommand_split(matches: &ArgMatches) -> Result<(), Error> {
 fn subcommand_table(matches: &ArgMatches) -> Result<(), Error> {
 
     // assign variables
-    let input_mol2s = matches.values_of("mol2");
-    let input_filelist = matches.value_of("input_files");
     let output_filename = matches.value_of("output").unwrap();
 
 
     // Instantiate Input File List
-    let input_files: Vec<String>;
-    match input_mol2s {
-
-        // case where one or multiple mol2 are given at CLI
-        Some(f) => {
-            input_files = f.into_iter()
-                .map(|x| x.to_string())
-                .collect()
-        },
-
-        // case where a single input file containing mol2 paths is given at CLI
-        None => {
-            input_files = read_input_list(input_filelist.unwrap()).unwrap()
-        }
+    let input_files = collect_input_files(matches);
 
+    let options = mol2utils::TableOptions {
+        write_header: !matches.is_present("no_header"),
+        sample: parse_sampler(matches),
+        long: matches.is_present("long"),
+        pretty: matches.is_present("pretty"),
+        limit: matches.value_of("limit")
+            .map(|x| x.parse::<usize>().expect("Malformed input: limit")),
+        geometry: matches.is_present("geometry"),
+        file_column: matches.is_present("file_column"),
+        all_fields: matches.is_present("all_fields"),
+        fields: matches.value_of("fields")
+            .map(|x| x.split(',').map(|c| c.trim().to_string()).collect())
     };
 
-    let add_header = !matches.is_present("no_header");
+    if matches.is_present("dry_run") {
+        print_dry_run(
+            "table",
+            &input_files,
+            &[output_filename.to_string()],
+            &[
+                ("header", options.write_header.to_string()),
+                ("long", options.long.to_string()),
+                ("pretty", options.pretty.to_string()),
+                ("limit", optional_setting(options.limit)),
+                ("geometry", options.geometry.to_string()),
+                ("file_column", options.file_column.to_string()),
+                ("all_fields", options.all_fields.to_string()),
+                ("fields", options.fields.as_ref().map_or("default".to_string(), |x| x.join(","))),
+                ("sample", optional_setting(options.sample.as_ref().map(|x| x.describe())))
+            ]
+        );
+        return Ok(())
+    }
 
     mol2utils::table(
         input_files,
         output_filename,
-        add_header
-    )
+        &options
+    )?;
+
+    Ok(())
 
 }
 
 
-// Receives arguments from CLI
-fn build_cli() -> App<'static, 'static> {
-    let app = App::new("mol2grep")
-        .version("0.1")
-        .author("Noam Teyssier")
-        .subcommand(SubCommand::with_name("grep")
-            .about("greps mol2 files and returns poses that match zincid and/or expected score")
-            .arg(
-                Arg::with_name("query")
-                    .short("q")
-                    .long("query")
-                    .value_name("ZINC-id,score.tsv")
-                    .help("Query table of ZINC-ids and scores to search for (tab separated, no header)")
-                    .takes_value(true)
-                    .required(true)
-                )
-            .arg(
-                Arg::with_name("tolerance")
-                    .short("e")
-                    .long("tol")
-                    .help("Minimum tolerance to accept in float comparisons (default = 1e-6)")
-                    .takes_value(true)
-                    .required(false)
-                    .default_value("1e-6")
-                )
-            .arg(
-                Arg::with_name("output")
-                    .short("o")
-                    .long("out")
-                    .help("mol2 formatted filename to write passing molecules to")
-                    .takes_value(true)
-                    .default_value("query_output.mol2.gz")
-                )
-            .arg(
-                Arg::with_name("mol2")
-                    .short("i")
-                    .long("input")
-                    .value_name("*.mol2.gz")
-                    .help("mol2.gz formatted files to grep (can take multiple inputs)")
-                    .takes_value(true)
-                    .required(true)
-                    .min_values(1)
-                    .required_unless_one(&["input_files"])
-            )
-            .arg(
-                Arg::with_name("input_files")
-                .short("f")
-                .long("files")
-                .value_name("<files>.txt")
-                .help("a list of filenames to process")
-                .takes_value(true)
-                .required(false)
-            )
-            .arg(
-                Arg::with_name("num_threads")
-                    .short("t")
-                    .long("threads")
-                    .help("Number of threads to use in parallel processing")
-                    .takes_value(true)
-                    .required(false)
-                    .default_value("4")
-                )
-            .setting(AppSettings::ArgRequiredElseHelp)
-        )
-        .subcommand(SubCommand::with_name("split")
-            .about("Splits a list of mol2 files into a given number of output files")
-            .arg(
-                Arg::with_name("mol2")
-                    .short("i")
-                    .long("input")
-                    .value_name("*.mol2.gz")
-                    .help("mol2.gz formatted files to grep (can take multiple inputs)")
-                    .takes_value(true)
-                    .required(true)
-                    .min_values(1)
-                    .required_unless_one(&["input_files"])
-            )
-            .arg(
-                Arg::with_name("input_files")
-                .short("f")
-                .long("files")
-                .value_name("<files>.txt")
-                .help("a list of filenames to process")
-                .takes_value(true)
-                .required(false)
-            )
-            .arg(
-                Arg::with_name("prefix")
-                    .short("o")
-                    .long("prefix")
-                    .help("prefix of output files: <prefix>.file_id.mol2.gz")
-                    .takes_value(true)
-                    .default_value("split")
-                )
-            .arg(
-                Arg::with_name("num_files")
-                    .short("n")
-                    .long("num_files")
-                    .help("Number of files to split items into")
-                    .takes_value(true)
-                    .required(false)
-                    .default_value("4")
-                )
-            .arg(
-                Arg::with_name("num_threads")
-                    .short("t")
-                    .long("threads")
-                    .help("Number of threads to use in parallel processing")
-                    .takes_value(true)
-                    .required(false)
-                    .default_value("4")
-                )
-        )
-        .subcommand(SubCommand::with_name("table")
-            .about("convert a list of mol2 files into tab-separated table of names + scores")
-            .arg(
-                Arg::with_name("mol2")
-                    .short("i")
-                    .long("input")
-                    .value_name("*.mol2.gz")
-                    .help("mol2.gz formatted files to grep (can take multiple inputs)")
-                    .takes_value(true)
-                    .required(true)
-                    .min_values(1)
-                    .required_unless_one(&["input_files"])
-            )
-            .arg(
-                Arg::with_name("input_files")
-                .short("f")
-                .long("files")
-                .value_name("<files>.txt")
-                .help("a list of filenames to process")
-                .takes_value(true)
-                .required(false)
-            )
-            .arg(
-                Arg::with_name("output")
-                    .short("o")
-                    .long("output")
-                    .help("output filename to write table to")
-                    .takes_value(true)
-                    .default_value("output.tab.gz")
-                )
-            .arg(
-                Arg::with_name("no_header")
-                    .short("n")
-                    .long("no_header")
-                    .help("do not include a header in output file")
-                    .takes_value(false)
-                )
-        )
-        .setting(AppSettings::SubcommandRequiredElseHelp);
+// runs transform subcommand
+fn subcommand_transform(matches: &ArgMatches) -> Result<(), Error> {
+
+    // assign variables
+    let output_filename = matches.value_of("output").unwrap();
+
+    let options = transform::TransformOptions {
+        scale: matches.value_of("scale_energy")
+            .unwrap()
+            .parse::<f64>()
+            .expect("Malformed input: scale-energy"),
+        offset: matches.value_of("offset")
+            .unwrap()
+            .parse::<f64>()
+            .expect("Malformed input: offset"),
+        zscore: matches.is_present("zscore")
+    };
+
+    // Instantiate Input File List
+    let input_files = collect_input_files(matches);
+
+    if matches.is_present("dry_run") {
+        print_dry_run(
+            "transform",
+            &input_files,
+            &[output_filename.to_string()],
+            &[
+                ("scale", options.scale.to_string()),
+                ("offset", options.offset.to_string()),
+                ("zscore", options.zscore.to_string())
+            ]
+        );
+        return Ok(())
+    }
 
+    transform::transform(
+        input_files,
+        output_filename,
+        &options
+    )?;
 
-    return app
+    Ok(())
 }
 
 
-fn main() {
+// runs filter subcommand
+fn subcommand_filter(matches: &ArgMatches) -> Result<(), Error> {
 
-    // Match Arguments
-    let app = build_cli();
-    let matches = app.get_matches();
+    // assign variables
+    let output_filename = matches.value_of("output").unwrap();
 
-    match matches.subcommand() {
-        ("grep", grep_matches) => {
-            subcommand_grep(grep_matches.unwrap())
-                .expect("Error: Failed to grep")
+    let num_threads = matches.value_of("num_threads")
+        .unwrap()
+        .parse::<usize>()
+        .expect("Malformed input: num_threads");
+
+    let options = filter::FilterOptions {
+        max_energy: matches.value_of("max_energy")
+            .map(|x| x.parse::<f64>().expect("Malformed input: max-energy")),
+        min_energy: matches.value_of("min_energy")
+            .map(|x| x.parse::<f64>().expect("Malformed input: min-energy")),
+        top_percent: matches.value_of("top_percent")
+            .map(|x| x.parse::<f64>().expect("Malformed input: top-percent")),
+        sample: parse_sampler(matches),
+        plugin: match matches.value_of("plugin") {
+            Some(x) => Some(plugin::load_plugin(x)?.into()),
+            None => None
         },
-        ("split", split_matches) => {
-            subcommand_split(split_matches.unwrap())
-                .expect("Error: Failed to split")
-        }
-        ("table", table_matches) => {
-            subcommand_table(table_matches.unwrap())
-                .expect("Error: Failed to build table")
-        }
-        _ => unreachable!()
+        max_radius_of_gyration: matches.value_of("max_rg")
+            .map(|x| x.parse::<f64>().expect("Malformed input: max-rg")),
+        pocket: matches.value_of("center").map(|x| {
+            let max_distance = matches.value_of("max_distance")
+                .unwrap()
+                .parse::<f64>()
+                .expect("Malformed input: max-distance");
+            filter::Pocket::from_args(x, max_distance).expect("Malformed input: center")
+        }),
+        min_poses: matches.value_of("min_poses")
+            .map(|x| x.parse::<usize>().expect("Malformed input: min-poses")),
+        max_poses: matches.value_of("max_poses")
+            .map(|x| x.parse::<usize>().expect("Malformed input: max-poses")),
+        exec: parse_exec(matches)
     };
 
+    // Instantiate Input File List
+    let input_files = collect_input_files(matches);
+
+    if matches.is_present("dry_run") {
+        print_dry_run(
+            "filter",
+            &input_files,
+            &[output_filename.to_string()],
+            &[
+                ("max_energy", optional_setting(options.max_energy)),
+                ("min_energy", optional_setting(options.min_energy)),
+                ("top_percent", optional_setting(options.top_percent)),
+                ("sample", optional_setting(options.sample.as_ref().map(|x| x.describe()))),
+                ("plugin", optional_setting(matches.value_of("plugin"))),
+                ("max_rg", optional_setting(options.max_radius_of_gyration)),
+                ("pocket", optional_setting(options.pocket.as_ref().map(|x| x.describe()))),
+                ("min_poses", optional_setting(options.min_poses)),
+                ("max_poses", optional_setting(options.max_poses)),
+                ("exec", optional_setting(options.exec.as_ref().map(|x| x.describe()))),
+                ("threads", num_threads.to_string())
+            ]
+        );
+        return Ok(())
+    }
+
+    build_threadpool(num_threads);
+
+    filter::filter(
+        input_files,
+        output_filename,
+        &options
+    )?;
+
+    Ok(())
+}
+
+
+// runs duplicates subcommand
+fn subcommand_duplicates(matches: &ArgMatches) -> Result<(), Error> {
+
+    // assign variables
+    let output_filename = matches.value_of("output").unwrap();
+
+    let decimals = matches.value_of("decimals")
+        .unwrap()
+        .parse::<u32>()
+        .expect("Malformed input: decimals");
+
+    let num_threads = matches.value_of("num_threads")
+        .unwrap()
+        .parse::<usize>()
+        .expect("Malformed input: num_threads");
+
+    // Instantiate Input File List
+    let input_files = collect_input_files(matches);
+
+    if matches.is_present("dry_run") {
+        print_dry_run(
+            "duplicates",
+            &input_files,
+            &[output_filename.to_string()],
+            &[
+                ("decimals", decimals.to_string()),
+                ("threads", num_threads.to_string())
+            ]
+        );
+        return Ok(())
+    }
+
+    build_threadpool(num_threads);
+
+    qc::duplicates(
+        input_files,
+        output_filename,
+        decimals
+    )?;
+
+    Ok(())
+}
+
+
+// runs atoms subcommand
+fn subcommand_atoms(matches: &ArgMatches) -> Result<(), Error> {
+
+    // assign variables
+    let output_filename = matches.value_of("output").unwrap();
+
+    let num_threads = matches.value_of("num_threads")
+        .unwrap()
+        .parse::<usize>()
+        .expect("Malformed input: num_threads");
+
+    // Instantiate Input File List
+    let input_files = collect_input_files(matches);
+
+    if matches.is_present("dry_run") {
+        print_dry_run(
+            "atoms",
+            &input_files,
+            &[output_filename.to_string()],
+            &[("threads", num_threads.to_string())]
+        );
+        return Ok(())
+    }
+
+    build_threadpool(num_threads);
+
+    qc::atom_census(
+        input_files,
+        output_filename
+    )?;
+
+    Ok(())
+}
+
+
+// runs charges subcommand
+fn subcommand_charges(matches: &ArgMatches) -> Result<(), Error> {
+
+    // assign variables
+    let output_filename = matches.value_of("output").unwrap();
+    let flagged_filename = matches.value_of("flagged").unwrap();
+
+    let tol = matches.value_of("tolerance")
+        .unwrap()
+        .parse::<f64>()
+        .expect("Malformed input: tolerance");
+
+    let num_threads = matches.value_of("num_threads")
+        .unwrap()
+        .parse::<usize>()
+        .expect("Malformed input: num_threads");
+
+    // Instantiate Input File List
+    let input_files = collect_input_files(matches);
+
+    if matches.is_present("dry_run") {
+        print_dry_run(
+            "charges",
+            &input_files,
+            &[output_filename.to_string(), flagged_filename.to_string()],
+            &[
+                ("tolerance", tol.to_string()),
+                ("threads", num_threads.to_string())
+            ]
+        );
+        return Ok(())
+    }
+
+    build_threadpool(num_threads);
+
+    qc::charge_stats(
+        input_files,
+        output_filename,
+        flagged_filename,
+        tol
+    )?;
+
+    Ok(())
+}
+
+
+// Collects one of the named input collections of a subcommand comparing several
+fn collect_named_inputs(matches: &ArgMatches, name: &str) -> Vec<String> {
+    let input_files = matches.values_of(name)
+        .unwrap()
+        .map(|x| x.to_string())
+        .collect();
+    let input_files = file_io::expand_input_patterns(input_files)
+        .expect("Error: Failed to expand input patterns");
+    archive::expand_archives(input_files)
+        .expect("Error: Failed to list archive members")
+}
+
+// runs diff subcommand
+fn subcommand_diff(matches: &ArgMatches) -> Result<(), Error> {
+
+    // assign variables
+    let output_filename = matches.value_of("output").unwrap();
+    let tolerance = matches.value_of("tolerance")
+        .map(|x| x.parse::<f64>().expect("Malformed input: tolerance"));
+
+    let num_threads = matches.value_of("num_threads")
+        .unwrap()
+        .parse::<usize>()
+        .expect("Malformed input: num_threads");
+
+    let input_a = collect_named_inputs(matches, "input_a");
+    let input_b = collect_named_inputs(matches, "input_b");
+
+    if matches.is_present("dry_run") {
+        let inputs: Vec<String> = input_a
+            .iter()
+            .map(|x| format!("A: {}", x))
+            .chain(input_b.iter().map(|x| format!("B: {}", x)))
+            .collect();
+        print_dry_run(
+            "diff",
+            &inputs,
+            &[output_filename.to_string()],
+            &[
+                ("tolerance", optional_setting(tolerance)),
+                ("threads", num_threads.to_string())
+            ]
+        );
+        return Ok(())
+    }
+
+    build_threadpool(num_threads);
+
+    compare::diff(
+        &input_a,
+        &input_b,
+        output_filename,
+        tolerance
+    )?;
+
+    Ok(())
+}
+
+
+// runs set subcommand
+fn subcommand_set(matches: &ArgMatches) -> Result<(), Error> {
+
+    // assign variables
+    let output_filename = matches.value_of("output").unwrap();
+    let op = compare::SetOperation::from_name(matches.value_of("op").unwrap())
+        .expect("Malformed input: op");
+
+    let num_threads = matches.value_of("num_threads")
+        .unwrap()
+        .parse::<usize>()
+        .expect("Malformed input: num_threads");
+
+    let input_a = collect_named_inputs(matches, "input_a");
+    let input_b = collect_named_inputs(matches, "input_b");
+
+    if matches.is_present("dry_run") {
+        let inputs: Vec<String> = input_a
+            .iter()
+            .map(|x| format!("A: {}", x))
+            .chain(input_b.iter().map(|x| format!("B: {}", x)))
+            .collect();
+        print_dry_run(
+            "set",
+            &inputs,
+            &[output_filename.to_string()],
+            &[
+                ("op", op.name().to_string()),
+                ("threads", num_threads.to_string())
+            ]
+        );
+        return Ok(())
+    }
+
+    build_threadpool(num_threads);
+
+    compare::set_operation(
+        input_a,
+        input_b,
+        op,
+        output_filename
+    )?;
+
+    Ok(())
+}
+
+
+// runs reorder subcommand
+fn subcommand_reorder(matches: &ArgMatches) -> Result<(), Error> {
+
+    // assign variables
+    let output_filename = matches.value_of("output").unwrap();
+    let list_filename = matches.value_of("list").unwrap();
+
+    let num_threads = matches.value_of("num_threads")
+        .unwrap()
+        .parse::<usize>()
+        .expect("Malformed input: num_threads");
+
+    // Instantiate Input File List
+    let input_files = collect_input_files(matches);
+    let names = read_name_list(list_filename)?;
+
+    if matches.is_present("dry_run") {
+        print_dry_run(
+            "reorder",
+            &input_files,
+            &[output_filename.to_string()],
+            &[
+                ("list", format!("{} ({} names)", list_filename, names.len())),
+                ("threads", num_threads.to_string())
+            ]
+        );
+        return Ok(())
+    }
+
+    build_threadpool(num_threads);
+
+    reorder::reorder(
+        input_files,
+        &names,
+        output_filename
+    )?;
+
+    Ok(())
+}
+
+
+// runs decoys subcommand
+fn subcommand_decoys(matches: &ArgMatches) -> Result<(), Error> {
+
+    // assign variables
+    let output_filename = matches.value_of("output").unwrap();
+    let pairs_filename = matches.value_of("pairs").unwrap();
+    let actives_filename = matches.value_of("actives").unwrap();
+
+    let per_active = matches.value_of("per_active")
+        .unwrap()
+        .parse::<usize>()
+        .expect("Malformed input: per-active");
+
+    let num_threads = matches.value_of("num_threads")
+        .unwrap()
+        .parse::<usize>()
+        .expect("Malformed input: num_threads");
+
+    // actives are either poses or a list of names found in the library
+    let actives = if actives_filename.ends_with(".mol2.gz") {
+        decoys::Actives::Molecules(actives_filename.to_string())
+    } else {
+        decoys::Actives::Names(read_name_list(actives_filename)?)
+    };
+
+    // Instantiate Input File List
+    let input_files = collect_input_files(matches);
+
+    if matches.is_present("dry_run") {
+        print_dry_run(
+            "decoys",
+            &input_files,
+            &[output_filename.to_string(), pairs_filename.to_string()],
+            &[
+                ("actives", actives_filename.to_string()),
+                ("per_active", per_active.to_string()),
+                ("threads", num_threads.to_string())
+            ]
+        );
+        return Ok(())
+    }
+
+    build_threadpool(num_threads);
+
+    decoys::decoys(
+        input_files,
+        &actives,
+        per_active,
+        output_filename,
+        pairs_filename
+    )?;
+
+    Ok(())
+}
+
+
+// runs enrichment subcommand
+fn subcommand_enrichment(matches: &ArgMatches) -> Result<(), Error> {
+
+    // assign variables
+    let output_filename = matches.value_of("output").unwrap();
+    let roc_filename = matches.value_of("roc").unwrap();
+    let actives_filename = matches.value_of("actives").unwrap();
+
+    let ef_percents = matches.value_of("ef")
+        .unwrap()
+        .split(',')
+        .map(|x| x.trim().parse::<f64>().expect("Malformed input: ef"))
+        .collect::<Vec<f64>>();
+
+    let num_threads = matches.value_of("num_threads")
+        .unwrap()
+        .parse::<usize>()
+        .expect("Malformed input: num_threads");
+
+    let actives: HashSet<String> = read_name_list(actives_filename)?
+        .into_iter()
+        .collect();
+
+    // scores are read from a table or from the best pose of every name in the inputs
+    let input_files = match matches.value_of("scores") {
+        Some(_) => Vec::new(),
+        None => collect_input_files(matches)
+    };
+
+    if matches.is_present("dry_run") {
+        print_dry_run(
+            "enrichment",
+            &input_files,
+            &[output_filename.to_string(), roc_filename.to_string()],
+            &[
+                ("scores", matches.value_of("scores").unwrap_or("mol2 energies").to_string()),
+                ("actives", format!("{} ({} names)", actives_filename, actives.len())),
+                ("ef", list_setting(&ef_percents)),
+                ("threads", num_threads.to_string())
+            ]
+        );
+        return Ok(())
+    }
+
+    build_threadpool(num_threads);
+
+    let scores = match matches.value_of("scores") {
+        Some(x) => enrichment::read_score_table(x)?,
+        None => enrichment::best_scores(&input_files)
+    };
+
+    let result = enrichment::enrichment(&scores, &actives, &ef_percents)
+        .ok_or_else(|| Error::new(
+            std::io::ErrorKind::InvalidData,
+            "enrichment requires at least one active and one decoy among the scored molecules"
+        ))?;
+
+    result.write_summary(output_filename)?;
+    result.write_roc(roc_filename)?;
+
+    println!(">>> Number of Actives: {}", result.num_actives);
+    println!(">>> Number of Decoys: {}", result.num_decoys);
+    println!(">>> ROC AUC: {:.4}", result.auc);
+    println!(">>> logAUC: {:.4} (adjusted: {:.4})", result.log_auc, result.adjusted_log_auc());
+    result.enrichment_factors
+        .iter()
+        .for_each(|(p, ef)| println!(">>> EF {}%: {:.2}", p, ef));
+    println!(">>> Summary Written to: {}", output_filename);
+    println!(">>> ROC Curve Written to: {}", roc_filename);
+
+    #[cfg(feature = "plot")]
+    if let Some(plot_filename) = matches.value_of("plot") {
+        plot::roc_curve(&result.roc, result.auc, plot_filename)?;
+        println!(">>> ROC Figure Written to: {}", plot_filename);
+    }
+
+    Ok(())
+}
+
+
+// runs plot subcommand
+#[cfg(feature = "plot")]
+fn subcommand_plot(matches: &ArgMatches) -> Result<(), Error> {
+
+    // assign variables
+    let histogram_filename = matches.value_of("histogram").unwrap();
+    let boxes_filename = matches.value_of("boxes").unwrap();
+
+    let num_bins = matches.value_of("bins")
+        .unwrap()
+        .parse::<usize>()
+        .expect("Malformed input: bins");
+
+    let num_threads = matches.value_of("num_threads")
+        .unwrap()
+        .parse::<usize>()
+        .expect("Malformed input: num_threads");
+
+    // Instantiate Input File List
+    let input_files = collect_input_files(matches);
+
+    if matches.is_present("dry_run") {
+        print_dry_run(
+            "plot",
+            &input_files,
+            &[histogram_filename.to_string(), boxes_filename.to_string()],
+            &[
+                ("bins", num_bins.to_string()),
+                ("threads", num_threads.to_string())
+            ]
+        );
+        return Ok(())
+    }
+
+    build_threadpool(num_threads);
+
+    let per_file = plot::file_energies(&input_files);
+    let energies: Vec<f64> = per_file
+        .iter()
+        .flat_map(|(_, e)| e.iter().copied())
+        .collect();
+
+    plot::energy_histogram(&energies, num_bins, histogram_filename)?;
+    plot::file_boxes(&per_file, boxes_filename)?;
+
+    println!(">>> Number of Molecules: {}", energies.len());
+    println!(">>> Histogram Written to: {}", histogram_filename);
+    println!(">>> File Boxes Written to: {}", boxes_filename);
+
+    Ok(())
+}
+
+
+// runs stats subcommand
+fn subcommand_stats(matches: &ArgMatches) -> Result<(), Error> {
+
+    // assign variables
+    let output_filename = matches.value_of("output").unwrap();
+    let per_ligand = matches.is_present("per_ligand");
+    let percentiles: Vec<f64> = matches.value_of("percentiles")
+        .unwrap()
+        .split(',')
+        .filter(|x| !x.trim().is_empty())
+        .map(|x| x.trim().parse::<f64>().expect("Malformed input: percentiles"))
+        .collect();
+    if percentiles.iter().any(|x| !(0.0..=100.0).contains(x)) {
+        panic!("Malformed input: percentiles must lie within 0-100");
+    }
+
+    let num_threads = matches.value_of("num_threads")
+        .unwrap()
+        .parse::<usize>()
+        .expect("Malformed input: num_threads");
+
+    // Instantiate Input File List
+    let input_files = collect_input_files(matches);
+
+    if matches.is_present("dry_run") {
+        print_dry_run(
+            "stats",
+            &input_files,
+            &[output_filename.to_string()],
+            &[
+                ("per_ligand", per_ligand.to_string()),
+                ("percentiles", list_setting(&percentiles)),
+                ("threads", num_threads.to_string())
+            ]
+        );
+        return Ok(())
+    }
+
+    build_threadpool(num_threads);
+
+    stats::stats(
+        input_files,
+        output_filename,
+        per_ligand,
+        &percentiles
+    )?;
+
+    Ok(())
+}
+
+
+// runs info subcommand
+fn subcommand_info(matches: &ArgMatches) -> Result<(), Error> {
+
+    // assign variables
+    let output_filename = matches.value_of("output");
+
+    let num_threads = matches.value_of("num_threads")
+        .unwrap()
+        .parse::<usize>()
+        .expect("Malformed input: num_threads");
+
+    // Instantiate Input File List
+    let input_files = collect_input_files(matches);
+
+    if matches.is_present("dry_run") {
+        print_dry_run(
+            "info",
+            &input_files,
+            &output_filename.map(|x| vec![x.to_string()]).unwrap_or_default(),
+            &[
+                ("threads", num_threads.to_string())
+            ]
+        );
+        return Ok(())
+    }
+
+    build_threadpool(num_threads);
+
+    report::info(
+        input_files,
+        output_filename
+    )?;
+
+    Ok(())
+}
+
+
+// runs count subcommand
+fn subcommand_count(matches: &ArgMatches) -> Result<(), Error> {
+
+    // assign variables
+    let output_filename = matches.value_of("output").unwrap();
+    let cache_path = match matches.value_of("cache") {
+        _ if matches.is_present("no_cache") => None,
+        Some(x) => Some(std::path::PathBuf::from(x)),
+        None => cache::MetadataCache::default_path()
+    };
+
+    let num_threads = matches.value_of("num_threads")
+        .unwrap()
+        .parse::<usize>()
+        .expect("Malformed input: num_threads");
+
+    // Instantiate Input File List
+    let input_files = collect_input_files(matches);
+
+    if matches.is_present("dry_run") {
+        print_dry_run(
+            "count",
+            &input_files,
+            &[output_filename.to_string()],
+            &[
+                ("cache", optional_setting(cache_path.as_ref().map(|x| x.display()))),
+                ("threads", num_threads.to_string())
+            ]
+        );
+        return Ok(())
+    }
+
+    build_threadpool(num_threads);
+
+    cache::count(
+        input_files,
+        output_filename,
+        cache_path.as_deref()
+    )?;
+
+    Ok(())
+}
+
+
+// runs zinc subcommand
+fn subcommand_zinc(matches: &ArgMatches) -> Result<(), Error> {
+
+    // assign variables
+    let output_filename = matches.value_of("output").unwrap();
+    let options = zinc::ZincOptions {
+        component: zinc::ZincComponent::from_name(matches.value_of("by").unwrap())
+            .expect("Malformed input: by"),
+        split_prefix: matches.value_of("split_prefix").map(|x| x.to_string())
+    };
+
+    let num_threads = matches.value_of("num_threads")
+        .unwrap()
+        .parse::<usize>()
+        .expect("Malformed input: num_threads");
+
+    // Instantiate Input File List
+    let input_files = collect_input_files(matches);
+
+    if matches.is_present("dry_run") {
+        print_dry_run(
+            "zinc",
+            &input_files,
+            &[output_filename.to_string()],
+            &[
+                ("by", options.component.name().to_string()),
+                ("split_prefix", optional_setting(options.split_prefix.as_ref())),
+                ("threads", num_threads.to_string())
+            ]
+        );
+        return Ok(())
+    }
+
+    build_threadpool(num_threads);
+
+    zinc::zinc(
+        input_files,
+        output_filename,
+        &options
+    )?;
+
+    Ok(())
+}
+
+
+// runs merge subcommand
+fn subcommand_merge(matches: &ArgMatches) -> Result<(), Error> {
+
+    // assign variables
+    let output_filename = matches.value_of("output").unwrap();
+    let sorted = matches.is_present("sorted");
+    let group_output = matches.is_present("group_output");
+
+    // Instantiate Input File List
+    let input_files = collect_input_files(matches);
+
+    if matches.is_present("dry_run") {
+        print_dry_run(
+            "merge",
+            &input_files,
+            &[output_filename.to_string()],
+            &[
+                ("sorted", sorted.to_string()),
+                ("group_output", group_output.to_string())
+            ]
+        );
+        return Ok(())
+    }
+
+    merge::merge(
+        input_files,
+        output_filename,
+        sorted,
+        group_output
+    )?;
+
+    Ok(())
+}
+
+
+// runs head and tail subcommands
+fn subcommand_head_tail(matches: &ArgMatches, subcommand: &str) -> Result<(), Error> {
+
+    // assign variables
+    let output_filename = matches.value_of("output").unwrap();
+
+    let num_molecules = matches.value_of("num_molecules")
+        .unwrap()
+        .parse::<usize>()
+        .expect("Malformed input: num_molecules");
+
+    // Instantiate Input File List
+    let input_files = collect_input_files(matches);
+
+    if matches.is_present("dry_run") {
+        print_dry_run(
+            subcommand,
+            &input_files,
+            &[output_filename.to_string()],
+            &[
+                ("num_molecules", num_molecules.to_string())
+            ]
+        );
+        return Ok(())
+    }
+
+    match subcommand {
+        "head" => slice::head(input_files, num_molecules, output_filename)?,
+        _ => slice::tail(input_files, num_molecules, output_filename)?
+    };
+
+    Ok(())
+}
+
+
+// runs slice subcommand
+fn subcommand_slice(matches: &ArgMatches) -> Result<(), Error> {
+
+    // assign variables
+    let output_filename = matches.value_of("output").unwrap();
+
+    let parse_ordinal = |x: &str| x.trim().parse::<usize>().expect("Malformed input: ordinals");
+    let selection = if let Some(x) = matches.value_of("ordinals") {
+        slice::Selection::Ordinals(x.split(',').filter(|x| !x.trim().is_empty()).map(parse_ordinal).collect())
+    } else if let Some(x) = matches.value_of("ordinals_file") {
+        slice::Selection::Ordinals(read_name_list(x)?.iter().map(|x| parse_ordinal(x)).collect())
+    } else {
+        let start = matches.value_of("start")
+            .unwrap()
+            .parse::<usize>()
+            .expect("Malformed input: start");
+        let end = matches.value_of("end")
+            .map(|x| x.parse::<usize>().expect("Malformed input: end"));
+        if end.is_some_and(|e| e < start) {
+            panic!("Malformed input: end is before start");
+        }
+        slice::Selection::Range(start, end)
+    };
+
+    // Instantiate Input File List
+    let input_files = collect_input_files(matches);
+
+    if matches.is_present("dry_run") {
+        print_dry_run(
+            "slice",
+            &input_files,
+            &[output_filename.to_string()],
+            &[
+                ("selection", selection.describe())
+            ]
+        );
+        return Ok(())
+    }
+
+    slice::slice(
+        input_files,
+        &selection,
+        output_filename
+    )?;
+
+    Ok(())
+}
+
+
+// runs interleave subcommand
+fn subcommand_interleave(matches: &ArgMatches) -> Result<(), Error> {
+
+    // assign variables
+    let output_filename = matches.value_of("output").unwrap();
+
+    // Instantiate Input File List
+    let input_files = collect_input_files(matches);
+
+    if matches.is_present("dry_run") {
+        print_dry_run(
+            "interleave",
+            &input_files,
+            &[output_filename.to_string()],
+            &[]
+        );
+        return Ok(())
+    }
+
+    merge::interleave(
+        input_files,
+        output_filename
+    )?;
+
+    Ok(())
+}
+
+
+// runs sample subcommand
+fn subcommand_sample(matches: &ArgMatches) -> Result<(), Error> {
+
+    // assign variables
+    let output_filename = matches.value_of("output").unwrap();
+
+    let num_molecules = matches.value_of("num_molecules")
+        .unwrap()
+        .parse::<usize>()
+        .expect("Malformed input: num_molecules");
+
+    let seed = matches.value_of("seed")
+        .unwrap()
+        .parse::<u64>()
+        .expect("Malformed input: seed");
+
+    let num_threads = matches.value_of("num_threads")
+        .unwrap()
+        .parse::<usize>()
+        .expect("Malformed input: num_threads");
+
+    // Instantiate Input File List
+    let input_files = collect_input_files(matches);
+
+    if matches.is_present("dry_run") {
+        print_dry_run(
+            "sample",
+            &input_files,
+            &[output_filename.to_string()],
+            &[
+                ("num_molecules", num_molecules.to_string()),
+                ("seed", seed.to_string()),
+                ("threads", num_threads.to_string())
+            ]
+        );
+        return Ok(())
+    }
+
+    build_threadpool(num_threads);
+
+    sample::sample(
+        input_files,
+        num_molecules,
+        seed,
+        output_filename
+    )?;
+
+    Ok(())
+}
+
+
+// runs shuffle subcommand
+fn subcommand_shuffle(matches: &ArgMatches) -> Result<(), Error> {
+
+    // assign variables
+    let output_filename = matches.value_of("output").unwrap();
+
+    let mut options = sample::ShuffleOptions {
+        seed: matches.value_of("seed")
+            .unwrap()
+            .parse::<u64>()
+            .expect("Malformed input: seed"),
+        buffer_bytes: matches.value_of("buffer_size")
+            .unwrap()
+            .parse::<u64>()
+            .expect("Malformed input: buffer-size") << 20,
+        ..Default::default()
+    };
+    if let Some(x) = matches.value_of("tmp_dir") {
+        options.tmp_dir = x.into();
+    }
+
+    // Instantiate Input File List
+    let input_files = collect_input_files(matches);
+
+    if matches.is_present("dry_run") {
+        print_dry_run(
+            "shuffle",
+            &input_files,
+            &[output_filename.to_string()],
+            &[
+                ("seed", options.seed.to_string()),
+                ("buffer_bytes", options.buffer_bytes.to_string()),
+                ("tmp_dir", options.tmp_dir.display().to_string())
+            ]
+        );
+        return Ok(())
+    }
+
+    sample::shuffle(
+        input_files,
+        output_filename,
+        &options
+    )?;
+
+    Ok(())
+}
+
+
+// runs dedup subcommand
+fn subcommand_dedup(matches: &ArgMatches) -> Result<(), Error> {
+
+    // assign variables
+    let output_filename = matches.value_of("output").unwrap();
+    let keep_first = matches.is_present("keep_first");
+
+    let num_threads = matches.value_of("num_threads")
+        .unwrap()
+        .parse::<usize>()
+        .expect("Malformed input: num_threads");
+
+    // Instantiate Input File List
+    let input_files = collect_input_files(matches);
+
+    if matches.is_present("dry_run") {
+        print_dry_run(
+            "dedup",
+            &input_files,
+            &[output_filename.to_string()],
+            &[
+                ("keep_first", keep_first.to_string()),
+                ("threads", num_threads.to_string())
+            ]
+        );
+        return Ok(())
+    }
+
+    build_threadpool(num_threads);
+
+    dedup::dedup(
+        input_files,
+        output_filename,
+        keep_first
+    )?;
+
+    Ok(())
+}
+
+
+// runs sort subcommand
+fn subcommand_sort(matches: &ArgMatches) -> Result<(), Error> {
+
+    // assign variables
+    let output_filename = matches.value_of("output").unwrap();
+    let buffer_mb = matches.value_of("buffer_size")
+        .unwrap()
+        .parse::<u64>()
+        .expect("Malformed input: buffer-size");
+    let options = sort::SortOptions {
+        descending: matches.is_present("descending"),
+        buffer_bytes: buffer_mb.max(1) << 20,
+        tmp_dir: matches.value_of("tmp_dir")
+            .map(std::path::PathBuf::from)
+            .unwrap_or_else(std::env::temp_dir)
+    };
+
+    // Instantiate Input File List
+    let input_files = collect_input_files(matches);
+
+    if matches.is_present("dry_run") {
+        print_dry_run(
+            "sort",
+            &input_files,
+            &[output_filename.to_string()],
+            &[
+                ("descending", options.descending.to_string()),
+                ("buffer_size", format!("{} MB", buffer_mb)),
+                ("tmp_dir", options.tmp_dir.display().to_string())
+            ]
+        );
+        return Ok(())
+    }
+
+    sort::sort(
+        input_files,
+        output_filename,
+        &options
+    )?;
+
+    Ok(())
+}
+
+
+// runs reassemble subcommand
+fn subcommand_reassemble(matches: &ArgMatches) -> Result<(), Error> {
+
+    // assign variables
+    let output_filename = matches.value_of("output").unwrap();
+    let report_filename = matches.value_of("report");
+    let allow_gaps = matches.is_present("allow_gaps");
+
+    // Instantiate Input File List
+    let input_files = collect_input_files(matches);
+
+    if matches.is_present("dry_run") {
+        print_dry_run(
+            "reassemble",
+            &input_files,
+            &[output_filename.to_string()],
+            &[
+                ("report", optional_setting(report_filename)),
+                ("allow_gaps", allow_gaps.to_string())
+            ]
+        );
+        return Ok(())
+    }
+
+    merge::reassemble(
+        input_files,
+        output_filename,
+        report_filename,
+        allow_gaps
+    )?;
+
+    Ok(())
+}
+
+
+// runs index subcommand
+fn subcommand_index(matches: &ArgMatches) -> Result<(), Error> {
+
+    // assign variables
+    let output_filename = matches.value_of("output").unwrap();
+
+    let num_threads = matches.value_of("num_threads")
+        .unwrap()
+        .parse::<usize>()
+        .expect("Malformed input: num_threads");
+
+    // Instantiate Input File List
+    let input_files = collect_input_files(matches);
+
+    if matches.is_present("dry_run") {
+        print_dry_run(
+            "index",
+            &input_files,
+            &[output_filename.to_string()],
+            &[
+                ("threads", num_threads.to_string())
+            ]
+        );
+        return Ok(())
+    }
+
+    build_threadpool(num_threads);
+
+    index::build_index(
+        input_files,
+        output_filename
+    )?;
+
+    Ok(())
+}
+
+
+// runs fetch subcommand
+fn subcommand_fetch(matches: &ArgMatches) -> Result<(), Error> {
+
+    // assign variables
+    let index_filename = matches.value_of("index").unwrap();
+    let output_filename = matches.value_of("output").unwrap();
+
+    let mut names: Vec<String> = matches.values_of("names")
+        .map(|x| x.map(|x| x.to_string()).collect())
+        .unwrap_or_default();
+    if let Some(x) = matches.value_of("name_list") {
+        names.extend(read_name_list(x)?);
+    }
+
+    if matches.is_present("dry_run") {
+        print_dry_run(
+            "fetch",
+            &[index_filename.to_string()],
+            &[output_filename.to_string()],
+            &[
+                ("names", names.len().to_string())
+            ]
+        );
+        return Ok(())
+    }
+
+    index::fetch(
+        index_filename,
+        &names.into_iter().collect(),
+        output_filename
+    )?;
+
+    Ok(())
+}
+
+
+// runs top subcommand
+fn subcommand_top(matches: &ArgMatches) -> Result<(), Error> {
+
+    // assign variables
+    let index_filename = matches.value_of("index").unwrap();
+    let output_filename = matches.value_of("output").unwrap();
+
+    let top = matches.value_of("top")
+        .map(|x| x.parse::<usize>().expect("Malformed input: top"));
+    let max_energy = matches.value_of("max_energy")
+        .map(|x| x.parse::<f64>().expect("Malformed input: max-energy"));
+
+    let num_threads = matches.value_of("num_threads")
+        .unwrap()
+        .parse::<usize>()
+        .expect("Malformed input: num_threads");
+
+    // inputs are streamed through a bounded heap when given instead of an index
+    if matches.is_present("mol2") || matches.is_present("input_files") {
+        let input_files = collect_input_files(matches);
+
+        if matches.is_present("dry_run") {
+            print_dry_run(
+                "top",
+                &input_files,
+                &[output_filename.to_string()],
+                &[
+                    ("top", optional_setting(top)),
+                    ("max_energy", optional_setting(max_energy)),
+                    ("threads", num_threads.to_string())
+                ]
+            );
+            return Ok(())
+        }
+
+        build_threadpool(num_threads);
+
+        sort::top(
+            input_files,
+            top,
+            max_energy,
+            output_filename
+        )?;
+
+        return Ok(())
+    }
+
+    if matches.is_present("dry_run") {
+        print_dry_run(
+            "top",
+            &[index_filename.to_string()],
+            &[output_filename.to_string()],
+            &[
+                ("top", optional_setting(top)),
+                ("max_energy", optional_setting(max_energy)),
+                ("threads", num_threads.to_string())
+            ]
+        );
+        return Ok(())
+    }
+
+    build_threadpool(num_threads);
+
+    index::top(
+        index_filename,
+        top,
+        max_energy,
+        output_filename
+    )?;
+
+    Ok(())
+}
+
+
+// Receives arguments from CLI
+fn build_cli() -> App<'static, 'static> {
+    let app = App::new("mol2grep")
+        .version("0.1")
+        .author("Noam Teyssier")
+        .arg(
+            Arg::with_name("dry_run")
+                .long("dry-run")
+                .help("Print the resolved inputs, outputs, and settings then exit without processing")
+                .takes_value(false)
+                .global(true)
+            )
+        .arg(
+            Arg::with_name("fallback_names")
+                .long("fallback-names")
+                .value_name("stem|path|none")
+                .help("Naming of molecules without a Name header or MOLECULE title: <file-stem>#<index>, <file-path>#<index>, or left blank")
+                .takes_value(true)
+                .possible_values(&["stem", "path", "none"])
+                .default_value("stem")
+                .global(true)
+            )
+        .arg(
+            Arg::with_name("io_threads")
+                .long("io-threads")
+                .value_name("N")
+                .help("Number of threads reading and decompressing inputs, separate from --threads (0: a quarter of the cores, at least 2)")
+                .takes_value(true)
+                .default_value("0")
+                .global(true)
+            )
+        .arg(
+            Arg::with_name("compression_level")
+                .long("compression-level")
+                .value_name("LEVEL")
+                .help("Compression level of outputs (gzip: 0-9, zstd: 1-22; default: 6 and 3)")
+                .takes_value(true)
+                .global(true)
+            )
+        .arg(
+            Arg::with_name("bgzf")
+                .long("bgzf")
+                .help("Write .gz outputs as BGZF: gzip made of independent blocks of whole molecules, for seekable and block-parallel reads")
+                .takes_value(false)
+                .global(true)
+            )
+        .arg(
+            Arg::with_name("hash_inputs")
+                .long("hash-inputs")
+                .help("Identify inputs in output provenance by a hash of their contents instead of their size and modification time (reads every input in full)")
+                .takes_value(false)
+                .global(true)
+            )
+        .arg(
+            Arg::with_name("max_read_mbps")
+                .long("max-read-mbps")
+                .value_name("MB/s")
+                .help("Limit reads of all inputs across every thread to MB/s megabytes per second (e.g. to spare a shared filesystem)")
+                .takes_value(true)
+                .global(true)
+            )
+        .arg(
+            Arg::with_name("low_io_priority")
+                .long("low-io-priority")
+                .help("Lower the IO scheduling priority of the process (Linux only)")
+                .takes_value(false)
+                .global(true)
+            )
+        .arg(
+            Arg::with_name("strict")
+                .long("strict")
+                .help("Exit with an error when an input fails mid-stream instead of keeping the molecules read before the failure")
+                .takes_value(false)
+                .global(true)
+            )
+        .subcommand(SubCommand::with_name("grep")
+            .about("greps mol2 files and returns poses that match zincid and/or expected score")
+            .arg(
+                Arg::with_name("query")
+                    .short("q")
+                    .long("query")
+                    .value_name("ZINC-id,score.tsv")
+                    .help("Query table of ZINC-ids and scores (or min and max energies) to search for (tab separated, header optional; may be given multiple times; - reads the standard input)")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1)
+                    .required(true)
+                )
+            .arg(
+                Arg::with_name("id_col")
+                    .long("id-col")
+                    .alias("id-field")
+                    .value_name("column")
+                    .help("Column of the query tables holding names: 1-based number or header name (default = 1)")
+                    .takes_value(true)
+                    .conflicts_with("regex")
+                )
+            .arg(
+                Arg::with_name("score_col")
+                    .long("score-col")
+                    .alias("score-field")
+                    .value_name("column")
+                    .help("Column of the query tables holding expected scores: 1-based number or header name (default = none)")
+                    .takes_value(true)
+                    .conflicts_with("regex")
+                )
+            .arg(
+                Arg::with_name("query_format")
+                    .long("query-format")
+                    .help("Format of the query tables: auto (whitespace, tab, or comma separated, .smi files as smi), csv (quoted fields allowed), or smi (`SMILES  ID` rows)")
+                    .takes_value(true)
+                    .possible_values(&["auto", "csv", "smi"])
+                    .default_value("auto")
+                    .conflicts_with("regex")
+                )
+            .arg(
+                Arg::with_name("group_col")
+                    .long("group-col")
+                    .value_name("column")
+                    .help("Column of the query tables holding a group (e.g. cluster): matches are written to <output>.<group>.mol2.gz")
+                    .takes_value(true)
+                    .conflicts_with_all(&["regex", "per_query_output", "invert"])
+                )
+            .arg(
+                Arg::with_name("tol_col")
+                    .long("tol-col")
+                    .value_name("column")
+                    .help("Column of the query tables holding a tolerance per row, overriding --tol: 1-based number or header name")
+                    .takes_value(true)
+                    .requires("score_col")
+                    .conflicts_with("regex")
+                )
+            .arg(
+                Arg::with_name("ignore_case")
+                    .long("ignore-case")
+                    .help("Match query names to molecule names regardless of case (e.g. zinc... and ZINC...)")
+                    .takes_value(false)
+                )
+            .arg(
+                Arg::with_name("regex")
+                    .long("regex")
+                    .help("Query lines are regular expressions matched against whole names (e.g. ZINC0000123.*)")
+                    .takes_value(false)
+                )
+            .arg(
+                Arg::with_name("alias")
+                    .long("alias")
+                    .value_name("code,ZINC-id.tsv")
+                    .help("Mapping of codes used in the queries to the names in the mol2 files (tab separated, no header); matches per code are reported in <output>.aliases.tsv")
+                    .takes_value(true)
+                )
+            .arg(
+                Arg::with_name("stats_memory")
+                    .long("stats-memory")
+                    .help("Report peak resident memory, query table size, and writer channel high-water mark")
+                    .takes_value(false)
+                )
+            .arg(
+                Arg::with_name("per_query_output")
+                    .long("per-query-output")
+                    .help("Write the matches of each query file to its own output (<output>.<query>.mol2.gz)")
+                    .takes_value(false)
+                )
+            .arg(
+                Arg::with_name("ordered")
+                    .long("ordered")
+                    .help("Write matches in input order so that outputs are reproducible (buffers matches in memory)")
+                    .takes_value(false)
+                    .conflicts_with_all(&["unique", "max_count"])
+                )
+            .arg(
+                Arg::with_name("best_per_id")
+                    .long("best-per-id")
+                    .help("Write only the lowest energy pose of every matched name, in input order (buffers matches in memory)")
+                    .takes_value(false)
+                    .conflicts_with_all(&["unique", "max_count", "rest_out", "per_input_output", "count", "files_with_matches"])
+                )
+            .arg(
+                Arg::with_name("max_per_file")
+                    .long("max-per-file")
+                    .value_name("N")
+                    .help("Roll the output over to a new numbered shard (<output>.0001.mol2.gz, ...) once it holds N molecules")
+                    .takes_value(true)
+                    .conflicts_with_all(&["per_query_output", "per_input_output", "split_by_query", "group_col", "archive", "count", "files_with_matches"])
+                )
+            .arg(
+                Arg::with_name("per_input_output")
+                    .long("per-input-output")
+                    .help("Write the matches of each input file to its own output (<input>.hits.mol2.gz next to the output)")
+                    .takes_value(false)
+                    .conflicts_with_all(&["per_query_output", "group_col", "group_output", "names_only", "annotate_source", "archive", "count", "files_with_matches"])
+                )
+            .arg(
+                Arg::with_name("split_by_query")
+                    .long("split-by-query")
+                    .help("Write the poses of every matched name to its own output (<output>.<name>.mol2.gz, buffers matches in memory)")
+                    .takes_value(false)
+                    .conflicts_with_all(&["per_query_output", "per_input_output", "group_col", "group_output", "invert", "archive", "count", "files_with_matches"])
+                )
+            .arg(
+                Arg::with_name("tolerance")
+                    .short("e")
+                    .long("tol")
+                    .help("Tolerance accepted between found and expected scores, on the side given by --tol-mode (default = 1e-6)")
+                    .takes_value(true)
+                    .required(false)
+                    .default_value("1e-6")
+                )
+            .arg(
+                Arg::with_name("tol_mode")
+                    .long("tol-mode")
+                    .help("Scores accepted around expected scores: abs (either side), below (at or below, the default), or above (at or above)")
+                    .takes_value(true)
+                    .possible_values(&["abs", "below", "above"])
+                    .required(false)
+                )
+            .arg(
+                Arg::with_name("rel_tol")
+                    .long("rel-tol")
+                    .help("Tolerance relative to the magnitude of expected scores, added to --tol (e.g. 0.01 accepts 1% of |score|)")
+                    .takes_value(true)
+                    .required(false)
+                )
+            .arg(
+                Arg::with_name("unique")
+                    .short("u")
+                    .long("unique")
+                    .help("Emit at most one pose per query name across all inputs (stops early once all are found)")
+                    .takes_value(false)
+                )
+            .arg(
+                Arg::with_name("invert")
+                    .short("v")
+                    .long("invert")
+                    .help("Write the molecules matching no query instead (e.g. to remove known actives from a deck)")
+                    .takes_value(false)
+                    .conflicts_with("per_query_output")
+                )
+            .arg(
+                Arg::with_name("exclude")
+                    .long("exclude")
+                    .value_name("blocklist.txt")
+                    .help("List of names never written even when queried (e.g. known aggregators; scores are ignored)")
+                    .takes_value(true)
+                    .required(false)
+                )
+            .arg(
+                Arg::with_name("annotate_source")
+                    .long("annotate-source")
+                    .help("Insert a `Source` header comment naming the input file into every molecule written")
+                    .takes_value(false)
+                )
+            .arg(
+                Arg::with_name("names_only")
+                    .long("names-only")
+                    .help("Write the names of matching molecules (one per pose) instead of their mol2 records")
+                    .takes_value(false)
+                    .conflicts_with("annotate_source")
+                )
+            .arg(
+                Arg::with_name("with_energy")
+                    .long("with-energy")
+                    .help("Write the found energy next to each name (name<TAB>energy)")
+                    .takes_value(false)
+                    .requires("names_only")
+                )
+            .arg(
+                Arg::with_name("count")
+                    .short("c")
+                    .long("count")
+                    .help("Print the number of matches of every input file and the total without writing any molecules")
+                    .takes_value(false)
+                    .conflicts_with_all(&["names_only", "annotate_source", "per_query_output", "group_col", "group_output", "archive", "exec"])
+                )
+            .arg(
+                Arg::with_name("files_with_matches")
+                    .short("l")
+                    .long("files-with-matches")
+                    .help("Print only the input files holding at least one match, reading each up to its first match")
+                    .takes_value(false)
+                    .conflicts_with_all(&["count", "names_only", "annotate_source", "per_query_output", "group_col", "group_output", "archive", "exec", "unique", "max_count"])
+                )
+            .arg(
+                Arg::with_name("rest_out")
+                    .long("rest-out")
+                    .value_name("FILE")
+                    .help("mol2 formatted filename to write every molecule not accepted to, in the same pass")
+                    .takes_value(true)
+                    .conflicts_with_all(&["count", "files_with_matches", "unique", "max_count"])
+                )
+            .arg(
+                Arg::with_name("max_count")
+                    .short("m")
+                    .long("max-count")
+                    .value_name("N")
+                    .help("Stop once N matching molecules were written (which N depends on thread scheduling)")
+                    .takes_value(true)
+                    .required(false)
+                )
+            .arg(
+                Arg::with_name("missing_out")
+                    .long("missing-out")
+                    .value_name("missing.txt")
+                    .help("Write the query names no molecule matched to a file (listed on stderr otherwise)")
+                    .takes_value(true)
+                    .required(false)
+                    .conflicts_with("invert")
+                )
+            .arg(
+                Arg::with_name("query_counts")
+                    .long("query-counts")
+                    .value_name("counts.tsv")
+                    .help("Write a table of the number of poses matched by every query name (including those never found)")
+                    .takes_value(true)
+                    .required(false)
+                    .conflicts_with("invert")
+                )
+            .arg(
+                Arg::with_name("group_output")
+                    .long("group-output")
+                    .help("Write all poses of the same ligand adjacently (buffers matches in memory)")
+                    .takes_value(false)
+                )
+            .arg(
+                Arg::with_name("file_report")
+                    .long("file-report")
+                    .value_name("report.tsv")
+                    .help("Write a table of per-file bytes, molecules, matches, and processing time")
+                    .takes_value(true)
+                    .required(false)
+                )
+            .arg(
+                Arg::with_name("output")
+                    .short("o")
+                    .long("out")
+                    .help("mol2 formatted filename to write passing molecules to")
+                    .takes_value(true)
+                    .default_value("query_output.mol2.gz")
+                )
+            .args(&input_args_unless(&["input_files", "index"]))
+            .arg(
+                Arg::with_name("index")
+                    .short("x")
+                    .long("index")
+                    .help("index built by the index subcommand: look queries up in it instead of scanning inputs (only query and tolerance options apply)")
+                    .takes_value(true)
+                    .conflicts_with_all(&["mol2", "input_files"])
+                )
+            .arg(
+                Arg::with_name("num_threads")
+                    .short("t")
+                    .long("threads")
+                    .help("Number of threads to use in parallel processing (0: one per core)")
+                    .takes_value(true)
+                    .required(false)
+                    .default_value("0")
+                )
+            .args(&budget_args())
+            .arg(archive_arg())
+            .args(&exec_args())
+            .setting(AppSettings::ArgRequiredElseHelp)
+        )
+        .subcommand(SubCommand::with_name("split")
+            .about("Splits a list of mol2 files into a given number of output files")
+            .args(&input_args())
+            .arg(
+                Arg::with_name("prefix")
+                    .short("o")
+                    .long("prefix")
+                    .help("prefix of output files: <prefix>.file_id.mol2.gz, or a template such as shards/part{}.mol2.zst (.gz: gzip, .zst: zstd, otherwise plain)")
+                    .takes_value(true)
+                    .default_value("split")
+                )
+            .arg(
+                Arg::with_name("num_files")
+                    .short("n")
+                    .long("num_files")
+                    .help("Number of files to split items into")
+                    .takes_value(true)
+                    .required(false)
+                    .default_value("4")
+                )
+            .arg(
+                Arg::with_name("num_threads")
+                    .short("t")
+                    .long("threads")
+                    .help("Number of threads to use in parallel processing (0: one per core)")
+                    .takes_value(true)
+                    .required(false)
+                    .default_value("0")
+                )
+            .args(&budget_args())
+            .arg(archive_arg())
+        )
+        .subcommand(SubCommand::with_name("table")
+            .about("convert a list of mol2 files into tab-separated table of names + scores")
+            .args(&input_args())
+            .arg(
+                Arg::with_name("output")
+                    .short("o")
+                    .long("output")
+                    .help("output filename to write table to")
+                    .takes_value(true)
+                    .default_value("output.tab.gz")
+                )
+            .arg(
+                Arg::with_name("no_header")
+                    .short("n")
+                    .long("no_header")
+                    .help("do not include a header in output file")
+                    .takes_value(false)
+                )
+            .arg(
+                Arg::with_name("long")
+                    .short("l")
+                    .long("long")
+                    .help("write one (name, pose_index, field, value) row per header field")
+                    .takes_value(false)
+                )
+            .arg(
+                Arg::with_name("pretty")
+                    .short("p")
+                    .long("pretty")
+                    .help("render an aligned table to the terminal instead of writing a file")
+                    .takes_value(false)
+                )
+            .arg(
+                Arg::with_name("limit")
+                    .long("limit")
+                    .value_name("N")
+                    .help("stop after writing N rows")
+                    .takes_value(true)
+                    .required(false)
+                )
+            .arg(
+                Arg::with_name("geometry")
+                    .long("geometry")
+                    .help("add the centroid (x, y, z) and radius of gyration of every pose")
+                    .takes_value(false)
+                )
+            .arg(
+                Arg::with_name("all_fields")
+                    .long("all-fields")
+                    .help("write one column per header field (e.g. van_der_waals, ligand_charge), following the fields of the first pose")
+                    .takes_value(false)
+                    .conflicts_with("long")
+                )
+            .arg(
+                Arg::with_name("fields")
+                    .long("fields")
+                    .value_name("name,energy,...")
+                    .help("comma separated columns to write in order: ligand_id, name, energy, pose_index, file, the geometry columns, or any header field (e.g. van_der_waals)")
+                    .takes_value(true)
+                    .conflicts_with_all(&["long", "all_fields", "geometry", "file_column"])
+                )
+            .arg(
+                Arg::with_name("file_column")
+                    .long("file-column")
+                    .help("add a `file` column recording the input file of every pose")
+                    .takes_value(false)
+                )
+            .args(&sample_args())
+        )
+        .subcommand(SubCommand::with_name("filter")
+            .about("selects poses by score without a query file")
+            .args(&input_args())
+            .arg(
+                Arg::with_name("output")
+                    .short("o")
+                    .long("output")
+                    .help("mol2 formatted filename to write passing molecules to")
+                    .takes_value(true)
+                    .default_value("filter.mol2.gz")
+                )
+            .arg(
+                Arg::with_name("max_energy")
+                    .long("max-energy")
+                    .value_name("ENERGY")
+                    .help("Keep only molecules with an energy of at most ENERGY")
+                    .takes_value(true)
+                    .allow_hyphen_values(true)
+                    .required(false)
+                )
+            .arg(
+                Arg::with_name("min_energy")
+                    .long("min-energy")
+                    .value_name("ENERGY")
+                    .help("Keep only molecules with an energy of at least ENERGY")
+                    .takes_value(true)
+                    .allow_hyphen_values(true)
+                    .required(false)
+                )
+            .arg(
+                Arg::with_name("top_percent")
+                    .long("top-percent")
+                    .value_name("PERCENT")
+                    .help("Keep only the best PERCENT% of molecules by energy (exact, requires two passes)")
+                    .takes_value(true)
+                    .required(false)
+                )
+            .arg(
+                Arg::with_name("plugin")
+                    .long("plugin")
+                    .value_name("filter.rhai")
+                    .help("Script defining fn keep(mol) -> bool applied to every molecule")
+                    .takes_value(true)
+                    .required(false)
+                )
+            .arg(
+                Arg::with_name("max_rg")
+                    .long("max-rg")
+                    .value_name("ANGSTROM")
+                    .help("Keep only poses with a radius of gyration of at most ANGSTROM")
+                    .takes_value(true)
+                    .required(false)
+                )
+            .arg(
+                Arg::with_name("center")
+                    .long("center")
+                    .value_name("X,Y,Z")
+                    .help("Keep only poses whose centroid lies within --max-distance of this point")
+                    .takes_value(true)
+                    .allow_hyphen_values(true)
+                    .required(false)
+                    .requires("max_distance")
+                )
+            .arg(
+                Arg::with_name("max_distance")
+                    .long("max-distance")
+                    .value_name("ANGSTROM")
+                    .help("Largest distance between a pose centroid and --center")
+                    .takes_value(true)
+                    .required(false)
+                    .requires("center")
+                )
+            .arg(
+                Arg::with_name("min_poses")
+                    .long("min-poses")
+                    .value_name("K")
+                    .help("Keep only ligands with at least K poses across the inputs (requires two passes)")
+                    .takes_value(true)
+                    .required(false)
+                )
+            .arg(
+                Arg::with_name("max_poses")
+                    .long("max-poses")
+                    .value_name("K")
+                    .help("Keep only ligands with at most K poses across the inputs (requires two passes)")
+                    .takes_value(true)
+                    .required(false)
+                )
+            .args(&exec_args())
+            .args(&sample_args())
+            .arg(
+                Arg::with_name("num_threads")
+                    .short("t")
+                    .long("threads")
+                    .help("Number of threads to use in parallel processing (0: one per core)")
+                    .takes_value(true)
+                    .required(false)
+                    .default_value("0")
+                )
+        )
+        .subcommand(SubCommand::with_name("duplicates")
+            .about("reports poses with identical (rounded) atom coordinates")
+            .args(&input_args())
+            .arg(
+                Arg::with_name("output")
+                    .short("o")
+                    .long("output")
+                    .help("tab-separated filename to write duplicate groups to")
+                    .takes_value(true)
+                    .default_value("duplicates.tsv")
+                )
+            .arg(
+                Arg::with_name("decimals")
+                    .short("d")
+                    .long("decimals")
+                    .help("Number of decimals coordinates are rounded to before comparison")
+                    .takes_value(true)
+                    .default_value("3")
+                )
+            .arg(
+                Arg::with_name("num_threads")
+                    .short("t")
+                    .long("threads")
+                    .help("Number of threads to use in parallel processing (0: one per core)")
+                    .takes_value(true)
+                    .required(false)
+                    .default_value("0")
+                )
+        )
+        .subcommand(SubCommand::with_name("atoms")
+            .about("counts SYBYL atom types and elements per file and in aggregate")
+            .args(&input_args())
+            .arg(
+                Arg::with_name("output")
+                    .short("o")
+                    .long("output")
+                    .help("tab-separated filename to write the census to")
+                    .takes_value(true)
+                    .default_value("atoms.tsv")
+                )
+            .arg(
+                Arg::with_name("num_threads")
+                    .short("t")
+                    .long("threads")
+                    .help("Number of threads to use in parallel processing (0: one per core)")
+                    .takes_value(true)
+                    .required(false)
+                    .default_value("0")
+                )
+        )
+        .subcommand(SubCommand::with_name("charges")
+            .about("reports net charge distributions and flags non-integer partial charge sums")
+            .args(&input_args())
+            .arg(
+                Arg::with_name("output")
+                    .short("o")
+                    .long("output")
+                    .help("tab-separated filename to write net charge distributions to")
+                    .takes_value(true)
+                    .default_value("charges.tsv")
+                )
+            .arg(
+                Arg::with_name("flagged")
+                    .long("flagged")
+                    .help("tab-separated filename to write molecules with non-integer charges to")
+                    .takes_value(true)
+                    .default_value("charges.flagged.tsv")
+                )
+            .arg(
+                Arg::with_name("tolerance")
+                    .short("e")
+                    .long("tol")
+                    .help("Maximum deviation of summed partial charges from an integer")
+                    .takes_value(true)
+                    .default_value("0.05")
+                )
+            .arg(
+                Arg::with_name("num_threads")
+                    .short("t")
+                    .long("threads")
+                    .help("Number of threads to use in parallel processing (0: one per core)")
+                    .takes_value(true)
+                    .required(false)
+                    .default_value("0")
+                )
+        )
+        .subcommand(SubCommand::with_name("index")
+            .about("writes an energy sorted index of the location of every molecule, and a binary name table (<index>.names) for lookups by name")
+            .args(&input_args())
+            .arg(
+                Arg::with_name("output")
+                    .short("o")
+                    .long("output")
+                    .help("filename to write the index to")
+                    .takes_value(true)
+                    .default_value("mol2grep.idx")
+                )
+            .arg(
+                Arg::with_name("num_threads")
+                    .short("t")
+                    .long("threads")
+                    .help("Number of threads to use in parallel processing (0: one per core)")
+                    .takes_value(true)
+                    .required(false)
+                    .default_value("0")
+                )
+        )
+        .subcommand(SubCommand::with_name("fetch")
+            .about("writes every pose of the given names, seeking through an index instead of scanning inputs")
+            .arg(
+                Arg::with_name("names")
+                    .value_name("NAME")
+                    .help("names of the molecules to fetch")
+                    .takes_value(true)
+                    .multiple(true)
+                    .required_unless("name_list")
+                )
+            .arg(
+                Arg::with_name("name_list")
+                    .short("q")
+                    .long("query")
+                    .value_name("<names>.txt")
+                    .help("file with one name to fetch per line")
+                    .takes_value(true)
+                )
+            .arg(
+                Arg::with_name("index")
+                    .short("x")
+                    .long("index")
+                    .help("index built by the index subcommand")
+                    .takes_value(true)
+                    .default_value("mol2grep.idx")
+                )
+            .arg(
+                Arg::with_name("output")
+                    .short("o")
+                    .long("output")
+                    .help("filename to write molecules to (-: print to the standard output)")
+                    .takes_value(true)
+                    .default_value("-")
+                )
+        )
+        .subcommand(SubCommand::with_name("top")
+            .about("writes the best poses of inputs streamed through a bounded heap, or of an index reading only the blocks holding them")
+            .args(&input_args_unless(&["input_files", "index"]))
+            .arg(
+                Arg::with_name("index")
+                    .short("x")
+                    .long("index")
+                    .help("index built by the index subcommand (read when no inputs are given)")
+                    .takes_value(true)
+                    .default_value("mol2grep.idx")
+                )
+            .arg(
+                Arg::with_name("top")
+                    .short("n")
+                    .long("top")
+                    .value_name("N")
+                    .help("Write the N best poses")
+                    .takes_value(true)
+                    .required_unless("max_energy")
+                )
+            .arg(
+                Arg::with_name("max_energy")
+                    .long("max-energy")
+                    .value_name("ENERGY")
+                    .help("Write every pose with an energy of at most ENERGY")
+                    .takes_value(true)
+                    .allow_hyphen_values(true)
+                    .required(false)
+                )
+            .arg(
+                Arg::with_name("output")
+                    .short("o")
+                    .long("output")
+                    .help("mol2.gz filename to write the selected poses to")
+                    .takes_value(true)
+                    .default_value("top.mol2.gz")
+                )
+            .arg(
+                Arg::with_name("num_threads")
+                    .short("t")
+                    .long("threads")
+                    .help("Number of threads to use in parallel processing (0: one per core)")
+                    .takes_value(true)
+                    .required(false)
+                    .default_value("0")
+                )
+        )
+        .subcommand(SubCommand::with_name("merge")
+            .alias("cat")
+            .about("combines mol2 files into a single file, recompressed with the output codec (alias: cat)")
+            .args(&input_args())
+            .arg(
+                Arg::with_name("sorted")
+                    .long("sorted")
+                    .help("Inputs are sorted by energy: stream a k-way merge so the output is sorted too")
+                    .takes_value(false)
+                )
+            .arg(
+                Arg::with_name("group_output")
+                    .long("group-output")
+                    .help("Write all poses of the same ligand adjacently (buffers molecules in memory)")
+                    .takes_value(false)
+                )
+            .arg(
+                Arg::with_name("output")
+                    .short("o")
+                    .long("output")
+                    .help("mol2.gz filename to write merged molecules to")
+                    .takes_value(true)
+                    .default_value("merge.mol2.gz")
+                )
+        )
+        .subcommand(SubCommand::with_name("head")
+            .about("writes the first molecules of the inputs, stopping early")
+            .args(&input_args())
+            .arg(
+                Arg::with_name("num_molecules")
+                    .short("n")
+                    .long("num-molecules")
+                    .value_name("N")
+                    .help("Number of molecules to write")
+                    .takes_value(true)
+                    .default_value("10")
+                )
+            .arg(
+                Arg::with_name("output")
+                    .short("o")
+                    .long("output")
+                    .help("filename to write molecules to (-: print to the standard output)")
+                    .takes_value(true)
+                    .default_value("-")
+                )
+        )
+        .subcommand(SubCommand::with_name("tail")
+            .about("writes the last molecules of the inputs")
+            .args(&input_args())
+            .arg(
+                Arg::with_name("num_molecules")
+                    .short("n")
+                    .long("num-molecules")
+                    .value_name("N")
+                    .help("Number of molecules to write")
+                    .takes_value(true)
+                    .default_value("10")
+                )
+            .arg(
+                Arg::with_name("output")
+                    .short("o")
+                    .long("output")
+                    .help("filename to write molecules to (-: print to the standard output)")
+                    .takes_value(true)
+                    .default_value("-")
+                )
+        )
+        .subcommand(SubCommand::with_name("slice")
+            .about("writes the molecules at a range or list of 0-based positions across the inputs")
+            .args(&input_args())
+            .arg(
+                Arg::with_name("start")
+                    .long("start")
+                    .value_name("N")
+                    .help("Position of the first molecule written")
+                    .takes_value(true)
+                    .default_value("0")
+                )
+            .arg(
+                Arg::with_name("end")
+                    .long("end")
+                    .value_name("M")
+                    .help("Position past the last molecule written (default: through the end of the inputs)")
+                    .takes_value(true)
+                    .required(false)
+                )
+            .arg(
+                Arg::with_name("ordinals")
+                    .long("ordinals")
+                    .value_name("N1,N2,...")
+                    .help("Comma separated positions of the molecules written, instead of a range")
+                    .takes_value(true)
+                    .conflicts_with_all(&["end", "ordinals_file"])
+                )
+            .arg(
+                Arg::with_name("ordinals_file")
+                    .long("ordinals-file")
+                    .value_name("<positions>.txt")
+                    .help("File with one position per line of the molecules written, instead of a range")
+                    .takes_value(true)
+                    .conflicts_with("end")
+                )
+            .arg(
+                Arg::with_name("output")
+                    .short("o")
+                    .long("output")
+                    .help("filename to write molecules to (-: print to the standard output)")
+                    .takes_value(true)
+                    .default_value("slice.mol2.gz")
+                )
+        )
+        .subcommand(SubCommand::with_name("sample")
+            .about("writes a reproducible uniform random subset of poses (reservoir sampling)")
+            .args(&input_args())
+            .arg(
+                Arg::with_name("num_molecules")
+                    .short("n")
+                    .long("num-molecules")
+                    .value_name("N")
+                    .help("Number of poses to sample")
+                    .takes_value(true)
+                    .required(true)
+                )
+            .arg(
+                Arg::with_name("seed")
+                    .long("seed")
+                    .help("Seed of the random subset")
+                    .takes_value(true)
+                    .default_value("0")
+                )
+            .arg(
+                Arg::with_name("output")
+                    .short("o")
+                    .long("output")
+                    .help("mol2.gz filename to write sampled poses to")
+                    .takes_value(true)
+                    .default_value("sample.mol2.gz")
+                )
+            .arg(
+                Arg::with_name("num_threads")
+                    .short("t")
+                    .long("threads")
+                    .help("Number of threads to use in parallel processing (0: one per core)")
+                    .takes_value(true)
+                    .required(false)
+                    .default_value("0")
+                )
+        )
+        .subcommand(SubCommand::with_name("shuffle")
+            .about("writes every pose in a reproducible random order, spilling to temporary shards beyond memory")
+            .args(&input_args())
+            .arg(
+                Arg::with_name("seed")
+                    .long("seed")
+                    .help("Seed of the random order")
+                    .takes_value(true)
+                    .default_value("0")
+                )
+            .arg(
+                Arg::with_name("buffer_size")
+                    .long("buffer-size")
+                    .value_name("MB")
+                    .help("Megabytes of molecules held in memory before spilling to temporary shards")
+                    .takes_value(true)
+                    .default_value("1024")
+                )
+            .arg(
+                Arg::with_name("tmp_dir")
+                    .long("tmp-dir")
+                    .value_name("DIR")
+                    .help("Directory temporary shards are written to (default: the system temporary directory)")
+                    .takes_value(true)
+                )
+            .arg(
+                Arg::with_name("output")
+                    .short("o")
+                    .long("output")
+                    .help("mol2.gz filename to write shuffled poses to")
+                    .takes_value(true)
+                    .default_value("shuffle.mol2.gz")
+                )
+        )
+        .subcommand(SubCommand::with_name("interleave")
+            .about("merges mol2 files round-robin, one molecule of every input in turn")
+            .args(&input_args())
+            .arg(
+                Arg::with_name("output")
+                    .short("o")
+                    .long("output")
+                    .help("mol2.gz filename to write interleaved molecules to")
+                    .takes_value(true)
+                    .default_value("interleave.mol2.gz")
+                )
+        )
+        .subcommand(SubCommand::with_name("dedup")
+            .about("keeps a single pose per name: the lowest energy one or the first seen")
+            .args(&input_args())
+            .arg(
+                Arg::with_name("output")
+                    .short("o")
+                    .long("output")
+                    .help("mol2.gz filename to write one pose per name to")
+                    .takes_value(true)
+                    .default_value("dedup.mol2.gz")
+                )
+            .arg(
+                Arg::with_name("keep_first")
+                    .long("keep-first")
+                    .help("Keep the first pose of every name in input order (single streaming pass) instead of the lowest energy one")
+                    .takes_value(false)
+                )
+            .arg(
+                Arg::with_name("num_threads")
+                    .short("t")
+                    .long("threads")
+                    .help("Number of threads to use in parallel processing (0: one per core)")
+                    .takes_value(true)
+                    .required(false)
+                    .default_value("0")
+                )
+        )
+        .subcommand(SubCommand::with_name("sort")
+            .about("writes every pose ordered by energy, spilling sorted runs to disk for large inputs")
+            .args(&input_args())
+            .arg(
+                Arg::with_name("output")
+                    .short("o")
+                    .long("output")
+                    .help("mol2.gz filename to write sorted molecules to")
+                    .takes_value(true)
+                    .default_value("sort.mol2.gz")
+                )
+            .arg(
+                Arg::with_name("descending")
+                    .long("descending")
+                    .help("Write the highest energies first")
+                    .takes_value(false)
+                )
+            .arg(
+                Arg::with_name("buffer_size")
+                    .long("buffer-size")
+                    .value_name("MB")
+                    .help("Molecule text held in memory before a sorted run is written to disk")
+                    .takes_value(true)
+                    .default_value("1024")
+                )
+            .arg(
+                Arg::with_name("tmp_dir")
+                    .long("tmp-dir")
+                    .value_name("DIR")
+                    .help("Directory sorted runs are written to (default: the system temporary directory)")
+                    .takes_value(true)
+                    .required(false)
+                )
+        )
+        .subcommand(SubCommand::with_name("reassemble")
+            .about("concatenates numbered split or tranche files in numeric order, checking none are missing")
+            .args(&input_args())
+            .arg(
+                Arg::with_name("output")
+                    .short("o")
+                    .long("output")
+                    .help("mol2.gz filename to write reassembled molecules to")
+                    .takes_value(true)
+                    .default_value("reassemble.mol2.gz")
+                )
+            .arg(
+                Arg::with_name("report")
+                    .short("r")
+                    .long("report")
+                    .value_name("boundaries.tsv")
+                    .help("tab-separated filename to write the first molecule and molecule count of every input to")
+                    .takes_value(true)
+                    .required(false)
+                )
+            .arg(
+                Arg::with_name("allow_gaps")
+                    .long("allow-gaps")
+                    .help("Warn instead of failing when numbers are missing between the inputs")
+                    .takes_value(false)
+                )
+        )
+        .subcommand(SubCommand::with_name("diff")
+            .about("compares two pose collections by name: names only in A, only in B, and with changed best energies")
+            .arg(
+                Arg::with_name("input_a")
+                    .short("a")
+                    .long("a")
+                    .value_name("*.mol2.gz")
+                    .help("mol2 files of the first collection")
+                    .takes_value(true)
+                    .min_values(1)
+                    .required(true)
+                )
+            .arg(
+                Arg::with_name("input_b")
+                    .short("b")
+                    .long("b")
+                    .value_name("*.mol2.gz")
+                    .help("mol2 files of the second collection")
+                    .takes_value(true)
+                    .min_values(1)
+                    .required(true)
+                )
+            .arg(
+                Arg::with_name("tolerance")
+                    .short("e")
+                    .long("tol")
+                    .help("Report shared names whose best energies differ by more than this (default: compare names only)")
+                    .takes_value(true)
+                    .required(false)
+                )
+            .arg(
+                Arg::with_name("output")
+                    .short("o")
+                    .long("output")
+                    .help("tab-separated filename to write differences to")
+                    .takes_value(true)
+                    .default_value("diff.tsv")
+                )
+            .arg(
+                Arg::with_name("num_threads")
+                    .short("t")
+                    .long("threads")
+                    .help("Number of threads to use in parallel processing (0: one per core)")
+                    .takes_value(true)
+                    .required(false)
+                    .default_value("0")
+                )
+        )
+        .subcommand(SubCommand::with_name("set")
+            .about("name-based set algebra on pose collections, writing whole pose records")
+            .arg(
+                Arg::with_name("op")
+                    .long("op")
+                    .value_name("intersect|union|subtract")
+                    .help("intersect: poses of A named in B, union: A then poses of B not named in A, subtract: poses of A not named in B")
+                    .takes_value(true)
+                    .possible_values(&["intersect", "union", "subtract"])
+                    .required(true)
+                )
+            .arg(
+                Arg::with_name("input_a")
+                    .short("a")
+                    .long("a")
+                    .value_name("*.mol2.gz")
+                    .help("mol2 files of the first collection")
+                    .takes_value(true)
+                    .min_values(1)
+                    .required(true)
+                )
+            .arg(
+                Arg::with_name("input_b")
+                    .short("b")
+                    .long("b")
+                    .value_name("*.mol2.gz")
+                    .help("mol2 files of the second collection")
+                    .takes_value(true)
+                    .min_values(1)
+                    .required(true)
+                )
+            .arg(
+                Arg::with_name("output")
+                    .short("o")
+                    .long("output")
+                    .help("mol2.gz filename to write the resulting poses to")
+                    .takes_value(true)
+                    .default_value("set.mol2.gz")
+                )
+            .arg(
+                Arg::with_name("num_threads")
+                    .short("t")
+                    .long("threads")
+                    .help("Number of threads to use in parallel processing (0: one per core)")
+                    .takes_value(true)
+                    .required(false)
+                    .default_value("0")
+                )
+        )
+        .subcommand(SubCommand::with_name("reorder")
+            .about("writes the molecules of a list of names in the order of the list")
+            .args(&input_args())
+            .arg(
+                Arg::with_name("list")
+                    .short("l")
+                    .long("list")
+                    .value_name("names.txt")
+                    .help("Ordered list of names (first column of each line)")
+                    .takes_value(true)
+                    .required(true)
+                )
+            .arg(
+                Arg::with_name("output")
+                    .short("o")
+                    .long("output")
+                    .help("mol2.gz filename to write reordered molecules to")
+                    .takes_value(true)
+                    .default_value("reorder.mol2.gz")
+                )
+            .arg(
+                Arg::with_name("num_threads")
+                    .short("t")
+                    .long("threads")
+                    .help("Number of threads to use in parallel processing (0: one per core)")
+                    .takes_value(true)
+                    .required(false)
+                    .default_value("0")
+                )
+        )
+        .subcommand(SubCommand::with_name("decoys")
+            .about("selects library molecules matching the net charge, weight, and size of known actives")
+            .args(&input_args())
+            .arg(
+                Arg::with_name("actives")
+                    .short("a")
+                    .long("actives")
+                    .value_name("actives.mol2.gz|names.txt")
+                    .help("Actives as poses (mol2.gz) or as a list of names found in the library")
+                    .takes_value(true)
+                    .required(true)
+                )
+            .arg(
+                Arg::with_name("per_active")
+                    .short("n")
+                    .long("per-active")
+                    .help("Number of decoys to select per active")
+                    .takes_value(true)
+                    .default_value("50")
+                )
+            .arg(
+                Arg::with_name("output")
+                    .short("o")
+                    .long("output")
+                    .help("mol2.gz filename to write decoy poses to")
+                    .takes_value(true)
+                    .default_value("decoys.mol2.gz")
+                )
+            .arg(
+                Arg::with_name("pairs")
+                    .long("pairs")
+                    .help("tab-separated filename to write active-decoy pairs to")
+                    .takes_value(true)
+                    .default_value("decoys.tsv")
+                )
+            .arg(
+                Arg::with_name("num_threads")
+                    .short("t")
+                    .long("threads")
+                    .help("Number of threads to use in parallel processing (0: one per core)")
+                    .takes_value(true)
+                    .required(false)
+                    .default_value("0")
+                )
+        )
+        .subcommand(SubCommand::with_name("enrichment")
+            .about("computes ROC AUC, logAUC, and enrichment factors of known actives")
+            .args(&input_args_unless(&["input_files", "scores"]))
+            .args(&plot_args())
+            .arg(
+                Arg::with_name("scores")
+                    .short("s")
+                    .long("scores")
+                    .value_name("name,score.tsv")
+                    .help("Table of names and scores to rank instead of the energies of mol2 inputs")
+                    .takes_value(true)
+                    .conflicts_with_all(&["mol2", "input_files"])
+                )
+            .arg(
+                Arg::with_name("actives")
+                    .short("a")
+                    .long("actives")
+                    .value_name("names.txt")
+                    .help("List of names of known actives (all other scored names are decoys)")
+                    .takes_value(true)
+                    .required(true)
+                )
+            .arg(
+                Arg::with_name("ef")
+                    .long("ef")
+                    .value_name("PERCENTS")
+                    .help("Comma separated percentages of the ranking to compute enrichment factors at")
+                    .takes_value(true)
+                    .default_value("0.1,1,5")
+                )
+            .arg(
+                Arg::with_name("output")
+                    .short("o")
+                    .long("output")
+                    .help("tab-separated filename to write summary metrics to")
+                    .takes_value(true)
+                    .default_value("enrichment.tsv")
+                )
+            .arg(
+                Arg::with_name("roc")
+                    .long("roc")
+                    .help("tab-separated filename to write ROC curve points to")
+                    .takes_value(true)
+                    .default_value("roc.tsv")
+                )
+            .arg(
+                Arg::with_name("num_threads")
+                    .short("t")
+                    .long("threads")
+                    .help("Number of threads to use in parallel processing (0: one per core)")
+                    .takes_value(true)
+                    .required(false)
+                    .default_value("0")
+                )
+        )
+        .subcommand(SubCommand::with_name("info")
+            .about("prints molecule count, sizes, energy range, and first and last names of every input")
+            .args(&input_args())
+            .arg(
+                Arg::with_name("output")
+                    .short("o")
+                    .long("output")
+                    .help("tab-separated filename to also write the summaries to")
+                    .takes_value(true)
+                    .required(false)
+                )
+            .arg(
+                Arg::with_name("num_threads")
+                    .short("t")
+                    .long("threads")
+                    .help("Number of threads to use in parallel processing (0: one per core)")
+                    .takes_value(true)
+                    .required(false)
+                    .default_value("0")
+                )
+        )
+        .subcommand(SubCommand::with_name("count")
+            .about("counts molecules and lists header fields of every input, cached between runs")
+            .args(&input_args())
+            .arg(
+                Arg::with_name("output")
+                    .short("o")
+                    .long("output")
+                    .help("tab-separated filename to write per-file counts to")
+                    .takes_value(true)
+                    .default_value("count.tsv")
+                )
+            .arg(
+                Arg::with_name("cache")
+                    .long("cache")
+                    .value_name("files.tsv")
+                    .help("Cache of per-file summaries keyed by path, size, and modification time (default: $MOL2GREP_CACHE or ~/.cache/mol2grep/files.tsv)")
+                    .takes_value(true)
+                    .required(false)
+                )
+            .arg(
+                Arg::with_name("no_cache")
+                    .long("no-cache")
+                    .help("Read every input without consulting or updating the cache")
+                    .takes_value(false)
+                    .conflicts_with("cache")
+                )
+            .arg(
+                Arg::with_name("num_threads")
+                    .short("t")
+                    .long("threads")
+                    .help("Number of threads to use in parallel processing (0: one per core)")
+                    .takes_value(true)
+                    .required(false)
+                    .default_value("0")
+                )
+        )
+        .subcommand(SubCommand::with_name("zinc")
+            .about("validates ZINC names and tabulates or splits molecules by generation or tranche")
+            .args(&input_args())
+            .arg(
+                Arg::with_name("output")
+                    .short("o")
+                    .long("output")
+                    .help("tab-separated filename to write counts per component value to")
+                    .takes_value(true)
+                    .default_value("zinc.tsv")
+                )
+            .arg(
+                Arg::with_name("by")
+                    .long("by")
+                    .help("Identifier component to group by (ZINC12: 8 digits, ZINC15/20: 12 digits, ZINC22: tranche + 10 base62 characters)")
+                    .takes_value(true)
+                    .possible_values(&["generation", "tranche", "heavy-atoms"])
+                    .default_value("generation")
+                )
+            .arg(
+                Arg::with_name("split_prefix")
+                    .short("p")
+                    .long("split-prefix")
+                    .value_name("PREFIX")
+                    .help("Also write the molecules of every group to PREFIX.<group>.mol2.gz")
+                    .takes_value(true)
+                    .required(false)
+                )
+            .arg(
+                Arg::with_name("num_threads")
+                    .short("t")
+                    .long("threads")
+                    .help("Number of threads to use in parallel processing (0: one per core)")
+                    .takes_value(true)
+                    .required(false)
+                    .default_value("0")
+                )
+        )
+        .subcommand(SubCommand::with_name("stats")
+            .about("summarizes pose energies (count, range, mean, std, median, percentiles) of the whole run or of every ligand")
+            .args(&input_args())
+            .arg(
+                Arg::with_name("per_ligand")
+                    .long("per-ligand")
+                    .help("Write one row per name: pose count, best, worst, mean, std, and spread of energies")
+                    .takes_value(false)
+                )
+            .arg(
+                Arg::with_name("percentiles")
+                    .long("percentiles")
+                    .value_name("P1,P2,...")
+                    .help("Comma separated energy percentiles reported besides the median (whole run only)")
+                    .takes_value(true)
+                    .default_value("1,5,25,75,95,99")
+                )
+            .arg(
+                Arg::with_name("output")
+                    .short("o")
+                    .long("output")
+                    .help("tab-separated filename to write statistics to")
+                    .takes_value(true)
+                    .default_value("stats.tsv")
+                )
+            .arg(
+                Arg::with_name("num_threads")
+                    .short("t")
+                    .long("threads")
+                    .help("Number of threads to use in parallel processing (0: one per core)")
+                    .takes_value(true)
+                    .required(false)
+                    .default_value("0")
+                )
+        )
+        .subcommand(SubCommand::with_name("transform")
+            .about("rescales the Total Energy of every molecule: e' = scale * e + offset")
+            .args(&input_args())
+            .arg(
+                Arg::with_name("output")
+                    .short("o")
+                    .long("output")
+                    .help("mol2 formatted filename to write transformed molecules to")
+                    .takes_value(true)
+                    .default_value("transform.mol2.gz")
+                )
+            .arg(
+                Arg::with_name("scale_energy")
+                    .short("k")
+                    .long("scale-energy")
+                    .help("Multiplicative factor applied to energies")
+                    .takes_value(true)
+                    .allow_hyphen_values(true)
+                    .default_value("1.0")
+                )
+            .arg(
+                Arg::with_name("offset")
+                    .short("b")
+                    .long("offset")
+                    .help("Additive offset applied to energies after scaling")
+                    .takes_value(true)
+                    .allow_hyphen_values(true)
+                    .default_value("0.0")
+                )
+            .arg(
+                Arg::with_name("zscore")
+                    .short("z")
+                    .long("zscore")
+                    .help("Standardize energies across the run before scaling (requires two passes)")
+                    .takes_value(false)
+                )
+        )
+        .setting(AppSettings::SubcommandRequiredElseHelp);
+
+    #[cfg(feature = "plot")]
+    let app = app.subcommand(SubCommand::with_name("plot")
+        .about("renders the energy histogram and per-file energy boxes to SVG")
+        .args(&input_args())
+        .arg(
+            Arg::with_name("histogram")
+                .long("histogram")
+                .help("SVG filename to draw the energy histogram to")
+                .takes_value(true)
+                .default_value("energy_histogram.svg")
+            )
+        .arg(
+            Arg::with_name("boxes")
+                .long("boxes")
+                .help("SVG filename to draw per-file energy boxes to")
+                .takes_value(true)
+                .default_value("energy_boxes.svg")
+            )
+        .arg(
+            Arg::with_name("bins")
+                .long("bins")
+                .help("Number of histogram bins")
+                .takes_value(true)
+                .default_value("50")
+            )
+        .arg(
+            Arg::with_name("num_threads")
+                .short("t")
+                .long("threads")
+                .help("Number of threads to use in parallel processing (0: one per core)")
+                .takes_value(true)
+                .required(false)
+                .default_value("0")
+            )
+    );
+
+    app
+}
+
+
+fn main() {
+
+    // Match Arguments
+    let app = build_cli();
+    let matches = app.get_matches();
+
+    file_io::set_io_threads(
+        matches.value_of("io_threads")
+            .unwrap()
+            .parse::<usize>()
+            .expect("Malformed input: io-threads")
+    );
+
+    if let Some(x) = matches.value_of("compression_level") {
+        file_io::set_compression_level(
+            x.parse::<i32>().expect("Malformed input: compression-level")
+        );
+    }
+
+    file_io::set_bgzf(matches.is_present("bgzf"));
+    provenance::set_hash_inputs(matches.is_present("hash_inputs"));
+
+    if let Some(x) = matches.value_of("max_read_mbps") {
+        file_io::set_max_read_mbps(
+            x.parse::<f64>().expect("Malformed input: max-read-mbps")
+        );
+    }
+
+    if matches.is_present("low_io_priority") && !file_io::lower_io_priority() {
+        eprintln!("Warning: Failed to lower the IO priority on this platform");
+    }
+
+    let scheme = matches.value_of("fallback_names").unwrap();
+    mol2::set_reader_config(ReaderConfig {
+        name_scheme: NameScheme::from_name(scheme).unwrap(),
+        strict: matches.is_present("strict")
+    });
+
+    match matches.subcommand() {
+        ("grep", grep_matches) => {
+            subcommand_grep(grep_matches.unwrap())
+                .expect("Error: Failed to grep")
+        },
+        ("split", split_matches) => {
+            subcommand_split(split_matches.unwrap())
+                .expect("Error: Failed to split")
+        }
+        ("table", table_matches) => {
+            subcommand_table(table_matches.unwrap())
+                .expect("Error: Failed to build table")
+        }
+        ("filter", filter_matches) => {
+            subcommand_filter(filter_matches.unwrap())
+                .expect("Error: Failed to filter")
+        }
+        ("duplicates", duplicates_matches) => {
+            subcommand_duplicates(duplicates_matches.unwrap())
+                .expect("Error: Failed to report duplicates")
+        }
+        ("atoms", atoms_matches) => {
+            subcommand_atoms(atoms_matches.unwrap())
+                .expect("Error: Failed to count atoms")
+        }
+        ("charges", charges_matches) => {
+            subcommand_charges(charges_matches.unwrap())
+                .expect("Error: Failed to compute charge statistics")
+        }
+        ("diff", diff_matches) => {
+            subcommand_diff(diff_matches.unwrap())
+                .expect("Error: Failed to compare collections")
+        }
+        ("set", set_matches) => {
+            subcommand_set(set_matches.unwrap())
+                .expect("Error: Failed to combine collections")
+        }
+        ("reorder", reorder_matches) => {
+            subcommand_reorder(reorder_matches.unwrap())
+                .expect("Error: Failed to reorder")
+        }
+        ("decoys", decoys_matches) => {
+            subcommand_decoys(decoys_matches.unwrap())
+                .expect("Error: Failed to select decoys")
+        }
+        ("enrichment", enrichment_matches) => {
+            subcommand_enrichment(enrichment_matches.unwrap())
+                .expect("Error: Failed to compute enrichment")
+        }
+        #[cfg(feature = "plot")]
+        ("plot", plot_matches) => {
+            subcommand_plot(plot_matches.unwrap())
+                .expect("Error: Failed to plot")
+        }
+        ("info", info_matches) => {
+            subcommand_info(info_matches.unwrap())
+                .expect("Error: Failed to summarize inputs")
+        }
+        ("count", count_matches) => {
+            subcommand_count(count_matches.unwrap())
+                .expect("Error: Failed to count molecules")
+        }
+        ("zinc", zinc_matches) => {
+            subcommand_zinc(zinc_matches.unwrap())
+                .expect("Error: Failed to tabulate ZINC names")
+        }
+        ("stats", stats_matches) => {
+            subcommand_stats(stats_matches.unwrap())
+                .expect("Error: Failed to compute statistics")
+        }
+        ("head", head_matches) => {
+            subcommand_head_tail(head_matches.unwrap(), "head")
+                .expect("Error: Failed to write first molecules")
+        }
+        ("tail", tail_matches) => {
+            subcommand_head_tail(tail_matches.unwrap(), "tail")
+                .expect("Error: Failed to write last molecules")
+        }
+        ("slice", slice_matches) => {
+            subcommand_slice(slice_matches.unwrap())
+                .expect("Error: Failed to slice molecules")
+        }
+        ("sample", sample_matches) => {
+            subcommand_sample(sample_matches.unwrap())
+                .expect("Error: Failed to sample molecules")
+        }
+        ("shuffle", shuffle_matches) => {
+            subcommand_shuffle(shuffle_matches.unwrap())
+                .expect("Error: Failed to shuffle molecules")
+        }
+        ("interleave", interleave_matches) => {
+            subcommand_interleave(interleave_matches.unwrap())
+                .expect("Error: Failed to interleave")
+        }
+        ("dedup", dedup_matches) => {
+            subcommand_dedup(dedup_matches.unwrap())
+                .expect("Error: Failed to deduplicate")
+        }
+        ("sort", sort_matches) => {
+            subcommand_sort(sort_matches.unwrap())
+                .expect("Error: Failed to sort")
+        }
+        ("reassemble", reassemble_matches) => {
+            subcommand_reassemble(reassemble_matches.unwrap())
+                .expect("Error: Failed to reassemble")
+        }
+        ("index", index_matches) => {
+            subcommand_index(index_matches.unwrap())
+                .expect("Error: Failed to build index")
+        }
+        ("fetch", fetch_matches) => {
+            subcommand_fetch(fetch_matches.unwrap())
+                .expect("Error: Failed to fetch molecules")
+        }
+        ("top", top_matches) => {
+            subcommand_top(top_matches.unwrap())
+                .expect("Error: Failed to read index")
+        }
+        ("merge", merge_matches) => {
+            subcommand_merge(merge_matches.unwrap())
+                .expect("Error: Failed to merge")
+        }
+        ("transform", transform_matches) => {
+            subcommand_transform(transform_matches.unwrap())
+                .expect("Error: Failed to transform")
+        }
+        _ => unreachable!()
+    };
 
+    // strict readers stop on a read error, which fails the whole run
+    if let Some(e) = mol2::read_failure() {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
 }
@@ -0,0 +1,73 @@
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// Returns the peak resident set size of the process in bytes
+// (read from /proc, so only available on Linux)
+pub fn peak_rss_bytes() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    status
+        .lines()
+        .find_map(|x| x.strip_prefix("VmHWM:"))
+        .and_then(|x| x.trim().strip_suffix("kB"))
+        .and_then(|x| x.trim().parse::<u64>().ok())
+        .map(|x| x * 1024)
+}
+
+// Formats a number of bytes for summaries (e.g. `12.3 MB`)
+pub fn format_bytes(bytes: u64) -> String {
+    let units = ["B", "KB", "MB", "GB", "TB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < units.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, units[unit])
+    }
+}
+
+// Struct tracking the items and bytes waiting in a channel and their high-water marks
+#[derive(Default)]
+pub struct QueueGauge {
+    len: AtomicU64,
+    bytes: AtomicU64,
+    max_len: AtomicU64,
+    max_bytes: AtomicU64
+}
+impl QueueGauge {
+
+    // Records an item of `bytes` entering the channel
+    pub fn push(&self, bytes: u64) {
+        let len = self.len.fetch_add(1, Ordering::Relaxed) + 1;
+        let total = self.bytes.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        self.max_len.fetch_max(len, Ordering::Relaxed);
+        self.max_bytes.fetch_max(total, Ordering::Relaxed);
+    }
+
+    // Records an item of `bytes` leaving the channel
+    pub fn pop(&self, bytes: u64) {
+        self.len.fetch_sub(1, Ordering::Relaxed);
+        self.bytes.fetch_sub(bytes, Ordering::Relaxed);
+    }
+
+    // Returns the largest number of items and bytes waiting at once
+    pub fn high_water(&self) -> (u64, u64) {
+        (self.max_len.load(Ordering::Relaxed), self.max_bytes.load(Ordering::Relaxed))
+    }
+}
+
+// Prints the memory statistics of a run, one line per stage
+pub fn print_memory_stats(stages: &[(&str, u64)], queue: &QueueGauge) {
+    match peak_rss_bytes() {
+        Some(x) => println!(">>> Peak Resident Memory: {}", format_bytes(x)),
+        None => println!(">>> Peak Resident Memory: unavailable")
+    }
+    for (stage, bytes) in stages {
+        println!(">>> {}: {}", stage, format_bytes(*bytes));
+    }
+    let (len, bytes) = queue.high_water();
+    println!(">>> Writer Channel High-Water Mark: {} molecules ({})", len, format_bytes(bytes));
+}
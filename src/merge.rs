@@ -0,0 +1,297 @@
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::fs::File;
+use std::io::{BufWriter, Error, ErrorKind};
+use std::io::prelude::*;
+use std::path::Path;
+
+use crate::mol2::{Mol2, Mol2Reader};
+use crate::mol2utils::{NameGroups, stream_tagged_molecules};
+use crate::file_io::writer;
+use crate::provenance::Provenance;
+
+use indicatif::ProgressBar;
+
+// Struct describing the current head molecule of one sorted input
+struct Head {
+    mol: Mol2,
+    file_index: usize,
+    descending: bool
+}
+impl Ord for Head {
+    // reversed so the max-heap pops the lowest energy (highest if descending,
+    // earliest file on ties) first
+    fn cmp(&self, other: &Self) -> Ordering {
+        let energy = match self.descending {
+            true => self.mol.get_energy().total_cmp(&other.mol.get_energy()),
+            false => other.mol.get_energy().total_cmp(&self.mol.get_energy())
+        };
+        energy.then(other.file_index.cmp(&self.file_index))
+    }
+}
+impl PartialOrd for Head {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl PartialEq for Head {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+impl Eq for Head {}
+
+// Streams the molecules of inputs sorted by energy in global energy order
+// returns the number of molecules written and the number found out of order within their input
+pub fn kway_merge(
+        input_files: &[String],
+        writer: &mut dyn Write,
+        descending: bool) -> Result<(u32, u32), Error> {
+    kway_merge_each(input_files, descending, |mol| writer.write_all(mol.get_lines().as_bytes()))
+}
+
+// Passes the molecules of inputs sorted by energy to `emit` in global energy order
+// returns the number of molecules emitted and the number found out of order within their input
+fn kway_merge_each<F: FnMut(Mol2) -> Result<(), Error>>(
+        input_files: &[String],
+        descending: bool,
+        mut emit: F) -> Result<(u32, u32), Error> {
+    let mut readers = input_files
+        .iter()
+        .map(|x| Mol2Reader::new(x))
+        .collect::<Result<Vec<Mol2Reader>, Error>>()?;
+
+    let mut heap = BinaryHeap::with_capacity(readers.len());
+    for (file_index, reader) in readers.iter_mut().enumerate() {
+        if let Some(mol) = reader.next() {
+            heap.push(Head { mol, file_index, descending });
+        }
+    }
+
+    let mut num_written = 0;
+    let mut num_unsorted = 0;
+    while let Some(Head { mol, file_index, .. }) = heap.pop() {
+        let energy = mol.get_energy();
+        emit(mol)?;
+        num_written += 1;
+
+        // refill from the input the molecule came from
+        if let Some(next) = readers[file_index].next() {
+            let unsorted = match descending {
+                true => next.get_energy() > energy,
+                false => next.get_energy() < energy
+            };
+            if unsorted {
+                num_unsorted += 1;
+            }
+            heap.push(Head { mol: next, file_index, descending });
+        }
+    }
+
+    Ok((num_written, num_unsorted))
+}
+
+// implements merge subcommand: concatenates the inputs into a single file
+// returns the number of molecules written
+//
+// With `sorted`, every input is expected to be sorted by ascending energy and
+// the inputs are streamed through a k-way merge so the output is sorted as well,
+// holding a single molecule per input in memory.
+//
+// With `group_output`, molecules are buffered by name and the poses of each name
+// are written adjacently once every input is read (names in first-seen order, so
+// by best pose when sorted).
+pub fn merge(
+        input_files: Vec<String>,
+        output_filename: &str,
+        sorted: bool,
+        group_output: bool) -> Result<u32, Error> {
+
+    let provenance = Provenance::new(&input_files)?;
+    let mut writer = writer(output_filename)?;
+    provenance.write(&mut writer)?;
+    let num_files = input_files.len();
+
+    let mut groups = group_output.then(NameGroups::default);
+    let mut emit = |mol: Mol2| -> Result<(), Error> {
+        match groups.as_mut() {
+            Some(g) => g.push(mol),
+            None => writer.write_all(mol.get_lines().as_bytes())?
+        }
+        Ok(())
+    };
+
+    let num_written = if sorted {
+        let (num_written, num_unsorted) = kway_merge_each(&input_files, false, &mut emit)?;
+        if num_unsorted > 0 {
+            eprintln!(
+                "Warning: {} molecules were out of energy order within their input; output is not globally sorted",
+                num_unsorted
+            );
+        }
+        num_written
+    } else {
+        // progress advances as the stream moves on to the next input
+        let progress = ProgressBar::new(num_files as u64);
+        let mut num_written = 0;
+        for (file_index, _, mol) in stream_tagged_molecules(input_files) {
+            progress.set_position(file_index as u64);
+            emit(mol)?;
+            num_written += 1;
+        }
+        progress.finish_and_clear();
+        num_written
+    };
+    if let Some(g) = groups {
+        for mol in g.into_molecules() {
+            writer.write_all(mol.get_lines().as_bytes())?;
+        }
+    }
+    writer.flush()?;
+
+    println!(">>> Number of Files Merged: {}", num_files);
+
+    println!(">>> Number of Molecules Written: {}", num_written);
+    println!(">>> Merged Output Written to: {}", output_filename);
+
+    Ok(num_written)
+}
+
+// implements interleave subcommand: writes one molecule of every input in turn
+// returns the number of molecules written
+//
+// Inputs that run out are dropped from the rotation, so the output ends with
+// the molecules of the longest inputs.
+pub fn interleave(
+        input_files: Vec<String>,
+        output_filename: &str) -> Result<u32, Error> {
+
+    let provenance = Provenance::new(&input_files)?;
+    let mut readers = input_files
+        .iter()
+        .map(|x| Mol2Reader::new(x))
+        .collect::<Result<Vec<Mol2Reader>, Error>>()?;
+
+    let mut writer = writer(output_filename)?;
+    provenance.write(&mut writer)?;
+    let mut num_written = 0;
+    while !readers.is_empty() {
+        readers.retain_mut(|reader| match reader.next() {
+            Some(mol) => {
+                writer
+                    .write_all(mol.get_lines().as_bytes())
+                    .expect("Error: Error writing to output file");
+                num_written += 1;
+                true
+            },
+            None => false
+        });
+    }
+    writer.flush()?;
+
+    println!(">>> Number of Files Interleaved: {}", input_files.len());
+    println!(">>> Number of Molecules Written: {}", num_written);
+
+    Ok(num_written)
+}
+
+// Returns the number a split or tranche file is named with: the last run of
+// digits of its name once the mol2 and compression suffixes are removed
+// (e.g. `split.0003.mol2.gz` -> 3, `chunk_12.mol2.gz` -> 12)
+pub fn file_number(filename: &str) -> Option<u64> {
+    let mut stem = Path::new(filename).file_name()?.to_str()?;
+    for suffix in [".gz", ".zst", ".mol2"] {
+        stem = stem.strip_suffix(suffix).unwrap_or(stem);
+    }
+    let end = stem.rfind(|c: char| c.is_ascii_digit())? + 1;
+    let start = stem[..end]
+        .rfind(|c: char| !c.is_ascii_digit())
+        .map_or(0, |x| x + 1);
+    stem[start..end].parse().ok()
+}
+
+// Returns the numbers missing between the first and last of a sorted list
+fn missing_numbers(numbers: &[u64]) -> Vec<u64> {
+    numbers
+        .windows(2)
+        .flat_map(|w| (w[0] + 1)..w[1])
+        .collect()
+}
+
+// implements reassemble subcommand: concatenates numbered files in natural numeric order
+// returns the number of molecules written
+//
+// Every input must carry a distinct number; missing numbers are an error
+// unless `allow_gaps` is set. The optional report lists where every input
+// starts in the reassembled output.
+pub fn reassemble(
+        input_files: Vec<String>,
+        output_filename: &str,
+        report_filename: Option<&str>,
+        allow_gaps: bool) -> Result<u32, Error> {
+
+    let mut numbered = input_files
+        .iter()
+        .map(|x| file_number(x)
+            .map(|n| (n, x.clone()))
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, format!("{} is not numbered", x))))
+        .collect::<Result<Vec<(u64, String)>, Error>>()?;
+    numbered.sort();
+
+    if let Some(w) = numbered.windows(2).find(|w| w[0].0 == w[1].0) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("{} and {} share number {}", w[0].1, w[1].1, w[0].0)
+        ))
+    }
+
+    let numbers: Vec<u64> = numbered.iter().map(|(n, _)| *n).collect();
+    let missing = missing_numbers(&numbers);
+    if !missing.is_empty() {
+        let listed: Vec<String> = missing.iter().take(10).map(|x| x.to_string()).collect();
+        let message = format!(
+            "{} numbered inputs are missing: {}{}",
+            missing.len(), listed.join(", "), if missing.len() > 10 { ", ..." } else { "" }
+        );
+        if !allow_gaps {
+            return Err(Error::new(ErrorKind::NotFound, message))
+        }
+        eprintln!("Warning: {}", message);
+    }
+
+    let ordered: Vec<String> = numbered.iter().map(|(_, x)| x.clone()).collect();
+    let provenance = Provenance::new(&ordered)?;
+    let mut writer = writer(output_filename)?;
+    provenance.write(&mut writer)?;
+
+    // first molecule and number of molecules of every input
+    let mut boundaries = Vec::with_capacity(ordered.len());
+    let mut num_written = 0;
+    for x in ordered.iter() {
+        let first = num_written;
+        for mol in Mol2Reader::new(x)? {
+            writer
+                .write_all(mol.get_lines().as_bytes())
+                .expect("Error: Error writing to output file");
+            num_written += 1;
+        }
+        boundaries.push((first, num_written - first));
+    }
+    writer.flush()?;
+
+    if let Some(report_filename) = report_filename {
+        let mut report = BufWriter::new(File::create(report_filename)?);
+        report.write_all(b"number\tfile\tfirst_molecule\tmolecules\n")?;
+        for ((n, x), (first, count)) in numbered.iter().zip(boundaries.iter()) {
+            writeln!(report, "{}\t{}\t{}\t{}", n, x, first, count)?;
+        }
+        println!(">>> Boundaries Written to: {}", report_filename);
+    }
+
+    println!(">>> Number of Files Reassembled: {} ({} missing)", ordered.len(), missing.len());
+    println!(">>> Number of Molecules Written: {}", num_written);
+    println!(">>> Reassembled Output Written to: {}", output_filename);
+
+    Ok(num_written)
+}
@@ -1,20 +1,153 @@
 
 use std::fmt;
+use std::collections::HashMap;
 use std::hash::{Hash, Hasher};
 
-use std::fs::File;
 use std::io::Error;
 use std::io::BufReader;
 use std::io::prelude::*;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
 
-use flate2::read::MultiGzDecoder;
 use regex::Regex;
 
+use crate::gzip;
+use crate::provenance::PROVENANCE_PREFIX;
+
+// Struct describing the unweighted centroid and radius of gyration of a pose
+#[derive(Clone, Debug)]
+pub struct Geometry {
+    pub centroid: (f64, f64, f64),
+    pub radius_of_gyration: f64
+}
+impl Geometry {
+
+    // Computes the geometry of a set of atoms
+    pub fn from_atoms(atoms: &[Atom]) -> Option<Self> {
+        if atoms.is_empty() {
+            return None
+        }
+        let n = atoms.len() as f64;
+        let centroid = (
+            atoms.iter().map(|a| a.x).sum::<f64>() / n,
+            atoms.iter().map(|a| a.y).sum::<f64>() / n,
+            atoms.iter().map(|a| a.z).sum::<f64>() / n
+        );
+        let msd = atoms
+            .iter()
+            .map(|a| {
+                (a.x - centroid.0).powi(2) + (a.y - centroid.1).powi(2) + (a.z - centroid.2).powi(2)
+            })
+            .sum::<f64>() / n;
+        Some(Geometry {
+            centroid,
+            radius_of_gyration: msd.sqrt()
+        })
+    }
+
+    // Returns the distance between the centroid and a point
+    pub fn distance_to(&self, point: (f64, f64, f64)) -> f64 {
+        ((self.centroid.0 - point.0).powi(2) +
+            (self.centroid.1 - point.1).powi(2) +
+            (self.centroid.2 - point.2).powi(2)).sqrt()
+    }
+}
+
+// Struct describing a single record of the @<TRIPOS>ATOM section
+#[derive(Clone, Debug)]
+pub struct Atom {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub atom_type: String,
+    pub charge: f64
+}
+impl Atom {
+
+    // Parses an atom from a whitespace separated ATOM record
+    fn from_line(line: &str) -> Option<Self> {
+        let items: Vec<&str> = line.split_whitespace().collect();
+        if items.len() < 6 {
+            return None
+        }
+        Some(Atom {
+            x: items[2].parse().ok()?,
+            y: items[3].parse().ok()?,
+            z: items[4].parse().ok()?,
+            atom_type: items[5].to_string(),
+            charge: items.get(8).and_then(|x| x.parse().ok()).unwrap_or(0.0)
+        })
+    }
+
+    // Returns the element of the atom from its SYBYL type (e.g. `C.ar` -> `C`)
+    pub fn element(&self) -> &str {
+        self.atom_type
+            .split('.')
+            .next()
+            .unwrap_or(&self.atom_type)
+    }
+
+    // Returns the standard atomic weight of the atom's element (0 for dummy atoms and lone pairs)
+    pub fn mass(&self) -> f64 {
+        match self.element() {
+            "H" => 1.008,
+            "Li" => 6.94,
+            "B" => 10.81,
+            "C" => 12.011,
+            "N" => 14.007,
+            "O" => 15.999,
+            "F" => 18.998,
+            "Na" => 22.990,
+            "Mg" => 24.305,
+            "Si" => 28.085,
+            "P" => 30.974,
+            "S" => 32.06,
+            "Cl" => 35.45,
+            "K" => 39.098,
+            "Ca" => 40.078,
+            "Fe" => 55.845,
+            "Zn" => 65.38,
+            "Se" => 78.971,
+            "Br" => 79.904,
+            "I" => 126.904,
+            _ => 0.0
+        }
+    }
+
+    // Returns true if the atom is neither a hydrogen nor a dummy atom or lone pair
+    pub fn is_heavy(&self) -> bool {
+        !matches!(self.element(), "H" | "LP" | "Du")
+    }
+}
+
+// Struct describing a single record of the @<TRIPOS>SUBSTRUCTURE section
+#[derive(Clone, Debug)]
+pub struct Substructure {
+    pub name: String,
+    pub subst_type: Option<String>
+}
+impl Substructure {
+
+    // Parses a substructure from a whitespace separated SUBSTRUCTURE record
+    fn from_line(line: &str) -> Option<Self> {
+        let items: Vec<&str> = line.split_whitespace().collect();
+        // records start with a numeric id and root atom
+        if items.len() < 3 || items[0].parse::<u32>().is_err() || items[2].parse::<u32>().is_err() {
+            return None
+        }
+        Some(Substructure {
+            name: items[1].to_string(),
+            subst_type: items.get(3).map(|x| x.to_string())
+        })
+    }
+}
+
 // Struct representing molecular data from a mol2 formatted file
 #[derive (Clone)]
 pub struct Mol2 {
     name: String,
     energy: f64,
+    properties: Vec<(String, String)>,
     lines: String
 }
 impl fmt::Debug for Mol2 {
@@ -44,6 +177,11 @@ impl PartialEq for Mol2 {
     }
 }
 impl Eq for Mol2 {}
+impl Default for Mol2 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 impl Mol2 {
 
     // Instantiate a new Mol2
@@ -51,6 +189,7 @@ impl Mol2 {
         Mol2 {
             name: String::new(),
             energy: 100.0,
+            properties: Vec::new(),
             lines: String::new()
         }
     }
@@ -65,6 +204,42 @@ impl Mol2 {
         self.energy = energy;
     }
 
+    // Sets the energy of current Mol2 and rewrites its Total Energy header line
+    pub fn set_energy(&mut self, energy: f64) {
+        self.energy = energy;
+
+        let key = "Total Energy:";
+        let start = match self.lines.find(key) {
+            Some(idx) => idx + key.len(),
+            None => return
+        };
+        let end = self.lines[start..]
+            .find('\n')
+            .map_or(self.lines.len(), |idx| start + idx);
+
+        // keep the original column width of the value
+        let width = self.lines[start..end].len();
+        let value = format!("{:>width$.6}", energy, width = width);
+        self.lines.replace_range(start..end, &value);
+    }
+
+    // Adds a header property (e.g. `Van der Waals`) to current Mol2
+    pub fn add_property(&mut self, key: String, value: String) {
+        self.properties.push((key, value));
+    }
+
+    // Inserts a `##########  Field: value` header comment after the header comments
+    // of current Mol2 and records it as a property
+    pub fn insert_header(&mut self, key: &str, value: &str) {
+        let pos: usize = self.lines
+            .split_inclusive('\n')
+            .take_while(|x| x.starts_with('#'))
+            .map(|x| x.len())
+            .sum();
+        self.lines.insert_str(pos, &format!("##########{:>21}:   {}\n", key, value));
+        self.properties.push((key.to_string(), value.to_string()));
+    }
+
     // Adds a raw text line to current Mol2
     pub fn add_line(&mut self, line: &str) {
         self.lines += line;
@@ -72,7 +247,7 @@ impl Mol2 {
 
     // Returns name from current Mol2
     pub fn get_name(&self) -> &str {
-        return &self.name
+        &self.name
     }
 
     // Returns energy from current Mol2
@@ -80,29 +255,164 @@ impl Mol2 {
         self.energy
     }
 
+    // Returns header properties from current Mol2 in file order
+    pub fn get_properties(&self) -> &[(String, String)] {
+        &self.properties
+    }
+
+    // Returns header properties from current Mol2 keyed by their normalized field name
+    // (e.g. `van_der_waals`; the first of repeated fields is kept)
+    pub fn property_map(&self) -> HashMap<String, &str> {
+        let mut map = HashMap::new();
+        for (k, v) in self.properties.iter() {
+            map.entry(normalize_field(k)).or_insert(v.as_str());
+        }
+        map
+    }
+
+    // Returns the names of the TRIPOS sections of current Mol2 in file order
+    pub fn section_names(&self) -> Vec<&str> {
+        self.lines
+            .lines()
+            .filter_map(|x| x.strip_prefix("@<TRIPOS>"))
+            .map(|x| x.trim())
+            .collect()
+    }
+
+    // Returns the non-empty data lines of a named TRIPOS section (e.g. `ATOM`)
+    pub fn section(&self, name: &str) -> impl Iterator<Item = &str> {
+        let header = format!("@<TRIPOS>{}", name);
+        self.lines
+            .lines()
+            .skip_while(move |x| x.trim_end() != header)
+            .skip(1)
+            .take_while(|x| !x.starts_with("@<TRIPOS>"))
+            .filter(|x| !x.trim().is_empty())
+    }
+
+    // Parses the atom records of current Mol2
+    pub fn atoms(&self) -> Vec<Atom> {
+        self.section("ATOM")
+            .filter_map(Atom::from_line)
+            .collect()
+    }
+
+    // Computes the centroid and radius of gyration of the atom coordinates
+    // (None if the molecule has no atoms)
+    pub fn geometry(&self) -> Option<Geometry> {
+        Geometry::from_atoms(&self.atoms())
+    }
+
+    // Parses the substructure records of current Mol2
+    pub fn substructures(&self) -> Vec<Substructure> {
+        self.section("SUBSTRUCTURE")
+            .filter_map(Substructure::from_line)
+            .collect()
+    }
+
     // Returns lines from current Mol2
     pub fn get_lines(&self) -> &str {
-        return &self.lines
+        &self.lines
+    }
+
+
+}
+
+// Enumerate describing how molecules without a name are named
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NameScheme {
+    Stem,
+    Path,
+    Blank
+}
+impl NameScheme {
+
+    // Parses a naming scheme from its CLI representation
+    pub fn from_name(scheme: &str) -> Option<Self> {
+        match scheme {
+            "stem" => Some(NameScheme::Stem),
+            "path" => Some(NameScheme::Path),
+            "none" => Some(NameScheme::Blank),
+            _ => None
+        }
+    }
+
+    // Returns the prefix of fallback names for molecules of a given file
+    fn prefix(&self, filename: &str) -> Option<String> {
+        match self {
+            NameScheme::Stem => {
+                let name = Path::new(filename)
+                    .file_name()
+                    .and_then(|x| x.to_str())
+                    .unwrap_or(filename);
+                Some(name.split('.').next().unwrap_or(name).to_string())
+            },
+            NameScheme::Path => Some(filename.to_string()),
+            NameScheme::Blank => None
+        }
+    }
+}
+
+// Settings applied by every reader (set once from the CLI)
+#[derive(Clone, Copy, Debug)]
+pub struct ReaderConfig {
+    pub name_scheme: NameScheme,
+    pub strict: bool
+}
+impl Default for ReaderConfig {
+    fn default() -> Self {
+        ReaderConfig {
+            name_scheme: NameScheme::Stem,
+            strict: false
+        }
     }
+}
+static READER_CONFIG: OnceLock<ReaderConfig> = OnceLock::new();
+
+// Sets the configuration of all subsequently created readers
+pub fn set_reader_config(config: ReaderConfig) {
+    READER_CONFIG.set(config).ok();
+}
+
+// First read error of any strict reader (readers often run on detached threads)
+static READ_FAILURE: Mutex<Option<String>> = Mutex::new(None);
 
+// Returns the first read error met by a strict reader, if any
+pub fn read_failure() -> Option<String> {
+    READ_FAILURE.lock().unwrap().clone()
+}
 
+// Returns true if a MOLECULE title carries no identifying information
+fn is_generic_title(title: &str) -> bool {
+    let title = title.trim();
+    title.is_empty() ||
+        title.chars().all(|c| c == '*') ||
+        ["unk", "unnamed", "molecule", "none"].contains(&title.to_lowercase().as_str())
 }
 
 // Struct describing file IO of a mol2 formatted file
 pub struct Mol2Reader {
-    reader: BufReader<MultiGzDecoder<File>>,
+    reader: BufReader<Box<dyn Read + Send>>,
     line: String,
+    pending: bool,
+    filename: String,
+    strict: bool,
+    truncated: bool,
+    error: Option<Error>,
+    fallback_prefix: Option<String>,
+    index: usize,
+    offset: u64,
+    line_start: u64,
+    span: (u64, u64),
     regex_name: Regex,
-    regex_energy: Regex,
-    regex_tripos: Regex,
-    regex_tripos_molecule: Regex
+    regex_energy: Regex
 }
 impl Iterator for Mol2Reader {
 
     type Item = Mol2;
 
     fn next(&mut self) -> Option<Mol2> {
-        return self.get_mol2();
+        self.get_mol2()
     }
 
 }
@@ -110,107 +420,230 @@ impl Mol2Reader {
 
     // Instantiate a new Mol2Reader
     pub fn new(filename: &str) -> Result<Self, Error> {
-        let file = File::open(filename)?;
-        let gzr = MultiGzDecoder::new(file);
+        let gzr = gzip::open(filename)?;
+        Ok(Self::from_reader(filename, gzr))
+    }
+
+    // Instantiate a new Mol2Reader over decompressed mol2 text
+    // (the filename is used for messages and fallback names)
+    pub fn from_reader(filename: &str, gzr: Box<dyn Read + Send>) -> Self {
         let reader = BufReader::new(gzr);
         let line = String::new();
         let regex_name = Regex::new(r"#+ +Name: +").unwrap();
         let regex_energy = Regex::new(r"#+ +Total Energy: +").unwrap();
-        let regex_tripos = Regex::new(r"^@<TRIPOS>").unwrap();
-        let regex_tripos_molecule = Regex::new(r"^@<TRIPOS>MOLECULE").unwrap();
 
-        Ok(Mol2Reader {
+        let config = READER_CONFIG
+            .get()
+            .copied()
+            .unwrap_or_default();
+        let fallback_prefix = config.name_scheme.prefix(filename);
+
+        Mol2Reader {
             reader,
             line,
+            pending: false,
+            filename: filename.to_string(),
+            strict: config.strict,
+            truncated: false,
+            error: None,
+            fallback_prefix,
+            index: 0,
+            offset: 0,
+            line_start: 0,
+            span: (0, 0),
             regex_name,
-            regex_energy,
-            regex_tripos,
-            regex_tripos_molecule
-        })
+            regex_energy
+        }
     }
 
-    // Step forward one line in the file
+    // Overrides the configured strictness of this reader
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    // Step forward one line in the file (or return the line pushed back by `unstep`)
     fn step(&mut self) -> bool {
+        if self.pending {
+            self.pending = false;
+            return true
+        }
         self.line.clear();
-        let eof = self.reader.read_line(&mut self.line).unwrap();
-        eof != 0
+        self.line_start = self.offset;
+        match self.reader.read_line(&mut self.line) {
+            Ok(eof) => {
+                self.offset += eof as u64;
+                eof != 0
+            },
+
+            // keep the molecules read so far unless strict reading is requested
+            Err(e) => {
+                // strict readers stop and keep the error for the caller
+                if self.strict {
+                    let message = format!("Failed reading {}: {}", self.filename, e);
+                    READ_FAILURE.lock().unwrap().get_or_insert(message);
+                    self.error = Some(e);
+                    return false
+                }
+                eprintln!(
+                    "Warning: Failed reading {} after {} molecules ({}); keeping partial results",
+                    self.filename, self.index, e
+                );
+                self.truncated = true;
+                false
+            }
+        }
+    }
+
+    // Returns true if the file failed mid-stream and only partial results were read
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    // Returns the error that stopped a strict reader, if any
+    pub fn error(&self) -> Option<&Error> {
+        self.error.as_ref()
+    }
+
+    // Returns the decompressed byte range (start, end) of the last molecule read
+    pub fn last_span(&self) -> (u64, u64) {
+        self.span
+    }
+
+    // Push the current line back so the next `step` returns it again
+    fn unstep(&mut self) {
+        self.pending = true;
+    }
+
+    // Parses the name and energy header comments into the molecule
+    fn parse_header(&self, mol: &mut Mol2) {
+
+        // Records every header field as a property
+        if let Some((key, value)) = parse_property(&self.line) {
+            mol.add_property(key.to_string(), value.to_string());
+        }
+
+        // The beginning of a new molecule
+        if self.regex_name.is_match(&self.line) {
+            mol.add_name(
+                self.regex_name
+                    .replace_all(&self.line, "")
+                    .trim()
+                    .to_string()
+            );
+        }
+
+        // Adds the energy for a molecule
+        else if self.regex_energy.is_match(&self.line) {
+            mol.add_energy(
+                self.regex_energy
+                    .replace_all(&self.line, "")
+                    .trim()
+                    .to_string()
+                    .parse::<f64>()
+                    .unwrap()
+            )
+        }
     }
 
     // Retrieve the next Mol2 in the file
+    //
+    // A molecule spans its header comments, its @<TRIPOS>MOLECULE record, and every
+    // following section (of any type, kept verbatim) until the header or MOLECULE
+    // record of the next molecule.
+    //
+    // Molecules without a Name header are named by their MOLECULE title, or, if it is
+    // blank or generic, by the fallback scheme as `<prefix>#<index>`.
     fn get_mol2(&mut self) -> Option<Mol2> {
         let mut mol = Mol2::new();
-        let mut tripos_state = 0;
-        let mut tripos_counts = Vec::new();
+        let mut has_record = false;
+        let mut title: Option<String> = None;
+        let mut start: Option<u64> = None;
 
         loop {
 
             if !self.step() {
-                return None
+                break;
             }
 
-            // The beginning of a new molecule
-            if self.regex_name.is_match(&self.line) {
-                mol.add_name(
-                    self.regex_name
-                        .replace_all(&self.line, "")
-                        .trim()
-                        .to_string()
-                );
+            // provenance comments describe the file rather than a molecule
+            // (they also end a molecule so its byte range excludes them)
+            if self.line.starts_with(PROVENANCE_PREFIX) {
+                if has_record {
+                    self.unstep();
+                    break;
+                }
+                continue;
             }
 
-            // Adds the energy for a molecule
-            else if self.regex_energy.is_match(&self.line) {
-                mol.add_energy(
-                    self.regex_energy
-                        .replace_all(&self.line, "")
-                        .trim()
-                        .to_string()
-                        .parse::<f64>()
-                        .unwrap()
-                )
-            }
+            let is_record = self.line.starts_with("@<TRIPOS>MOLECULE");
+            let is_header = self.line.starts_with('#');
 
-            // Case where TRIPOS data is found
-            else if self.regex_tripos.is_match(&self.line) {
+            // The beginning of the next molecule
+            if has_record && (is_record || is_header) {
+                self.unstep();
+                break;
+            }
 
-                // case where TRIPOS Molecule Is found providing number of elements in following
-                if self.regex_tripos_molecule.is_match(&self.line) {
-                    mol.add_line(&self.line);
+            start.get_or_insert(self.line_start);
 
-                    for _ in 0..2 {
-                        if !self.step() {return None}
-                        mol.add_line(&self.line);
-                    }
+            if is_record {
+                has_record = true;
+            }
+            else if has_record && title.is_none() {
+                title = Some(self.line.split_whitespace().next().unwrap_or("").to_string());
+            }
+            else if is_header {
+                self.parse_header(&mut mol);
+            }
 
-                    tripos_counts = self.line
-                        .trim()
-                        .split_whitespace()
-                        .map(|x| x.parse::<u8>().unwrap())
-                        .collect();
-                }
+            // the last line of a file may lack its newline
+            if !self.line.ends_with('\n') {
+                self.line.push('\n');
+            }
+            mol.add_line(&self.line);
+        }
 
-                else {
-                    mol.add_line(&self.line);
+        // a molecule interrupted by a read failure is incomplete, while one
+        // reaching the end of the file without a MOLECULE record is kept if named
+        if self.truncated || (!has_record && mol.get_name().is_empty()) {
+            return None
+        }
 
-                    for _ in 0..tripos_counts[tripos_state] {
-                        if !self.step() {return None}
-                        mol.add_line(&self.line);
-                    }
+        // the molecule ends where the pushed back line starts
+        let end = if self.pending { self.line_start } else { self.offset };
+        self.span = (start.unwrap_or(end), end);
 
-                    tripos_state += 1;
+        if mol.get_name().is_empty() {
+            match title {
+                Some(t) if !is_generic_title(&t) => mol.add_name(t),
+                _ => if let Some(prefix) = &self.fallback_prefix {
+                    mol.add_name(format!("{}#{}", prefix, self.index));
                 }
-
-                self.line.clear();
-            }
-
-            if (tripos_state > 0) && (tripos_counts[tripos_state] == 0) {
-                break;
             }
-
-            mol.add_line(&self.line);
         }
+        self.index += 1;
 
         Some(mol)
     }
 
 }
+
+// Splits a `##########  Field: value` header line into its field and value
+fn parse_property(line: &str) -> Option<(&str, &str)> {
+    if !line.starts_with("#####") {
+        return None
+    }
+    let (key, value) = line
+        .trim_start_matches('#')
+        .split_once(':')?;
+    Some((key.trim(), value.trim()))
+}
+
+// Normalizes a header field name into a column name (e.g. `Van der Waals` -> `van_der_waals`)
+pub fn normalize_field(key: &str) -> String {
+    key.split_whitespace()
+        .map(|x| x.to_lowercase())
+        .collect::<Vec<String>>()
+        .join("_")
+}
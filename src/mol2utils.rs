@@ -3,94 +3,764 @@ use std::thread;
 use std::sync::mpsc;
 use std::sync::mpsc::{Sender, Receiver};
 use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Instant;
 
+use std::borrow::Cow;
 use std::collections::{HashMap, HashSet};
-use std::io::Error;
+use std::fs::File;
+use std::io::{BufWriter, Error, ErrorKind};
 use std::io::prelude::*;
-
-use crate::mol2::{Mol2, Mol2Reader};
-use crate::query::{QueryFormat, QueryReader};
-use crate::file_io::writer;
+use std::path::Path;
+
+use crate::mol2::{Mol2, Mol2Reader, normalize_field};
+use crate::query::{Aliases, QueryColumns, QueryFormat, QueryGroups, QueryReader, Tolerance, ToleranceMode};
+use crate::file_io::{writer, is_stdin, OutputSet};
+use crate::report::{FileReport, file_size, write_file_report, print_aligned};
+use crate::sample::{Sampler, sequence_id};
+use crate::provenance::Provenance;
+use crate::budget::{Budget, BudgetTracker};
+use crate::exec::{ExecHook, ExecOptions};
+use crate::memory::{QueueGauge, print_memory_stats};
 
 use indicatif::ProgressIterator;
 use rayon::prelude::*;
 
-// Function to perform grep without checking for score matches
-fn grep_with_set(
-        mol2_reader: Mol2Reader,
-        table: &HashSet<Mol2>,
-        channel: &mut Sender<Mol2>) -> (u32, u32) {
+// Options modifying the behavior of the grep subcommand
+#[derive(Default)]
+pub struct GrepOptions {
+    pub unique: bool,
+    pub file_report: Option<String>,
+    pub group_output: bool,
+    pub per_query_output: bool,
+    pub alias: Option<String>,
+    pub budget: Budget,
+    pub archive: Option<String>,
+    pub exec: Option<ExecOptions>,
+    pub stats_memory: bool,
+    // query lines are regular expressions matched against whole names
+    pub regex: bool,
+    // names are compared lowercased on both the query and molecule sides
+    pub ignore_case: bool,
+    // columns holding names and scores in multi-column query tables
+    pub query_columns: QueryColumns,
+    // deviation from expected scores accepted in proportion to their magnitude
+    pub rel_tol: f64,
+    // side of expected scores accepted
+    pub tol_mode: ToleranceMode,
+    // write the molecules matching no query instead
+    pub invert: bool,
+    // stop once this many matches were written
+    pub max_count: Option<u64>,
+    // file listing the query names never found (the standard error otherwise)
+    pub missing_out: Option<String>,
+    // table of the number of poses matched by every query name
+    pub query_counts: Option<String>,
+    // list of names skipped even when queried (e.g. known aggregators)
+    pub exclude: Option<String>,
+    // inserts a `Source` header comment naming the input file of every molecule written
+    pub annotate_source: bool,
+    // writes the names of matching molecules instead of their records
+    pub names_only: bool,
+    // writes the found energy next to each name
+    pub with_energy: bool,
+    // reports the number of matches of every input file without writing molecules
+    pub count_only: bool,
+    // lists the input files holding at least one match, reading each only up to its first
+    pub files_with_matches: bool,
+    // output receiving every molecule not accepted, in the same pass
+    pub rest_out: Option<String>,
+    // writes the matches of each input file to its own output, next to the main output
+    pub per_input_output: bool,
+    // writes the poses of every matched name to its own output (buffers matches in memory)
+    pub split_by_query: bool,
+    // writes matches in input order (buffers matches in memory)
+    pub ordered: bool,
+    // rolls the output over to a new numbered shard once it holds this many molecules
+    pub max_per_file: Option<u64>,
+    // writes only the lowest energy pose of every matched name (buffers matches in memory)
+    pub best_per_id: bool
+}
+
+// Options modifying the behavior of the split subcommand
+#[derive(Default)]
+pub struct SplitOptions {
+    pub budget: Budget,
+    pub archive: Option<String>
+}
+
+// Buffers molecules so that all poses of the same name are written adjacently
+#[derive(Default)]
+pub struct NameGroups {
+    positions: HashMap<String, usize>,
+    groups: Vec<Vec<Mol2>>
+}
+impl NameGroups {
+
+    // Adds a molecule to the group of its name (groups keep first-seen order)
+    pub fn push(&mut self, mol: Mol2) {
+        match self.positions.get(mol.get_name()) {
+            Some(&idx) => self.groups[idx].push(mol),
+            None => {
+                self.positions.insert(mol.get_name().to_string(), self.groups.len());
+                self.groups.push(vec![mol]);
+            }
+        }
+    }
+
+    // Returns the number of bytes of buffered molecule text
+    pub fn num_bytes(&self) -> u64 {
+        self.groups
+            .iter()
+            .flatten()
+            .map(|x| x.get_lines().len() as u64)
+            .sum()
+    }
+
+    // Returns the buffered poses of every name in first-seen order
+    pub fn into_groups(self) -> impl Iterator<Item = Vec<Mol2>> {
+        self.groups.into_iter()
+    }
+
+    // Returns the buffered molecules grouped by name
+    pub fn into_molecules(self) -> impl Iterator<Item = Mol2> {
+        self.groups
+            .into_iter()
+            .flatten()
+    }
+}
+
+// Set of query names already emitted, shared between workers
+struct SeenNames {
+    names: Mutex<HashSet<String>>,
+    num_queries: usize,
+    complete: AtomicBool
+}
+impl SeenNames {
+
+    // Instantiate a new SeenNames for a query table of a given size
+    fn new(num_queries: usize) -> Self {
+        SeenNames {
+            names: Mutex::new(HashSet::new()),
+            num_queries,
+            complete: AtomicBool::new(false)
+        }
+    }
+
+    // Claims a name for output, returning false if it was already emitted
+    fn claim(&self, name: &str) -> bool {
+        let mut names = self.names.lock().unwrap();
+        let claimed = names.insert(name.to_string());
+        if names.len() >= self.num_queries {
+            self.complete.store(true, Ordering::Relaxed);
+        }
+        claimed
+    }
+
+    // Returns true once every query name has been emitted
+    fn is_complete(&self) -> bool {
+        self.complete.load(Ordering::Relaxed)
+    }
+}
+
+// Returns true if the name has not yet been emitted (or uniqueness is not requested)
+fn is_unseen(seen: Option<&SeenNames>, name: &str) -> bool {
+    seen.is_none_or(|s| s.claim(name))
+}
+
+// Returns the name under which a molecule or query is compared
+// (lowercased when names are matched regardless of case)
+fn lookup_name(name: &str, ignore_case: bool) -> Cow<'_, str> {
+    match ignore_case {
+        true => Cow::Owned(name.to_lowercase()),
+        false => Cow::Borrowed(name)
+    }
+}
+
+// Number of matches written so far out of a maximum, shared between workers
+struct MatchLimit {
+    max_count: u64,
+    count: AtomicU64
+}
+impl MatchLimit {
+
+    // Instantiate a new MatchLimit stopping after `max_count` matches
+    fn new(max_count: u64) -> Self {
+        MatchLimit { max_count, count: AtomicU64::new(0) }
+    }
+
+    // Claims a slot for a match, returning false once the maximum is reached
+    fn claim(&self) -> bool {
+        self.count.fetch_add(1, Ordering::Relaxed) < self.max_count
+    }
+
+    // Returns true once every slot has been claimed
+    fn is_reached(&self) -> bool {
+        self.count.load(Ordering::Relaxed) >= self.max_count
+    }
+}
+
+// Returns true if workers should stop reading as every query has been emitted
+// or the maximum number of matches was written
+fn is_exhausted(seen: Option<&SeenNames>, limit: Option<&MatchLimit>) -> bool {
+    seen.is_some_and(|s| s.is_complete()) || limit.is_some_and(|l| l.is_reached())
+}
+
+// A molecule, the indices of the query tables it matched (None for molecules
+// not accepted, sent only when the rest is written), its input file, and its
+// position (file index, record index) within the inputs
+type QueryHit = (Mol2, Option<Vec<usize>>, Arc<str>, (usize, usize));
+
+// Struct describing how molecules are matched against the query tables
+struct QueryMatcher {
+    tables: Vec<QueryFormat>,
+    tol: Tolerance,
+    ignore_case: bool,
+    // accept the molecules matching no table instead
+    invert: bool,
+    // names never accepted, whatever the query tables hold
+    exclude: Option<QueryFormat>,
+    // keep the molecules not accepted for the rest output
+    rest: bool
+}
+impl QueryMatcher {
+
+    // Returns the indices of the query tables a molecule matches,
+    // or None if it is not accepted
+    fn hits(&self, mol: &Mol2) -> Option<Vec<usize>> {
+        // a lowercased copy is looked up, the molecule keeps its name as read
+        let folded;
+        let mol = match self.ignore_case {
+            true => {
+                let mut key = Mol2::new();
+                key.add_name(mol.get_name().to_lowercase());
+                key.add_energy(mol.get_energy());
+                folded = key;
+                &folded
+            },
+            false => mol
+        };
+        if self.exclude.as_ref().is_some_and(|t| t.contains_name(mol)) {
+            return None
+        }
+        let hits: Vec<usize> = self.tables
+            .iter()
+            .enumerate()
+            .filter(|(_, t)| t.matches(mol, &self.tol))
+            .map(|(idx, _)| idx)
+            .collect();
+        match (hits.is_empty(), self.invert) {
+            (false, false) => Some(hits),
+            (true, true) => Some(Vec::new()),
+            _ => None
+        }
+    }
+}
+
+// Function to perform grep of a single file against every query table
+//
+// Molecules are passed to the sink along with the indices of the query tables they match.
+// The source is the index and name of the input file.
+fn grep_reader(
+        mol2_reader: &mut Mol2Reader,
+        matcher: &QueryMatcher,
+        seen: Option<&SeenNames>,
+        limit: Option<&MatchLimit>,
+        source: (usize, &Arc<str>),
+        sink: &mut dyn FnMut(QueryHit),
+        gauge: &QueueGauge) -> (u32, u32) {
 
     let mut num_molecules = 0;
     let mut num_passing = 0;
 
     mol2_reader
-        .into_iter()
-        .map(|x|{
-            num_molecules += 1;
-            x
+        .take_while(|_| !is_exhausted(seen, limit))
+        .enumerate()
+        .inspect(|_| num_molecules += 1)
+        .filter_map(|(record_index, x)| {
+            let hits = matcher
+                .hits(&x)
+                .filter(|_| is_unseen(seen, &lookup_name(x.get_name(), matcher.ignore_case)));
+            match (hits, matcher.rest) {
+                (None, false) => None,
+                (hits, _) => Some((x, hits, source.1.clone(), (source.0, record_index)))
+            }
         })
-        .filter(|x|
-            table.contains(x)
-        )
+        .take_while(|(_, hits, _, _)| hits.is_none() || limit.is_none_or(|l| l.claim()))
         .for_each(|x|{
-            num_passing += 1;
-            channel.send(x).expect("Error: Broken Send Channel");
+            if x.1.is_some() {
+                num_passing += 1;
+            }
+            gauge.push(x.0.get_lines().len() as u64);
+            sink(x);
         });
 
     (num_molecules, num_passing)
 
 }
 
+// Keeps the lowest energy pose of every name (the earliest pose in input order wins ties)
+// returns the kept poses in input order
+fn best_hits(hits: impl Iterator<Item = QueryHit>, ignore_case: bool) -> Vec<QueryHit> {
+    let mut best: HashMap<String, QueryHit> = HashMap::new();
+    for hit in hits {
+        let key = lookup_name(hit.0.get_name(), ignore_case).into_owned();
+        match best.get_mut(&key) {
+            Some(b) => {
+                let better = hit.0.get_energy()
+                    .total_cmp(&b.0.get_energy())
+                    .then(hit.3.cmp(&b.3))
+                    .is_lt();
+                if better {
+                    *b = hit;
+                }
+            },
+            None => { best.insert(key, hit); }
+        }
+    }
+    let mut kept: Vec<QueryHit> = best.into_values().collect();
+    kept.sort_unstable_by_key(|(_, _, _, position)| *position);
+    kept
+}
 
-// Function to perform grep while checking for score matches
-fn grep_with_map(
-        mol2_reader: Mol2Reader,
-        table: &HashMap<Mol2, f64>,
-        tol: f64,
-        channel: &mut Sender<Mol2>) -> (u32, u32) {
+// Struct describing an output rolled over to a new numbered shard once it holds
+// a maximum number of molecules (e.g. `out.0001.mol2.gz`, `out.0002.mol2.gz`, ...)
+struct Shards {
+    output_filename: String,
+    max_per_file: u64,
+    num_written: u64,
+    num_shards: usize
+}
+impl Shards {
+
+    // Instantiate a new Shards whose first shard is opened as the output
+    fn new(output_filename: &str, max_per_file: u64) -> Self {
+        Shards {
+            output_filename: output_filename.to_string(),
+            max_per_file,
+            num_written: 0,
+            num_shards: 1
+        }
+    }
 
-    let mut num_molecules = 0;
-    let mut num_passing = 0;
+    // Claims a slot for the next molecule, replacing a full writer by the next shard
+    // (opened only once a molecule is written to it, so no shard is left empty)
+    fn claim(&mut self, writer: &mut Box<dyn Write>, header: Option<&Provenance>) -> Result<(), Error> {
+        if self.num_written == self.max_per_file {
+            writer.flush()?;
+            self.num_shards += 1;
+            *writer = crate::file_io::writer(&shard_filename(&self.output_filename, self.num_shards))?;
+            if let Some(p) = header {
+                p.write(writer)?;
+            }
+            self.num_written = 0;
+        }
+        self.num_written += 1;
+        Ok(())
+    }
+}
 
-    mol2_reader
-        .into_iter()
-        .map(|x|{
-            num_molecules += 1;
-            x
-        })
-        .filter(|x|
-            table.contains_key(x)
+// returns the text written for a matching molecule: its record, or its name
+// (and found energy) when only names are written
+fn output_text<'a>(mol: &'a Mol2, options: &GrepOptions) -> Cow<'a, str> {
+    match (options.names_only, options.with_energy) {
+        (false, _) => Cow::Borrowed(mol.get_lines()),
+        (true, false) => Cow::Owned(format!("{}\n", mol.get_name())),
+        (true, true) => Cow::Owned(format!("{}\t{}\n", mol.get_name(), mol.get_energy()))
+    }
+}
+
+// writes a molecule to one of the outputs (buffering it by name if requested)
+fn write_molecule(
+        mol: &Mol2,
+        target: usize,
+        groups: &mut [NameGroups],
+        writers: &mut [Box<dyn Write>],
+        options: &GrepOptions) {
+
+    if options.group_output {
+        groups[target].push(mol.clone());
+        return
+    }
+    writers[target]
+        .write_all(
+            output_text(mol, options).as_bytes()
         )
-        .filter(|x|
-            (x.get_energy() - table.get(x).unwrap() <= tol)
+        .expect(
+            "Error: Error writing to output file"
         )
-        .for_each(|x|{
-            num_passing += 1;
-            channel.send(x).expect("Error: Broken Send Channel");
-        });
+}
 
-    (num_molecules, num_passing)
+// returns the filename of the output of a single query file
+// (e.g. `out.mol2.gz` and `hits/a.tsv` give `out.a.mol2.gz`)
+// (the query is inserted before the `.mol2` suffix so the compression suffix is kept)
+pub fn per_query_filename(output_filename: &str, query_filename: &str) -> String {
+    let query = Path::new(query_filename)
+        .file_stem()
+        .and_then(|x| x.to_str())
+        .unwrap_or(query_filename);
+    insert_label(output_filename, query)
+}
+
+// returns the filename of the output of a single input file, in the directory of the main output
+// (e.g. `hits/out.mol2.gz` and `zinc/AAAB.xaa.mol2.gz` give `hits/AAAB.xaa.hits.mol2.gz`)
+pub fn per_input_filename(output_filename: &str, input_filename: &str) -> String {
+    let name = Path::new(input_filename)
+        .file_name()
+        .and_then(|x| x.to_str())
+        .unwrap_or(input_filename);
+    let base = match name.rfind(".mol2") {
+        Some(pos) => &name[..pos],
+        None => name
+    };
+    Path::new(output_filename)
+        .with_file_name(format!("{}.hits.mol2.gz", base))
+        .to_string_lossy()
+        .to_string()
+}
+
+// returns the filename of a numbered shard of an output
+// (e.g. `out.mol2.gz` and 2 give `out.0002.mol2.gz`)
+pub fn shard_filename(output_filename: &str, shard: usize) -> String {
+    insert_label(output_filename, &format!("{:04}", shard))
+}
 
+// returns the filename of the output of a query group
+// (e.g. `out.mol2.gz` and `cluster 7` give `out.cluster_7.mol2.gz`)
+pub fn per_group_filename(output_filename: &str, group: &str) -> String {
+    let label: String = group
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || "._-".contains(c) { c } else { '_' })
+        .collect();
+    insert_label(output_filename, &label)
 }
 
+// inserts a label before the `.mol2` suffix of an output filename
+fn insert_label(output_filename: &str, label: &str) -> String {
+    match output_filename.rfind(".mol2") {
+        Some(pos) => format!("{}.{}{}", &output_filename[..pos], label, &output_filename[pos..]),
+        None => format!("{}.{}.mol2.gz", output_filename, label)
+    }
+}
+
+
+// returns the filename of the alias report of a given grep output
+pub fn alias_report_filename(output_filename: &str) -> String {
+    let base = output_filename
+        .strip_suffix(".mol2.gz")
+        .unwrap_or(output_filename);
+    format!("{}.aliases.tsv", base)
+}
+
+// Writes the number of poses matched by each aliased query as rows of: query, name, poses
+fn write_alias_report(
+        filename: &str,
+        aliased: &[(String, String)],
+        poses_of: &dyn Fn(&str) -> u32) -> Result<(), Error> {
+
+    let mut writer = BufWriter::new(File::create(filename)?);
+    writer.write_all(b"query\tname\tposes\n")?;
+    for (code, name) in aliased {
+        writeln!(writer, "{}\t{}\t{}", code, name, poses_of(name))?;
+    }
+    Ok(())
+}
+
+// Writes the number of poses matched by each query name as rows of: query, poses
+fn write_query_counts(
+        filename: &str,
+        query_names: &[&str],
+        poses_of: &dyn Fn(&str) -> u32) -> Result<(), Error> {
+
+    let mut writer = BufWriter::new(File::create(filename)?);
+    writer.write_all(b"query\tposes\n")?;
+    for name in query_names {
+        writeln!(writer, "{}\t{}", name, poses_of(name))?;
+    }
+    writer.flush()
+}
+
+// Writes the query names never found one per line, to a file or else to the standard error
+fn write_missing_report(filename: Option<&str>, missing: &[&str]) -> Result<(), Error> {
+    match filename {
+        Some(x) => {
+            let mut writer = BufWriter::new(File::create(x)?);
+            for name in missing {
+                writeln!(writer, "{}", name)?;
+            }
+            writer.flush()
+        },
+        None => {
+            let mut writer = std::io::stderr().lock();
+            for name in missing {
+                writeln!(writer, "Warning: query never found: {}", name)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+// Returns the names of the outputs a grep opens up front (one per query file or
+// per group if requested); per-input and per-name outputs are opened as they are written
+pub fn grep_output_names(
+        options: &GrepOptions,
+        query_filenames: &[&str],
+        group_labels: &[String],
+        output_filename: &str) -> Vec<String> {
+
+    if options.count_only || options.files_with_matches || options.per_input_output || options.split_by_query {
+        Vec::new()
+    } else if options.per_query_output {
+        query_filenames
+            .iter()
+            .map(|x| per_query_filename(output_filename, x))
+            .collect()
+    } else if options.query_columns.group.is_some() {
+        group_labels
+            .iter()
+            .map(|x| per_group_filename(output_filename, x))
+            .collect()
+    } else if options.max_per_file.is_some() {
+        vec![shard_filename(output_filename, 1)]
+    } else {
+        vec![output_filename.to_string()]
+    }
+}
+
+// Describes a grep without running it, reading only the query files
+// returns the query files described and every file the grep would write
+//
+// Outputs split by name are listed under the query names, as the names matched
+// are only known once the inputs are read.
+pub fn grep_dry_run(
+        input_files: &[String],
+        query_filenames: &[&str],
+        output_filename: &str,
+        options: &GrepOptions) -> Result<(Vec<String>, Vec<String>), Error> {
+
+    let mut queries = Vec::new();
+    let mut group_rows = Vec::new();
+    let mut query_names: Vec<String> = Vec::new();
+    for x in query_filenames.iter() {
+        let mut reader = QueryReader::new(x)?;
+        if options.query_columns.group.is_some() {
+            group_rows.extend(reader.load_groups(&options.query_columns)?);
+        }
+        let table = match options.regex {
+            true => reader.load_regex()?,
+            false => reader.load_queries_with(&options.query_columns)?
+        };
+        queries.push(format!("{} ({})", x, table.describe()));
+        query_names.extend(table.names().map(|x| x.to_string()));
+    }
+    query_names.sort_unstable();
+    query_names.dedup();
+
+    // the outputs opened up front (and the rest) are members of the archive if requested
+    let query_groups = QueryGroups::new(group_rows);
+    let mut members = grep_output_names(options, query_filenames, &query_groups.labels, output_filename);
+    members.extend(options.rest_out.clone());
+    let mut outputs = match options.archive {
+        Some(ref x) if !members.is_empty() => vec![x.clone()],
+        _ => members
+    };
+    if options.per_input_output {
+        outputs.extend(input_files.iter().map(|x| per_input_filename(output_filename, x)));
+    }
+    if options.split_by_query {
+        outputs.extend(query_names.iter().map(|x| per_group_filename(output_filename, x)));
+    }
+    if options.alias.is_some() {
+        outputs.push(alias_report_filename(output_filename));
+    }
+    outputs.extend(options.missing_out.clone());
+    outputs.extend(options.query_counts.clone());
+    outputs.extend(options.file_report.clone());
+    outputs.extend(options.exec.as_ref().map(|x| x.report.clone()));
+    Ok((queries, outputs))
+}
 
 // implements grep subcommand
 pub fn grep(
         input_files: Vec<String>,
-        query_filename: &str,
+        query_filenames: &[&str],
         output_filename: &str,
-        tol: f64) -> Result<u32, Error> {
+        tol: f64,
+        options: &GrepOptions) -> Result<u32, Error> {
+
+    let grouped = options.query_columns.group.is_some();
+    if options.invert && (options.per_query_output || grouped) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "inverted matches belong to no query and cannot be written per query or per group"
+        ))
+    }
+    if options.per_query_output && grouped {
+        return Err(Error::new(ErrorKind::InvalidInput, "outputs are written either per query or per group"))
+    }
+    if options.split_by_query && (options.invert || options.per_query_output || grouped) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "outputs split by query name cannot be inverted, nor written per query file or per group"
+        ))
+    }
+    if options.per_input_output {
+        let mut names = HashSet::new();
+        if let Some(x) = input_files.iter().map(|x| per_input_filename(output_filename, x)).find(|x| !names.insert(x.clone())) {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("inputs sharing a name would be written to the same output: {}", x)
+            ))
+        }
+    }
+    if options.rest_out.is_some() && (options.unique || options.max_count.is_some()) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "the rest is only complete when every molecule is read, which cannot be combined with unique or max-count"
+        ))
+    }
+    if options.ordered && (options.unique || options.max_count.is_some()) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "the poses kept by unique or max-count depend on timing, so they cannot be ordered reproducibly"
+        ))
+    }
+    if options.max_per_file.is_some() && (options.per_query_output || grouped || options.archive.is_some()) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "only a single output written as files can be sharded"
+        ))
+    }
+    if options.best_per_id && (options.unique || options.max_count.is_some() || options.rest_out.is_some() || options.per_input_output) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "best poses are chosen among every match, which cannot be combined with unique, max-count, rest-out or per-input outputs"
+        ))
+    }
+    if options.max_per_file == Some(0) {
+        return Err(Error::new(ErrorKind::InvalidInput, "shards must hold at least one molecule"))
+    }
+    if options.files_with_matches && (options.unique || options.max_count.is_some()) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "files with matches are read up to their first match, which cannot be combined with unique or max-count"
+        ))
+    }
+
+    // the standard input can only be read once
+    let num_stdin = query_filenames.iter().filter(|x| is_stdin(x)).count()
+        + options.exclude.iter().filter(|x| is_stdin(x)).count()
+        + input_files.iter().filter(|x| is_stdin(x)).count();
+    if num_stdin > 1 {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "the standard input (-) can only be read once, by a single query list or input"
+        ))
+    }
+
+    // Instantiate QueryReaders and read each file into a table (and its groups if requested)
+    let mut group_rows: Vec<(String, String)> = Vec::new();
+    let mut tables = query_filenames
+        .iter()
+        .map(|x| {
+            let mut reader = QueryReader::new(x)?;
+            if grouped {
+                group_rows.extend(reader.load_groups(&options.query_columns)?);
+            }
+            match options.regex {
+                true => reader.load_regex(),
+                false => reader.load_queries_with(&options.query_columns)
+            }
+        })
+        .collect::<Result<Vec<QueryFormat>, Error>>()?;
+    if grouped && tables.iter().any(|t| t.has_patterns()) {
+        return Err(Error::new(ErrorKind::InvalidInput, "query patterns cannot be routed to groups"))
+    }
+
+    // translate aliased query codes into the names found in the mol2 files
+    let mut aliased: Vec<(String, String)> = Vec::new();
+    if let Some(ref alias_filename) = options.alias {
+        let aliases = Aliases::from_file(alias_filename)?;
+        tables = tables
+            .into_iter()
+            .map(|t| {
+                let (table, translated) = t.translate(&aliases);
+                aliased.extend(translated);
+                table
+            })
+            .collect();
+        for (name, _) in group_rows.iter_mut() {
+            if let Some(x) = aliases.resolve(name) {
+                *name = x.to_string();
+            }
+        }
+        println!(">>> Number of Aliased Queries: {} ({} aliases)", aliased.len(), aliases.num_aliases());
+    }
+
+    // compare lowercased names so that queries match molecules regardless of case
+    // (reports keep the query names as given)
+    let mut original_names: Option<Vec<String>> = None;
+    if options.ignore_case {
+        original_names = Some(tables.iter().flat_map(|t| t.names()).map(|x| x.to_string()).collect());
+        tables = tables
+            .into_iter()
+            .map(|t| t.fold_case())
+            .collect::<Result<Vec<QueryFormat>, Error>>()?;
+        for (name, _) in group_rows.iter_mut() {
+            *name = name.to_lowercase();
+        }
+    }
+
+    // load the names excluded from every match
+    let exclude = match options.exclude {
+        Some(ref x) => {
+            let table = QueryReader::new(x)?.load_queries()?;
+            let table = if options.ignore_case { table.fold_case()? } else { table };
+            println!(">>> Excluded Names: {}", table.describe());
+            Some(table)
+        },
+        None => None
+    };
 
-    // Instantiate QueryReader and read file into table
-    let mut qr = QueryReader::new(query_filename)?;
-    let table = qr.load_queries()?;
+    // Instantiate the shared set of emitted names if only one pose per query is requested
+    let seen = if options.unique {
+        let names: HashSet<&str> = tables
+            .iter()
+            .flat_map(|t| t.names())
+            .collect();
 
-    // Instantiate Writer
-    let mut writer_file = writer(output_filename);
+        // names matched by patterns or by inversion are not known, so reading never stops early
+        let num_queries = match options.invert || tables.iter().any(|t| t.has_patterns()) {
+            true => usize::MAX,
+            false => names.len()
+        };
+        Some(Arc::new(SeenNames::new(num_queries)))
+    } else {
+        None
+    };
+
+    // Instantiate Writers (one per query file or per group if requested)
+    let query_groups = QueryGroups::new(group_rows);
+    let output_filenames = grep_output_names(options, query_filenames, &query_groups.labels, output_filename);
+    // the rest is written by the last writer
+    let mut output_names = output_filenames.clone();
+    let rest_idx = output_names.len();
+    output_names.extend(options.rest_out.clone());
+    let provenance = Arc::new(Provenance::new(&input_files)?);
+    let provenance_fmt = provenance.clone();
+    let (outputs, mut writers) = OutputSet::open(&output_names, options.archive.as_deref())?;
+    // name lists are plain text without provenance comments
+    if !options.names_only {
+        for w in writers.iter_mut() {
+            provenance.write(w)?;
+        }
+    }
 
     // Instantiate Send/Receive Channels
-    let (channel_send, channel_recv): (Sender<Mol2>, Receiver<Mol2>) = mpsc::channel();
+    let (channel_send, channel_recv): (Sender<QueryHit>, Receiver<QueryHit>) = mpsc::channel();
+    let gauge = Arc::new(QueueGauge::default());
+    let gauge_fmt = gauge.clone();
+    let table_bytes: u64 = tables.iter().map(|t| t.estimated_bytes()).sum();
 
     // Keep statistics on number of molecules processed
     let num_molecules = Arc::new(Mutex::new(0));
@@ -99,50 +769,241 @@ pub fn grep(
     let num_molecules_fmt = num_molecules.clone();
     let num_passing_fmt = num_passing.clone();
 
+    // Keep statistics on each processed file
+    let file_reports = Arc::new(Mutex::new(Vec::new()));
+    let file_reports_fmt = file_reports.clone();
+
+    // stops scheduling new files once the budget is spent
+    let num_files = input_files.len();
+    let budget = Arc::new(BudgetTracker::new(options.budget));
+    let budget_fmt = budget.clone();
+    let limit = options.max_count.map(MatchLimit::new);
+    let matcher = Arc::new(QueryMatcher {
+        tables,
+        tol: Tolerance { absolute: tol, relative: options.rel_tol, mode: options.tol_mode },
+        ignore_case: options.ignore_case,
+        invert: options.invert,
+        exclude,
+        rest: options.rest_out.is_some()
+    });
+    let matcher_fmt = matcher.clone();
+    let files_with_matches = options.files_with_matches;
+    let per_input_output = options.per_input_output.then(|| output_filename.to_string());
+
     // places molecules into writer channel
     thread::spawn(move || {
 
         // iterate through input files in parallel
         input_files
             .into_iter()
+            .enumerate()
             .progress()  // adds a progress bar on the file processing
             .par_bridge()
-            .for_each_with(channel_send, |sender, x| {
+            .for_each_with(channel_send, |sender, (index, x)| {
 
-                // instantiate a new mol2 reader
-                let mol2_reader = Mol2Reader::new(&x).unwrap();
+                // skip remaining files once every query has been emitted
+                if is_exhausted(seen.as_deref(), limit.as_ref()) || budget.skip_file() {
+                    return
+                }
 
-                // depending on the query input format
-                let (nm, np) = match table {
-
-                    // filter molecules without considering query score
-                    QueryFormat::WithoutScore(ref t) => {
-                        grep_with_set(mol2_reader, &t, sender)
-                    },
+                let start = Instant::now();
 
-                    // filter molecules considering query score
-                    QueryFormat::WithScore(ref t) => {
-                        grep_with_map(mol2_reader, &t, tol, sender)
+                // instantiate a new mol2 reader
+                let mut mol2_reader = Mol2Reader::new(&x).unwrap();
+
+                // filter molecules against every query table
+                // a file is only read up to its first match when listing files with matches
+                let source: Arc<str> = Arc::from(x.as_str());
+                let first_match = files_with_matches.then(|| MatchLimit::new(1));
+                let file_limit = first_match.as_ref().or(limit.as_ref());
+
+                // each worker writes the matches of its file to the output of that file
+                // (opened on the first match, so files without matches leave no output)
+                let mut file_writer: Option<Box<dyn Write>> = None;
+                let mut sink = |hit: QueryHit| {
+                    if let (Some(output_filename), Some(_)) = (&per_input_output, &hit.1) {
+                        let w = file_writer.get_or_insert_with(|| {
+                            let mut w = writer(&per_input_filename(output_filename, &x))
+                                .expect("Error: Error creating output file");
+                            provenance.write(&mut w).expect("Error: Error writing to output file");
+                            w
+                        });
+                        w.write_all(hit.0.get_lines().as_bytes())
+                            .expect("Error: Error writing to output file");
                     }
+                    sender.send(hit).expect("Error: Broken Send Channel");
                 };
+                let (nm, np) = grep_reader(&mut mol2_reader, &matcher, seen.as_deref(), file_limit, (index, &source), &mut sink, &gauge);
+                if let Some(mut w) = file_writer {
+                    w.flush().expect("Error: Error writing to output file");
+                }
 
                 *num_molecules.lock().unwrap() += nm;
                 *num_passing.lock().unwrap() += np;
+                budget.add_molecules(nm as u64);
+
+                file_reports.lock().unwrap().push(FileReport {
+                    index,
+                    bytes: file_size(&x),
+                    filename: x,
+                    molecules: nm,
+                    matches: np,
+                    seconds: start.elapsed().as_secs_f64(),
+                    truncated: mol2_reader.is_truncated()
+                });
 
             });
     });
 
-    // writes passing molecules to file
-    for mol in channel_recv {
-        writer_file
-            .write_all(
-                mol.get_lines().as_bytes()
-            )
-            .expect(
-                "Error: Error writing to output file"
-            )
+    // writes passing molecules to the output of each matching query
+    // (buffering by name if requested)
+    let mut groups: Vec<NameGroups> = writers
+        .iter()
+        .map(|_| NameGroups::default())
+        .collect();
+    let mut by_name = NameGroups::default();
+    let mut shards = options.max_per_file.map(|x| Shards::new(output_filename, x));
+    // name lists are plain text without provenance comments
+    let shard_header = (!options.names_only).then_some(&*provenance_fmt);
+    let mut poses_per_name: HashMap<String, u32> = HashMap::new();
+    let mut hook = options.exec.clone().map(ExecHook::new);
+    // poses are counted per name for the alias report and to find the queries never matched
+    let count_names = options.alias.is_some() || !options.invert;
+    // the order in which workers send molecules varies between runs, so it is
+    // restored from their positions within the inputs when requested
+    // (best poses are kept in input order as well)
+    let mut buffered_bytes = 0;
+    let mut num_best = None;
+    let received: Box<dyn Iterator<Item = QueryHit>> = if options.best_per_id {
+        let kept = best_hits(channel_recv.into_iter(), options.ignore_case);
+        buffered_bytes = kept.iter().map(|x| x.0.get_lines().len() as u64).sum();
+        num_best = Some(kept.len());
+        Box::new(kept.into_iter())
+    } else if options.ordered {
+        let mut buffered: Vec<QueryHit> = channel_recv.into_iter().collect();
+        buffered.sort_unstable_by_key(|(_, _, _, position)| *position);
+        buffered_bytes = buffered.iter().map(|x| x.0.get_lines().len() as u64).sum();
+        Box::new(buffered.into_iter())
+    } else {
+        Box::new(channel_recv.into_iter())
+    };
+    for (mut mol, hits, source, _) in received {
+        gauge_fmt.pop(mol.get_lines().len() as u64);
+        if options.annotate_source {
+            mol.insert_header("Source", &source);
+        }
+        let hits = match hits {
+            Some(x) => x,
+            None => {
+                write_molecule(&mol, rest_idx, &mut groups, &mut writers, options);
+                continue;
+            }
+        };
+        if count_names {
+            *poses_per_name.entry(lookup_name(mol.get_name(), options.ignore_case).into_owned()).or_insert(0) += 1;
+        }
+        if options.count_only || options.files_with_matches {
+            continue;
+        }
+        if let Some(ref mut h) = hook {
+            h.push(&mol)?;
+        }
+        // matches were already written by the worker reading their file
+        if options.per_input_output {
+            continue;
+        }
+        if options.split_by_query {
+            by_name.push(mol);
+            continue;
+        }
+        let targets = if options.per_query_output {
+            hits
+        } else if grouped {
+            query_groups.of(&lookup_name(mol.get_name(), options.ignore_case)).to_vec()
+        } else {
+            vec![0]
+        };
+        for t in targets {
+            if let (Some(s), false) = (shards.as_mut(), options.group_output) {
+                s.claim(&mut writers[t], shard_header)?;
+            }
+            write_molecule(&mol, t, &mut groups, &mut writers, options);
+        }
+    };
+
+    let group_bytes: u64 = groups.iter().map(|g| g.num_bytes()).sum();
+    for (idx, (group, writer_file)) in groups.into_iter().zip(writers.iter_mut()).enumerate() {
+        for mol in group.into_molecules() {
+            if let (Some(s), true) = (shards.as_mut(), idx != rest_idx) {
+                s.claim(writer_file, shard_header)?;
+            }
+            writer_file
+                .write_all(
+                    output_text(&mol, options).as_bytes()
+                )
+                .expect(
+                    "Error: Error writing to output file"
+                )
+        }
     };
 
+    outputs.finish(writers)?;
+
+    // writes the poses of every name to its own output, one output open at a time
+    let by_name_bytes = by_name.num_bytes();
+    let mut num_split = 0;
+    for poses in by_name.into_groups() {
+        let mut w = writer(&per_group_filename(output_filename, poses[0].get_name()))?;
+        if !options.names_only {
+            provenance_fmt.write(&mut w)?;
+        }
+        for mol in poses.iter() {
+            w.write_all(output_text(mol, options).as_bytes())?;
+        }
+        w.flush()?;
+        num_split += 1;
+    }
+    if let Some(s) = shards {
+        println!(">>> Number of Output Shards: {}", s.num_shards);
+        println!(">>> Output Shards Written to: {}", insert_label(output_filename, "<shard>"));
+    }
+    if options.split_by_query {
+        println!(">>> Number of Per-Query Outputs: {}", num_split);
+        println!(">>> Per-Query Outputs Written to: {}", insert_label(output_filename, "<name>"));
+    }
+
+    if let Some(h) = hook {
+        h.finish()?;
+    }
+    if let Some(ref archive) = options.archive {
+        println!(">>> Outputs Archived in: {}", archive);
+    }
+    if options.per_input_output {
+        println!(">>> Per-Input Outputs Written to: {}", per_input_filename(output_filename, "*"));
+    }
+    if let Some(ref rest_filename) = options.rest_out {
+        println!(">>> Molecules Not Accepted Written to: {}", rest_filename);
+    }
+
+    // reports the matches of every input file in input order
+    if options.count_only {
+        let mut reports = file_reports_fmt.lock().unwrap();
+        reports.sort_by_key(|r| r.index);
+        for r in reports.iter() {
+            println!("{}\t{}", r.filename, r.matches);
+        }
+    }
+
+    // lists the input files holding a match in input order
+    if options.files_with_matches {
+        let mut reports = file_reports_fmt.lock().unwrap();
+        reports.sort_by_key(|r| r.index);
+        reports
+            .iter()
+            .filter(|r| r.matches > 0)
+            .for_each(|r| println!("{}", r.filename));
+    }
+
     println!(
         ">>> Number of Molecules Processed: {}",
         num_molecules_fmt.lock().unwrap()
@@ -153,6 +1014,93 @@ pub fn grep(
         num_passing_fmt.lock().unwrap()
     );
 
+    if let Some(n) = num_best {
+        println!(">>> Number of Best Poses Written: {}", n);
+    }
+
+    let num_truncated = file_reports_fmt
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|r| r.truncated)
+        .count();
+    if num_truncated > 0 {
+        println!(">>> Number of Truncated Files (partial results kept): {}", num_truncated);
+    }
+    budget_fmt.report(num_files);
+
+    if options.stats_memory {
+        let mut stages = vec![("Query Table Size (estimated)", table_bytes)];
+        if options.group_output {
+            stages.push(("Grouped Output Buffer", group_bytes));
+        }
+        if options.split_by_query {
+            stages.push(("Per-Query Output Buffer", by_name_bytes));
+        }
+        if options.best_per_id {
+            stages.push(("Best Pose Buffer", buffered_bytes));
+        } else if options.ordered {
+            stages.push(("Ordered Output Buffer", buffered_bytes));
+        }
+        print_memory_stats(&stages, &gauge_fmt);
+    }
+
+    if options.per_query_output {
+        output_filenames
+            .iter()
+            .for_each(|x| println!(">>> Query Output Written to: {}", x));
+    }
+    if grouped {
+        println!(">>> Number of Query Groups: {}", query_groups.labels.len());
+        output_filenames
+            .iter()
+            .for_each(|x| println!(">>> Group Output Written to: {}", x));
+    }
+
+    // number of poses matched by a query name
+    let poses_of = |name: &str| *poses_per_name
+        .get(lookup_name(name, options.ignore_case).as_ref())
+        .unwrap_or(&0);
+
+    // reports matches under the original query codes when aliasing
+    if options.alias.is_some() {
+        let alias_report = alias_report_filename(output_filename);
+        write_alias_report(&alias_report, &aliased, &poses_of)?;
+        println!(">>> Alias Report Written to: {}", alias_report);
+    }
+
+    // reports the query names no molecule matched (inverted matches are never query names)
+    if !options.invert {
+        let mut query_names: Vec<&str> = match original_names {
+            Some(ref x) => x.iter().map(|x| x.as_str()).collect(),
+            None => matcher_fmt.tables
+                .iter()
+                .flat_map(|t| t.names())
+                .collect()
+        };
+        query_names.sort_unstable();
+        query_names.dedup();
+        if let Some(ref counts_filename) = options.query_counts {
+            write_query_counts(counts_filename, &query_names, &poses_of)?;
+            println!(">>> Per-Query Match Counts Written to: {}", counts_filename);
+        }
+        let missing: Vec<&str> = query_names
+            .into_iter()
+            .filter(|x| poses_of(x) == 0)
+            .collect();
+        println!(">>> Number of Queries Never Found: {}", missing.len());
+        write_missing_report(options.missing_out.as_deref(), &missing)?;
+        if let Some(ref missing_filename) = options.missing_out {
+            println!(">>> Missing Queries Written to: {}", missing_filename);
+        }
+    }
+
+    // writes per-file statistics if requested
+    if let Some(ref report_filename) = options.file_report {
+        write_file_report(report_filename, &mut file_reports_fmt.lock().unwrap())?;
+        println!(">>> Per-File Report Written to: {}", report_filename);
+    }
+
     let result = *num_passing_fmt
         .lock()
         .unwrap();
@@ -160,11 +1108,29 @@ pub fn grep(
     Ok(result)
 }
 
+// returns the filename of a given split output
+// (a prefix containing `{}` is a template whose suffix sets the compression of each shard)
+pub fn split_filename(prefix: &str, file_id: usize) -> String {
+    if prefix.contains("{}") {
+        prefix.replace("{}", &format!("{:04}", file_id))
+    } else {
+        format!("{}.{:04}.mol2.gz", prefix, file_id)
+    }
+}
+
 // implements split subcommand
 pub fn split(
         input_files: Vec<String>,
         prefix: &str,
-        num_files: usize) -> Result<Vec<u32>, Error> {
+        num_files: usize,
+        options: &SplitOptions) -> Result<Vec<u32>, Error> {
+
+        let provenance = Provenance::new(&input_files)?;
+
+        // stops scheduling new files once the budget is spent
+        let num_inputs = input_files.len();
+        let budget = Arc::new(BudgetTracker::new(options.budget));
+        let budget_fmt = budget.clone();
 
         // Instantiate Send/Receive Channels
         let (channel_send, channel_recv): (Sender<Mol2>, Receiver<Mol2>) = mpsc::channel();
@@ -179,31 +1145,37 @@ pub fn split(
                 .par_bridge()
                 .for_each_with(channel_send, |sender, x| {
 
+                    if budget.skip_file() {
+                        return
+                    }
+
                     // instantiate a new mol2 reader
                     let mol2_reader = Mol2Reader::new(&x).unwrap();
 
+                    let mut nm = 0;
                     mol2_reader
                         .into_iter()
                         .for_each(|x|{
                             sender.send(x).expect("Error in sending through channel");
-                        })
+                            nm += 1;
+                        });
+                    budget.add_molecules(nm);
 
                 });
         });
 
-        let mut writer_vec: Vec<Box<dyn Write>> = (0..num_files)
-            .into_iter()
-            .map(|i| {
-                writer(&format!("{}.{:04}.mol2.gz", prefix, i))
-            })
+        let output_filenames: Vec<String> = (0..num_files)
+            .map(|i| split_filename(prefix, i))
             .collect();
+        let (outputs, mut writer_vec) = OutputSet::open(&output_filenames, options.archive.as_deref())?;
+        for w in writer_vec.iter_mut() {
+            provenance.write(w)?;
+        }
 
         let mut count_vec = vec![0; num_files];
 
 
-        let mut num_molecules = 0;
-
-        for mol in channel_recv {
+        for (num_molecules, mol) in channel_recv.into_iter().enumerate() {
 
             let file_id = num_molecules % num_files;
 
@@ -211,24 +1183,202 @@ pub fn split(
                 .write_all(mol.get_lines().as_bytes())
                 .expect("Error in writing to output file");
             count_vec[file_id] += 1;
-
-            num_molecules += 1;
         };
 
+        outputs.finish(writer_vec)?;
+
         println!("\nFile Totals:");
         (0..num_files)
-            .into_iter()
             .for_each(|i| {
-                println!("  {}.{:04}.mol2.gz:\t{}", prefix, i, count_vec[i])
+                println!("  {}:\t{}", split_filename(prefix, i), count_vec[i])
             });
+        budget_fmt.report(num_inputs);
 
         Ok(count_vec)
 }
 
-pub fn table(
-        input_files: Vec<String>,
-        output_filename: &str,
-        write_header: bool) -> Result<(), Error> {
+// Options modifying the behavior of the table subcommand
+#[derive(Default)]
+pub struct TableOptions {
+    pub write_header: bool,
+    pub sample: Option<Sampler>,
+    pub long: bool,
+    pub pretty: bool,
+    pub limit: Option<usize>,
+    pub geometry: bool,
+    // records the input file of every pose
+    pub file_column: bool,
+    // writes one column per header field instead of the name and energy
+    pub all_fields: bool,
+    // columns written in order, built-in columns or header fields (e.g. `name,energy,van_der_waals,file`)
+    pub fields: Option<Vec<String>>
+}
+
+// Column names of the geometric descriptors
+const GEOMETRY_COLUMNS: [&str; 4] = ["centroid_x", "centroid_y", "centroid_z", "radius_of_gyration"];
+
+// Returns the formatted geometric descriptors of a molecule (empty if it has no atoms)
+fn geometry_values(mol: &Mol2) -> Vec<String> {
+    match mol.geometry() {
+        Some(g) => vec![
+            format!("{:.4}", g.centroid.0),
+            format!("{:.4}", g.centroid.1),
+            format!("{:.4}", g.centroid.2),
+            format!("{:.4}", g.radius_of_gyration)
+        ],
+        None => vec![String::new(); GEOMETRY_COLUMNS.len()]
+    }
+}
+
+// Returns the value of a column for a molecule read from a given input file:
+// a built-in or geometric column, or else a header field (empty if the molecule lacks it)
+fn column_value(
+        column: &str,
+        mol: &Mol2,
+        properties: &HashMap<String, &str>,
+        ligand_id: u32,
+        pose_index: u32,
+        file: &str) -> String {
+
+    match column {
+        "ligand_id" => ligand_id.to_string(),
+        "name" => mol.get_name().to_string(),
+        "energy" => mol.get_energy().to_string(),
+        "pose_index" => pose_index.to_string(),
+        "file" => file.to_string(),
+        x => match GEOMETRY_COLUMNS.iter().position(|c| *c == x) {
+            Some(idx) => geometry_values(mol).swap_remove(idx),
+            None => properties.get(x).unwrap_or(&"").to_string()
+        }
+    }
+}
+
+// Returns the column names of the table (`columns` are the selected columns, if any)
+fn table_header(options: &TableOptions, columns: &[String]) -> Vec<String> {
+    if options.long {
+        return ["name", "pose_index", "field", "value"].iter().map(|x| x.to_string()).collect()
+    }
+    let defaults: Vec<String> = ["ligand_id", "name", "energy"].iter().map(|x| x.to_string()).collect();
+    let columns: &[String] = if columns.is_empty() {
+        &defaults
+    } else {
+        columns
+    };
+    let geometry: &[&str] = if options.geometry && !options.long {
+        &GEOMETRY_COLUMNS
+    } else {
+        &[]
+    };
+    let file: &[&str] = if options.file_column && !options.long {
+        &["file"]
+    } else {
+        &[]
+    };
+    columns
+        .iter()
+        .cloned()
+        .chain(geometry.iter().chain(file.iter()).map(|x| x.to_string()))
+        .collect()
+}
+
+// Returns the table rows describing a molecule read from a given input file
+// (`columns` are the selected columns, if any)
+fn table_rows(mol: &Mol2, ligand_id: u32, pose_index: u32, file: &str, columns: &[String], options: &TableOptions) -> Vec<Vec<String>> {
+    if options.long {
+
+        // one row per header field: name, pose_index, field, value
+        let mut fields: Vec<(String, String)> = mol.get_properties()
+            .iter()
+            .map(|(k, v)| (normalize_field(k), v.to_string()))
+            .collect();
+        if options.geometry {
+            fields.extend(
+                GEOMETRY_COLUMNS
+                    .iter()
+                    .map(|x| x.to_string())
+                    .zip(geometry_values(mol))
+            );
+        }
+        if options.file_column {
+            fields.push(("file".to_string(), file.to_string()));
+        }
+        fields
+            .into_iter()
+            .map(|(k, v)| {
+                vec![
+                    mol.get_name().to_string(),
+                    pose_index.to_string(),
+                    k,
+                    v
+                ]
+            })
+            .collect()
+
+    } else {
+        let mut row = if !columns.is_empty() {
+            let properties = mol.property_map();
+            columns
+                .iter()
+                .map(|x| column_value(x, mol, &properties, ligand_id, pose_index, file))
+                .collect()
+        } else {
+            vec![
+                ligand_id.to_string(),
+                mol.get_name().to_string(),
+                mol.get_energy().to_string()
+            ]
+        };
+        if options.geometry {
+            row.extend(geometry_values(mol));
+        }
+        if options.file_column {
+            row.push(file.to_string());
+        }
+        vec![row]
+    }
+}
+
+// streams molecules tagged with their (file index, record index) sequentially and in order
+pub fn stream_tagged_molecules(input_files: Vec<String>) -> Receiver<(usize, usize, Mol2)> {
+
+    // Instantiate Send/Receive Channels
+    let (channel_send, channel_recv) = mpsc::channel();
+
+    // places molecules into writer channel
+    thread::spawn(move || {
+
+        // iterate through input files in order
+        for (file_index, x) in input_files.iter().enumerate() {
+
+            // instantiate a new mol2 reader
+            let mol2_reader = Mol2Reader::new(x).unwrap();
+
+            // stops quietly once the receiver is no longer listening
+            let sent = mol2_reader
+                .into_iter()
+                .enumerate()
+                .try_for_each(|(record_index, x)| channel_send.send((file_index, record_index, x)));
+
+            if sent.is_err() {
+                break;
+            }
+        }
+    });
+
+    channel_recv
+}
+
+// streams molecules from input files sequentially and in order through a channel
+pub fn stream_molecules(input_files: Vec<String>) -> impl Iterator<Item = Mol2> {
+    stream_tagged_molecules(input_files)
+        .into_iter()
+        .map(|(_, _, mol)| mol)
+}
+
+// streams molecules passing a predicate from input files in parallel through a channel
+// the predicate receives the file index, the record index within the file, and the molecule
+pub fn par_stream_filtered<F>(input_files: Vec<String>, predicate: F) -> Receiver<Mol2>
+    where F: Fn(usize, usize, &Mol2) -> bool + Send + Sync + 'static {
 
     // Instantiate Send/Receive Channels
     let (channel_send, channel_recv): (Sender<Mol2>, Receiver<Mol2>) = mpsc::channel();
@@ -238,47 +1388,132 @@ pub fn table(
 
         // iterate through input files in parallel
         input_files
-            .iter()
-            .for_each(|x| {
+            .into_iter()
+            .enumerate()
+            .progress()
+            .par_bridge()
+            .for_each_with(channel_send, |sender, (file_index, x)| {
 
                 // instantiate a new mol2 reader
                 let mol2_reader = Mol2Reader::new(&x).unwrap();
 
                 mol2_reader
                     .into_iter()
-                    .for_each(|x|{
-                        channel_send.send(x).expect("Error in sending through channel");
-                    })
+                    .enumerate()
+                    .filter(|(record_index, mol)| predicate(file_index, *record_index, mol))
+                    .try_for_each(|(_, mol)| sender.send(mol))
+                    .ok();
 
             });
     });
 
-    // Instantiate Writer
-    let mut writer = writer(output_filename);
+    channel_recv
+}
+
+// implements table subcommand
+pub fn table(
+        input_files: Vec<String>,
+        output_filename: &str,
+        options: &TableOptions) -> Result<u32, Error> {
+
+    if options.fields.as_ref().is_some_and(|x| x.is_empty() || x.iter().any(|c| c.is_empty())) {
+        return Err(Error::new(ErrorKind::InvalidInput, "the selected fields hold an empty column name"))
+    }
+
+    // places molecules into receiving channel
+    let filenames = input_files.clone();
+    let mut molecules = stream_tagged_molecules(input_files)
+        .into_iter()
+        .peekable();
+
+    // selected columns, where all header fields follow the fields of the first pose
+    let fields: Vec<String> = match (&options.fields, options.all_fields) {
+        (Some(x), _) => x.clone(),
+        (None, true) => {
+            let first: Vec<String> = molecules
+                .peek()
+                .map(|(_, _, mol)| mol.get_properties().iter().map(|(k, _)| normalize_field(k)).collect())
+                .unwrap_or_default();
+            let mut seen = HashSet::new();
+            std::iter::once("ligand_id".to_string())
+                .chain(first)
+                .filter(|x| seen.insert(x.clone()))
+                .collect()
+        },
+        (None, false) => Vec::new()
+    };
+
+    // Instantiate Writer (pretty tables are rendered to the terminal instead)
+    let mut writer = if options.pretty {
+        None
+    } else {
+        Some(writer(output_filename)?)
+    };
+    let mut pretty_rows = Vec::new();
 
     // Writer a header if no_header flag isn't present
-    if write_header {
-        writer
-            .write_all(
-                &format!("ligand_id\tname\tenergy\n").into_bytes()
-            )
-            .expect("Error in writing to output file");
+    if options.write_header {
+        let header = table_header(options, &fields);
+        match writer {
+            Some(ref mut w) => {
+                w.write_all(format!("{}\n", header.join("\t")).as_bytes())
+                    .expect("Error in writing to output file");
+            },
+            None => pretty_rows.push(header)
+        }
     }
 
-    let mut ligand_id = 0;
-    for mol in channel_recv {
+    // number of poses seen per name
+    let mut pose_counts: HashMap<String, u32> = HashMap::new();
 
-        writer
-            .write_all(
-                &format!("{}\t{}\t{}\n", ligand_id, mol.get_name(), mol.get_energy()).into_bytes()
-            )
-            .expect("Error in writing to output file");
+    let mut ligand_id = 0;
+    let mut num_written = 0;
+    let mut num_rows = 0;
+    'molecules: for (file_index, record_index, mol) in molecules {
+
+        // skip molecules outside of the requested subsample
+        let keep = options.sample
+            .as_ref()
+            .is_none_or(|s| s.keep(mol.get_name(), sequence_id(file_index, record_index)));
+
+        if keep {
+            let pose_index = pose_counts
+                .entry(mol.get_name().to_string())
+                .or_insert(0);
+            *pose_index += 1;
+
+            for row in table_rows(&mol, ligand_id, *pose_index - 1, &filenames[file_index], &fields, options) {
+
+                // stop reading once the row limit is reached
+                if options.limit.is_some_and(|l| num_rows >= l) {
+                    break 'molecules;
+                }
+
+                match writer {
+                    Some(ref mut w) => {
+                        w.write_all(format!("{}\n", row.join("\t")).as_bytes())
+                            .expect("Error in writing to output file");
+                    },
+                    None => pretty_rows.push(row)
+                }
+                num_rows += 1;
+            }
+            num_written += 1;
+        }
 
         ligand_id += 1;
     };
 
+    if options.pretty {
+        print_aligned(&pretty_rows);
+        return Ok(num_written)
+    }
+
     println!("\n Total Poses: {}", ligand_id);
+    if options.sample.is_some() {
+        println!(" Sampled Poses: {}", num_written);
+    }
     println!(" Written to: {}", output_filename);
 
-    Ok(())
+    Ok(num_written)
 }
@@ -0,0 +1,151 @@
+
+use std::io::Error;
+
+use crate::mol2::Mol2Reader;
+
+use plotters::prelude::*;
+use rayon::prelude::*;
+
+const FIGURE_SIZE: (u32, u32) = (900, 540);
+
+// Converts a drawing error into an io error
+fn plot_error<E: std::fmt::Display>(e: E) -> Error {
+    Error::other(format!("failed drawing figure: {}", e))
+}
+
+// Reads the energies of every molecule of each input file
+pub fn file_energies(input_files: &[String]) -> Vec<(String, Vec<f64>)> {
+    input_files
+        .par_iter()
+        .map(|x| {
+            let energies = Mol2Reader::new(x)
+                .unwrap()
+                .map(|mol| mol.get_energy())
+                .collect();
+            (x.clone(), energies)
+        })
+        .collect()
+}
+
+// Draws a histogram of energies into an SVG file
+pub fn energy_histogram(energies: &[f64], num_bins: usize, filename: &str) -> Result<(), Error> {
+    let min = energies.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = energies.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    if energies.is_empty() || num_bins == 0 {
+        return Err(Error::other("no energies to plot"))
+    }
+
+    // a single distinct value still gets a visible bin
+    let width = if max > min { (max - min) / num_bins as f64 } else { 1.0 };
+    let mut counts = vec![0u32; num_bins];
+    for e in energies {
+        let bin = (((e - min) / width) as usize).min(num_bins - 1);
+        counts[bin] += 1;
+    }
+    let max_count = counts.iter().copied().max().unwrap_or(0);
+
+    let root = SVGBackend::new(filename, FIGURE_SIZE).into_drawing_area();
+    root.fill(&WHITE).map_err(plot_error)?;
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption(format!("Energy distribution (n = {})", energies.len()), ("sans-serif", 20))
+        .margin(15)
+        .x_label_area_size(40)
+        .y_label_area_size(60)
+        .build_cartesian_2d(min..min + width * num_bins as f64, 0u32..max_count + max_count / 20 + 1)
+        .map_err(plot_error)?;
+
+    chart.configure_mesh()
+        .x_desc("Total Energy")
+        .y_desc("Poses")
+        .disable_x_mesh()
+        .draw()
+        .map_err(plot_error)?;
+
+    chart.draw_series(
+        counts.iter().enumerate().map(|(i, c)| {
+            let x0 = min + i as f64 * width;
+            Rectangle::new([(x0, 0), (x0 + width, *c)], BLUE.mix(0.6).filled())
+        })
+    ).map_err(plot_error)?;
+
+    root.present().map_err(plot_error)
+}
+
+// Draws a box summary (quartiles and range) of the energies of each file into an SVG file
+pub fn file_boxes(per_file: &[(String, Vec<f64>)], filename: &str) -> Result<(), Error> {
+    let summaries: Vec<(String, Quartiles)> = per_file
+        .iter()
+        .filter(|(_, e)| !e.is_empty())
+        .map(|(x, e)| {
+            let label = std::path::Path::new(x)
+                .file_name()
+                .and_then(|f| f.to_str())
+                .unwrap_or(x)
+                .to_string();
+            (label, Quartiles::new(e))
+        })
+        .collect();
+    if summaries.is_empty() {
+        return Err(Error::other("no energies to plot"))
+    }
+
+    let min = summaries.iter().map(|(_, q)| q.values()[0]).fold(f32::INFINITY, f32::min);
+    let max = summaries.iter().map(|(_, q)| q.values()[4]).fold(f32::NEG_INFINITY, f32::max);
+    let pad = ((max - min) * 0.05).max(0.5);
+    let labels: Vec<String> = summaries.iter().map(|(x, _)| x.clone()).collect();
+
+    let root = SVGBackend::new(filename, FIGURE_SIZE).into_drawing_area();
+    root.fill(&WHITE).map_err(plot_error)?;
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption("Energy by file", ("sans-serif", 20))
+        .margin(15)
+        .x_label_area_size(60)
+        .y_label_area_size(60)
+        .build_cartesian_2d(labels[..].into_segmented(), (min - pad)..(max + pad))
+        .map_err(plot_error)?;
+
+    chart.configure_mesh()
+        .y_desc("Total Energy")
+        .disable_x_mesh()
+        .draw()
+        .map_err(plot_error)?;
+
+    chart.draw_series(
+        summaries.iter().zip(labels.iter()).map(|((_, q), label)| {
+            Boxplot::new_vertical(SegmentValue::CenterOf(label), q)
+        })
+    ).map_err(plot_error)?;
+
+    root.present().map_err(plot_error)
+}
+
+// Draws a ROC curve (false positive rate, true positive rate) into an SVG file
+pub fn roc_curve(roc: &[(f64, f64, f64)], auc: f64, filename: &str) -> Result<(), Error> {
+    let root = SVGBackend::new(filename, (FIGURE_SIZE.1, FIGURE_SIZE.1)).into_drawing_area();
+    root.fill(&WHITE).map_err(plot_error)?;
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption(format!("ROC (AUC = {:.3})", auc), ("sans-serif", 20))
+        .margin(15)
+        .x_label_area_size(40)
+        .y_label_area_size(50)
+        .build_cartesian_2d(0f64..1f64, 0f64..1f64)
+        .map_err(plot_error)?;
+
+    chart.configure_mesh()
+        .x_desc("False positive rate")
+        .y_desc("True positive rate")
+        .draw()
+        .map_err(plot_error)?;
+
+    // random ranking
+    chart.draw_series(LineSeries::new(vec![(0.0, 0.0), (1.0, 1.0)], BLACK.mix(0.3)))
+        .map_err(plot_error)?;
+
+    chart.draw_series(LineSeries::new(roc.iter().map(|(x, y, _)| (*x, *y)), RED.stroke_width(2)))
+        .map_err(plot_error)?;
+
+    root.present().map_err(plot_error)
+}
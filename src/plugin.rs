@@ -0,0 +1,101 @@
+
+use std::io::{Error, ErrorKind};
+
+use crate::mol2::Mol2;
+
+// Trait describing a custom per-molecule filter
+//
+// This is the plugin interface: implementations receive every parsed molecule
+// and decide whether it is kept. Implementations must be shareable between the
+// worker threads.
+pub trait MoleculeFilter: Send + Sync {
+
+    // Returns true if the molecule is kept (or the error that prevented deciding)
+    fn keep(&self, mol: &Mol2) -> Result<bool, Error>;
+}
+
+// Filter evaluating a `keep(mol)` function defined in a Rhai script
+//
+// The molecule is passed as a map of `name`, `energy`, `properties` (a map of
+// header fields), and `atoms` (an array of maps of `x`, `y`, `z`, `type`,
+// `element`, and `charge`).
+#[cfg(feature = "plugins")]
+pub struct ScriptFilter {
+    engine: rhai::Engine,
+    ast: rhai::AST
+}
+#[cfg(feature = "plugins")]
+impl ScriptFilter {
+
+    // Instantiate a new ScriptFilter from a script file
+    pub fn from_file(filename: &str) -> Result<Self, Error> {
+        let engine = rhai::Engine::new();
+        let ast = engine
+            .compile_file(filename.into())
+            .map_err(|e| Error::new(ErrorKind::InvalidData, format!("{}: {}", filename, e)))?;
+
+        if !ast.iter_functions().any(|f| f.name == "keep" && f.params.len() == 1) {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("{}: script does not define fn keep(mol)", filename)
+            ))
+        }
+
+        Ok(ScriptFilter { engine, ast })
+    }
+
+    // Builds the script representation of a molecule
+    fn to_dynamic(mol: &Mol2) -> rhai::Map {
+        let properties: rhai::Map = mol.get_properties()
+            .iter()
+            .map(|(k, v)| (k.as_str().into(), v.clone().into()))
+            .collect();
+
+        let atoms: rhai::Array = mol.atoms()
+            .iter()
+            .map(|a| {
+                let mut atom = rhai::Map::new();
+                atom.insert("x".into(), a.x.into());
+                atom.insert("y".into(), a.y.into());
+                atom.insert("z".into(), a.z.into());
+                atom.insert("element".into(), a.element().to_string().into());
+                atom.insert("charge".into(), a.charge.into());
+                atom.insert("type".into(), a.atom_type.clone().into());
+                atom.into()
+            })
+            .collect();
+
+        let mut map = rhai::Map::new();
+        map.insert("name".into(), mol.get_name().to_string().into());
+        map.insert("energy".into(), mol.get_energy().into());
+        map.insert("properties".into(), properties.into());
+        map.insert("atoms".into(), atoms.into());
+        map
+    }
+}
+#[cfg(feature = "plugins")]
+impl MoleculeFilter for ScriptFilter {
+
+    fn keep(&self, mol: &Mol2) -> Result<bool, Error> {
+        self.engine
+            .call_fn::<bool>(&mut rhai::Scope::new(), &self.ast, "keep", (Self::to_dynamic(mol),))
+            .map_err(|e| Error::other(format!("Plugin failed on {}: {}", mol.get_name(), e)))
+    }
+}
+
+// Loads a filter plugin by its file extension
+pub fn load_plugin(filename: &str) -> Result<Box<dyn MoleculeFilter>, Error> {
+    match filename.rsplit('.').next() {
+        #[cfg(feature = "plugins")]
+        Some("rhai") => Ok(Box::new(ScriptFilter::from_file(filename)?)),
+        #[cfg(not(feature = "plugins"))]
+        Some("rhai") => Err(Error::new(
+            ErrorKind::Unsupported,
+            "script plugins require mol2grep to be built with the `plugins` feature"
+        )),
+        _ => Err(Error::new(
+            ErrorKind::Unsupported,
+            format!("{}: unsupported plugin type (expected a .rhai script)", filename)
+        ))
+    }
+}
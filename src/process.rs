@@ -0,0 +1,167 @@
+
+use std::io::{Error, ErrorKind};
+use std::io::prelude::*;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+use crate::mol2::{Mol2, Mol2Reader};
+use crate::file_io::writer;
+
+use rayon::prelude::*;
+
+// Decision of a visitor about the molecule it was given
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    // continue without writing the molecule
+    Skip,
+    // write the molecule to the output (if any) and continue
+    Keep,
+    // stop reading the current file
+    SkipFile,
+    // stop reading every file (files in flight stop at their next molecule)
+    Stop
+}
+
+// Options of `process_files`
+#[derive(Clone, Debug, Default)]
+pub struct ProcessOptions {
+    // worker threads (0: run on the current rayon pool)
+    pub num_threads: usize,
+    // file kept molecules are written to, compressed according to its suffix
+    pub output: Option<String>
+}
+
+// Struct describing the outcome of `process_files`
+#[derive(Debug, Default)]
+pub struct ProcessSummary {
+    pub num_files: usize,
+    pub num_molecules: u64,
+    pub num_kept: u64,
+    pub stopped: bool,
+    // files that could not be opened along with the reason
+    pub failed_files: Vec<(String, String)>
+}
+
+// Visits every molecule of a file until the visitor asks to stop
+fn visit_file<F>(
+        filename: &str,
+        visitor: &F,
+        stop: &AtomicBool,
+        num_molecules: &AtomicU64,
+        num_kept: &AtomicU64,
+        sender: Option<mpsc::SyncSender<Mol2>>) -> Result<(), Error>
+    where F: Fn(&Mol2) -> Action + Sync {
+
+    if stop.load(Ordering::Relaxed) {
+        return Ok(())
+    }
+
+    for mol in Mol2Reader::new(filename)? {
+        if stop.load(Ordering::Relaxed) {
+            break;
+        }
+        num_molecules.fetch_add(1, Ordering::Relaxed);
+        match visitor(&mol) {
+            Action::Skip => {},
+            Action::Keep => {
+                num_kept.fetch_add(1, Ordering::Relaxed);
+                if let Some(ref s) = sender {
+                    s.send(mol).map_err(|_| Error::new(ErrorKind::BrokenPipe, "output writer stopped"))?;
+                }
+            },
+            Action::SkipFile => break,
+            Action::Stop => {
+                stop.store(true, Ordering::Relaxed);
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+// Runs a visitor over every molecule of a set of mol2 files in parallel
+//
+// Files are read concurrently (molecules of one file are visited in order) and
+// molecules the visitor keeps are written to the optional output as they
+// arrive. Files that fail to open are reported in the summary rather than
+// aborting the run; failing to write the output is an error.
+//
+//     let summary = mol2grep::process_files(&files, &ProcessOptions::default(), |mol| {
+//         if mol.get_energy() < -40.0 { Action::Keep } else { Action::Skip }
+//     })?;
+pub fn process_files<F>(
+        input_files: &[String],
+        options: &ProcessOptions,
+        visitor: F) -> Result<ProcessSummary, Error>
+    where F: Fn(&Mol2) -> Action + Send + Sync {
+
+    let stop = AtomicBool::new(false);
+    let num_molecules = AtomicU64::new(0);
+    let num_kept = AtomicU64::new(0);
+
+    let pool = match options.num_threads {
+        0 => None,
+        n => Some(
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(n)
+                .build()
+                .map_err(|e| Error::other(e.to_string()))?
+        )
+    };
+
+    let (sender, receiver) = match options.output {
+        Some(_) => {
+            let (s, r) = mpsc::sync_channel::<Mol2>(1024);
+            (Some(s), Some(r))
+        },
+        None => (None, None)
+    };
+
+    let failed_files = thread::scope(|scope| {
+
+        // writes kept molecules as they arrive
+        let writer_thread = options.output
+            .as_deref()
+            .zip(receiver)
+            .map(|(x, r)| scope.spawn(move || -> Result<(), Error> {
+                let mut w = writer(x)?;
+                for mol in r {
+                    w.write_all(mol.get_lines().as_bytes())?;
+                }
+                w.flush()
+            }));
+
+        let run = || -> Vec<(String, String)> {
+            input_files
+                .par_iter()
+                .filter_map(|x| {
+                    visit_file(x, &visitor, &stop, &num_molecules, &num_kept, sender.clone())
+                        .err()
+                        .map(|e| (x.clone(), e.to_string()))
+                })
+                .collect()
+        };
+        let failed_files = match pool {
+            Some(ref p) => p.install(run),
+            None => run()
+        };
+        drop(sender);
+
+        match writer_thread {
+            Some(handle) => handle
+                .join()
+                .map_err(|_| Error::other("output writer panicked"))?
+                .map(|_| failed_files),
+            None => Ok(failed_files)
+        }
+    })?;
+
+    Ok(ProcessSummary {
+        num_files: input_files.len(),
+        num_molecules: num_molecules.into_inner(),
+        num_kept: num_kept.into_inner(),
+        stopped: stop.into_inner(),
+        failed_files
+    })
+}
@@ -0,0 +1,155 @@
+
+use std::fs::File;
+use std::io::{BufReader, Error};
+use std::io::prelude::*;
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::file_io::{RetryReader, is_stdin};
+use crate::archive;
+use crate::remote::is_remote;
+
+use rayon::prelude::*;
+
+// Prefix of provenance comment lines (skipped by the reader)
+pub const PROVENANCE_PREFIX: &str = "#@";
+
+// Whether inputs are identified by a hash of their contents (set from the CLI)
+static HASH_INPUTS: OnceLock<bool> = OnceLock::new();
+
+// Identifies the inputs of all subsequent provenance blocks by a content hash
+pub fn set_hash_inputs(hash: bool) {
+    HASH_INPUTS.set(hash).ok();
+}
+
+// Enum describing how an input is identified in a provenance block
+enum InputRecord {
+    Stdin,
+    Remote,
+    // size in bytes and modification time in seconds since the unix epoch
+    Stat(u64, u64),
+    Hash(u64)
+}
+impl InputRecord {
+
+    // Identifies a file by its size and modification time, or by the hash of its contents
+    fn of(filename: &str, hash: bool) -> Result<Self, Error> {
+        if is_stdin(filename) {
+            return Ok(InputRecord::Stdin)
+        }
+        if is_remote(filename) {
+            return Ok(InputRecord::Remote)
+        }
+        if hash {
+            return Ok(InputRecord::Hash(file_hash(filename)?))
+        }
+        // archive members are identified by their archive
+        let path = archive::split_member_name(filename).map_or(filename, |(x, _)| x);
+        let metadata = std::fs::metadata(path)?;
+        let mtime = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        Ok(InputRecord::Stat(metadata.len(), mtime))
+    }
+}
+
+// Struct describing the run that produced an output file
+pub struct Provenance {
+    version: String,
+    command: String,
+    timestamp: String,
+    inputs: Vec<(String, InputRecord)>
+}
+impl Provenance {
+
+    // Instantiate a new Provenance for the current process and its inputs
+    pub fn new(input_files: &[String]) -> Result<Self, Error> {
+        Self::with_hashes(input_files, HASH_INPUTS.get().copied().unwrap_or(false))
+    }
+
+    // Instantiate a new Provenance identifying the inputs by a content hash if requested
+    // (hashing reads every input in full)
+    pub fn with_hashes(input_files: &[String], hash: bool) -> Result<Self, Error> {
+        let inputs = input_files
+            .par_iter()
+            .map(|x| Ok((x.clone(), InputRecord::of(x, hash)?)))
+            .collect::<Result<Vec<(String, InputRecord)>, Error>>()?;
+
+        let seconds = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+
+        Ok(Provenance {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            command: std::env::args().collect::<Vec<String>>().join(" "),
+            timestamp: format_utc(seconds),
+            inputs
+        })
+    }
+
+    // Writes the provenance as a leading block of comment lines
+    pub fn write<W: Write + ?Sized>(&self, writer: &mut W) -> Result<(), Error> {
+        let p = PROVENANCE_PREFIX;
+        writeln!(writer, "{} mol2grep-version: {}", p, self.version)?;
+        writeln!(writer, "{} command: {}", p, self.command)?;
+        writeln!(writer, "{} timestamp: {}", p, self.timestamp)?;
+        for (filename, record) in self.inputs.iter() {
+            match record {
+                InputRecord::Stat(size, mtime) => writeln!(
+                    writer, "{} input: {}\tsize:{}\tmtime:{}", p, filename, size, format_utc(*mtime)
+                )?,
+                InputRecord::Hash(h) => writeln!(writer, "{} input: {}\tfnv1a64:{:016x}", p, filename, h)?,
+                InputRecord::Stdin => writeln!(writer, "{} input: {}\tstdin", p, filename)?,
+                InputRecord::Remote => writeln!(writer, "{} input: {}\tremote", p, filename)?
+            }
+        }
+        Ok(())
+    }
+}
+
+// Hashes the bytes of a file with 64 bit FNV-1a
+fn file_hash(filename: &str) -> Result<u64, Error> {
+    let inner: Box<dyn Read> = match archive::split_member_name(filename) {
+        Some(_) => Box::new(archive::open_member(filename)?),
+        None => Box::new(RetryReader::new(File::open(filename)?))
+    };
+    let mut reader = BufReader::with_capacity(1 << 20, inner);
+    let mut hash: u64 = 0xcbf29ce484222325;
+    loop {
+        let buffer = reader.fill_buf()?;
+        if buffer.is_empty() {
+            break;
+        }
+        for b in buffer {
+            hash = (hash ^ *b as u64).wrapping_mul(0x100000001b3);
+        }
+        let n = buffer.len();
+        reader.consume(n);
+    }
+    Ok(hash)
+}
+
+// Formats seconds since the unix epoch as an ISO 8601 UTC timestamp
+pub fn format_utc(seconds: u64) -> String {
+    let days = (seconds / 86400) as i64;
+    let rem = seconds % 86400;
+
+    // civil date from days since epoch (Howard Hinnant's algorithm)
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year, month, day, rem / 3600, (rem % 3600) / 60, rem % 60
+    )
+}
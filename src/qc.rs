@@ -0,0 +1,295 @@
+
+use std::fs::File;
+use std::io::Error;
+use std::io::BufWriter;
+use std::io::prelude::*;
+use std::collections::{BTreeMap, HashMap};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use crate::mol2::{Mol2, Mol2Reader};
+
+use rayon::prelude::*;
+
+// Hashes the atom types and coordinates of a pose rounded to a number of decimals
+pub fn coordinate_hash(mol: &Mol2, decimals: u32) -> u64 {
+    let scale = 10f64.powi(decimals as i32);
+    let mut hasher = DefaultHasher::new();
+
+    for atom in mol.atoms() {
+        atom.atom_type.hash(&mut hasher);
+        for c in [atom.x, atom.y, atom.z] {
+            ((c * scale).round() as i64).hash(&mut hasher);
+        }
+    }
+
+    hasher.finish()
+}
+
+// Struct describing the location and geometry of a single pose
+struct PoseRecord {
+    file_index: usize,
+    record_index: usize,
+    name: String,
+    energy: f64,
+    hash: u64
+}
+
+// implements duplicates subcommand: reports geometrically identical poses
+// returns the number of redundant poses (every pose of a group beyond the first)
+pub fn duplicates(
+        input_files: Vec<String>,
+        output_filename: &str,
+        decimals: u32) -> Result<usize, Error> {
+
+    // hash the coordinates of every pose in parallel
+    let records: Vec<PoseRecord> = input_files
+        .par_iter()
+        .enumerate()
+        .flat_map_iter(|(file_index, x)| {
+            Mol2Reader::new(x)
+                .unwrap()
+                .enumerate()
+                .map(move |(record_index, mol)| {
+                    PoseRecord {
+                        file_index,
+                        record_index,
+                        name: mol.get_name().to_string(),
+                        energy: mol.get_energy(),
+                        hash: coordinate_hash(&mol, decimals)
+                    }
+                })
+        })
+        .collect();
+
+    // group poses by geometry keeping the order of first appearance
+    let mut positions: HashMap<u64, usize> = HashMap::new();
+    let mut groups: Vec<Vec<&PoseRecord>> = Vec::new();
+    for record in records.iter() {
+        match positions.get(&record.hash) {
+            Some(&idx) => groups[idx].push(record),
+            None => {
+                positions.insert(record.hash, groups.len());
+                groups.push(vec![record]);
+            }
+        }
+    }
+
+    let mut writer = BufWriter::new(File::create(output_filename)?);
+    writer.write_all(b"geometry\tname\tfile\trecord_index\tenergy\n")?;
+
+    let mut num_redundant = 0;
+    for group in groups.iter().filter(|g| g.len() > 1) {
+        num_redundant += group.len() - 1;
+        for r in group {
+            writeln!(
+                writer,
+                "{:016x}\t{}\t{}\t{}\t{}",
+                r.hash, r.name, input_files[r.file_index], r.record_index, r.energy
+            )?;
+        }
+    }
+
+    println!(">>> Number of Poses: {}", records.len());
+    println!(">>> Number of Unique Geometries: {}", groups.len());
+    println!(">>> Number of Redundant Poses: {}", num_redundant);
+    println!(">>> Duplicate Groups Written to: {}", output_filename);
+
+    Ok(num_redundant)
+}
+
+// Struct describing the counts of SYBYL atom types and elements
+// alongside the TRIPOS sections and substructure types they were found in
+#[derive(Default)]
+pub struct AtomCensus {
+    pub types: BTreeMap<String, u64>,
+    pub elements: BTreeMap<String, u64>,
+    pub sections: BTreeMap<String, u64>,
+    pub substructures: BTreeMap<String, u64>
+}
+impl AtomCensus {
+
+    // Adds the atoms of a molecule to the census
+    fn add_molecule(&mut self, mol: &Mol2) {
+        for atom in mol.atoms() {
+            *self.elements.entry(atom.element().to_string()).or_insert(0) += 1;
+            *self.types.entry(atom.atom_type).or_insert(0) += 1;
+        }
+        for section in mol.section_names() {
+            *self.sections.entry(section.to_string()).or_insert(0) += 1;
+        }
+        for subst in mol.substructures() {
+            let label = subst.subst_type.unwrap_or(subst.name);
+            *self.substructures.entry(label).or_insert(0) += 1;
+        }
+    }
+
+    // Merges the counts of another census into this one
+    fn merge(&mut self, other: &AtomCensus) {
+        for (k, v) in other.types.iter() {
+            *self.types.entry(k.clone()).or_insert(0) += v;
+        }
+        for (k, v) in other.elements.iter() {
+            *self.elements.entry(k.clone()).or_insert(0) += v;
+        }
+        for (k, v) in other.sections.iter() {
+            *self.sections.entry(k.clone()).or_insert(0) += v;
+        }
+        for (k, v) in other.substructures.iter() {
+            *self.substructures.entry(k.clone()).or_insert(0) += v;
+        }
+    }
+
+    // Writes the census as rows of: file, category, label, count
+    fn write_rows<W: Write>(&self, writer: &mut W, filename: &str) -> Result<(), Error> {
+        for (k, v) in self.types.iter() {
+            writeln!(writer, "{}\ttype\t{}\t{}", filename, k, v)?;
+        }
+        for (k, v) in self.elements.iter() {
+            writeln!(writer, "{}\telement\t{}\t{}", filename, k, v)?;
+        }
+        for (k, v) in self.sections.iter() {
+            writeln!(writer, "{}\tsection\t{}\t{}", filename, k, v)?;
+        }
+        for (k, v) in self.substructures.iter() {
+            writeln!(writer, "{}\tsubstructure\t{}\t{}", filename, k, v)?;
+        }
+        Ok(())
+    }
+}
+
+// implements atoms subcommand: counts atom types and elements per file and in aggregate
+pub fn atom_census(
+        input_files: Vec<String>,
+        output_filename: &str) -> Result<AtomCensus, Error> {
+
+    // count atoms of each file in parallel
+    let per_file: Vec<AtomCensus> = input_files
+        .par_iter()
+        .map(|x| {
+            let mut census = AtomCensus::default();
+            Mol2Reader::new(x)
+                .unwrap()
+                .for_each(|mol| census.add_molecule(&mol));
+            census
+        })
+        .collect();
+
+    let mut total = AtomCensus::default();
+    per_file
+        .iter()
+        .for_each(|c| total.merge(c));
+
+    let mut writer = BufWriter::new(File::create(output_filename)?);
+    writer.write_all(b"file\tcategory\tlabel\tcount\n")?;
+    for (census, filename) in per_file.iter().zip(input_files.iter()) {
+        census.write_rows(&mut writer, filename)?;
+    }
+    total.write_rows(&mut writer, "*")?;
+
+    println!(">>> Number of Atoms: {}", total.types.values().sum::<u64>());
+    println!(">>> Number of Atom Types: {}", total.types.len());
+    println!(">>> Number of Elements: {}", total.elements.len());
+    println!(">>> Number of Section Types: {}", total.sections.len());
+    println!(">>> Census Written to: {}", output_filename);
+
+    Ok(total)
+}
+
+// Struct describing the net charge distribution of a set of molecules
+#[derive(Default)]
+pub struct ChargeSummary {
+    pub distribution: BTreeMap<i64, u64>,
+    pub num_flagged: u64
+}
+impl ChargeSummary {
+
+    // Merges the counts of another summary into this one
+    fn merge(&mut self, other: &ChargeSummary) {
+        for (k, v) in other.distribution.iter() {
+            *self.distribution.entry(*k).or_insert(0) += v;
+        }
+        self.num_flagged += other.num_flagged;
+    }
+
+    // Writes the distribution as rows of: file, net_charge, count
+    fn write_rows<W: Write>(&self, writer: &mut W, filename: &str) -> Result<(), Error> {
+        for (k, v) in self.distribution.iter() {
+            writeln!(writer, "{}\t{}\t{}", filename, k, v)?;
+        }
+        Ok(())
+    }
+}
+
+// Struct describing a molecule whose partial charges do not sum to an integer
+struct FlaggedCharge {
+    record_index: usize,
+    name: String,
+    charge: f64
+}
+
+// implements charges subcommand: net charge distributions and non-integer charge flags
+pub fn charge_stats(
+        input_files: Vec<String>,
+        output_filename: &str,
+        flagged_filename: &str,
+        tolerance: f64) -> Result<ChargeSummary, Error> {
+
+    // summarize the charges of each file in parallel
+    let per_file: Vec<(ChargeSummary, Vec<FlaggedCharge>)> = input_files
+        .par_iter()
+        .map(|x| {
+            let mut summary = ChargeSummary::default();
+            let mut flagged = Vec::new();
+
+            for (record_index, mol) in Mol2Reader::new(x).unwrap().enumerate() {
+                let charge: f64 = mol.atoms().iter().map(|a| a.charge).sum();
+                let net_charge = charge.round();
+
+                *summary.distribution.entry(net_charge as i64).or_insert(0) += 1;
+                // small epsilon so sums landing exactly on the tolerance are not flagged
+                if (charge - net_charge).abs() > tolerance + 1e-9 {
+                    summary.num_flagged += 1;
+                    flagged.push(FlaggedCharge {
+                        record_index,
+                        name: mol.get_name().to_string(),
+                        charge
+                    });
+                }
+            }
+
+            (summary, flagged)
+        })
+        .collect();
+
+    let mut total = ChargeSummary::default();
+    let mut writer = BufWriter::new(File::create(output_filename)?);
+    writer.write_all(b"file\tnet_charge\tcount\n")?;
+    for ((summary, _), filename) in per_file.iter().zip(input_files.iter()) {
+        summary.write_rows(&mut writer, filename)?;
+        total.merge(summary);
+    }
+    total.write_rows(&mut writer, "*")?;
+
+    let mut flagged_writer = BufWriter::new(File::create(flagged_filename)?);
+    flagged_writer.write_all(b"file\trecord_index\tname\tcharge_sum\tdeviation\n")?;
+    for ((_, flagged), filename) in per_file.iter().zip(input_files.iter()) {
+        for f in flagged {
+            writeln!(
+                flagged_writer,
+                "{}\t{}\t{}\t{:.4}\t{:.4}",
+                filename, f.record_index, f.name, f.charge, f.charge - f.charge.round()
+            )?;
+        }
+    }
+
+    println!(">>> Net Charge Distribution:");
+    total.distribution
+        .iter()
+        .for_each(|(k, v)| println!("  {:+}:\t{}", k, v));
+    println!(">>> Number of Non-Integer Charges: {}", total.num_flagged);
+    println!(">>> Distribution Written to: {}", output_filename);
+    println!(">>> Flagged Molecules Written to: {}", flagged_filename);
+
+    Ok(total)
+}
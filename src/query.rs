@@ -1,129 +1,898 @@
 
 use crate::mol2::Mol2;
+use crate::file_io::is_stdin;
 
 use std::fs::File;
-use std::io::Error;
+use std::io::{Error, ErrorKind};
 use std::io::BufReader;
 use std::io::prelude::*;
+use std::borrow::Cow;
+use std::ops::Deref;
 
 use std::collections::{HashMap, HashSet};
 
+use indicatif::{ProgressBar, ProgressStyle};
+use memmap2::Mmap;
+use rayon::prelude::*;
+use regex::{RegexSet, RegexSetBuilder};
+
 
 // Enumerate describing input query format
 pub enum QueryFormat {
-    WithScore(HashMap<Mol2, f64>),
-    WithoutScore(HashSet<Mol2>)
+    // every expected score of a name, in file order
+    WithScore(HashMap<Mol2, Vec<ExpectedScore>>),
+    WithRange(HashMap<Mol2, (f64, f64)>),
+    WithoutScore(HashSet<Mol2>),
+    Patterns(NamePatterns)
 }
+impl QueryFormat {
 
-// Struct describing file IO of input query
-pub struct QueryReader {
-    bufreader: BufReader<File>,
-    line: String
+    // Returns the names of the query table
+    pub fn names(&self) -> Box<dyn Iterator<Item = &str> + '_> {
+        match self {
+            QueryFormat::WithScore(t) => Box::new(t.keys().map(|x| x.get_name())),
+            QueryFormat::WithRange(t) => Box::new(t.keys().map(|x| x.get_name())),
+            QueryFormat::WithoutScore(t) => Box::new(t.iter().map(|x| x.get_name())),
+            QueryFormat::Patterns(t) => Box::new(t.exact.iter().map(|x| x.get_name()))
+        }
+    }
+
+    // Returns true if the table holds patterns, so that the names it matches are not known up front
+    pub fn has_patterns(&self) -> bool {
+        matches!(self, QueryFormat::Patterns(t) if !t.patterns.is_empty())
+    }
+
+    // Replaces aliased query names by the names they map to
+    // returns the translated table and the (code, name) pairs that were translated
+    pub fn translate(self, aliases: &Aliases) -> (QueryFormat, Vec<(String, String)>) {
+        let mut translated = Vec::new();
+        let mut rename = |mut mol: Mol2| {
+            if let Some(name) = aliases.resolve(mol.get_name()) {
+                translated.push((mol.get_name().to_string(), name.to_string()));
+                mol.add_name(name.to_string());
+            }
+            mol
+        };
+        let table = match self {
+            QueryFormat::WithScore(t) => QueryFormat::WithScore(
+                t.into_iter().map(|(k, v)| (rename(k), v)).collect()
+            ),
+            QueryFormat::WithRange(t) => QueryFormat::WithRange(
+                t.into_iter().map(|(k, v)| (rename(k), v)).collect()
+            ),
+            QueryFormat::Patterns(t) => QueryFormat::Patterns(NamePatterns {
+                exact: t.exact.into_iter().map(&mut rename).collect(),
+                patterns: t.patterns
+            }),
+            QueryFormat::WithoutScore(t) => QueryFormat::WithoutScore(
+                t.into_iter().map(&mut rename).collect()
+            )
+        };
+        (table, translated)
+    }
+
+    // Lowercases the query names and makes patterns case insensitive,
+    // so that molecules looked up by their lowercased names match regardless of case
+    // (the expected scores of names differing only in case are all kept)
+    pub fn fold_case(self) -> Result<QueryFormat, Error> {
+        let lowercase = |mut mol: Mol2| {
+            let name = mol.get_name().to_lowercase();
+            mol.add_name(name);
+            mol
+        };
+        Ok(match self {
+            QueryFormat::WithScore(t) => {
+                let mut folded: HashMap<Mol2, Vec<ExpectedScore>> = HashMap::new();
+                for (k, v) in t {
+                    folded.entry(lowercase(k)).or_default().extend(v);
+                }
+                QueryFormat::WithScore(folded)
+            },
+            QueryFormat::WithRange(t) => QueryFormat::WithRange(
+                t.into_iter().map(|(k, v)| (lowercase(k), v)).collect()
+            ),
+            QueryFormat::WithoutScore(t) => QueryFormat::WithoutScore(
+                t.into_iter().map(lowercase).collect()
+            ),
+            QueryFormat::Patterns(t) => QueryFormat::Patterns(NamePatterns {
+                exact: t.exact.into_iter().map(lowercase).collect(),
+                patterns: RegexSetBuilder::new(t.patterns.patterns())
+                    .case_insensitive(true)
+                    .build()
+                    .map_err(|e| Error::new(ErrorKind::InvalidData, format!("Malformed query pattern: {}", e)))?
+            })
+        })
+    }
+
+    // Returns true if a molecule is queried (within tolerance of its expected score
+    // or of its energy window)
+    pub fn matches(&self, mol: &Mol2, tol: &Tolerance) -> bool {
+        match self {
+            QueryFormat::WithScore(t) => t
+                .get(mol)
+                .is_some_and(|scores| scores.iter().any(|e| e.tolerance(tol).accepts(mol.get_energy(), e.score))),
+            QueryFormat::WithRange(t) => t.get(mol).is_some_and(|(min, max)| {
+                let energy = mol.get_energy();
+                energy >= min - tol.bound(*min) && energy <= max + tol.bound(*max)
+            }),
+            QueryFormat::WithoutScore(t) => t.contains(mol),
+            QueryFormat::Patterns(t) => t.matches(mol)
+        }
+    }
+
+    // Returns true if a molecule name is in the table, whatever its score
+    pub fn contains_name(&self, mol: &Mol2) -> bool {
+        match self {
+            QueryFormat::WithScore(t) => t.contains_key(mol),
+            QueryFormat::WithRange(t) => t.contains_key(mol),
+            QueryFormat::WithoutScore(t) => t.contains(mol),
+            QueryFormat::Patterns(t) => t.matches(mol)
+        }
+    }
+
+    // Returns an estimate of the memory held by the table
+    pub fn estimated_bytes(&self) -> u64 {
+        // every entry holds a molecule, its name, and a hash table slot
+        let entry = |mol: &Mol2, value: usize| {
+            (std::mem::size_of::<Mol2>() + value + mol.get_name().len() + 8) as u64
+        };
+        match self {
+            QueryFormat::WithScore(t) => t
+                .iter()
+                .map(|(x, scores)| entry(x, std::mem::size_of::<Vec<f64>>() + scores.len() * std::mem::size_of::<ExpectedScore>()))
+                .sum(),
+            QueryFormat::WithRange(t) => t.keys().map(|x| entry(x, 2 * std::mem::size_of::<f64>())).sum(),
+            QueryFormat::WithoutScore(t) => t.iter().map(|x| entry(x, 0)).sum(),
+            QueryFormat::Patterns(t) => {
+                let patterns: usize = t.patterns.patterns().iter().map(|x| x.len()).sum();
+                t.exact.iter().map(|x| entry(x, 0)).sum::<u64>() + patterns as u64
+            }
+        }
+    }
+
+    // Describes the query table for summaries
+    pub fn describe(&self) -> String {
+        match self {
+            QueryFormat::WithScore(t) => format!(
+                "{} names with {} expected scores",
+                t.len(),
+                t.values().map(|x| x.len()).sum::<usize>()
+            ),
+            QueryFormat::WithRange(t) => format!("{} names with energy windows", t.len()),
+            QueryFormat::WithoutScore(t) => format!("{} names without scores", t.len()),
+            QueryFormat::Patterns(t) => format!("{} names and {} patterns without scores", t.exact.len(), t.patterns.len())
+        }
+    }
 }
-impl QueryReader {
 
-    // Inserts a molecule into a HashSet
-    fn insert_to_set(&self, table: &mut HashSet<Mol2>, mol: Mol2) {
-        table.insert(mol);
+// Struct describing an expected score and the tolerance it overrides the global one with
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ExpectedScore {
+    pub score: f64,
+    pub tol: Option<f64>
+}
+impl ExpectedScore {
+
+    // Returns the tolerance applied to the score (its own absolute tolerance, if any)
+    pub fn tolerance(&self, tol: &Tolerance) -> Tolerance {
+        match self.tol {
+            Some(x) => Tolerance { absolute: x, ..*tol },
+            None => *tol
+        }
+    }
+}
+
+// Enumerate describing which side of an expected score is accepted
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ToleranceMode {
+    // within the bound on either side
+    Abs,
+    // any score at or below the expected score plus the bound (better poses always pass)
+    #[default]
+    Below,
+    // any score at or above the expected score minus the bound
+    Above
+}
+impl ToleranceMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "abs" => Some(ToleranceMode::Abs),
+            "below" => Some(ToleranceMode::Below),
+            "above" => Some(ToleranceMode::Above),
+            _ => None
+        }
+    }
+
+    // Returns the name the mode is selected by
+    pub fn name(&self) -> &'static str {
+        match self {
+            ToleranceMode::Abs => "abs",
+            ToleranceMode::Below => "below",
+            ToleranceMode::Above => "above"
+        }
+    }
+}
+
+// Struct describing the deviation accepted between found and expected scores
+//
+// The bound grows with the expected score as `absolute + relative * |expected|`,
+// so either part may be zero to use the other alone.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Tolerance {
+    pub absolute: f64,
+    pub relative: f64,
+    pub mode: ToleranceMode
+}
+impl Tolerance {
+
+    // Instantiate a tolerance of a fixed absolute deviation
+    pub fn absolute(tol: f64) -> Self {
+        Tolerance { absolute: tol, ..Tolerance::default() }
+    }
+
+    // Returns the largest accepted deviation from an expected score
+    pub fn bound(&self, expected: f64) -> f64 {
+        self.absolute + self.relative * expected.abs()
+    }
+
+    // Returns true if a found score is accepted for an expected score
+    pub fn accepts(&self, found: f64, expected: f64) -> bool {
+        let bound = self.bound(expected);
+        match self.mode {
+            ToleranceMode::Abs => (found - expected).abs() <= bound,
+            ToleranceMode::Below => found - expected <= bound,
+            ToleranceMode::Above => expected - found <= bound
+        }
+    }
+}
+
+// Struct describing queries matching names exactly or by pattern
+//
+// Exact names are hash lookups; patterns are regular expressions matched
+// against the whole name and only scanned for names that are not exact hits.
+pub struct NamePatterns {
+    pub exact: HashSet<Mol2>,
+    pub patterns: RegexSet
+}
+impl NamePatterns {
+
+    // Compiles patterns, each anchored to match whole names
+    pub fn new(exact: HashSet<Mol2>, patterns: &[String]) -> Result<Self, Error> {
+        let anchored: Vec<String> = patterns.iter().map(|x| format!("^(?:{})$", x)).collect();
+        let patterns = RegexSet::new(anchored)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, format!("Malformed query pattern: {}", e)))?;
+        Ok(NamePatterns { exact, patterns })
+    }
+
+    // Returns true if a molecule name is queried
+    pub fn matches(&self, mol: &Mol2) -> bool {
+        self.exact.contains(mol) || self.patterns.is_match(mol.get_name())
+    }
+}
+
+// Returns true if a query name holds glob wildcards (`*`, `?`, or `[...]`)
+pub fn is_glob(name: &str) -> bool {
+    name.contains(['*', '?', '['])
+}
+
+// Translates a glob into an (unanchored) regular expression:
+// `*` matches any run of characters, `?` any single character, and `[...]`
+// a character class (negated by a leading `!`)
+pub fn glob_to_regex(glob: &str) -> String {
+    let mut pattern = String::with_capacity(glob.len() * 2);
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' => pattern.push_str(".*"),
+            '?' => pattern.push('.'),
+            '[' => {
+                pattern.push('[');
+                if chars.next_if_eq(&'!').is_some() {
+                    pattern.push('^');
+                }
+                for c in chars.by_ref() {
+                    if c == ']' {
+                        break;
+                    }
+                    if c == '\\' || c == '[' {
+                        pattern.push('\\');
+                    }
+                    pattern.push(c);
+                }
+                pattern.push(']');
+            },
+            c => pattern.push_str(&regex::escape(&c.to_string()))
+        }
+    }
+    pattern
+}
+
+// Struct describing the groups (e.g. clusters or targets) query names belong to,
+// with groups numbered in first-seen order
+#[derive(Default)]
+pub struct QueryGroups {
+    pub labels: Vec<String>,
+    members: HashMap<String, Vec<usize>>
+}
+impl QueryGroups {
+
+    // Instantiate groups from (name, group) pairs
+    pub fn new(rows: Vec<(String, String)>) -> Self {
+        let mut groups = QueryGroups::default();
+        let mut positions: HashMap<String, usize> = HashMap::new();
+        for (name, label) in rows {
+            let idx = *positions.entry(label.clone()).or_insert_with(|| {
+                groups.labels.push(label);
+                groups.labels.len() - 1
+            });
+            let member = groups.members.entry(name).or_default();
+            if !member.contains(&idx) {
+                member.push(idx);
+            }
+        }
+        groups
+    }
+
+    // Returns the groups of a name
+    pub fn of(&self, name: &str) -> &[usize] {
+        self.members.get(name).map_or(&[], |x| x.as_slice())
+    }
+}
+
+// Struct describing a mapping of supplier or internal codes to ZINC names
+#[derive(Default)]
+pub struct Aliases {
+    names: HashMap<String, String>
+}
+impl Aliases {
+
+    // Reads a two column (code, name) tab separated mapping file
+    pub fn from_file(filename: &str) -> Result<Self, Error> {
+        let reader = BufReader::new(File::open(filename)?);
+        let mut names = HashMap::new();
+        for (idx, line) in reader.lines().enumerate() {
+            let line = line?;
+            let items: Vec<&str> = line.split_whitespace().collect();
+            match items.len() {
+                0 => continue,
+                2 => { names.insert(items[0].to_string(), items[1].to_string()); },
+                n => return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Malformed alias input: {} line {} has {} columns but expecting 2 (code, name)", filename, idx + 1, n)
+                ))
+            }
+        }
+        Ok(Aliases { names })
+    }
+
+    // Returns the number of aliased codes
+    pub fn num_aliases(&self) -> usize {
+        self.names.len()
+    }
+
+    // Returns the name of a code, if aliased
+    pub fn resolve(&self, code: &str) -> Option<&str> {
+        self.names.get(code).map(|x| x.as_str())
+    }
+}
+
+// Size of the chunks a query file is split into for parallel parsing
+const QUERY_CHUNK_SIZE: usize = 8 << 20;
+
+// Column of a query table, by position or by header name
+#[derive(Clone, Debug, PartialEq)]
+pub enum ColumnSpec {
+    Index(usize),
+    Name(String)
+}
+impl ColumnSpec {
+
+    // Parses a column given by its 1-based position or its header name
+    pub fn parse(spec: &str) -> Self {
+        match spec.parse::<usize>() {
+            Ok(x) if x > 0 => ColumnSpec::Index(x - 1),
+            _ => ColumnSpec::Name(spec.to_string())
+        }
     }
+}
 
-    // Inserts a molecule into a HashMap
-    fn insert_to_map(&self, table: &mut HashMap<Mol2, f64>, mol: Mol2) {
-        let energy = mol.get_energy();
-        table.insert(mol, energy);
+// Columns holding the names and scores of multi-column query tables
+// (unset columns keep the one or two column format)
+#[derive(Clone, Debug, Default)]
+pub struct QueryColumns {
+    pub id: Option<ColumnSpec>,
+    pub score: Option<ColumnSpec>,
+    // absolute tolerance of each row, overriding the global one
+    pub tol: Option<ColumnSpec>,
+    // group of each row, routing its matches to the output of the group
+    pub group: Option<ColumnSpec>,
+    // fields are comma separated and may be quoted, whatever the first line holds
+    pub csv: bool,
+    // rows are `SMILES  ID` with names in the second column (implied by a .smi file)
+    pub smiles: bool
+}
+impl QueryColumns {
+
+    // Returns true if no column was selected
+    pub fn is_default(&self) -> bool {
+        self.id.is_none() && self.score.is_none() && self.tol.is_none() && self.group.is_none() && !self.csv && !self.smiles
     }
 
+    // Returns true if a column is selected by name, so that the table has a header
+    pub fn has_names(&self) -> bool {
+        [&self.id, &self.score, &self.tol, &self.group]
+            .iter()
+            .any(|x| matches!(x, Some(ColumnSpec::Name(_))))
+    }
+}
+
+// Enumerate describing how the fields of a query line are separated
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Delimiter {
+    Whitespace,
+    Tab,
+    // comma separated, with fields optionally quoted (`"a, b"` and `""` for a quote)
+    Csv
+}
+
+// Splits a line into its fields
+fn split_fields(line: &str, delimiter: Delimiter) -> Vec<Cow<'_, str>> {
+    match delimiter {
+        Delimiter::Whitespace => line.split_whitespace().map(Cow::Borrowed).collect(),
+        Delimiter::Tab => line.split('\t').map(|x| Cow::Borrowed(x.trim())).collect(),
+        Delimiter::Csv => split_csv(line)
+    }
+}
+
+// Splits a comma separated line into its fields, unquoting quoted fields
+// (quoted fields cannot span lines)
+pub fn split_csv(line: &str) -> Vec<Cow<'_, str>> {
+    let mut fields = Vec::new();
+    let mut rest = line;
+    loop {
+        let field = rest.trim_start();
+        let after = match field.strip_prefix('"') {
+            Some(quoted) => {
+                let mut value = String::new();
+                let mut end = quoted.len();
+                let mut chars = quoted.char_indices().peekable();
+                while let Some((idx, c)) = chars.next() {
+                    if c != '"' {
+                        value.push(c);
+                    } else if chars.next_if(|(_, c)| *c == '"').is_some() {
+                        value.push('"');
+                    } else {
+                        end = idx + 1;
+                        break;
+                    }
+                }
+                fields.push(Cow::Owned(value));
+                let after = &quoted[end..];
+                after.find(',').map(|idx| &after[idx + 1..])
+            },
+            None => {
+                let end = field.find(',').unwrap_or(field.len());
+                fields.push(Cow::Borrowed(field[..end].trim()));
+                field.get(end + 1..)
+            }
+        };
+        match after {
+            Some(x) => rest = x,
+            None => break
+        }
+    }
+    fields
+}
+
+// Struct describing the delimiter, selected column indices, and rows of a query table
+struct TableLayout<'a> {
+    delimiter: Delimiter,
+    id: usize,
+    score: Option<usize>,
+    tol: Option<usize>,
+    group: Option<usize>,
+    // the rows after any header
+    body: &'a [u8]
+}
+
+// Splits a buffer into chunks of about `size` bytes ending on line boundaries
+pub fn line_chunks(data: &[u8], size: usize) -> Vec<&[u8]> {
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    while start < data.len() {
+        let end = match data[(start + size).min(data.len())..].iter().position(|&c| c == b'\n') {
+            Some(idx) => (start + size).min(data.len()) + idx + 1,
+            None => data.len()
+        };
+        chunks.push(&data[start..end]);
+        start = end;
+    }
+    chunks
+}
+
+// Struct describing file IO of input query
+//
+// The file is memory mapped and parsed in chunks on the rayon pool; partial
+// tables are merged in file order so a repeated name keeps each of its scores
+// (a pose passes if it matches any of them).
+// Other sources (e.g. the standard input) are read into memory first.
+pub struct QueryReader {
+    filename: String,
+    data: Box<dyn Deref<Target = [u8]>>
+}
+impl QueryReader {
+
     // Creates a molecule with a given name
-    fn mol_with_name(&self, name: &str) -> Mol2 {
+    fn mol_with_name(name: &str) -> Mol2 {
         let mut mol = Mol2::new();
         mol.add_name(name.trim().to_owned());
         mol
     }
 
+    // Parses an energy column
+    fn parse_energy(energy: &str) -> f64 {
+        energy.parse::<f64>()
+            .expect("\n\nError: Malformed Energy Column...\n...Unable to be parsed into float\n\n")
+    }
+
     // Creates a molecule with a given name and energy
-    fn mol_with_name_and_energy(&self, name: &str, energy: &str) -> Mol2 {
+    fn mol_with_name_and_energy(name: &str, energy: &str) -> Mol2 {
         let mut mol = Mol2::new();
         mol.add_name(name.to_owned());
-        mol.add_energy(
-            energy.parse::<f64>()
-            .expect("\n\nError: Malformed Energy Column...\n...Unable to be parsed into float\n\n"));
+        mol.add_energy(Self::parse_energy(energy));
         mol
     }
 
-    // Split a string on whitespace and return a vector of elements
-    fn split_items(&self) -> Vec<&str> {
-        self.line.trim()
-            .split_whitespace()
-            .collect()
+    // Returns the non empty lines of a chunk
+    fn chunk_lines(chunk: &[u8]) -> impl Iterator<Item = &str> {
+        std::str::from_utf8(chunk)
+            .expect("\n\nError: Malformed Query Input...\n..Not valid UTF-8\n\n")
+            .lines()
+            .filter(|x| !x.trim().is_empty())
     }
 
     // Read in a list of IDs without scores and construct a HashSet
-    fn read_zinc_list(&mut self) -> HashSet<Mol2> {
-        let mut table = HashSet::new();
-
-        let mol = self.mol_with_name(&self.line);
-        self.insert_to_set(&mut table, mol);
-        loop {
-            if self.step().unwrap() == 0 {break;}
+    fn read_zinc_list(chunks: &[&[u8]], progress: &ProgressBar) -> HashSet<Mol2> {
+        chunks
+            .par_iter()
+            .map(|chunk| {
+                let table: HashSet<Mol2> = Self::chunk_lines(chunk)
+                    .map(Self::mol_with_name)
+                    .collect();
+                progress.inc(chunk.len() as u64);
+                table
+            })
+            .reduce(HashSet::new, |mut a, b| {
+                a.extend(b);
+                a
+            })
+    }
 
-            let mol = self.mol_with_name(&self.line);
-            self.insert_to_set(&mut table, mol);
+    // Groups the expected scores of every name, keeping them in file order
+    fn collect_scores(rows: impl Iterator<Item = (Mol2, ExpectedScore)>) -> HashMap<Mol2, Vec<ExpectedScore>> {
+        let mut table: HashMap<Mol2, Vec<ExpectedScore>> = HashMap::new();
+        for (mol, expected) in rows {
+            table.entry(mol).or_default().push(expected);
         }
-
         table
     }
 
     // Read in a list of IDS with scores and construct a HashMap
-    fn read_zinc_score_table(&mut self) -> HashMap<Mol2, f64> {
-        let mut table = HashMap::new();
-
-        let items = self.split_items();
+    fn read_zinc_score_table(chunks: &[&[u8]], progress: &ProgressBar) -> HashMap<Mol2, Vec<ExpectedScore>> {
+        let partials: Vec<Vec<(Mol2, ExpectedScore)>> = chunks
+            .par_iter()
+            .map(|chunk| {
+                let rows = Self::chunk_lines(chunk)
+                    .map(|line| {
+                        let items: Vec<&str> = line.split_whitespace().collect();
+                        if items.len() < 2 {
+                            panic!("\n\nError: Malformed Query Input...\n..Found 1 column but expecting 2\n\n")
+                        }
+                        let mol = Self::mol_with_name_and_energy(items[0], items[1]);
+                        let score = mol.get_energy();
+                        (mol, ExpectedScore { score, tol: None })
+                    })
+                    .collect();
+                progress.inc(chunk.len() as u64);
+                rows
+            })
+            .collect();
 
-        let mol = self.mol_with_name_and_energy(items[0], items[1]);
-        self.insert_to_map(&mut table, mol);
+        // merged in file order so the scores of repeated names stay in file order
+        Self::collect_scores(partials.into_iter().flatten())
+    }
 
-        loop {
-            if self.step().unwrap() == 0 {break;}
+    // Read in a list of IDs with minimum and maximum energies and construct a HashMap
+    fn read_zinc_range_table(chunks: &[&[u8]], progress: &ProgressBar) -> Result<HashMap<Mol2, (f64, f64)>, Error> {
+        let partials: Vec<Vec<(Mol2, (f64, f64))>> = chunks
+            .par_iter()
+            .map(|chunk| {
+                let rows = Self::chunk_lines(chunk)
+                    .map(|line| {
+                        let items: Vec<&str> = line.split_whitespace().collect();
+                        if items.len() < 3 {
+                            panic!("\n\nError: Malformed Query Input...\n..Found fewer than 3 columns but expecting 3\n\n")
+                        }
+                        let window = (Self::parse_energy(items[1]), Self::parse_energy(items[2]));
+                        (Self::mol_with_name(items[0]), window)
+                    })
+                    .collect();
+                progress.inc(chunk.len() as u64);
+                rows
+            })
+            .collect();
 
-            let items = self.split_items();
-            let mol = self.mol_with_name_and_energy(items[0], items[1]);
-            self.insert_to_map(&mut table, mol);
+        // merged in file order so later rows replace earlier ones
+        let mut table = HashMap::with_capacity(partials.iter().map(|x| x.len()).sum());
+        for rows in partials {
+            table.extend(rows);
         }
-
-        table
+        if let Some((mol, (min, max))) = table.iter().find(|(_, (min, max))| min > max) {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Malformed query input: {} has a minimum energy {} above its maximum {}", mol.get_name(), min, max)
+            ))
+        }
+        Ok(table)
     }
 
-    // Step forward one line in file
-    fn step(&mut self) -> Result<usize, Error> {
-        /*
-        Steps through the file one line at a time and returns false if EOF is found
-        */
-        self.line.clear();
-        let eof = self.bufreader.read_line(&mut self.line)?;
-        Ok(eof)
+    // Splits off the first non empty line of a buffer, returning it and the bytes after it
+    fn split_first_line(data: &[u8]) -> (&str, &[u8]) {
+        let mut start = 0;
+        while start < data.len() {
+            let end = data[start..]
+                .iter()
+                .position(|&c| c == b'\n')
+                .map_or(data.len(), |x| start + x + 1);
+            let line = Self::chunk_lines(&data[start..end]).next();
+            if let Some(line) = line {
+                return (line, &data[end..])
+            }
+            start = end;
+        }
+        ("", &data[data.len()..])
     }
 
     // Load in query input file with necessary format
+    //
+    // A first line whose scores are not numbers (e.g. `zinc_id  dock_score`)
+    // is a header and skipped. SMILES files (`.smi`) give names in their second column.
     pub fn load_queries(&mut self) -> Result<QueryFormat, Error> {
-        self.step()?;
+        if self.is_smiles() {
+            return self.load_queries_with(&QueryColumns { smiles: true, ..QueryColumns::default() })
+        }
+        let data: &[u8] = &self.data;
+
+        // the first line decides the format of the whole file
+        let (first, rest) = Self::split_first_line(data);
+        let items: Vec<&str> = first.split_whitespace().collect();
+        let num_columns = items.len();
+        let has_header = num_columns > 1 && items[1..].iter().any(|x| x.parse::<f64>().is_err());
+        let data = if has_header { rest } else { data };
+
+        let chunks = line_chunks(data, QUERY_CHUNK_SIZE);
 
-        let items = self.split_items();
+        let progress = self.loading_progress(data.len(), chunks.len());
+        let table = match num_columns {
+            1 => Self::partition_globs(Self::read_zinc_list(&chunks, &progress))?,
+            2 => QueryFormat::WithScore(Self::read_zinc_score_table(&chunks, &progress)),
+            3 => QueryFormat::WithRange(Self::read_zinc_range_table(&chunks, &progress)?),
+            _ => panic!("\n\nError: Malformed Query Input...\n..Found >3 columns but expecting at most 3 (select columns with --id-col and --score-col)\n\n")
+        };
+        progress.finish_and_clear();
 
-        match items.len() {
-            1 => Ok(QueryFormat::WithoutScore(self.read_zinc_list())),
-            2 => Ok(QueryFormat::WithScore(self.read_zinc_score_table())),
-            _ => panic!("\n\nError: Malformed Query Input...\n..Found >2 columns but expecting 2\n\n")
+        Self::reject_scored_globs(table)
+    }
+
+    // Load in query input file, taking names and scores from selected columns
+    //
+    // Fields are split on tabs or commas (whichever the first line holds, with
+    // quoted comma separated fields) and on whitespace otherwise; selecting a
+    // column by name reads the first line as a header.
+    pub fn load_queries_with(&mut self, columns: &QueryColumns) -> Result<QueryFormat, Error> {
+        if columns.is_default() {
+            return self.load_queries()
         }
+        let TableLayout { delimiter, id, score, tol, body, .. } = self.layout(columns)?;
+
+        let chunks = line_chunks(body, QUERY_CHUNK_SIZE);
+        let progress = self.loading_progress(body.len(), chunks.len());
+        let partials = chunks
+            .par_iter()
+            .map(|chunk| {
+                let rows = Self::chunk_lines(chunk)
+                    .map(|line| {
+                        let fields = split_fields(line, delimiter);
+                        let field = |idx: usize| fields.get(idx).map(|x| x.as_ref()).ok_or_else(|| Error::new(
+                            ErrorKind::InvalidData,
+                            format!("Malformed query input: found {} columns but selected column {}", fields.len(), idx + 1)
+                        ));
+                        let name = field(id)?;
+                        let mol = match score {
+                            Some(idx) => Self::mol_with_name_and_energy(name, field(idx)?),
+                            None => Self::mol_with_name(name)
+                        };
+                        let row_tol = match tol {
+                            Some(idx) => Some(Self::parse_energy(field(idx)?)),
+                            None => None
+                        };
+                        Ok((mol, row_tol))
+                    })
+                    .collect::<Result<Vec<(Mol2, Option<f64>)>, Error>>();
+                progress.inc(chunk.len() as u64);
+                rows
+            })
+            .collect::<Result<Vec<Vec<(Mol2, Option<f64>)>>, Error>>()?;
+        progress.finish_and_clear();
+
+        // merged in file order so the scores of repeated names stay in file order
+        let rows = partials.into_iter().flatten();
+        let table = match score {
+            Some(_) => QueryFormat::WithScore(Self::collect_scores(
+                rows.map(|(x, tol)| {
+                    let score = x.get_energy();
+                    (x, ExpectedScore { score, tol })
+                })
+            )),
+            None => Self::partition_globs(rows.map(|(x, _)| x).collect())?
+        };
+        Self::reject_scored_globs(table)
     }
 
-    // Instantiate a new QueryReader
+    // Finds the delimiter, the selected columns, and the rows (after any header) of a query table
+    fn layout(&self, columns: &QueryColumns) -> Result<TableLayout<'_>, Error> {
+        let data: &[u8] = &self.data;
+        std::str::from_utf8(data)
+            .map_err(|_| Error::new(ErrorKind::InvalidData, format!("Malformed query input: {} is not valid UTF-8", self.filename)))?;
+
+        // the first line decides the delimiter and holds the header if columns are named
+        let (first, rest) = Self::split_first_line(data);
+        let delimiter = match (columns.csv, first.contains('\t'), first.contains(',')) {
+            (true, _, _) => Delimiter::Csv,
+            (false, true, _) => Delimiter::Tab,
+            (false, false, true) => Delimiter::Csv,
+            _ => Delimiter::Whitespace
+        };
+        let header = split_fields(first, delimiter);
+        let resolve = |spec: &ColumnSpec| match spec {
+            ColumnSpec::Index(x) => Ok(*x),
+            ColumnSpec::Name(name) => header
+                .iter()
+                .position(|x| x == name)
+                .ok_or_else(|| Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("Malformed query input: {} has no column named {}", self.filename, name)
+                ))
+        };
+        let smiles = columns.smiles || self.is_smiles();
+        let id = columns.id.as_ref().map_or(Ok(if smiles { 1 } else { 0 }), resolve)?;
+
+        // without selected columns the second column (if any) holds scores
+        let score = match columns.id.is_none() && columns.group.is_none() && !smiles {
+            true if columns.score.is_none() => (header.len() > 1).then_some(1),
+            _ => columns.score.as_ref().map(resolve).transpose()?
+        };
+        let tol = columns.tol.as_ref().map(resolve).transpose()?;
+        if tol.is_some() && score.is_none() {
+            return Err(Error::new(ErrorKind::InvalidInput, "a tolerance column requires a score column"))
+        }
+        let group = columns.group.as_ref().map(resolve).transpose()?;
+
+        // skip past the header line (given by named columns or by a score that is not a number)
+        let has_header = columns.has_names()
+            || score.and_then(|x| header.get(x)).is_some_and(|x| x.parse::<f64>().is_err())
+            || (smiles && header.first().is_some_and(|x| x.eq_ignore_ascii_case("smiles")));
+        let body = match has_header {
+            true => rest,
+            false => data
+        };
+        Ok(TableLayout { delimiter, id, score, tol, group, body })
+    }
+
+    // Load in the group of every row of a query table as (name, group) pairs in file order
+    pub fn load_groups(&mut self, columns: &QueryColumns) -> Result<Vec<(String, String)>, Error> {
+        let TableLayout { delimiter, id, group, body, .. } = self.layout(columns)?;
+        let group = group.ok_or_else(|| Error::new(ErrorKind::InvalidInput, "no group column was selected"))?;
+        Self::chunk_lines(body)
+            .map(|line| {
+                let fields = split_fields(line, delimiter);
+                match (fields.get(id), fields.get(group)) {
+                    (Some(name), Some(label)) => Ok((name.trim().to_string(), label.to_string())),
+                    _ => Err(Error::new(
+                        ErrorKind::InvalidData,
+                        format!("Malformed query input: found {} columns but selected columns {} and {}", fields.len(), id + 1, group + 1)
+                    ))
+                }
+            })
+            .collect()
+    }
+
+    // Returns a progress bar over the bytes of a query file
+    // (only files spanning several chunks take long enough to report progress)
+    fn loading_progress(&self, num_bytes: usize, num_chunks: usize) -> ProgressBar {
+        match num_chunks {
+            0 | 1 => ProgressBar::hidden(),
+            _ => ProgressBar::new(num_bytes as u64)
+                .with_style(
+                    ProgressStyle::default_bar()
+                        .template("{msg} {bar:40} {bytes}/{total_bytes}")
+                        .expect("Error: Malformed progress template")
+                )
+                .with_message(format!("loading {}", self.filename))
+        }
+    }
+
+    // Returns an error if a table with scores or energy windows holds glob patterns
+    fn reject_scored_globs(table: QueryFormat) -> Result<QueryFormat, Error> {
+        if let QueryFormat::WithScore(_) | QueryFormat::WithRange(_) = &table {
+            if let Some(name) = table.names().find(|x| is_glob(x)) {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Malformed query input: pattern {} cannot carry a score", name)
+                ))
+            }
+        }
+        Ok(table)
+    }
+
+    // Moves names holding glob wildcards out of the exact names,
+    // keeping a plain table when there are none
+    fn partition_globs(names: HashSet<Mol2>) -> Result<QueryFormat, Error> {
+        if !names.iter().any(|x| is_glob(x.get_name())) {
+            return Ok(QueryFormat::WithoutScore(names))
+        }
+        let (globs, exact): (HashSet<Mol2>, HashSet<Mol2>) = names
+            .into_iter()
+            .partition(|x| is_glob(x.get_name()));
+        let mut patterns: Vec<String> = globs
+            .iter()
+            .map(|x| glob_to_regex(x.get_name()))
+            .collect();
+        patterns.sort();
+        Ok(QueryFormat::Patterns(NamePatterns::new(exact, &patterns)?))
+    }
+
+    // Load in query input file as one regular expression per line (first column),
+    // each matched against whole molecule names
+    pub fn load_regex(&mut self) -> Result<QueryFormat, Error> {
+        let patterns: Vec<String> = Self::chunk_lines(&self.data)
+            .filter_map(|x| x.split_whitespace().next())
+            .map(|x| x.to_string())
+            .collect();
+        Ok(QueryFormat::Patterns(NamePatterns::new(HashSet::new(), &patterns)?))
+    }
+
+    // Instantiate a new QueryReader (`-` reads the standard input)
     pub fn new(filename: &str) -> Result<Self, Error> {
+        if is_stdin(filename) {
+            return Self::from_reader("stdin", std::io::stdin().lock())
+        }
         let file = File::open(filename)?;
 
+        // the query file must not change while it is mapped
+        let data = unsafe { Mmap::map(&file)? };
+
+        Ok(
+            QueryReader {
+                filename: filename.to_string(),
+                data: Box::new(data)
+            }
+        )
+    }
+
+    // Returns true if the query file is a SMILES file
+    fn is_smiles(&self) -> bool {
+        self.filename.ends_with(".smi")
+    }
+
+    // Instantiate a new QueryReader over any buffered source, read to its end
+    pub fn from_reader<R: BufRead>(name: &str, mut reader: R) -> Result<Self, Error> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
         Ok(
             QueryReader {
-                bufreader: BufReader::new(file),
-                line: String::new()
+                filename: name.to_string(),
+                data: Box::new(data)
             }
         )
     }
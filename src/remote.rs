@@ -0,0 +1,49 @@
+
+use std::io::{self, Error, ErrorKind};
+use std::io::prelude::*;
+
+// Returns true if an input is a URL read over the network
+pub fn is_remote(filename: &str) -> bool {
+    ["http://", "https://", "s3://"]
+        .iter()
+        .any(|x| filename.starts_with(x))
+}
+
+// Translates an s3:// URL into the https URL of the object
+//
+// Objects are requested without signing, so they must be public or served by
+// an endpoint that handles authentication; private objects can be read through
+// presigned https URLs instead. The endpoint is taken from AWS_ENDPOINT_URL
+// (path style, e.g. a MinIO server) and defaults to AWS (virtual host style).
+pub fn s3_to_https(url: &str, endpoint: Option<&str>) -> Option<String> {
+    let (bucket, key) = url.strip_prefix("s3://")?.split_once('/')?;
+    if bucket.is_empty() || key.is_empty() {
+        return None
+    }
+    Some(match endpoint {
+        Some(e) => format!("{}/{}/{}", e.trim_end_matches('/'), bucket, key),
+        None => format!("https://{}.s3.amazonaws.com/{}", bucket, key)
+    })
+}
+
+// Opens a remote input as a stream of its (possibly compressed) bytes
+#[cfg(feature = "remote")]
+pub fn open_remote(url: &str) -> io::Result<Box<dyn Read + Send>> {
+    let url = match url.starts_with("s3://") {
+        true => s3_to_https(url, std::env::var("AWS_ENDPOINT_URL").ok().as_deref())
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, format!("Malformed s3 URL: {}", url)))?,
+        false => url.to_string()
+    };
+    let response = ureq::get(&url)
+        .call()
+        .map_err(|e| Error::other(format!("{}: {}", url, e)))?;
+    Ok(Box::new(response.into_reader()))
+}
+
+#[cfg(not(feature = "remote"))]
+pub fn open_remote(url: &str) -> io::Result<Box<dyn Read + Send>> {
+    Err(Error::new(
+        ErrorKind::Unsupported,
+        format!("reading {} requires the remote feature", url)
+    ))
+}
@@ -0,0 +1,67 @@
+
+use std::collections::HashMap;
+use std::io::Error;
+use std::io::prelude::*;
+
+use crate::mol2::{Mol2, Mol2Reader};
+use crate::file_io::writer;
+use crate::provenance::Provenance;
+
+use rayon::prelude::*;
+
+// implements reorder subcommand: writes molecules in the order of a list of names
+// returns the number of molecules written
+//
+// Matching molecules are buffered in memory; all poses of a name are written
+// together in their input order.
+pub fn reorder(
+        input_files: Vec<String>,
+        names: &[String],
+        output_filename: &str) -> Result<u32, Error> {
+
+    // position of the first occurrence of every name in the list
+    let mut positions: HashMap<&str, usize> = HashMap::new();
+    for (idx, name) in names.iter().enumerate() {
+        positions.entry(name.as_str()).or_insert(idx);
+    }
+
+    // collect matching molecules in parallel with their input position
+    let mut matches: Vec<(usize, usize, usize, Mol2)> = input_files
+        .par_iter()
+        .enumerate()
+        .flat_map_iter(|(file_index, x)| {
+            let positions = &positions;
+            Mol2Reader::new(x)
+                .unwrap()
+                .enumerate()
+                .filter_map(move |(record_index, mol)| {
+                    positions
+                        .get(mol.get_name())
+                        .map(|&rank| (rank, file_index, record_index, mol))
+                })
+        })
+        .collect();
+    matches.sort_unstable_by_key(|(rank, file_index, record_index, _)| (*rank, *file_index, *record_index));
+
+    let provenance = Provenance::new(&input_files)?;
+    let mut writer = writer(output_filename)?;
+    provenance.write(&mut writer)?;
+
+    let mut num_found = 0;
+    let mut last_rank = None;
+    for (rank, _, _, mol) in matches.iter() {
+        if last_rank != Some(*rank) {
+            num_found += 1;
+            last_rank = Some(*rank);
+        }
+        writer
+            .write_all(mol.get_lines().as_bytes())
+            .expect("Error: Error writing to output file");
+    }
+
+    println!(">>> Number of Names Listed: {}", positions.len());
+    println!(">>> Number of Names Found: {}", num_found);
+    println!(">>> Number of Molecules Written: {}", matches.len());
+
+    Ok(matches.len() as u32)
+}
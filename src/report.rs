@@ -0,0 +1,238 @@
+
+use std::fs::File;
+use std::io::Error;
+use std::io::BufWriter;
+use std::io::prelude::*;
+
+use crate::archive;
+use crate::mol2::Mol2Reader;
+
+use rayon::prelude::*;
+
+// Struct describing the processing statistics of a single input file
+pub struct FileReport {
+    pub index: usize,
+    pub filename: String,
+    pub bytes: u64,
+    pub molecules: u32,
+    pub matches: u32,
+    pub seconds: f64,
+    pub truncated: bool
+}
+impl FileReport {
+
+    // Returns the number of molecules processed per second
+    pub fn molecules_per_second(&self) -> f64 {
+        if self.seconds > 0.0 {
+            self.molecules as f64 / self.seconds
+        } else {
+            0.0
+        }
+    }
+
+}
+
+// Returns the size in bytes of a file on disk (0 if it cannot be determined)
+pub fn file_size(filename: &str) -> u64 {
+    if archive::split_member_name(filename).is_some() {
+        return archive::member_size(filename).unwrap_or(0)
+    }
+    std::fs::metadata(filename)
+        .map(|m| m.len())
+        .unwrap_or(0)
+}
+
+// Writes a tab-separated table of per-file statistics in input order
+pub fn write_file_report(filename: &str, reports: &mut [FileReport]) -> Result<(), Error> {
+    reports.sort_by_key(|r| r.index);
+
+    let mut writer = BufWriter::new(File::create(filename)?);
+    writer.write_all(b"filename\tbytes\tmolecules\tmatches\tseconds\tmolecules_per_second\ttruncated\n")?;
+
+    for r in reports.iter() {
+        writeln!(
+            writer,
+            "{}\t{}\t{}\t{}\t{:.3}\t{:.1}\t{}",
+            r.filename, r.bytes, r.molecules, r.matches, r.seconds, r.molecules_per_second(), r.truncated
+        )?;
+    }
+
+    Ok(())
+}
+
+// Formats an optional setting of a dry run ("none" when unset)
+pub fn optional_setting<T: std::fmt::Display>(value: Option<T>) -> String {
+    value.map_or("none".to_string(), |x| x.to_string())
+}
+
+// Formats a list setting of a dry run as comma separated values
+pub fn list_setting<T: std::fmt::Display>(values: &[T]) -> String {
+    values
+        .iter()
+        .map(|x| x.to_string())
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+// Formats the resolved plan of a subcommand
+pub fn format_dry_run(
+        subcommand: &str,
+        input_files: &[String],
+        outputs: &[String],
+        settings: &[(&str, String)]) -> String {
+
+    let mut text = format!(">>> Dry Run: {}\n", subcommand);
+
+    text += &format!("\nInputs ({}):\n", input_files.len());
+    input_files
+        .iter()
+        .for_each(|x| text += &format!("  {}\n", x));
+
+    text += &format!("\nOutputs ({}):\n", outputs.len());
+    outputs
+        .iter()
+        .for_each(|x| text += &format!("  {}\n", x));
+
+    text += "\nSettings:\n";
+    settings
+        .iter()
+        .for_each(|(k, v)| text += &format!("  {}:\t{}\n", k, v));
+
+    text
+}
+
+// Prints the resolved plan of a subcommand without processing any molecules
+pub fn print_dry_run(
+        subcommand: &str,
+        input_files: &[String],
+        outputs: &[String],
+        settings: &[(&str, String)]) {
+
+    print!("{}", format_dry_run(subcommand, input_files, outputs, settings));
+}
+
+// Formats rows as an aligned, human-readable table
+pub fn format_aligned(rows: &[Vec<String>]) -> String {
+    let num_columns = rows.iter().map(|r| r.len()).max().unwrap_or(0);
+
+    // width of each column is the widest cell it contains
+    let widths: Vec<usize> = (0..num_columns)
+        .map(|i| {
+            rows.iter()
+                .filter_map(|r| r.get(i))
+                .map(|x| x.chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+
+    let mut text = String::new();
+    for row in rows {
+        let line: Vec<String> = row
+            .iter()
+            .zip(widths.iter())
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        text += line.join("  ").trim_end();
+        text += "\n";
+    }
+    text
+}
+
+// Prints rows as an aligned, human-readable table to the terminal
+pub fn print_aligned(rows: &[Vec<String>]) {
+    print!("{}", format_aligned(rows));
+}
+
+// Struct describing the contents of a single input file
+pub struct FileInfo {
+    pub filename: String,
+    pub molecules: u64,
+    pub compressed_bytes: u64,
+    // bytes of molecule text once decompressed
+    pub uncompressed_bytes: u64,
+    pub best: Option<f64>,
+    pub worst: Option<f64>,
+    pub first_name: Option<String>,
+    pub last_name: Option<String>,
+    pub truncated: bool
+}
+impl FileInfo {
+
+    // Reads a file once to summarize its molecules
+    pub fn read(filename: &str) -> Result<Self, Error> {
+        let mut info = FileInfo {
+            filename: filename.to_string(),
+            molecules: 0,
+            compressed_bytes: file_size(filename),
+            uncompressed_bytes: 0,
+            best: None,
+            worst: None,
+            first_name: None,
+            last_name: None,
+            truncated: false
+        };
+        let mut reader = Mol2Reader::new(filename)?;
+        for mol in reader.by_ref() {
+            let energy = mol.get_energy();
+            info.molecules += 1;
+            info.uncompressed_bytes += mol.get_lines().len() as u64;
+            info.best = Some(info.best.map_or(energy, |x| x.min(energy)));
+            info.worst = Some(info.worst.map_or(energy, |x| x.max(energy)));
+            if info.first_name.is_none() {
+                info.first_name = Some(mol.get_name().to_string());
+            }
+            info.last_name = Some(mol.get_name().to_string());
+        }
+        info.truncated = reader.is_truncated();
+        Ok(info)
+    }
+
+    // Returns the cells of a table row, with `-` for missing values
+    fn row(&self) -> Vec<String> {
+        let missing = || "-".to_string();
+        vec![
+            self.filename.clone(),
+            self.molecules.to_string(),
+            self.compressed_bytes.to_string(),
+            self.uncompressed_bytes.to_string(),
+            self.best.map_or_else(missing, |x| x.to_string()),
+            self.worst.map_or_else(missing, |x| x.to_string()),
+            self.first_name.clone().unwrap_or_else(missing),
+            self.last_name.clone().unwrap_or_else(missing),
+            self.truncated.to_string()
+        ]
+    }
+}
+
+// implements info subcommand: prints a summary of every input in input order
+// and optionally writes it as a tab separated table
+pub fn info(input_files: Vec<String>, output_filename: Option<&str>) -> Result<Vec<FileInfo>, Error> {
+    let infos = input_files
+        .par_iter()
+        .map(|x| FileInfo::read(x))
+        .collect::<Result<Vec<FileInfo>, Error>>()?;
+
+    let header: Vec<String> = [
+        "filename", "molecules", "compressed_bytes", "uncompressed_bytes",
+        "best", "worst", "first_name", "last_name", "truncated"
+    ].iter().map(|x| x.to_string()).collect();
+    let mut rows = vec![header];
+    rows.extend(infos.iter().map(|x| x.row()));
+
+    print_aligned(&rows);
+    if let Some(x) = output_filename {
+        let mut writer = BufWriter::new(File::create(x)?);
+        for row in rows.iter() {
+            writeln!(writer, "{}", row.join("\t"))?;
+        }
+        writer.flush()?;
+    }
+
+    let num_truncated = infos.iter().filter(|x| x.truncated).count();
+    if num_truncated > 0 {
+        eprintln!("Warning: {} inputs are truncated", num_truncated);
+    }
+
+    Ok(infos)
+}
@@ -0,0 +1,322 @@
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Error};
+use std::io::prelude::*;
+use std::path::PathBuf;
+
+use crate::mol2::{Mol2, Mol2Reader};
+use crate::mol2utils::stream_tagged_molecules;
+use crate::file_io::writer;
+use crate::provenance::Provenance;
+
+use rayon::prelude::*;
+
+// Enumerate describing which molecule attribute decides sample inclusion
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SampleKey {
+    Name,
+    Index
+}
+impl SampleKey {
+
+    // Parses a sample key from its CLI representation
+    pub fn from_name(key: &str) -> Option<Self> {
+        match key {
+            "name" => Some(SampleKey::Name),
+            "index" => Some(SampleKey::Index),
+            _ => None
+        }
+    }
+
+    // Returns the name the key is selected by
+    pub fn name(&self) -> &'static str {
+        match self {
+            SampleKey::Name => "name",
+            SampleKey::Index => "index"
+        }
+    }
+}
+
+// Struct describing a deterministic hash-based subsample of molecules
+#[derive(Clone, Debug)]
+pub struct Sampler {
+    frac: f64,
+    seed: u64,
+    key: SampleKey
+}
+impl Sampler {
+
+    // Instantiate a new Sampler keeping a given fraction of molecules
+    pub fn new(frac: f64, seed: u64, key: SampleKey) -> Self {
+        Sampler { frac, seed, key }
+    }
+
+    // Describes the fraction kept, the key it is decided by, and the seed
+    pub fn describe(&self) -> String {
+        format!("{} by {} (seed {})", self.frac, self.key.name(), self.seed)
+    }
+
+    // Maps a 64 bit hash onto the unit interval
+    fn to_unit(&self, hash: u64) -> f64 {
+        (mix(hash ^ mix(self.seed)) >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    // Returns true if a molecule with the given name and sequence id is kept
+    pub fn keep(&self, name: &str, index: u64) -> bool {
+        let hash = match self.key {
+            SampleKey::Name => fnv1a(name.as_bytes()),
+            SampleKey::Index => index
+        };
+        self.to_unit(hash) < self.frac
+    }
+}
+
+// Returns the sequence id of a molecule from its input file and record position
+pub fn sequence_id(file_index: usize, record_index: usize) -> u64 {
+    ((file_index as u64) << 32) | record_index as u64
+}
+
+// FNV-1a hash, stable across platforms and compiler versions
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .fold(0xcbf29ce484222325, |hash, b| {
+            (hash ^ *b as u64).wrapping_mul(0x100000001b3)
+        })
+}
+
+// SplitMix64 finalizer used to decorrelate hashes from the seed
+fn mix(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+// Molecule drawn into a reservoir with its random priority and sequence id
+struct Drawn {
+    priority: u64,
+    id: u64,
+    mol: Mol2
+}
+impl Ord for Drawn {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.priority, self.id).cmp(&(other.priority, other.id))
+    }
+}
+impl PartialOrd for Drawn {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl PartialEq for Drawn {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+impl Eq for Drawn {}
+
+// Pushes a molecule into a reservoir of at most `n` molecules, keeping the lowest priorities
+fn push_reservoir(reservoir: &mut BinaryHeap<Drawn>, x: Drawn, n: usize) {
+    if reservoir.len() < n {
+        reservoir.push(x);
+    } else if reservoir.peek().is_some_and(|worst| x < *worst) {
+        reservoir.pop();
+        reservoir.push(x);
+    }
+}
+
+// implements sample subcommand: writes a uniform random subset of `n` poses
+// returns the number of molecules written
+//
+// Every pose is given a priority hashed from the seed and its sequence id and
+// the `n` lowest priorities are kept, which is a reservoir sample that is
+// reproducible whatever the number of threads. Sampled poses keep input order.
+pub fn sample(
+        input_files: Vec<String>,
+        n: usize,
+        seed: u64,
+        output_filename: &str) -> Result<u32, Error> {
+
+    let provenance = Provenance::new(&input_files)?;
+    let seed = mix(seed);
+
+    let (reservoir, num_molecules) = input_files
+        .par_iter()
+        .enumerate()
+        .map(|(file_index, x)| {
+            let mut reservoir = BinaryHeap::new();
+            let mut num_molecules = 0;
+            for (record_index, mol) in Mol2Reader::new(x).unwrap().enumerate() {
+                num_molecules += 1;
+                let id = sequence_id(file_index, record_index);
+                push_reservoir(&mut reservoir, Drawn { priority: mix(id ^ seed), id, mol }, n);
+            }
+            (reservoir, num_molecules)
+        })
+        .reduce(|| (BinaryHeap::new(), 0u64), |(mut a, na), (b, nb)| {
+            for x in b {
+                push_reservoir(&mut a, x, n);
+            }
+            (a, na + nb)
+        });
+
+    let mut sampled = reservoir.into_vec();
+    sampled.sort_by_key(|x| x.id);
+
+    let mut writer = writer(output_filename)?;
+    provenance.write(&mut writer)?;
+    for x in sampled.iter() {
+        writer
+            .write_all(x.mol.get_lines().as_bytes())
+            .expect("Error: Error writing to output file");
+    }
+    writer.flush()?;
+
+    println!(">>> Number of Molecules Processed: {}", num_molecules);
+    println!(">>> Number of Molecules Sampled: {}", sampled.len());
+
+    Ok(sampled.len() as u32)
+}
+
+// Molecules exceeding the buffer are spread over 2^SHUFFLE_SHARD_BITS temporary
+// shards by the leading bits of their keys
+const SHUFFLE_SHARD_BITS: u32 = 6;
+
+// Options of the shuffle subcommand
+#[derive(Clone, Debug)]
+pub struct ShuffleOptions {
+    pub seed: u64,
+    // bytes of molecule text held in memory before spilling to temporary shards
+    pub buffer_bytes: u64,
+    // directory temporary shards are written to
+    pub tmp_dir: PathBuf
+}
+impl Default for ShuffleOptions {
+    fn default() -> Self {
+        ShuffleOptions {
+            seed: 0,
+            buffer_bytes: 1 << 30,
+            tmp_dir: std::env::temp_dir()
+        }
+    }
+}
+
+// Struct describing a temporary shard: molecules and their keys, in arrival order
+struct Shard {
+    molecules: PathBuf,
+    keys: PathBuf,
+    molecule_writer: BufWriter<File>,
+    key_writer: BufWriter<File>
+}
+impl Shard {
+    fn create(dir: &std::path::Path, idx: usize) -> Result<Self, Error> {
+        let molecules = dir.join(format!("shard.{:03}.mol2", idx));
+        let keys = dir.join(format!("shard.{:03}.keys", idx));
+        Ok(Shard {
+            molecule_writer: BufWriter::new(File::create(&molecules)?),
+            key_writer: BufWriter::new(File::create(&keys)?),
+            molecules,
+            keys
+        })
+    }
+
+    fn push(&mut self, key: u64, mol: &Mol2) -> Result<(), Error> {
+        self.molecule_writer.write_all(mol.get_lines().as_bytes())?;
+        self.key_writer.write_all(&key.to_le_bytes())
+    }
+
+    // Reads the molecules of the shard back with their keys
+    fn read(mut self) -> Result<Vec<(u64, Mol2)>, Error> {
+        self.molecule_writer.flush()?;
+        self.key_writer.flush()?;
+        let mut keys = BufReader::new(File::open(&self.keys)?);
+        let mut buffer = [0u8; 8];
+        Mol2Reader::new(&self.molecules.to_string_lossy())?
+            .map(|mol| {
+                keys.read_exact(&mut buffer)?;
+                Ok((u64::from_le_bytes(buffer), mol))
+            })
+            .collect()
+    }
+}
+
+// Writes molecules in the order of their keys, returning how many were written
+fn write_by_key(buffer: &mut [(u64, Mol2)], writer: &mut dyn Write) -> u32 {
+    buffer.sort_unstable_by_key(|(key, _)| *key);
+    for (_, mol) in buffer.iter() {
+        writer
+            .write_all(mol.get_lines().as_bytes())
+            .expect("Error: Error writing to output file");
+    }
+    buffer.len() as u32
+}
+
+// implements shuffle subcommand: writes every pose in a random order
+// returns the number of molecules written
+//
+// Every pose is keyed by a hash of the seed and its sequence id and written
+// in key order, so a seed always gives the same order. Beyond `buffer_bytes`
+// poses are spread over temporary shards by key range, alongside their keys,
+// and every shard is then ordered in memory: the output is the same as when
+// shuffling in memory.
+pub fn shuffle(
+        input_files: Vec<String>,
+        output_filename: &str,
+        options: &ShuffleOptions) -> Result<u32, Error> {
+
+    let provenance = Provenance::new(&input_files)?;
+    let seed = mix(options.seed);
+
+    let shard_dir = options.tmp_dir.join(format!("mol2grep-shuffle.{}", std::process::id()));
+    let mut shards: Vec<Shard> = Vec::new();
+    let mut buffer: Vec<(u64, Mol2)> = Vec::new();
+    let mut buffered_bytes = 0;
+    let shard_of = |key: u64| (key >> (64 - SHUFFLE_SHARD_BITS)) as usize;
+
+    // inputs are read in order so that shards are filled reproducibly
+    for (file_index, record_index, mol) in stream_tagged_molecules(input_files) {
+        let key = mix(sequence_id(file_index, record_index) ^ seed);
+        if !shards.is_empty() {
+            shards[shard_of(key)].push(key, &mol)?;
+            continue;
+        }
+        buffered_bytes += mol.get_lines().len() as u64;
+        buffer.push((key, mol));
+
+        // spill everything buffered once the buffer is full
+        if buffered_bytes >= options.buffer_bytes {
+            fs::create_dir_all(&shard_dir)?;
+            shards = (0..1 << SHUFFLE_SHARD_BITS)
+                .map(|idx| Shard::create(&shard_dir, idx))
+                .collect::<Result<Vec<Shard>, Error>>()?;
+            for (key, mol) in buffer.drain(..) {
+                shards[shard_of(key)].push(key, &mol)?;
+            }
+        }
+    }
+
+    let mut writer = writer(output_filename)?;
+    provenance.write(&mut writer)?;
+    let num_spilled = shards.len();
+    let num_written = if shards.is_empty() {
+        write_by_key(&mut buffer, &mut writer)
+    } else {
+        let mut num_written = 0;
+        for shard in shards {
+            num_written += write_by_key(&mut shard.read()?, &mut writer);
+        }
+        fs::remove_dir_all(&shard_dir)?;
+        num_written
+    };
+    writer.flush()?;
+
+    if num_spilled > 0 {
+        println!(">>> Number of Temporary Shards: {}", num_spilled);
+    }
+    println!(">>> Number of Molecules Written: {}", num_written);
+
+    Ok(num_written)
+}
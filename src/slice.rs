@@ -0,0 +1,128 @@
+
+use std::collections::{BTreeSet, VecDeque};
+use std::io::Error;
+use std::io::prelude::*;
+
+use crate::mol2::Mol2;
+use crate::mol2utils::stream_molecules;
+use crate::file_io::{writer, is_stdout};
+use crate::provenance::Provenance;
+
+// Writes molecules to an output, with provenance unless printing to the standard output
+// returns the number of molecules written
+fn write_molecules<'a, I>(
+        input_files: &[String],
+        molecules: I,
+        output_filename: &str) -> Result<u32, Error>
+    where I: IntoIterator<Item = &'a Mol2> {
+
+    let mut writer = writer(output_filename)?;
+    if !is_stdout(output_filename) {
+        Provenance::new(input_files)?.write(&mut writer)?;
+    }
+    let mut num_written = 0;
+    for mol in molecules {
+        writer
+            .write_all(mol.get_lines().as_bytes())
+            .expect("Error: Error writing to output file");
+        num_written += 1;
+    }
+    writer.flush()?;
+
+    // summaries would be mixed into molecules printed to the standard output
+    if !is_stdout(output_filename) {
+        println!(">>> Number of Molecules Written: {}", num_written);
+    }
+    Ok(num_written)
+}
+
+// implements head subcommand: writes the first `n` molecules of the inputs
+// returns the number of molecules written
+//
+// Inputs are read in order and reading stops once `n` molecules are found.
+pub fn head(
+        input_files: Vec<String>,
+        n: usize,
+        output_filename: &str) -> Result<u32, Error> {
+
+    let first: Vec<Mol2> = stream_molecules(input_files.clone())
+        .take(n)
+        .collect();
+    write_molecules(&input_files, first.iter(), output_filename)
+}
+
+// implements tail subcommand: writes the last `n` molecules of the inputs
+// returns the number of molecules written
+//
+// Every input is streamed once holding only the last `n` molecules in memory.
+pub fn tail(
+        input_files: Vec<String>,
+        n: usize,
+        output_filename: &str) -> Result<u32, Error> {
+
+    let mut last: VecDeque<Mol2> = VecDeque::with_capacity(n);
+    if n > 0 {
+        for mol in stream_molecules(input_files.clone()) {
+            if last.len() == n {
+                last.pop_front();
+            }
+            last.push_back(mol);
+        }
+    }
+    write_molecules(&input_files, last.iter(), output_filename)
+}
+
+// Enumerate describing which molecules of the inputs a slice keeps, by their
+// 0-based position across all inputs in order
+#[derive(Clone, Debug)]
+pub enum Selection {
+    // positions from start (inclusive) to end (exclusive, open when unset)
+    Range(usize, Option<usize>),
+    Ordinals(BTreeSet<usize>)
+}
+impl Selection {
+
+    // Returns true if the molecule at a position is kept
+    pub fn contains(&self, position: usize) -> bool {
+        match self {
+            Selection::Range(start, end) => position >= *start && end.is_none_or(|e| position < e),
+            Selection::Ordinals(x) => x.contains(&position)
+        }
+    }
+
+    // Describes the positions kept by the selection
+    pub fn describe(&self) -> String {
+        match self {
+            Selection::Range(start, Some(end)) => format!("positions {} to {}", start, end),
+            Selection::Range(start, None) => format!("positions from {}", start),
+            Selection::Ordinals(x) => format!("{} ordinals", x.len())
+        }
+    }
+
+    // Returns the number of molecules to read before the selection is exhausted
+    // (unbounded for an open range)
+    pub fn limit(&self) -> Option<usize> {
+        match self {
+            Selection::Range(_, end) => *end,
+            Selection::Ordinals(x) => Some(x.last().map_or(0, |x| x + 1))
+        }
+    }
+}
+
+// implements slice subcommand: writes the molecules at the selected positions
+// returns the number of molecules written
+//
+// Inputs are read in order and reading stops past the last selected position.
+pub fn slice(
+        input_files: Vec<String>,
+        selection: &Selection,
+        output_filename: &str) -> Result<u32, Error> {
+
+    let selected: Vec<Mol2> = stream_molecules(input_files.clone())
+        .take(selection.limit().unwrap_or(usize::MAX))
+        .enumerate()
+        .filter(|(position, _)| selection.contains(*position))
+        .map(|(_, mol)| mol)
+        .collect();
+    write_molecules(&input_files, selected.iter(), output_filename)
+}
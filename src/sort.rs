@@ -0,0 +1,218 @@
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::fs;
+use std::io::Error;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+
+use crate::mol2::{Mol2, Mol2Reader};
+use crate::merge::kway_merge;
+use crate::file_io::writer;
+use crate::provenance::Provenance;
+
+use rayon::prelude::*;
+
+// Options of the sort subcommand
+#[derive(Clone, Debug)]
+pub struct SortOptions {
+    pub descending: bool,
+    // bytes of molecule text held in memory before a sorted run is written to disk
+    pub buffer_bytes: u64,
+    // directory sorted runs are written to
+    pub tmp_dir: PathBuf
+}
+impl Default for SortOptions {
+    fn default() -> Self {
+        SortOptions {
+            descending: false,
+            buffer_bytes: 1 << 30,
+            tmp_dir: std::env::temp_dir()
+        }
+    }
+}
+
+// Sorts buffered molecules by energy, keeping input order on ties
+fn sort_buffer(buffer: &mut [Mol2], descending: bool) {
+    match descending {
+        true => buffer.sort_by(|a, b| b.get_energy().total_cmp(&a.get_energy())),
+        false => buffer.sort_by(|a, b| a.get_energy().total_cmp(&b.get_energy()))
+    }
+}
+
+// Writes a sorted run of molecules to an uncompressed temporary file
+fn write_run(buffer: &[Mol2], path: &Path) -> Result<(), Error> {
+    let mut run = std::io::BufWriter::new(fs::File::create(path)?);
+    for mol in buffer {
+        run.write_all(mol.get_lines().as_bytes())?;
+    }
+    run.flush()
+}
+
+// implements sort subcommand: writes every pose ordered by energy
+// returns the number of molecules written
+//
+// Molecules are buffered up to `buffer_bytes`; larger inputs are sorted as
+// runs written to a temporary directory and streamed through a k-way merge,
+// so only one molecule per run is held in memory while merging.
+pub fn sort(
+        input_files: Vec<String>,
+        output_filename: &str,
+        options: &SortOptions) -> Result<u32, Error> {
+
+    let provenance = Provenance::new(&input_files)?;
+
+    let run_dir = options.tmp_dir.join(format!("mol2grep-sort.{}", std::process::id()));
+    let mut runs: Vec<String> = Vec::new();
+    let mut buffer: Vec<Mol2> = Vec::new();
+    let mut buffered_bytes = 0;
+
+    for x in input_files.iter() {
+        for mol in Mol2Reader::new(x)? {
+            buffered_bytes += mol.get_lines().len() as u64;
+            buffer.push(mol);
+
+            // spill a sorted run once the buffer is full
+            if buffered_bytes >= options.buffer_bytes {
+                if runs.is_empty() {
+                    fs::create_dir_all(&run_dir)?;
+                }
+                sort_buffer(&mut buffer, options.descending);
+                let path = run_dir.join(format!("run.{:06}.mol2", runs.len()));
+                write_run(&buffer, &path)?;
+                runs.push(path.to_string_lossy().into_owned());
+                buffer.clear();
+                buffered_bytes = 0;
+            }
+        }
+    }
+    sort_buffer(&mut buffer, options.descending);
+
+    let mut writer = writer(output_filename)?;
+    provenance.write(&mut writer)?;
+
+    let num_written = if runs.is_empty() {
+        for mol in buffer.iter() {
+            writer
+                .write_all(mol.get_lines().as_bytes())
+                .expect("Error: Error writing to output file");
+        }
+        buffer.len() as u32
+    } else {
+        if !buffer.is_empty() {
+            let path = run_dir.join(format!("run.{:06}.mol2", runs.len()));
+            write_run(&buffer, &path)?;
+            runs.push(path.to_string_lossy().into_owned());
+            buffer.clear();
+        }
+        let (num_written, _) = kway_merge(&runs, &mut writer, options.descending)?;
+        fs::remove_dir_all(&run_dir)?;
+        num_written
+    };
+    writer.flush()?;
+
+    if !runs.is_empty() {
+        println!(">>> Number of Sorted Runs Merged: {}", runs.len());
+    }
+    println!(">>> Number of Molecules Written: {}", num_written);
+    println!(">>> Sorted Output Written to: {}", output_filename);
+
+    Ok(num_written)
+}
+
+// Molecule ranked by energy then input order (file index, record index)
+struct Ranked {
+    energy: f64,
+    file_index: usize,
+    record_index: usize,
+    mol: Mol2
+}
+impl Ranked {
+    fn key(&self) -> (usize, usize) {
+        (self.file_index, self.record_index)
+    }
+}
+impl Ord for Ranked {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.energy
+            .total_cmp(&other.energy)
+            .then(self.key().cmp(&other.key()))
+    }
+}
+impl PartialOrd for Ranked {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl PartialEq for Ranked {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+impl Eq for Ranked {}
+
+// Pushes a molecule into a max-heap holding at most `top` molecules,
+// dropping the worst one once it is full
+fn push_bounded(heap: &mut BinaryHeap<Ranked>, x: Ranked, top: Option<usize>) {
+    if let Some(n) = top {
+        if heap.len() >= n {
+            match heap.peek() {
+                Some(worst) if x < *worst => { heap.pop(); },
+                _ => return
+            }
+        }
+    }
+    heap.push(x);
+}
+
+// implements top subcommand over inputs: writes the best poses in energy order
+// returns the number of molecules written
+//
+// Every input is streamed once through a max-heap bounded to `top` molecules,
+// so memory holds at most `top` molecules per reading thread.
+pub fn top(
+        input_files: Vec<String>,
+        top: Option<usize>,
+        max_energy: Option<f64>,
+        output_filename: &str) -> Result<u32, Error> {
+
+    let provenance = Provenance::new(&input_files)?;
+
+    let (heap, num_molecules) = input_files
+        .par_iter()
+        .enumerate()
+        .map(|(file_index, x)| {
+            let mut heap = BinaryHeap::new();
+            let mut num_molecules = 0;
+            for (record_index, mol) in Mol2Reader::new(x).unwrap().enumerate() {
+                num_molecules += 1;
+                let energy = mol.get_energy();
+                if max_energy.is_some_and(|m| energy > m) {
+                    continue;
+                }
+                push_bounded(&mut heap, Ranked { energy, file_index, record_index, mol }, top);
+            }
+            (heap, num_molecules)
+        })
+        .reduce(|| (BinaryHeap::new(), 0), |(mut a, na), (b, nb)| {
+            for x in b {
+                push_bounded(&mut a, x, top);
+            }
+            (a, na + nb)
+        });
+
+    let mut writer = writer(output_filename)?;
+    provenance.write(&mut writer)?;
+    let best = heap.into_sorted_vec();
+    for x in best.iter() {
+        writer
+            .write_all(x.mol.get_lines().as_bytes())
+            .expect("Error: Error writing to output file");
+    }
+    writer.flush()?;
+
+    println!(">>> Number of Molecules Processed: {}", num_molecules);
+    println!(">>> Number of Molecules Written: {}", best.len());
+
+    Ok(best.len() as u32)
+}
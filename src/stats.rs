@@ -0,0 +1,177 @@
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Error};
+use std::io::prelude::*;
+
+use crate::mol2::Mol2Reader;
+
+use rayon::prelude::*;
+
+// Struct describing summary statistics of a set of pose energies
+#[derive(Clone, Debug)]
+pub struct EnergyStats {
+    pub count: u64,
+    pub best: f64,
+    pub worst: f64,
+    sum: f64,
+    sum_sq: f64
+}
+impl Default for EnergyStats {
+    fn default() -> Self {
+        EnergyStats {
+            count: 0,
+            best: f64::INFINITY,
+            worst: f64::NEG_INFINITY,
+            sum: 0.0,
+            sum_sq: 0.0
+        }
+    }
+}
+impl EnergyStats {
+
+    // Adds an energy to the statistics
+    pub fn push(&mut self, energy: f64) {
+        self.count += 1;
+        self.best = self.best.min(energy);
+        self.worst = self.worst.max(energy);
+        self.sum += energy;
+        self.sum_sq += energy * energy;
+    }
+
+    // Merges the statistics of another set of energies into this one
+    pub fn merge(&mut self, other: &EnergyStats) {
+        self.count += other.count;
+        self.best = self.best.min(other.best);
+        self.worst = self.worst.max(other.worst);
+        self.sum += other.sum;
+        self.sum_sq += other.sum_sq;
+    }
+
+    // Returns the mean energy
+    pub fn mean(&self) -> f64 {
+        if self.count > 0 { self.sum / self.count as f64 } else { 0.0 }
+    }
+
+    // Returns the population standard deviation of the energies
+    pub fn std(&self) -> f64 {
+        if self.count == 0 {
+            return 0.0
+        }
+        let mean = self.mean();
+        (self.sum_sq / self.count as f64 - mean * mean).max(0.0).sqrt()
+    }
+
+    // Returns the difference between the worst and best energy
+    pub fn spread(&self) -> f64 {
+        if self.count > 0 { self.worst - self.best } else { 0.0 }
+    }
+}
+
+// Aggregates the energies of every pose by name across the inputs
+// (and collects every energy when `keep_energies` is set)
+fn collect_stats(input_files: &[String], keep_energies: bool) -> (HashMap<String, EnergyStats>, Vec<f64>) {
+    input_files
+        .par_iter()
+        .map(|x| {
+            let mut stats: HashMap<String, EnergyStats> = HashMap::new();
+            let mut energies = Vec::new();
+            for mol in Mol2Reader::new(x).unwrap() {
+                stats
+                    .entry(mol.get_name().to_string())
+                    .or_default()
+                    .push(mol.get_energy());
+                if keep_energies {
+                    energies.push(mol.get_energy());
+                }
+            }
+            (stats, energies)
+        })
+        .reduce(|| (HashMap::new(), Vec::new()), |(mut a, mut ea), (b, eb)| {
+            for (k, v) in b {
+                a.entry(k).or_default().merge(&v);
+            }
+            ea.extend(eb);
+            (a, ea)
+        })
+}
+
+// Aggregates the energies of every pose by name across the inputs
+pub fn per_ligand_stats(input_files: &[String]) -> HashMap<String, EnergyStats> {
+    collect_stats(input_files, false).0
+}
+
+// Returns the `percent` percentile of sorted energies, interpolating between
+// the closest ranks
+pub fn percentile(sorted: &[f64], percent: f64) -> Option<f64> {
+    if sorted.is_empty() {
+        return None
+    }
+    let rank = (percent.clamp(0.0, 100.0) / 100.0) * (sorted.len() - 1) as f64;
+    let (low, high) = (rank.floor() as usize, rank.ceil() as usize);
+    Some(sorted[low] + (sorted[high] - sorted[low]) * (rank - low as f64))
+}
+
+// implements stats subcommand: energy statistics of the whole run or of every ligand
+// returns the statistics of all poses
+pub fn stats(
+        input_files: Vec<String>,
+        output_filename: &str,
+        per_ligand: bool,
+        percentiles: &[f64]) -> Result<EnergyStats, Error> {
+
+    let (ligands, mut energies) = collect_stats(&input_files, !per_ligand);
+    energies.sort_by(|a, b| a.total_cmp(b));
+
+    let mut total = EnergyStats::default();
+    ligands
+        .values()
+        .for_each(|s| total.merge(s));
+
+    let mut writer = BufWriter::new(File::create(output_filename)?);
+    if per_ligand {
+        // best ligands first
+        let mut rows: Vec<(&String, &EnergyStats)> = ligands.iter().collect();
+        rows.sort_by(|a, b| a.1.best.total_cmp(&b.1.best).then(a.0.cmp(b.0)));
+
+        writer.write_all(b"name\tposes\tbest\tworst\tmean\tstd\tspread\n")?;
+        for (name, s) in rows {
+            writeln!(
+                writer,
+                "{}\t{}\t{}\t{}\t{:.6}\t{:.6}\t{:.6}",
+                name, s.count, s.best, s.worst, s.mean(), s.std(), s.spread()
+            )?;
+        }
+    } else {
+        write!(writer, "poses\tligands\tbest\tworst\tmean\tstd\tmedian")?;
+        for p in percentiles {
+            write!(writer, "\tp{}", p)?;
+        }
+        writeln!(writer)?;
+        write!(
+            writer,
+            "{}\t{}\t{}\t{}\t{:.6}\t{:.6}\t{}",
+            total.count, ligands.len(), total.best, total.worst, total.mean(), total.std(),
+            percentile(&energies, 50.0).unwrap_or(0.0)
+        )?;
+        for p in percentiles {
+            write!(writer, "\t{}", percentile(&energies, *p).unwrap_or(0.0))?;
+        }
+        writeln!(writer)?;
+    }
+
+    println!(">>> Number of Poses: {}", total.count);
+    println!(">>> Number of Ligands: {}", ligands.len());
+    println!(">>> Best Energy: {}", total.best);
+    println!(">>> Mean Energy: {:.6}", total.mean());
+    if !per_ligand {
+        println!(">>> Std Energy: {:.6}", total.std());
+        println!(">>> Median Energy: {}", percentile(&energies, 50.0).unwrap_or(0.0));
+        for p in percentiles {
+            println!(">>> Percentile {}: {}", p, percentile(&energies, *p).unwrap_or(0.0));
+        }
+    }
+    println!(">>> Statistics Written to: {}", output_filename);
+
+    Ok(total)
+}
@@ -2,10 +2,59 @@
 #[cfg(test)]
 mod tests {
 
+    use std::collections::{HashMap, HashSet};
+    use crate::query::{self, ColumnSpec, QueryColumns, QueryFormat, QueryReader, ToleranceMode};
     // use serial_test::serial;
-    use crate::mol2::Mol2Reader;
-    use crate::file_io::read_input_list;
+    use std::io::{Read, Write};
+    use crate::mol2::{Mol2, Mol2Reader};
+    use crate::file_io::{read_input_list, read_name_list, select_input_range};
     use crate::mol2utils;
+    use crate::mol2utils::{GrepOptions, SplitOptions, TableOptions};
+    use crate::budget::Budget;
+    use crate::sample::{self, Sampler, SampleKey};
+    use crate::transform::{self, TransformOptions};
+    use crate::filter::{self, FilterOptions};
+    use crate::qc;
+    use crate::decoys;
+    use crate::enrichment;
+    use crate::stats;
+    use crate::cache;
+    use crate::zinc;
+    use crate::reorder;
+    use crate::merge;
+    use crate::sort;
+    use crate::dedup;
+    use crate::slice;
+    use crate::report;
+    use crate::compare;
+    use crate::index;
+    use crate::process::{Action, ProcessOptions};
+    use crate::provenance;
+    use crate::gzip;
+
+    // Struct describing the scratch directory of a single test, removed when dropped
+    // (also when the test fails)
+    struct Scratch(std::path::PathBuf);
+    impl Scratch {
+
+        // Creates an empty scratch directory under the system temporary directory
+        fn new(test: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("mol2grep-{}-{}", test, std::process::id()));
+            std::fs::remove_dir_all(&dir).ok();
+            std::fs::create_dir_all(&dir).unwrap();
+            Scratch(dir)
+        }
+
+        // Returns the path of a file in the scratch directory
+        fn path(&self, name: &str) -> String {
+            self.0.join(name).to_str().unwrap().to_string()
+        }
+    }
+    impl Drop for Scratch {
+        fn drop(&mut self) {
+            std::fs::remove_dir_all(&self.0).ok();
+        }
+    }
 
     #[test]
     fn read_mol2() {
@@ -82,24 +131,56 @@ mod tests {
         assert!(num_molecules == 6972);
     }
 
+    #[test]
+    fn select_list_range() {
+        /*
+        Tests whether an input list can be restricted by resume, skip, and limit
+        */
+
+        let input_list = "data/input_list.txt";
+        let input_files = read_input_list(input_list)
+            .expect("Error: Failed Reading Input List");
+
+        let selection = select_input_range(
+            input_files.clone(),
+            Some("data/test0002.mol2.gz"),
+            1,
+            Some(2)
+        ).unwrap();
+
+        assert!(selection == vec!["data/test0003.mol2.gz", "data/test0004.mol2.gz"]);
+
+        let missing = select_input_range(
+            input_files,
+            Some("data/missing.mol2.gz"),
+            0,
+            None
+        );
+
+        assert!(missing.is_err());
+    }
+
     #[test]
     fn run_grep_without_energy() {
         /*
         Tests whether a query can be read and processed without energy
         */
 
+        let scratch = Scratch::new("run_grep_without_energy");
+
         let input_list = "data/input_list.txt";
         let input_files = read_input_list(input_list)
             .expect("Error: Failed Reading Input List");
-        let output_filename = "test_grep_without_energy.mol2.gz";
+        let output_filename = &scratch.path("test_grep_without_energy.mol2.gz");
         let query_filename = "data/zinc_list.txt";
         let tol = 1e-6;
 
         let num_passing = mol2utils::grep(
             input_files,
-            query_filename,
+            &[query_filename],
             output_filename,
-            tol
+            tol,
+            &GrepOptions::default()
         ).unwrap();
 
         assert!(num_passing == 10);
@@ -109,36 +190,4264 @@ mod tests {
     #[test]
     fn run_grep_with_energy() {
 
+        let scratch = Scratch::new("run_grep_with_energy");
+
         let input_list = "data/input_list.txt";
         let input_files = read_input_list(input_list)
             .expect("Error: Failed Reading Input List");
-        let output_filename = "test_grep_with_energy.mol2.gz";
+        let output_filename = &scratch.path("test_grep_with_energy.mol2.gz");
         let query_filename = "data/zinc_list.tsv";
         let tol = 1e-6;
 
         let num_passing = mol2utils::grep(
             input_files,
-            query_filename,
+            &[query_filename],
             output_filename,
-            tol
+            tol,
+            &GrepOptions::default()
         ).unwrap();
 
         assert!(num_passing == 8);
     }
 
     #[test]
-    fn run_split() {
+    fn run_grep_index() {
+        /*
+        Tests whether looking queries up in an index gives the same molecules
+        as scanning the inputs, with and without expected scores
+        */
+
+        let scratch = Scratch::new("run_grep_index");
+
+        let input_files = read_input_list("data/input_list.txt")
+            .expect("Error: Failed Reading Input List");
+        let index_filename = &scratch.path("test_grep_index.idx");
+        index::build_index(input_files.clone(), index_filename).unwrap();
+
+        let read = |x: &str| -> HashSet<String> {
+            Mol2Reader::new(x).unwrap().map(|m| m.get_lines().to_string()).collect()
+        };
+        for query_filename in ["data/zinc_list.tsv", "data/zinc_list.txt"] {
+            let scanned = mol2utils::grep(
+                input_files.clone(),
+                &[query_filename],
+                &scratch.path("test_grep_index_scan.mol2.gz"),
+                1e-6,
+                &GrepOptions::default()
+            ).unwrap();
+            let looked_up = index::grep_index(
+                index_filename,
+                &[query_filename],
+                &QueryColumns::default(),
+                &scratch.path("test_grep_index.mol2.gz"),
+                query::Tolerance::absolute(1e-6)
+            ).unwrap();
+            assert!(looked_up as u32 == scanned);
+            assert!(read(&scratch.path("test_grep_index.mol2.gz")) == read(&scratch.path("test_grep_index_scan.mol2.gz")));
+        }
+
+        // query columns and relative tolerances are honored as when scanning
+        let query_filename = &scratch.path("test_grep_index.csv");
+        let rows: Vec<String> = Mol2Reader::new(&input_files[0])
+            .unwrap()
+            .take(20)
+            .map(|m| format!("{},x,{}", m.get_energy() * 0.95, m.get_name()))
+            .collect();
+        std::fs::write(query_filename, format!("score,other,id\n{}\n", rows.join("\n"))).unwrap();
+        let options = GrepOptions {
+            query_columns: QueryColumns {
+                id: Some(ColumnSpec::parse("id")),
+                score: Some(ColumnSpec::parse("score")),
+                csv: true,
+                ..QueryColumns::default()
+            },
+            rel_tol: 0.1,
+            tol_mode: ToleranceMode::Abs,
+            ..GrepOptions::default()
+        };
+        let scanned = mol2utils::grep(
+            input_files.clone(),
+            &[query_filename],
+            &scratch.path("test_grep_index_scan.mol2.gz"),
+            0.0,
+            &options
+        ).unwrap();
+        let tolerance = query::Tolerance { absolute: 0.0, relative: 0.1, mode: ToleranceMode::Abs };
+        let looked_up = index::grep_index(
+            index_filename,
+            &[query_filename],
+            &options.query_columns,
+            &scratch.path("test_grep_index.mol2.gz"),
+            tolerance
+        ).unwrap();
+        assert!(scanned > 0);
+        assert!(looked_up as u32 == scanned);
+        assert!(read(&scratch.path("test_grep_index.mol2.gz")) == read(&scratch.path("test_grep_index_scan.mol2.gz")));
+    }
+
+    #[test]
+    fn run_grep_regex() {
+        /*
+        Tests whether query lines read as regular expressions select every
+        molecule whose whole name matches, and whether a malformed pattern is
+        reported as an error
+        */
+
+        let scratch = Scratch::new("run_grep_regex");
+
+        let input_files = vec!["data/test0000.mol2.gz".to_string()];
+        let query_filename = &scratch.path("test_grep_regex.txt");
+        std::fs::write(query_filename, "ZINC0000047.*\nZINC00015391979[0-9]\nZINC0001539\n").unwrap();
+        let options = GrepOptions { regex: true, ..GrepOptions::default() };
+        let num_passing = mol2utils::grep(
+            input_files.clone(),
+            &[query_filename],
+            &scratch.path("test_grep_regex.mol2.gz"),
+            1e-6,
+            &options
+        ).unwrap();
+
+        let pattern = regex::Regex::new("^(?:ZINC0000047.*|ZINC00015391979[0-9])$").unwrap();
+        let expected: Vec<String> = Mol2Reader::new(&input_files[0])
+            .unwrap()
+            .filter(|m| pattern.is_match(m.get_name()))
+            .map(|m| m.get_lines().to_string())
+            .collect();
+        let mut observed: Vec<String> = Mol2Reader::new(&scratch.path("test_grep_regex.mol2.gz"))
+            .unwrap()
+            .map(|m| m.get_lines().to_string())
+            .collect();
+        observed.sort();
+        let mut sorted = expected.clone();
+        sorted.sort();
+        assert!(!expected.is_empty());
+        assert!(num_passing as usize == expected.len());
+        assert!(observed == sorted);
+
+        std::fs::write(query_filename, "ZINC(\n").unwrap();
+        let malformed = mol2utils::grep(input_files, &[query_filename], &scratch.path("test_grep_regex.mol2.gz"), 1e-6, &options);
+        assert!(malformed.is_err());
+    }
+
+    #[test]
+    fn run_grep_glob() {
+        /*
+        Tests whether query names holding glob wildcards are matched as patterns
+        while the remaining names are still matched exactly
+        */
+
+        let scratch = Scratch::new("run_grep_glob");
+
+        assert!(query::glob_to_regex("ZINC0*[!0-4]?.x") == "ZINC0.*[^0-4].\\.x");
+        assert!(!query::is_glob("ZINC000004737119"));
+
+        let input_files = vec!["data/test0000.mol2.gz".to_string()];
+        let query_filename = &scratch.path("test_grep_glob.txt");
+        std::fs::write(query_filename, "ZINC0000047*\nZINC00015391979?\nZINC000153919692\n").unwrap();
+        let table = QueryReader::new(query_filename).unwrap().load_queries().unwrap();
+        assert!(table.has_patterns());
+        assert!(table.names().collect::<Vec<&str>>() == vec!["ZINC000153919692"]);
+
+        let num_passing = mol2utils::grep(
+            input_files.clone(),
+            &[query_filename],
+            &scratch.path("test_grep_glob.mol2.gz"),
+            1e-6,
+            &GrepOptions::default()
+        ).unwrap();
+
+        let pattern = regex::Regex::new("^(?:ZINC0000047.*|ZINC00015391979.|ZINC000153919692)$").unwrap();
+        let mut expected: Vec<String> = Mol2Reader::new(&input_files[0])
+            .unwrap()
+            .filter(|m| pattern.is_match(m.get_name()))
+            .map(|m| m.get_lines().to_string())
+            .collect();
+        let mut observed: Vec<String> = Mol2Reader::new(&scratch.path("test_grep_glob.mol2.gz"))
+            .unwrap()
+            .map(|m| m.get_lines().to_string())
+            .collect();
+        expected.sort();
+        observed.sort();
+        assert!(expected.len() > 1);
+        assert!(num_passing as usize == expected.len());
+        assert!(observed == expected);
+
+        // patterns cannot carry expected scores
+        std::fs::write(query_filename, "ZINC0000047*\t-30.0\n").unwrap();
+        assert!(QueryReader::new(query_filename).unwrap().load_queries().is_err());
+    }
+
+    #[test]
+    fn run_grep_ignore_case() {
+        /*
+        Tests whether lowercased query names only match molecules when case is
+        ignored, and then match exactly the molecules of the original names
+        */
+
+        let scratch = Scratch::new("run_grep_ignore_case");
+
+        let input_files = read_input_list("data/input_list.txt")
+            .expect("Error: Failed Reading Input List");
+        let query_filename = &scratch.path("test_grep_ignore_case.txt");
+        std::fs::write(
+            query_filename,
+            std::fs::read_to_string("data/zinc_list.txt").unwrap().to_lowercase()
+        ).unwrap();
+
+        let expected = mol2utils::grep(
+            input_files.clone(),
+            &["data/zinc_list.txt"],
+            &scratch.path("test_grep_ignore_case_expected.mol2.gz"),
+            1e-6,
+            &GrepOptions::default()
+        ).unwrap();
+        let case_sensitive = mol2utils::grep(
+            input_files.clone(),
+            &[query_filename],
+            &scratch.path("test_grep_ignore_case.mol2.gz"),
+            1e-6,
+            &GrepOptions::default()
+        ).unwrap();
+        assert!(expected > 0);
+        assert!(case_sensitive == 0);
+
+        let options = GrepOptions { ignore_case: true, ..GrepOptions::default() };
+        let ignored = mol2utils::grep(
+            input_files.clone(),
+            &[query_filename],
+            &scratch.path("test_grep_ignore_case.mol2.gz"),
+            1e-6,
+            &options
+        ).unwrap();
+        assert!(ignored == expected);
+        let read = |x: &str| -> HashSet<String> {
+            Mol2Reader::new(x).unwrap().map(|m| m.get_lines().to_string()).collect()
+        };
+        assert!(read(&scratch.path("test_grep_ignore_case.mol2.gz")) == read(&scratch.path("test_grep_ignore_case_expected.mol2.gz")));
+
+        // patterns are matched regardless of case as well
+        std::fs::write(query_filename, "zinc0000047.*\n").unwrap();
+        let options = GrepOptions { ignore_case: true, regex: true, ..GrepOptions::default() };
+        let num_patterns = mol2utils::grep(
+            vec!["data/test0000.mol2.gz".to_string()],
+            &[query_filename],
+            &scratch.path("test_grep_ignore_case.mol2.gz"),
+            1e-6,
+            &options
+        ).unwrap();
+        assert!(num_patterns > 0);
+    }
+
+    #[test]
+    fn run_grep_ignore_case_scores() {
+        /*
+        Tests whether expected scores of names differing only in case are all kept
+        when case is ignored, so a pose matching either score is found
+        */
+
+        let scratch = Scratch::new("run_grep_ignore_case_scores");
+
+        let query_filename = &scratch.path("test_grep_ignore_case_scores.tsv");
+        std::fs::write(query_filename, "ZINC1\t-10.0\nzinc1\t-20.0\n").unwrap();
+        let table = QueryReader::new(query_filename).unwrap().load_queries().unwrap().fold_case().unwrap();
+        match table {
+            QueryFormat::WithScore(t) => {
+                assert!(t.len() == 1);
+                let mut scores: Vec<f64> = t.values().flatten().map(|x| x.score).collect();
+                scores.sort_by(|a, b| a.total_cmp(b));
+                assert!(scores == vec![-20.0, -10.0]);
+            },
+            _ => panic!("expected a table with scores")
+        }
+
+        // the pose only matches the score given under the lowercased name
+        let mol = Mol2Reader::new("data/test0000.mol2.gz").unwrap().next().unwrap();
+        let rows = [
+            (mol.get_name().to_uppercase(), mol.get_energy() + 5.0),
+            (mol.get_name().to_lowercase(), mol.get_energy())
+        ];
+        for order in [[0, 1], [1, 0]] {
+            let text: String = order.iter().map(|&i| format!("{}\t{}\n", rows[i].0, rows[i].1)).collect();
+            std::fs::write(query_filename, text).unwrap();
+            let options = GrepOptions { ignore_case: true, tol_mode: ToleranceMode::Abs, ..GrepOptions::default() };
+            let num_passing = mol2utils::grep(
+                vec!["data/test0000.mol2.gz".to_string()],
+                &[query_filename],
+                &scratch.path("test_grep_ignore_case_scores.mol2.gz"),
+                1e-6,
+                &options
+            ).unwrap();
+            assert!(num_passing >= 1);
+        }
+    }
+
+    #[test]
+    fn run_grep_dry_run() {
+        /*
+        Tests whether a dry run lists the files a grep writes, the archive in place
+        of its members, and creates none of them
+        */
+
+        let scratch = Scratch::new("run_grep_dry_run");
+        let path = |x: &str| scratch.path(x);
+        let input_files = vec!["data/test0000.mol2.gz".to_string(), "data/test0001.mol2.gz".to_string()];
+        let output_filename = path("out.mol2.gz");
+
+        let options = GrepOptions {
+            rest_out: Some(path("rest.mol2.gz")),
+            missing_out: Some(path("missing.txt")),
+            ..GrepOptions::default()
+        };
+        let (queries, outputs) = mol2utils::grep_dry_run(
+            &input_files,
+            &["data/zinc_list.txt"],
+            &output_filename,
+            &options
+        ).unwrap();
+        assert!(queries.len() == 1 && queries[0].starts_with("data/zinc_list.txt"));
+        assert!(outputs == vec![output_filename.clone(), path("rest.mol2.gz"), path("missing.txt")]);
+
+        let options = GrepOptions {
+            rest_out: Some(path("rest.mol2.gz")),
+            archive: Some(path("out.tar")),
+            ..GrepOptions::default()
+        };
+        let (_, outputs) = mol2utils::grep_dry_run(&input_files, &["data/zinc_list.txt"], &output_filename, &options).unwrap();
+        assert!(outputs == vec![path("out.tar")]);
+
+        let options = GrepOptions { per_input_output: true, ..GrepOptions::default() };
+        let (_, outputs) = mol2utils::grep_dry_run(&input_files, &["data/zinc_list.txt"], &output_filename, &options).unwrap();
+        assert!(outputs == input_files
+            .iter()
+            .map(|x| mol2utils::per_input_filename(&output_filename, x))
+            .collect::<Vec<String>>());
+
+        assert!(std::fs::read_dir(&scratch.0).unwrap().next().is_none());
+    }
+
+    #[test]
+    fn format_dry_run_settings() {
+        /*
+        Tests whether dry run settings are printed as plain values, with unset
+        options shown as none and lists as comma separated values
+        */
+
+        let sampler = Sampler::new(0.1, 42, SampleKey::Name);
+        let text = report::format_dry_run(
+            "table",
+            &["data/test0000.mol2.gz".to_string()],
+            &["out.tsv.gz".to_string()],
+            &[
+                ("limit", report::optional_setting(Some(3))),
+                ("max_energy", report::optional_setting(None::<f64>)),
+                ("sample", report::optional_setting(Some(sampler.describe()))),
+                ("ef", report::list_setting(&[1.0, 5.0])),
+                ("selection", slice::Selection::Range(10, None).describe())
+            ]
+        );
+        let lines: Vec<&str> = text.lines().collect();
+        assert!(lines[0] == ">>> Dry Run: table");
+        assert!(lines.contains(&"  limit:\t3"));
+        assert!(lines.contains(&"  max_energy:\tnone"));
+        assert!(lines.contains(&"  sample:\t0.1 by name (seed 42)"));
+        assert!(lines.contains(&"  ef:\t1,5"));
+        assert!(lines.contains(&"  selection:\tpositions from 10"));
+        assert!(!text.contains("Some("));
+    }
+
+    #[test]
+    fn run_grep_ignore_case_names() {
+        /*
+        Tests whether matching regardless of case keeps the molecule names as
+        read in the output and the query names as given in the reports
+        */
+
+        let scratch = Scratch::new("run_grep_ignore_case_names");
+        let path = |x: &str| scratch.path(x);
+        let query_filename = path("queries.txt");
+        let queries = std::fs::read_to_string("data/zinc_list.txt").unwrap().to_lowercase();
+        std::fs::write(&query_filename, &queries).unwrap();
+
+        let options = GrepOptions {
+            ignore_case: true,
+            names_only: true,
+            query_counts: Some(path("counts.tsv")),
+            ..GrepOptions::default()
+        };
+        let num_matches = mol2utils::grep(
+            read_input_list("data/input_list.txt").unwrap(),
+            &[&query_filename],
+            &path("names.txt"),
+            1e-6,
+            &options
+        ).unwrap();
+        assert!(num_matches > 0);
+
+        let names = std::fs::read_to_string(path("names.txt")).unwrap();
+        assert!(names.lines().count() == num_matches as usize);
+        assert!(names.lines().all(|x| x.starts_with("ZINC")));
+
+        let given: HashSet<&str> = queries.lines().collect();
+        let counts = std::fs::read_to_string(path("counts.tsv")).unwrap();
+        let counted: Vec<(&str, u32)> = counts
+            .lines()
+            .filter_map(|x| x.split_once('\t'))
+            .filter_map(|(name, count)| Some((name, count.parse().ok()?)))
+            .collect();
+        assert!(counted.iter().all(|(name, _)| given.contains(name)));
+        assert!(counted.iter().map(|(_, count)| count).sum::<u32>() == num_matches);
+    }
+
+    #[test]
+    fn run_query_reader_stdin() {
+        /*
+        Tests whether a query list read from any buffered source gives the same
+        table as the file itself, and whether the standard input is refused as
+        both a query list and an input
+        */
+
+        let scratch = Scratch::new("run_query_reader_stdin");
+
+        let text = std::fs::read("data/zinc_list.tsv").unwrap();
+        let from_file = QueryReader::new("data/zinc_list.tsv").unwrap().load_queries().unwrap();
+        let from_reader = QueryReader::from_reader("stdin", std::io::Cursor::new(text))
+            .unwrap()
+            .load_queries()
+            .unwrap();
+        match (from_file, from_reader) {
+            (QueryFormat::WithScore(a), QueryFormat::WithScore(b)) => {
+                assert!(a.len() == b.len());
+                assert!(a.iter().all(|(k, v)| b.get(k) == Some(v)));
+            },
+            _ => panic!("expected tables with scores")
+        }
+
+        let twice = mol2utils::grep(
+            vec!["-".to_string()],
+            &["-"],
+            &scratch.path("test_query_reader_stdin.mol2.gz"),
+            1e-6,
+            &GrepOptions::default()
+        );
+        assert!(twice.is_err());
+    }
+
+    #[test]
+    fn run_grep_query_columns() {
+        /*
+        Tests whether names and scores selected from multi-column tables, by
+        header name or by position, match the same molecules as the two column
+        table they were built from
+        */
+
+        let scratch = Scratch::new("run_grep_query_columns");
+
+        let input_files = read_input_list("data/input_list.txt")
+            .expect("Error: Failed Reading Input List");
+        let rows: Vec<(String, String)> = std::fs::read_to_string("data/zinc_list.tsv")
+            .unwrap()
+            .lines()
+            .filter(|x| !x.trim().is_empty())
+            .map(|x| {
+                let items: Vec<&str> = x.split_whitespace().collect();
+                (items[0].to_string(), items[1].to_string())
+            })
+            .collect();
+
+        // a csv with a header and a tsv without one, each with extra columns
+        let csv: String = std::iter::once("id,rank,score,cluster\n".to_string())
+            .chain(rows.iter().enumerate().map(|(idx, (name, score))| format!("{},{},{},c{}\n", name, idx + 1, score, idx % 3)))
+            .collect();
+        let tsv: String = rows
+            .iter()
+            .enumerate()
+            .map(|(idx, (name, score))| format!("{}\t{}\t{}\n", score, idx + 1, name))
+            .collect();
+        std::fs::write(scratch.path("test_grep_query_columns.csv"), csv).unwrap();
+        std::fs::write(scratch.path("test_grep_query_columns.tsv"), tsv).unwrap();
+
+        let read = |x: &str| -> HashSet<String> {
+            Mol2Reader::new(x).unwrap().map(|m| m.get_lines().to_string()).collect()
+        };
+        let expected = mol2utils::grep(
+            input_files.clone(),
+            &["data/zinc_list.tsv"],
+            &scratch.path("test_grep_query_columns_expected.mol2.gz"),
+            1e-6,
+            &GrepOptions::default()
+        ).unwrap();
+
+        let by_name = QueryColumns {
+            id: Some(ColumnSpec::parse("id")),
+            score: Some(ColumnSpec::parse("score")),
+            ..QueryColumns::default()
+        };
+        let by_index = QueryColumns {
+            id: Some(ColumnSpec::parse("3")),
+            score: Some(ColumnSpec::parse("1")),
+            ..QueryColumns::default()
+        };
+        for (query_filename, columns) in [(&scratch.path("test_grep_query_columns.csv"), by_name), (&scratch.path("test_grep_query_columns.tsv"), by_index)] {
+            let options = GrepOptions { query_columns: columns, ..GrepOptions::default() };
+            let num_passing = mol2utils::grep(
+                input_files.clone(),
+                &[query_filename],
+                &scratch.path("test_grep_query_columns.mol2.gz"),
+                1e-6,
+                &options
+            ).unwrap();
+            assert!(num_passing == expected);
+            assert!(read(&scratch.path("test_grep_query_columns.mol2.gz")) == read(&scratch.path("test_grep_query_columns_expected.mol2.gz")));
+        }
+
+        // names without scores, and a missing header name
+        let names_only = QueryColumns { id: Some(ColumnSpec::Name("id".to_string())), ..QueryColumns::default() };
+        match QueryReader::new(&scratch.path("test_grep_query_columns.csv")).unwrap().load_queries_with(&names_only).unwrap() {
+            QueryFormat::WithoutScore(t) => assert!(t.len() == rows.len()),
+            _ => panic!("expected a table without scores")
+        }
+        let missing = QueryColumns { id: Some(ColumnSpec::parse("zinc_id")), ..QueryColumns::default() };
+        assert!(QueryReader::new(&scratch.path("test_grep_query_columns.csv")).unwrap().load_queries_with(&missing).is_err());
+    }
+
+    #[test]
+    fn run_grep_energy_window() {
+        /*
+        Tests whether a three column table accepts every pose of a name whose
+        energy falls inside its window, and rejects inverted windows
+        */
+
+        let scratch = Scratch::new("run_grep_energy_window");
+
+        let input_files = vec!["data/test0000.mol2.gz".to_string()];
+        let molecules: Vec<Mol2> = Mol2Reader::new(&input_files[0]).unwrap().collect();
+        let names: Vec<&str> = molecules.iter().map(|m| m.get_name()).take(20).collect();
+
+        // a window around the energy of every other name
+        let mut windows: HashMap<&str, (f64, f64)> = HashMap::new();
+        for (idx, name) in names.iter().enumerate() {
+            let energy = molecules.iter().find(|m| m.get_name() == *name).unwrap().get_energy();
+            let window = match idx % 2 {
+                0 => (energy - 1.0, energy + 1.0),
+                _ => (energy + 1.0, energy + 2.0)
+            };
+            windows.insert(name, window);
+        }
+        let table: String = windows
+            .iter()
+            .map(|(name, (min, max))| format!("{}\t{}\t{}\n", name, min, max))
+            .collect();
+        let query_filename = &scratch.path("test_grep_energy_window.tsv");
+        std::fs::write(query_filename, table).unwrap();
+
+        let num_passing = mol2utils::grep(
+            input_files,
+            &[query_filename],
+            &scratch.path("test_grep_energy_window.mol2.gz"),
+            1e-6,
+            &GrepOptions::default()
+        ).unwrap();
+        let expected = molecules
+            .iter()
+            .filter(|m| windows
+                .get(m.get_name())
+                .is_some_and(|(min, max)| m.get_energy() >= *min && m.get_energy() <= *max))
+            .count();
+        assert!(expected > 0);
+        assert!(num_passing as usize == expected);
+        assert!(Mol2Reader::new(&scratch.path("test_grep_energy_window.mol2.gz")).unwrap().count() == expected);
+
+        std::fs::write(query_filename, "ZINC000004737119\t-20.0\t-30.0\n").unwrap();
+        assert!(QueryReader::new(query_filename).unwrap().load_queries().is_err());
+    }
+
+    #[test]
+    fn run_grep_relative_tolerance() {
+        /*
+        Tests whether a relative tolerance accepts scores deviating from their
+        expected values in proportion to the magnitude of the expected score
+        */
+
+        let scratch = Scratch::new("run_grep_relative_tolerance");
+
+        let input_files = vec!["data/test0000.mol2.gz".to_string()];
+        let molecules: Vec<Mol2> = Mol2Reader::new(&input_files[0]).unwrap().collect();
+
+        // expected scores 5% away from the first pose of each name
+        let mut expected_scores: HashMap<&str, f64> = HashMap::new();
+        for m in molecules.iter().take(40) {
+            expected_scores.entry(m.get_name()).or_insert(m.get_energy() * 1.05);
+        }
+        let table: String = expected_scores
+            .iter()
+            .map(|(name, score)| format!("{}\t{}\n", name, score))
+            .collect();
+        let query_filename = &scratch.path("test_grep_relative_tolerance.tsv");
+        std::fs::write(query_filename, table).unwrap();
+
+        for rel_tol in [0.0, 0.04, 0.06] {
+            let options = GrepOptions { rel_tol, ..GrepOptions::default() };
+            let num_passing = mol2utils::grep(
+                input_files.clone(),
+                &[query_filename],
+                &scratch.path("test_grep_relative_tolerance.mol2.gz"),
+                1e-6,
+                &options
+            ).unwrap();
+            let expected = molecules
+                .iter()
+                .filter(|m| expected_scores
+                    .get(m.get_name())
+                    .is_some_and(|e| m.get_energy() - e <= 1e-6 + rel_tol * e.abs()))
+                .count();
+            assert!(num_passing as usize == expected);
+            if rel_tol == 0.06 {
+                assert!(expected >= expected_scores.len());
+            }
+        }
+    }
+
+    #[test]
+    fn run_grep_tolerance_mode() {
+        /*
+        Tests whether the tolerance mode accepts scores on either side of the
+        expected score, only at or below it, or only at or above it
+        */
+
+        let scratch = Scratch::new("run_grep_tolerance_mode");
+
+        let input_files = vec!["data/test0000.mol2.gz".to_string()];
+        let molecules: Vec<Mol2> = Mol2Reader::new(&input_files[0]).unwrap().collect();
+
+        // the last pose of each name is its expected score, so other poses fall on both sides
+        let mut expected_scores: HashMap<&str, f64> = HashMap::new();
+        for m in molecules.iter().take(60) {
+            expected_scores.insert(m.get_name(), m.get_energy());
+        }
+        let table: String = expected_scores
+            .iter()
+            .map(|(name, score)| format!("{}\t{}\n", name, score))
+            .collect();
+        let query_filename = &scratch.path("test_grep_tolerance_mode.tsv");
+        std::fs::write(query_filename, table).unwrap();
+
+        let tol = 0.5;
+        let mut counts = Vec::new();
+        for mode in [ToleranceMode::Abs, ToleranceMode::Below, ToleranceMode::Above] {
+            let options = GrepOptions { tol_mode: mode, ..GrepOptions::default() };
+            let num_passing = mol2utils::grep(
+                input_files.clone(),
+                &[query_filename],
+                &scratch.path("test_grep_tolerance_mode.mol2.gz"),
+                tol,
+                &options
+            ).unwrap();
+            let expected = molecules
+                .iter()
+                .filter(|m| expected_scores.get(m.get_name()).is_some_and(|e| {
+                    let delta = m.get_energy() - e;
+                    match mode {
+                        ToleranceMode::Abs => delta.abs() <= tol,
+                        ToleranceMode::Below => delta <= tol,
+                        ToleranceMode::Above => -delta <= tol
+                    }
+                }))
+                .count();
+            assert!(num_passing as usize == expected);
+            assert!(expected >= expected_scores.len());
+            counts.push(expected);
+        }
+
+        // poses on either side are split between the one-sided modes
+        assert!(counts[0] < counts[1] || counts[0] < counts[2]);
+    }
+
+    #[test]
+    fn run_grep_invert() {
+        /*
+        Tests whether an inverted grep writes exactly the molecules a regular
+        grep leaves out
+        */
+
+        let scratch = Scratch::new("run_grep_invert");
+
+        let input_files = read_input_list("data/input_list.txt")
+            .expect("Error: Failed Reading Input List");
+        let num_matching = mol2utils::grep(
+            input_files.clone(),
+            &["data/zinc_list.txt"],
+            &scratch.path("test_grep_invert_matching.mol2.gz"),
+            1e-6,
+            &GrepOptions::default()
+        ).unwrap();
+        let options = GrepOptions { invert: true, ..GrepOptions::default() };
+        let num_inverted = mol2utils::grep(
+            input_files.clone(),
+            &["data/zinc_list.txt"],
+            &scratch.path("test_grep_invert.mol2.gz"),
+            1e-6,
+            &options
+        ).unwrap();
+
+        let all: Vec<String> = input_files
+            .iter()
+            .flat_map(|x| Mol2Reader::new(x).unwrap())
+            .map(|m| m.get_lines().to_string())
+            .collect();
+        let mut combined: Vec<String> = [&scratch.path("test_grep_invert_matching.mol2.gz"), &scratch.path("test_grep_invert.mol2.gz")]
+            .iter()
+            .flat_map(|x| Mol2Reader::new(x).unwrap())
+            .map(|m| m.get_lines().to_string())
+            .collect();
+        let mut sorted = all.clone();
+        sorted.sort();
+        combined.sort();
+        assert!(num_matching > 0);
+        assert!((num_matching + num_inverted) as usize == all.len());
+        assert!(combined == sorted);
+
+        let per_query = GrepOptions { invert: true, per_query_output: true, ..GrepOptions::default() };
+        assert!(mol2utils::grep(input_files, &["data/zinc_list.txt"], &scratch.path("test_grep_invert.mol2.gz"), 1e-6, &per_query).is_err());
+    }
+
+    #[test]
+    fn run_grep_max_count() {
+        /*
+        Tests whether grep stops after writing the maximum number of matches,
+        each of which would also be written without a maximum
+        */
+
+        let scratch = Scratch::new("run_grep_max_count");
+
+        let input_files = read_input_list("data/input_list.txt")
+            .expect("Error: Failed Reading Input List");
+        let num_matching = mol2utils::grep(
+            input_files.clone(),
+            &["data/zinc_list.txt"],
+            &scratch.path("test_grep_max_count_all.mol2.gz"),
+            1e-6,
+            &GrepOptions::default()
+        ).unwrap();
+        let all: HashSet<String> = Mol2Reader::new(&scratch.path("test_grep_max_count_all.mol2.gz"))
+            .unwrap()
+            .map(|m| m.get_lines().to_string())
+            .collect();
+        assert!(num_matching > 3);
+
+        for max_count in [0, 3, num_matching as u64 + 10] {
+            let options = GrepOptions { max_count: Some(max_count), ..GrepOptions::default() };
+            let num_passing = mol2utils::grep(
+                input_files.clone(),
+                &["data/zinc_list.txt"],
+                &scratch.path("test_grep_max_count.mol2.gz"),
+                1e-6,
+                &options
+            ).unwrap();
+            let written: Vec<String> = Mol2Reader::new(&scratch.path("test_grep_max_count.mol2.gz"))
+                .unwrap()
+                .map(|m| m.get_lines().to_string())
+                .collect();
+            assert!(num_passing as u64 == max_count.min(num_matching as u64));
+            assert!(written.len() == num_passing as usize);
+            assert!(written.iter().all(|x| all.contains(x)));
+        }
+    }
+
+    #[test]
+    fn run_grep_missing_queries() {
+        /*
+        Tests whether the query names no molecule matched are listed,
+        including misspelled ones
+        */
+
+        let scratch = Scratch::new("run_grep_missing_queries");
+
+        let input_files = read_input_list("data/input_list.txt")
+            .expect("Error: Failed Reading Input List");
+        let mut query = std::fs::read_to_string("data/zinc_list.txt").unwrap();
+        query.push_str("ZINC00000TYPO1\nZINC00000TYPO2\n");
+        let query_filename = &scratch.path("test_grep_missing_queries.txt");
+        std::fs::write(query_filename, &query).unwrap();
+
+        let options = GrepOptions {
+            missing_out: Some(scratch.path("test_grep_missing_queries.missing.txt")),
+            ..GrepOptions::default()
+        };
+        mol2utils::grep(
+            input_files,
+            &[query_filename],
+            &scratch.path("test_grep_missing_queries.mol2.gz"),
+            1e-6,
+            &options
+        ).unwrap();
+
+        let found: HashSet<String> = Mol2Reader::new(&scratch.path("test_grep_missing_queries.mol2.gz"))
+            .unwrap()
+            .map(|m| m.get_name().to_string())
+            .collect();
+        let mut expected: Vec<String> = query
+            .lines()
+            .map(|x| x.trim().to_string())
+            .filter(|x| !x.is_empty() && !found.contains(x))
+            .collect();
+        expected.sort();
+        expected.dedup();
+        let missing: Vec<String> = std::fs::read_to_string(scratch.path("test_grep_missing_queries.missing.txt"))
+            .unwrap()
+            .lines()
+            .map(|x| x.to_string())
+            .collect();
+        assert!(!found.is_empty());
+        assert!(missing.contains(&"ZINC00000TYPO1".to_string()));
+        assert!(missing == expected);
+    }
+
+    #[test]
+    fn run_grep_query_counts() {
+        /*
+        Tests whether the per-query table counts the poses written for every
+        query name, listing names never found with zero poses
+        */
+
+        let scratch = Scratch::new("run_grep_query_counts");
+
+        let input_files = read_input_list("data/input_list.txt")
+            .expect("Error: Failed Reading Input List");
+        let options = GrepOptions {
+            query_counts: Some(scratch.path("test_grep_query_counts.tsv")),
+            ..GrepOptions::default()
+        };
+        let num_passing = mol2utils::grep(
+            input_files,
+            &["data/zinc_list.txt"],
+            &scratch.path("test_grep_query_counts.mol2.gz"),
+            1e-6,
+            &options
+        ).unwrap();
+
+        let mut expected: HashMap<String, u32> = std::fs::read_to_string("data/zinc_list.txt")
+            .unwrap()
+            .lines()
+            .map(|x| x.trim().to_string())
+            .filter(|x| !x.is_empty())
+            .map(|x| (x, 0))
+            .collect();
+        for mol in Mol2Reader::new(&scratch.path("test_grep_query_counts.mol2.gz")).unwrap() {
+            *expected.get_mut(mol.get_name()).unwrap() += 1;
+        }
+        let text = std::fs::read_to_string(scratch.path("test_grep_query_counts.tsv")).unwrap();
+        let mut lines = text.lines();
+        assert!(lines.next() == Some("query\tposes"));
+        let counts: HashMap<String, u32> = lines
+            .map(|x| {
+                let (name, poses) = x.split_once('\t').unwrap();
+                (name.to_string(), poses.parse::<u32>().unwrap())
+            })
+            .collect();
+        assert!(counts == expected);
+        assert!(counts.values().sum::<u32>() == num_passing);
+        assert!(counts.values().any(|x| *x == 0));
+    }
+
+    #[test]
+    fn run_grep_exclude() {
+        /*
+        Tests whether names in the exclusion list are skipped even when they
+        are queried, leaving the other matches untouched
+        */
+
+        let scratch = Scratch::new("run_grep_exclude");
+
+        let input_files = read_input_list("data/input_list.txt")
+            .expect("Error: Failed Reading Input List");
+        mol2utils::grep(
+            input_files.clone(),
+            &["data/zinc_list.txt"],
+            &scratch.path("test_grep_exclude_all.mol2.gz"),
+            1e-6,
+            &GrepOptions::default()
+        ).unwrap();
+        let all: Vec<Mol2> = Mol2Reader::new(&scratch.path("test_grep_exclude_all.mol2.gz")).unwrap().collect();
+        let mut names: Vec<&str> = all.iter().map(|m| m.get_name()).collect();
+        names.sort_unstable();
+        names.dedup();
+        assert!(names.len() > 2);
+
+        // exclude two of the names found
+        let blocked: HashSet<&str> = names.iter().take(2).copied().collect();
+        std::fs::write(scratch.path("test_grep_exclude.txt"), format!("{}\n{}\n", names[0], names[1])).unwrap();
+        let options = GrepOptions { exclude: Some(scratch.path("test_grep_exclude.txt")), ..GrepOptions::default() };
+        let num_passing = mol2utils::grep(
+            input_files,
+            &["data/zinc_list.txt"],
+            &scratch.path("test_grep_exclude.mol2.gz"),
+            1e-6,
+            &options
+        ).unwrap();
+
+        let mut expected: Vec<String> = all
+            .iter()
+            .filter(|m| !blocked.contains(m.get_name()))
+            .map(|m| m.get_lines().to_string())
+            .collect();
+        let mut observed: Vec<String> = Mol2Reader::new(&scratch.path("test_grep_exclude.mol2.gz"))
+            .unwrap()
+            .map(|m| m.get_lines().to_string())
+            .collect();
+        expected.sort();
+        observed.sort();
+        assert!(num_passing as usize == expected.len());
+        assert!(observed == expected);
+    }
+
+    #[test]
+    fn run_grep_repeated_scores() {
+        /*
+        Tests whether a name listed with several expected scores accepts a pose
+        matching any of them
+        */
+
+        let scratch = Scratch::new("run_grep_repeated_scores");
+
+        let input_files = vec!["data/test0000.mol2.gz".to_string()];
+        let molecules: Vec<Mol2> = Mol2Reader::new(&input_files[0]).unwrap().collect();
+
+        // a name with poses of at least two distinct energies
+        let mut energies: HashMap<&str, Vec<f64>> = HashMap::new();
+        for m in molecules.iter() {
+            energies.entry(m.get_name()).or_default().push(m.get_energy());
+        }
+        let (name, scores) = energies
+            .iter()
+            .find(|(_, e)| e.iter().any(|x| (x - e[0]).abs() > 1e-3))
+            .unwrap();
+        let second = *scores.iter().find(|x| (*x - scores[0]).abs() > 1e-3).unwrap();
+        let query_filename = &scratch.path("test_grep_repeated_scores.tsv");
+        std::fs::write(query_filename, format!("{}\t{}\n{}\t{}\n", name, scores[0], name, second)).unwrap();
+
+        let options = GrepOptions { tol_mode: ToleranceMode::Abs, ..GrepOptions::default() };
+        let num_passing = mol2utils::grep(
+            input_files,
+            &[query_filename],
+            &scratch.path("test_grep_repeated_scores.mol2.gz"),
+            1e-6,
+            &options
+        ).unwrap();
+        let expected = scores
+            .iter()
+            .filter(|x| (*x - scores[0]).abs() <= 1e-6 || (*x - second).abs() <= 1e-6)
+            .count();
+        assert!(expected >= 2);
+        assert!(num_passing as usize == expected);
+    }
+
+    #[test]
+    fn run_grep_tolerance_column() {
+        /*
+        Tests whether a tolerance column overrides the global tolerance for
+        its row only
+        */
+
+        let scratch = Scratch::new("run_grep_tolerance_column");
+
+        let input_files = vec!["data/test0000.mol2.gz".to_string()];
+        let molecules: Vec<Mol2> = Mol2Reader::new(&input_files[0]).unwrap().collect();
+
+        // the first pose of names with several poses, with loose and exact tolerances in turn
+        let mut num_poses: HashMap<&str, usize> = HashMap::new();
+        for m in molecules.iter() {
+            *num_poses.entry(m.get_name()).or_insert(0) += 1;
+        }
+        let mut rows: Vec<(&str, f64, f64)> = Vec::new();
+        for m in molecules.iter() {
+            if num_poses[m.get_name()] < 2 {
+                continue;
+            }
+            if rows.len() < 10 && rows.iter().all(|(name, _, _)| *name != m.get_name()) {
+                let tol = if rows.len().is_multiple_of(2) { 5.0 } else { 1e-6 };
+                rows.push((m.get_name(), m.get_energy(), tol));
+            }
+        }
+        let table: String = std::iter::once("name\tscore\ttol\n".to_string())
+            .chain(rows.iter().map(|(name, score, tol)| format!("{}\t{}\t{}\n", name, score, tol)))
+            .collect();
+        let query_filename = &scratch.path("test_grep_tolerance_column.tsv");
+        std::fs::write(query_filename, table).unwrap();
+
+        let options = GrepOptions {
+            tol_mode: ToleranceMode::Abs,
+            query_columns: QueryColumns {
+                id: Some(ColumnSpec::parse("name")),
+                score: Some(ColumnSpec::parse("score")),
+                tol: Some(ColumnSpec::parse("tol")),
+                ..QueryColumns::default()
+            },
+            ..GrepOptions::default()
+        };
+        let num_passing = mol2utils::grep(
+            input_files,
+            &[query_filename],
+            &scratch.path("test_grep_tolerance_column.mol2.gz"),
+            1e-6,
+            &options
+        ).unwrap();
+        let expected = molecules
+            .iter()
+            .filter(|m| rows
+                .iter()
+                .any(|(name, score, tol)| *name == m.get_name() && (m.get_energy() - score).abs() <= *tol))
+            .count();
+        assert!(expected > rows.len());
+        assert!(num_passing as usize == expected);
+    }
+
+    #[test]
+    fn run_grep_query_header() {
+        /*
+        Tests whether a header row is skipped rather than read as a query,
+        both in plain tables and when fields are picked by header name
+        */
+
+        let scratch = Scratch::new("run_grep_query_header");
+
+        let input_files = read_input_list("data/input_list.txt")
+            .expect("Error: Failed Reading Input List");
+        let mut table = "zinc_id\tdock_score\n".to_string();
+        table.push_str(&std::fs::read_to_string("data/zinc_list.tsv").unwrap());
+        let query_filename = &scratch.path("test_grep_query_header.tsv");
+        std::fs::write(query_filename, &table).unwrap();
+
+        let expected = match QueryReader::new("data/zinc_list.tsv").unwrap().load_queries().unwrap() {
+            QueryFormat::WithScore(t) => t.len(),
+            _ => panic!("expected a table with scores")
+        };
+        match QueryReader::new(query_filename).unwrap().load_queries().unwrap() {
+            QueryFormat::WithScore(t) => {
+                assert!(t.len() == expected);
+                assert!(t.keys().all(|x| x.get_name() != "zinc_id"));
+            },
+            _ => panic!("expected a table with scores")
+        }
+
+        let by_field = QueryColumns {
+            id: Some(ColumnSpec::parse("zinc_id")),
+            score: Some(ColumnSpec::parse("dock_score")),
+            ..QueryColumns::default()
+        };
+        for columns in [QueryColumns::default(), by_field] {
+            let options = GrepOptions { query_columns: columns, ..GrepOptions::default() };
+            let num_passing = mol2utils::grep(
+                input_files.clone(),
+                &[query_filename],
+                &scratch.path("test_grep_query_header.mol2.gz"),
+                1e-6,
+                &options
+            ).unwrap();
+            assert!(num_passing == 8);
+        }
+    }
+
+    #[test]
+    fn run_grep_csv_query() {
+        /*
+        Tests whether quoted comma separated hit lists (as exported by pandas or
+        Excel) are parsed, header included, into the same table as the tsv
+        */
+
+        let scratch = Scratch::new("run_grep_csv_query");
+
+        let fields = query::split_csv(r#"ZINC1, "-30.5" ,"a, ""quoted"" note",,last"#);
+        assert!(fields == vec!["ZINC1", "-30.5", "a, \"quoted\" note", "", "last"]);
+
+        let input_files = read_input_list("data/input_list.txt")
+            .expect("Error: Failed Reading Input List");
+        let csv: String = std::iter::once("\"zinc_id\",\"dock score\",\"note\"\n".to_string())
+            .chain(
+                std::fs::read_to_string("data/zinc_list.tsv")
+                    .unwrap()
+                    .lines()
+                    .filter(|x| !x.trim().is_empty())
+                    .map(|x| {
+                        let items: Vec<&str> = x.split_whitespace().collect();
+                        format!("\"{}\",\"{}\",\"hit, round 1\"\n", items[0], items[1])
+                    })
+            )
+            .collect();
+        let query_filename = &scratch.path("test_grep_csv_query.csv");
+        std::fs::write(query_filename, csv).unwrap();
+
+        let options = GrepOptions {
+            query_columns: QueryColumns { csv: true, ..QueryColumns::default() },
+            ..GrepOptions::default()
+        };
+        let num_passing = mol2utils::grep(
+            input_files,
+            &[query_filename],
+            &scratch.path("test_grep_csv_query.mol2.gz"),
+            1e-6,
+            &options
+        ).unwrap();
+        assert!(num_passing == 8);
+    }
+
+    #[test]
+    fn run_grep_smiles_query() {
+        /*
+        Tests whether SMILES files give their second column as names, with or
+        without a header, and match the same molecules as a list of names
+        */
+
+        let scratch = Scratch::new("run_grep_smiles_query");
+
+        let input_files = read_input_list("data/input_list.txt")
+            .expect("Error: Failed Reading Input List");
+        let names: Vec<String> = std::fs::read_to_string("data/zinc_list.txt")
+            .unwrap()
+            .split_whitespace()
+            .map(|x| x.to_string())
+            .collect();
+        let rows: String = names
+            .iter()
+            .map(|x| format!("C1=CC=CC=C1\t{}\n", x))
+            .collect();
+        let query_filename = &scratch.path("test_grep_smiles_query.smi");
+        std::fs::write(query_filename, format!("smiles\tzinc_id\n{}", rows)).unwrap();
+
+        match QueryReader::new(query_filename).unwrap().load_queries().unwrap() {
+            QueryFormat::WithoutScore(table) => assert!(table.len() == names.len()),
+            _ => panic!("SMILES file was not loaded as names")
+        }
+        let smiles = QueryColumns { smiles: true, ..QueryColumns::default() };
+        match QueryReader::from_reader("stdin", rows.as_bytes()).unwrap().load_queries_with(&smiles).unwrap() {
+            QueryFormat::WithoutScore(table) => assert!(table.len() == names.len()),
+            _ => panic!("SMILES rows were not loaded as names")
+        }
+
+        let num_smiles = mol2utils::grep(
+            input_files.clone(),
+            &[query_filename],
+            &scratch.path("test_grep_smiles_query.mol2.gz"),
+            1e-6,
+            &GrepOptions::default()
+        ).unwrap();
+        let num_names = mol2utils::grep(
+            input_files,
+            &["data/zinc_list.txt"],
+            &scratch.path("test_grep_smiles_query.names.mol2.gz"),
+            1e-6,
+            &GrepOptions::default()
+        ).unwrap();
+        assert!(num_smiles == num_names);
+    }
+
+    #[test]
+    fn run_grep_annotate_source() {
+        /*
+        Tests whether every molecule written carries a Source header comment
+        naming the input file it was read from
+        */
+
+        let scratch = Scratch::new("run_grep_annotate_source");
+
+        let input_files = read_input_list("data/input_list.txt")
+            .expect("Error: Failed Reading Input List");
+        let options = GrepOptions { annotate_source: true, ..GrepOptions::default() };
+        let num_passing = mol2utils::grep(
+            input_files.clone(),
+            &["data/zinc_list.tsv"],
+            &scratch.path("test_grep_annotate_source.mol2.gz"),
+            1e-6,
+            &options
+        ).unwrap();
+        assert!(num_passing == 8);
+
+        let mut num_written = 0;
+        for mol in Mol2Reader::new(&scratch.path("test_grep_annotate_source.mol2.gz")).unwrap() {
+            let source = mol
+                .get_properties()
+                .iter()
+                .find(|(key, _)| key == "Source")
+                .map(|(_, value)| value.clone())
+                .unwrap();
+            assert!(input_files.contains(&source));
+            assert!(Mol2Reader::new(&source).unwrap().any(|x| x.get_lines() == mol.get_lines().replace(&format!("##########               Source:   {}\n", source), "")));
+            num_written += 1;
+        }
+        assert!(num_written == num_passing);
+    }
+
+    #[test]
+    fn run_grep_names_only() {
+        /*
+        Tests whether names-only output lists the name (and found energy) of
+        every pose a regular grep writes
+        */
+
+        let scratch = Scratch::new("run_grep_names_only");
+
+        let input_files = read_input_list("data/input_list.txt")
+            .expect("Error: Failed Reading Input List");
+        mol2utils::grep(
+            input_files.clone(),
+            &["data/zinc_list.tsv"],
+            &scratch.path("test_grep_names_only.mol2.gz"),
+            1e-6,
+            &GrepOptions::default()
+        ).unwrap();
+        let mut expected: Vec<(String, f64)> = Mol2Reader::new(&scratch.path("test_grep_names_only.mol2.gz"))
+            .unwrap()
+            .map(|x| (x.get_name().to_string(), x.get_energy()))
+            .collect();
+
+        let options = GrepOptions { names_only: true, with_energy: true, ..GrepOptions::default() };
+        let num_passing = mol2utils::grep(
+            input_files,
+            &["data/zinc_list.tsv"],
+            &scratch.path("test_grep_names_only.tsv"),
+            1e-6,
+            &options
+        ).unwrap();
+        let mut found: Vec<(String, f64)> = std::fs::read_to_string(scratch.path("test_grep_names_only.tsv"))
+            .unwrap()
+            .lines()
+            .map(|x| {
+                let (name, energy) = x.split_once('\t').unwrap();
+                (name.to_string(), energy.parse::<f64>().unwrap())
+            })
+            .collect();
+        assert!(num_passing as usize == found.len());
+        expected.sort_by(|a, b| a.0.cmp(&b.0).then(a.1.total_cmp(&b.1)));
+        found.sort_by(|a, b| a.0.cmp(&b.0).then(a.1.total_cmp(&b.1)));
+        assert!(found == expected);
+    }
+
+    #[test]
+    fn run_grep_count_only() {
+        /*
+        Tests whether count-only mode finds as many matches as a regular grep,
+        reports them per input file, and writes no output
+        */
+
+        let scratch = Scratch::new("run_grep_count_only");
+
+        let input_files = read_input_list("data/input_list.txt")
+            .expect("Error: Failed Reading Input List");
+        let options = GrepOptions {
+            count_only: true,
+            file_report: Some(scratch.path("test_grep_count_only.tsv")),
+            ..GrepOptions::default()
+        };
+        let num_passing = mol2utils::grep(
+            input_files,
+            &["data/zinc_list.tsv"],
+            &scratch.path("test_grep_count_only.mol2.gz"),
+            1e-6,
+            &options
+        ).unwrap();
+        assert!(num_passing == 8);
+        assert!(!std::path::Path::new(&scratch.path("test_grep_count_only.mol2.gz")).exists());
+
+        let per_file: u32 = std::fs::read_to_string(scratch.path("test_grep_count_only.tsv"))
+            .unwrap()
+            .lines()
+            .skip(1)
+            .map(|x| x.split('\t').nth(3).unwrap().parse::<u32>().unwrap())
+            .sum();
+        assert!(per_file == num_passing);
+    }
+
+    #[test]
+    fn run_grep_files_with_matches() {
+        /*
+        Tests whether listing files with matches accepts a single match per
+        input file and writes no output
+        */
+
+        let scratch = Scratch::new("run_grep_files_with_matches");
+
+        let input_files = read_input_list("data/input_list.txt")
+            .expect("Error: Failed Reading Input List");
+        let options = GrepOptions {
+            files_with_matches: true,
+            file_report: Some(scratch.path("test_grep_files_with_matches.tsv")),
+            ..GrepOptions::default()
+        };
+        let num_passing = mol2utils::grep(
+            input_files,
+            &["data/zinc_list.tsv"],
+            &scratch.path("test_grep_files_with_matches.mol2.gz"),
+            1e-6,
+            &options
+        ).unwrap();
+        assert!(!std::path::Path::new(&scratch.path("test_grep_files_with_matches.mol2.gz")).exists());
+
+        let per_file: Vec<u32> = std::fs::read_to_string(scratch.path("test_grep_files_with_matches.tsv"))
+            .unwrap()
+            .lines()
+            .skip(1)
+            .map(|x| x.split('\t').nth(3).unwrap().parse::<u32>().unwrap())
+            .collect();
+        assert!(per_file.iter().all(|x| *x <= 1));
+        assert!(per_file.iter().sum::<u32>() == num_passing);
+        assert!(num_passing > 0);
+    }
+
+    #[test]
+    fn run_grep_rest_out() {
+        /*
+        Tests whether the rest output holds every molecule not accepted,
+        so that it adds up with the matches to the molecules read
+        */
+
+        let scratch = Scratch::new("run_grep_rest_out");
+
+        let input_files = read_input_list("data/input_list.txt")
+            .expect("Error: Failed Reading Input List");
+        let options = GrepOptions {
+            rest_out: Some(scratch.path("test_grep_rest_out.rest.mol2.gz")),
+            ..GrepOptions::default()
+        };
+        let num_passing = mol2utils::grep(
+            input_files.clone(),
+            &["data/zinc_list.tsv"],
+            &scratch.path("test_grep_rest_out.mol2.gz"),
+            1e-6,
+            &options
+        ).unwrap();
+        assert!(num_passing == 8);
+
+        let num_input: usize = input_files
+            .iter()
+            .map(|x| Mol2Reader::new(x).unwrap().count())
+            .sum();
+        let num_hits = Mol2Reader::new(&scratch.path("test_grep_rest_out.mol2.gz")).unwrap().count();
+        let num_rest = Mol2Reader::new(&scratch.path("test_grep_rest_out.rest.mol2.gz")).unwrap().count();
+        assert!(num_hits == 8);
+        assert!(num_hits + num_rest == num_input);
+    }
+
+    #[test]
+    fn run_grep_per_input_output() {
+        /*
+        Tests whether the matches of every input file are written to the
+        output of that file, leaving no output for files without matches
+        */
+
+        let scratch = Scratch::new("run_grep_per_input_output");
+
+        let input_files = read_input_list("data/input_list.txt")
+            .expect("Error: Failed Reading Input List");
+        let options = GrepOptions {
+            per_input_output: true,
+            file_report: Some(scratch.path("test_grep_per_input.tsv")),
+            ..GrepOptions::default()
+        };
+        let num_passing = mol2utils::grep(
+            input_files.clone(),
+            &["data/zinc_list.tsv"],
+            &scratch.path("test_grep_per_input.mol2.gz"),
+            1e-6,
+            &options
+        ).unwrap();
+        assert!(num_passing == 8);
+        assert!(!std::path::Path::new(&scratch.path("test_grep_per_input.mol2.gz")).exists());
+
+        let per_file: HashMap<String, usize> = std::fs::read_to_string(scratch.path("test_grep_per_input.tsv"))
+            .unwrap()
+            .lines()
+            .skip(1)
+            .map(|x| {
+                let fields: Vec<&str> = x.split('\t').collect();
+                (fields[0].to_string(), fields[3].parse::<usize>().unwrap())
+            })
+            .collect();
+        for x in input_files.iter() {
+            let filename = mol2utils::per_input_filename(&scratch.path("test_grep_per_input.mol2.gz"), x);
+            match per_file[x] {
+                0 => assert!(!std::path::Path::new(&filename).exists()),
+                n => {
+                    assert!(Mol2Reader::new(&filename).unwrap().count() == n);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn run_grep_split_by_query() {
+        /*
+        Tests whether every matched name is written to its own output
+        holding all of its poses
+        */
+
+        let scratch = Scratch::new("run_grep_split_by_query");
+
+        let input_files = read_input_list("data/input_list.txt")
+            .expect("Error: Failed Reading Input List");
+        let options = GrepOptions {
+            split_by_query: true,
+            ..GrepOptions::default()
+        };
+        let num_passing = mol2utils::grep(
+            input_files,
+            &["data/zinc_list.tsv"],
+            &scratch.path("test_grep_split_by_query.mol2.gz"),
+            1e-6,
+            &options
+        ).unwrap();
+        assert!(num_passing == 8);
+        assert!(!std::path::Path::new(&scratch.path("test_grep_split_by_query.mol2.gz")).exists());
+
+        let table = QueryReader::new("data/zinc_list.tsv").unwrap().load_queries().unwrap();
+        let mut num_written = 0;
+        for name in table.names() {
+            let filename = mol2utils::per_group_filename(&scratch.path("test_grep_split_by_query.mol2.gz"), name);
+            if !std::path::Path::new(&filename).exists() {
+                continue;
+            }
+            for mol in Mol2Reader::new(&filename).unwrap() {
+                assert!(mol.get_name() == name);
+                num_written += 1;
+            }
+        }
+        assert!(num_written == num_passing);
+    }
+
+    #[test]
+    fn run_grep_ordered() {
+        /*
+        Tests whether ordered matches are written in input order, as a
+        sequential scan of the inputs finds them
+        */
+
+        let scratch = Scratch::new("run_grep_ordered");
+
+        let input_files = read_input_list("data/input_list.txt")
+            .expect("Error: Failed Reading Input List");
+        let options = GrepOptions {
+            ordered: true,
+            ..GrepOptions::default()
+        };
+        mol2utils::grep(
+            input_files.clone(),
+            &["data/zinc_list.tsv"],
+            &scratch.path("test_grep_ordered.mol2.gz"),
+            1e-6,
+            &options
+        ).unwrap();
+
+        let written: Vec<String> = Mol2Reader::new(&scratch.path("test_grep_ordered.mol2.gz"))
+            .unwrap()
+            .map(|x| x.get_lines().to_string())
+            .collect();
+        let accepted: HashSet<&String> = written.iter().collect();
+        let expected: Vec<String> = input_files
+            .iter()
+            .flat_map(|x| Mol2Reader::new(x).unwrap())
+            .map(|x| x.get_lines().to_string())
+            .filter(|x| accepted.contains(x))
+            .collect();
+        assert!(written.len() == 8);
+        assert!(written == expected);
+    }
+
+    #[test]
+    fn run_grep_max_per_file() {
+        /*
+        Tests whether the output rolls over to numbered shards holding at
+        most the requested number of molecules
+        */
+
+        let scratch = Scratch::new("run_grep_max_per_file");
+
+        let input_files = read_input_list("data/input_list.txt")
+            .expect("Error: Failed Reading Input List");
+        let options = GrepOptions {
+            max_per_file: Some(3),
+            ..GrepOptions::default()
+        };
+        let num_passing = mol2utils::grep(
+            input_files,
+            &["data/zinc_list.tsv"],
+            &scratch.path("test_grep_max_per_file.mol2.gz"),
+            1e-6,
+            &options
+        ).unwrap();
+        assert!(num_passing == 8);
+
+        let sizes: Vec<usize> = (1..=3)
+            .map(|x| mol2utils::shard_filename(&scratch.path("test_grep_max_per_file.mol2.gz"), x))
+            .map(|x| Mol2Reader::new(&x).unwrap().count())
+            .collect();
+        assert!(sizes == vec![3, 3, 2]);
+        assert!(!std::path::Path::new(&mol2utils::shard_filename(&scratch.path("test_grep_max_per_file.mol2.gz"), 4)).exists());
+    }
+
+    #[test]
+    fn run_grep_best_per_id() {
+        /*
+        Tests whether a single pose, of the lowest energy, is written for
+        every name matching poses of different energies
+        */
+
+        let scratch = Scratch::new("run_grep_best_per_id");
+
+        let input_files = read_input_list("data/input_list.txt")
+            .expect("Error: Failed Reading Input List");
+        let names: HashSet<String> = read_name_list("data/zinc_list.tsv")
+            .unwrap()
+            .into_iter()
+            .collect();
+        let mut best: HashMap<String, f64> = HashMap::new();
+        for mol in input_files.iter().flat_map(|x| Mol2Reader::new(x).unwrap()) {
+            if names.contains(mol.get_name()) {
+                let e = best.entry(mol.get_name().to_string()).or_insert(f64::INFINITY);
+                *e = e.min(mol.get_energy());
+            }
+        }
+
+        // names without scores match every pose
+        let query_file = &scratch.path("test_grep_best_per_id.txt");
+        std::fs::write(query_file, names.iter().map(|x| format!("{}\n", x)).collect::<String>()).unwrap();
+        let options = GrepOptions {
+            best_per_id: true,
+            ..GrepOptions::default()
+        };
+        mol2utils::grep(
+            input_files,
+            &[query_file],
+            &scratch.path("test_grep_best_per_id.mol2.gz"),
+            1e-6,
+            &options
+        ).unwrap();
+
+        let written: Vec<Mol2> = Mol2Reader::new(&scratch.path("test_grep_best_per_id.mol2.gz")).unwrap().collect();
+        assert!(written.len() == best.len());
+        for mol in written.iter() {
+            assert!(mol.get_energy() == best[mol.get_name()]);
+        }
+    }
+
+    #[test]
+    fn run_grep_query_groups() {
+        /*
+        Tests whether a group column routes each match to the output of its
+        group, together covering the matches of an ungrouped grep
+        */
+
+        let scratch = Scratch::new("run_grep_query_groups");
+
+        let input_files = read_input_list("data/input_list.txt")
+            .expect("Error: Failed Reading Input List");
+        let names: Vec<String> = std::fs::read_to_string("data/zinc_list.tsv")
+            .unwrap()
+            .lines()
+            .filter_map(|x| x.split_whitespace().next())
+            .map(|x| x.to_string())
+            .collect();
+        let table: String = names
+            .iter()
+            .enumerate()
+            .map(|(idx, x)| format!("{}\tcluster {}\n", x, idx % 2))
+            .collect();
+        let query_filename = &scratch.path("test_grep_query_groups.tsv");
+        std::fs::write(query_filename, table).unwrap();
+
+        let options = GrepOptions {
+            query_columns: QueryColumns { group: Some(ColumnSpec::Index(1)), ..QueryColumns::default() },
+            ..GrepOptions::default()
+        };
+        let num_grouped = mol2utils::grep(
+            input_files.clone(),
+            &[query_filename],
+            &scratch.path("test_grep_query_groups.mol2.gz"),
+            1e-6,
+            &options
+        ).unwrap();
+        let num_passing = mol2utils::grep(
+            input_files,
+            &[query_filename],
+            &scratch.path("test_grep_query_groups.all.mol2.gz"),
+            1e-6,
+            &GrepOptions {
+                query_columns: QueryColumns { id: Some(ColumnSpec::Index(0)), ..QueryColumns::default() },
+                ..GrepOptions::default()
+            }
+        ).unwrap();
+        assert!(num_grouped == num_passing);
+
+        let mut num_written = 0;
+        for group in 0..2 {
+            let filename = mol2utils::per_group_filename(&scratch.path("test_grep_query_groups.mol2.gz"), &format!("cluster {}", group));
+            assert!(filename == scratch.path(&format!("test_grep_query_groups.cluster_{}.mol2.gz", group)));
+            for mol in Mol2Reader::new(&filename).unwrap() {
+                let idx = names.iter().position(|x| x == mol.get_name()).unwrap();
+                assert!(idx % 2 == group);
+                num_written += 1;
+            }
+        }
+        assert!(num_written == num_passing);
+    }
+
+    #[test]
+    fn run_grep_plain_input() {
+        /*
+        Tests whether uncompressed mol2 inputs are detected and give the same
+        results as their gzip compressed originals
+        */
+
+        let scratch = Scratch::new("run_grep_plain_input");
+
+        let input_files = read_input_list("data/input_list.txt")
+            .expect("Error: Failed Reading Input List");
+
+        // uncompressed copies of every input
+        let plain_files: Vec<String> = input_files
+            .iter()
+            .enumerate()
+            .map(|(idx, x)| {
+                let mut text = Vec::new();
+                flate2::read::MultiGzDecoder::new(std::fs::File::open(x).unwrap())
+                    .read_to_end(&mut text)
+                    .unwrap();
+                let plain = scratch.path(&format!("test_plain_input.{}.mol2", idx));
+                std::fs::write(&plain, text).unwrap();
+                plain
+            })
+            .collect();
+
+        let num_passing = mol2utils::grep(
+            plain_files.clone(),
+            &["data/zinc_list.tsv"],
+            &scratch.path("test_grep_plain_input.mol2.gz"),
+            1e-6,
+            &GrepOptions::default()
+        ).unwrap();
+        assert!(num_passing == 8);
+
+        let options = TableOptions::default();
+        let num_plain = mol2utils::table(plain_files, &scratch.path("test_table_plain_input.tab.gz"), &options).unwrap();
+        let num_compressed = mol2utils::table(input_files, &scratch.path("test_table_plain_input.tab.gz"), &options).unwrap();
+        assert!(num_plain == num_compressed);
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn run_grep_zstd() {
+        /*
+        Tests whether zstd compressed inputs are read and a .zst output is written as zstd
+        */
+
+        let scratch = Scratch::new("run_grep_zstd");
+
+        let input_files = read_input_list("data/input_list.txt")
+            .expect("Error: Failed Reading Input List");
+
+        // zstd recompressed copies of every input
+        let zstd_files: Vec<String> = input_files
+            .iter()
+            .enumerate()
+            .map(|(idx, x)| {
+                let filename = scratch.path(&format!("test_grep_zstd.{}.mol2.zst", idx));
+                let mut writer = crate::file_io::writer(&filename).unwrap();
+                for mol in Mol2Reader::new(x).unwrap() {
+                    writer.write_all(mol.get_lines().as_bytes()).unwrap();
+                }
+                filename
+            })
+            .collect();
+
+        let output_filename = &scratch.path("test_grep_zstd.mol2.zst");
+        let num_passing = mol2utils::grep(
+            zstd_files,
+            &["data/zinc_list.tsv"],
+            output_filename,
+            1e-6,
+            &GrepOptions::default()
+        ).unwrap();
+        assert!(num_passing == 8);
+        assert!(std::fs::read(output_filename).unwrap().starts_with(b"\x28\xb5\x2f\xfd"));
+        assert!(Mol2Reader::new(output_filename).unwrap().count() == 8);
+    }
+
+    #[test]
+    fn run_filter_stdin_two_pass() {
+        /*
+        Tests whether criteria reading the inputs twice refuse the standard input
+        */
+
+        let scratch = Scratch::new("run_filter_stdin_two_pass");
+
+        assert!(crate::file_io::is_stdin("-"));
+        assert!(!crate::file_io::is_stdin("data/test0000.mol2.gz"));
+
+        let options = FilterOptions {
+            top_percent: Some(10.0),
+            ..Default::default()
+        };
+        let result = filter::filter(vec!["-".to_string()], &scratch.path("test_filter_stdin.mol2.gz"), &options);
+        assert!(result.unwrap_err().kind() == std::io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn run_two_pass_stdin() {
+        /*
+        Tests whether z-scored transforms and decoy selection refuse the standard
+        input, which they would otherwise read twice
+        */
+
+        let scratch = Scratch::new("run_two_pass_stdin");
+
+        let options = TransformOptions { zscore: true, ..Default::default() };
+        let result = transform::transform(vec!["-".to_string()], &scratch.path("test_transform_stdin.mol2.gz"), &options);
+        assert!(result.unwrap_err().kind() == std::io::ErrorKind::InvalidInput);
+        assert!(!std::path::Path::new(&scratch.path("test_transform_stdin.mol2.gz")).exists());
+
+        let actives = decoys::Actives::Names(vec!["ZINC000000032944".to_string()]);
+        let result = decoys::decoys(
+            vec!["-".to_string()],
+            &actives,
+            5,
+            &scratch.path("test_decoys_stdin.mol2.gz"),
+            &scratch.path("test_decoys_stdin.tsv")
+        );
+        assert!(result.unwrap_err().kind() == std::io::ErrorKind::InvalidInput);
+        assert!(!std::path::Path::new(&scratch.path("test_decoys_stdin.tsv")).exists());
+    }
+
+    #[test]
+    fn read_input_patterns() {
+        /*
+        Tests whether glob patterns among the inputs are expanded in sorted order
+        */
+
+        let inputs = vec![
+            "data/test000[0-2].mol2.gz".to_string(),
+            "data/zinc_list.txt".to_string(),
+            "-".to_string()
+        ];
+        let expanded = crate::file_io::expand_input_patterns(inputs).unwrap();
+        assert!(expanded == vec![
+            "data/test0000.mol2.gz",
+            "data/test0001.mol2.gz",
+            "data/test0002.mol2.gz",
+            "data/zinc_list.txt",
+            "-"
+        ]);
+
+        let missing = crate::file_io::expand_input_patterns(vec!["data/*.nothing".to_string()]);
+        assert!(missing.unwrap_err().kind() == std::io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn run_grep_tar_members() {
+        /*
+        Tests whether the mol2 members of a tar archive are read in place as inputs
+        (compressed and uncompressed members alike, other members ignored)
+        */
+
+        let scratch = Scratch::new("run_grep_tar_members");
+
+        let archive_filename = &scratch.path("test_grep_tar_members.tar");
+        let mut builder = tar::Builder::new(std::fs::File::create(archive_filename).unwrap());
+        builder.append_path_with_name("data/test0000.mol2.gz", "run/a.mol2.gz").unwrap();
+        builder.append_path_with_name("data/zinc_list.txt", "run/README").unwrap();
+        let mut text = Vec::new();
+        flate2::read::MultiGzDecoder::new(std::fs::File::open("data/test0001.mol2.gz").unwrap())
+            .read_to_end(&mut text)
+            .unwrap();
+        let mut header = tar::Header::new_gnu();
+        header.set_size(text.len() as u64);
+        header.set_mode(0o644);
+        builder.append_data(&mut header, "run/b.mol2", text.as_slice()).unwrap();
+        builder.into_inner().unwrap();
+
+        let input_files = crate::archive::expand_archives(vec![archive_filename.to_string()]).unwrap();
+        assert!(input_files == vec![
+            scratch.path("test_grep_tar_members.tar::run/a.mol2.gz"),
+            scratch.path("test_grep_tar_members.tar::run/b.mol2")
+        ]);
+        assert!(Mol2Reader::new(&input_files[0]).unwrap().count() == 451);
+        assert!(Mol2Reader::new(&input_files[1]).unwrap().count() == 2797);
+
+        let num_passing = mol2utils::grep(
+            input_files,
+            &["data/zinc_list.txt"],
+            &scratch.path("test_grep_tar_members.mol2.gz"),
+            1e-6,
+            &GrepOptions::default()
+        ).unwrap();
+        let expected = mol2utils::grep(
+            vec!["data/test0000.mol2.gz".to_string(), "data/test0001.mol2.gz".to_string()],
+            &["data/zinc_list.txt"],
+            &scratch.path("test_grep_tar_members.mol2.gz"),
+            1e-6,
+            &GrepOptions::default()
+        ).unwrap();
+        assert!(num_passing == expected);
+        assert!(Mol2Reader::new(&scratch.path("test_grep_tar_members.tar::run/c.mol2")).is_err());
+    }
+
+    #[test]
+    fn run_grep_zip_members() {
+        /*
+        Tests whether the mol2 members of a zip archive are read in place as inputs
+        (a stored gzip member and a deflated plain member)
+        */
+
+        let scratch = Scratch::new("run_grep_zip_members");
+
+        let archive_filename = &scratch.path("test_grep_zip_members.zip");
+        let mut writer = zip::ZipWriter::new(std::fs::File::create(archive_filename).unwrap());
+        let stored = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Stored);
+        writer.start_file("a.mol2.gz", stored).unwrap();
+        writer.write_all(&std::fs::read("data/test0000.mol2.gz").unwrap()).unwrap();
+
+        let deflated = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Deflated);
+        writer.start_file("b.mol2", deflated).unwrap();
+        for mol in Mol2Reader::new("data/test0002.mol2.gz").unwrap() {
+            writer.write_all(mol.get_lines().as_bytes()).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let input_files = crate::archive::expand_archives(vec![archive_filename.to_string()]).unwrap();
+        assert!(input_files == vec![
+            scratch.path("test_grep_zip_members.zip::a.mol2.gz"),
+            scratch.path("test_grep_zip_members.zip::b.mol2")
+        ]);
+        assert!(Mol2Reader::new(&input_files[0]).unwrap().count() == 451);
+        assert!(Mol2Reader::new(&input_files[1]).unwrap().count() == 238);
+
+        let options = TableOptions::default();
+        let num_written = mol2utils::table(input_files, &scratch.path("test_grep_zip_members.tab.gz"), &options).unwrap();
+        assert!(num_written == 451 + 238);
+    }
+
+    #[test]
+    fn read_remote_urls() {
+        /*
+        Tests whether URLs are recognized as remote inputs and s3 URLs are mapped to https
+        */
+
+        use crate::remote::{is_remote, s3_to_https};
+        assert!(is_remote("https://example.org/poses.mol2.gz"));
+        assert!(is_remote("s3://bucket/run/poses.mol2.gz"));
+        assert!(!is_remote("data/test0000.mol2.gz"));
+
+        assert!(s3_to_https("s3://bucket/run/poses.mol2.gz", None).unwrap() == "https://bucket.s3.amazonaws.com/run/poses.mol2.gz");
+        assert!(s3_to_https("s3://bucket/run/poses.mol2.gz", Some("http://localhost:9000/")).unwrap() == "http://localhost:9000/bucket/run/poses.mol2.gz");
+        assert!(s3_to_https("s3://bucket", None).is_none());
+
+        // presigned URLs carry a query string that is not a glob pattern
+        let url = "https://example.org/poses.mol2.gz?X-Amz-Signature=abc".to_string();
+        assert!(crate::file_io::expand_input_patterns(vec![url.clone()]).unwrap() == vec![url]);
+    }
+
+    #[test]
+    fn run_grep_unique() {
+        /*
+        Tests whether each query is emitted at most once across all inputs
+        */
+
+        let scratch = Scratch::new("run_grep_unique");
+
+        let input_list = "data/input_list.txt";
+        let input_files = read_input_list(input_list)
+            .expect("Error: Failed Reading Input List");
+        let output_filename = &scratch.path("test_grep_unique.mol2.gz");
+        let query_filename = "data/zinc_list.txt";
+        let tol = 1e-6;
+
+        let options = GrepOptions {
+            unique: true,
+            ..Default::default()
+        };
+
+        let num_passing = mol2utils::grep(
+            input_files,
+            &[query_filename],
+            output_filename,
+            tol,
+            &options
+        ).unwrap();
+
+        assert!(num_passing == 4);
+    }
+
+    #[test]
+    fn run_grep_group_output() {
+        /*
+        Tests whether all poses of the same ligand are written adjacently
+        */
+
+        let scratch = Scratch::new("run_grep_group_output");
+
+        let input_list = "data/input_list.txt";
+        let input_files = read_input_list(input_list)
+            .expect("Error: Failed Reading Input List");
+        let output_filename = &scratch.path("test_grep_group_output.mol2.gz");
+        let query_filename = "data/zinc_list.txt";
+        let tol = 1e-6;
+
+        let options = GrepOptions {
+            group_output: true,
+            ..Default::default()
+        };
+
+        let num_passing = mol2utils::grep(
+            input_files,
+            &[query_filename],
+            output_filename,
+            tol,
+            &options
+        ).unwrap();
+
+        assert!(num_passing == 10);
+
+        let mut names: Vec<String> = Mol2Reader::new(output_filename)
+            .unwrap()
+            .map(|x| x.get_name().to_string())
+            .collect();
+        assert!(names.len() == 10);
+
+        // once consecutive duplicates are removed each name appears exactly once
+        names.dedup();
+        assert!(names.len() == 4);
+    }
+
+    #[test]
+    fn run_grep_per_query_output() {
+        /*
+        Tests whether matches of each query file are written to their own output
+        (every name of zinc_list_subset.txt is also in zinc_list.txt)
+        */
+
+        let scratch = Scratch::new("run_grep_per_query_output");
+
+        let input_list = "data/input_list.txt";
+        let input_files = read_input_list(input_list)
+            .expect("Error: Failed Reading Input List");
+        let output_filename = &scratch.path("test_grep_per_query.mol2.gz");
+        let query_filenames = ["data/zinc_list.txt", "data/zinc_list_subset.txt"];
+        let tol = 1e-6;
+
+        let options = GrepOptions {
+            per_query_output: true,
+            ..Default::default()
+        };
+
+        let num_passing = mol2utils::grep(
+            input_files,
+            &query_filenames,
+            output_filename,
+            tol,
+            &options
+        ).unwrap();
+        assert!(num_passing == 10);
+
+        let count = |x: &str| Mol2Reader::new(x).unwrap().count();
+        assert!(count(&scratch.path("test_grep_per_query.zinc_list.mol2.gz")) == 10);
+        assert!(count(&scratch.path("test_grep_per_query.zinc_list_subset.mol2.gz")) == 4);
+    }
+
+    #[test]
+    fn run_grep_alias() {
+        /*
+        Tests whether aliased query codes are translated before matching and
+        reported under their original code
+        */
+
+        let scratch = Scratch::new("run_grep_alias");
+
+        let input_list = "data/input_list.txt";
+        let input_files = read_input_list(input_list)
+            .expect("Error: Failed Reading Input List");
+        let output_filename = &scratch.path("test_grep_alias.mol2.gz");
+        let tol = 1e-6;
+
+        let options = GrepOptions {
+            alias: Some("data/aliases.tsv".to_string()),
+            ..Default::default()
+        };
+
+        let num_passing = mol2utils::grep(
+            input_files,
+            &["data/supplier_codes.txt"],
+            output_filename,
+            tol,
+            &options
+        ).unwrap();
+        assert!(num_passing == 8);
+
+        let report = std::fs::read_to_string(scratch.path("test_grep_alias.aliases.tsv")).unwrap();
+        let mut rows: Vec<&str> = report.lines().skip(1).collect();
+        rows.sort();
+        assert!(rows == vec![
+            "SUP-001\tZINC000012505565\t2",
+            "SUP-002\tZINC000002366042\t4",
+            "SUP-003\tZINC000000000001\t0"
+        ]);
+
+        // a malformed alias row is reported with its line number
+        let alias_filename = &scratch.path("test_grep_alias_malformed.tsv");
+        std::fs::write(alias_filename, "SUP-001\tZINC000012505565\n\nSUP-002\n").unwrap();
+        let error = query::Aliases::from_file(alias_filename).err().unwrap();
+        assert!(error.kind() == std::io::ErrorKind::InvalidData);
+        assert!(error.to_string().contains("line 3"));
+    }
+
+    #[test]
+    fn run_grep_provenance() {
+        /*
+        Tests whether outputs begin with a provenance block that readers skip
+        */
+
+        let scratch = Scratch::new("run_grep_provenance");
+
+        let input_files = vec!["data/test0000.mol2.gz".to_string()];
+        let output_filename = &scratch.path("test_grep_provenance.mol2.gz");
+
+        let num_passing = mol2utils::grep(
+            input_files,
+            &["data/zinc_list.txt"],
+            output_filename,
+            1e-6,
+            &GrepOptions::default()
+        ).unwrap();
+
+        let mut content = String::new();
+        flate2::read::MultiGzDecoder::new(std::fs::File::open(output_filename).unwrap())
+            .read_to_string(&mut content)
+            .unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert!(lines[0].starts_with("#@ mol2grep-version: "));
+        assert!(lines[3].starts_with("#@ input: data/test0000.mol2.gz\tsize:"));
+        assert!(lines[3].contains("\tmtime:"));
+
+        let mols: Vec<Mol2> = Mol2Reader::new(output_filename).unwrap().collect();
+        assert!(mols.len() as u32 == num_passing);
+        assert!(!mols[0].get_lines().contains("#@"));
+
+        // content hashes are recorded only on request
+        let mut block = Vec::new();
+        provenance::Provenance::with_hashes(&["data/test0000.mol2.gz".to_string()], true)
+            .unwrap()
+            .write(&mut block)
+            .unwrap();
+        let block = String::from_utf8(block).unwrap();
+        assert!(block.contains("#@ input: data/test0000.mol2.gz\tfnv1a64:"));
+
+        assert!(provenance::format_utc(0) == "1970-01-01T00:00:00Z");
+        assert!(provenance::format_utc(951825600) == "2000-02-29T12:00:00Z");
+    }
+
+    #[test]
+    fn run_grep_file_report() {
+        /*
+        Tests whether a per-file report is written with one row per input
+        */
+
+        let scratch = Scratch::new("run_grep_file_report");
+
+        let input_list = "data/input_list.txt";
+        let input_files = read_input_list(input_list)
+            .expect("Error: Failed Reading Input List");
+        let output_filename = &scratch.path("test_grep_file_report.mol2.gz");
+        let report_filename = &scratch.path("test_grep_file_report.tsv");
+        let query_filename = "data/zinc_list.txt";
+        let tol = 1e-6;
+
+        let options = GrepOptions {
+            file_report: Some(report_filename.to_string()),
+            ..Default::default()
+        };
+
+        mol2utils::grep(
+            input_files,
+            &[query_filename],
+            output_filename,
+            tol,
+            &options
+        ).unwrap();
+
+        let report = std::fs::read_to_string(report_filename).unwrap();
+        let rows: Vec<&str> = report.lines().collect();
+
+        assert!(rows.len() == 7);
+        assert!(rows[1].starts_with("data/test0000.mol2.gz\t"));
+
+        let num_molecules: u32 = rows[1..]
+            .iter()
+            .map(|r| r.split('\t').nth(2).unwrap().parse::<u32>().unwrap())
+            .sum();
+        assert!(num_molecules == 6972);
+    }
+
+    #[test]
+    fn read_strict_failure() {
+        /*
+        Tests whether a strict reader stops at a mid-stream failure and keeps the
+        error for the caller instead of exiting
+        */
+
+        let mut reader = Mol2Reader::new("data/truncated.mol2.gz").unwrap().strict(true);
+        let num_partial = reader.by_ref().count();
+        assert!(num_partial < 238);
+        assert!(reader.error().is_some());
+        assert!(!reader.is_truncated());
+        assert!(crate::mol2::read_failure().unwrap().contains("data/truncated.mol2.gz"));
+
+        let mut reader = Mol2Reader::new("data/test0002.mol2.gz").unwrap().strict(true);
+        assert!(reader.by_ref().count() == 238);
+        assert!(reader.error().is_none());
+    }
+
+    #[test]
+    fn run_grep_truncated() {
+        /*
+        Tests whether molecules read before a mid-stream failure are kept and the
+        file is flagged in the report (truncated.mol2.gz is test0002 cut in half)
+        */
+
+        let scratch = Scratch::new("run_grep_truncated");
+
+        let mut reader = Mol2Reader::new("data/truncated.mol2.gz").unwrap();
+        let num_partial = reader.by_ref().count();
+        assert!(num_partial > 0 && num_partial < 238);
+        assert!(reader.is_truncated());
+
+        let input_files = vec![
+            "data/test0000.mol2.gz".to_string(),
+            "data/truncated.mol2.gz".to_string()
+        ];
+        let output_filename = &scratch.path("test_grep_truncated.mol2.gz");
+        let report_filename = &scratch.path("test_grep_truncated.tsv");
+
+        let options = GrepOptions {
+            file_report: Some(report_filename.to_string()),
+            ..Default::default()
+        };
+
+        mol2utils::grep(
+            input_files,
+            &["data/zinc_list.txt"],
+            output_filename,
+            1e-6,
+            &options
+        ).unwrap();
+
+        let report = std::fs::read_to_string(report_filename).unwrap();
+        let rows: Vec<Vec<&str>> = report
+            .lines()
+            .map(|r| r.split('\t').collect())
+            .collect();
+
+        assert!(rows[0][6] == "truncated");
+        assert!(rows[1][6] == "false");
+        assert!(rows[2][6] == "true");
+        assert!(rows[2][2].parse::<usize>().unwrap() == num_partial);
+    }
+
+    #[test]
+    fn run_table_sample() {
+        /*
+        Tests whether subsampling is deterministic and keeps roughly the requested fraction
+        */
+
+        let scratch = Scratch::new("run_table_sample");
+
+        let input_files = vec!["data/test0000.mol2.gz".to_string()];
+        let output_filename = &scratch.path("test_table_sample.tab.gz");
+
+        let options = TableOptions {
+            write_header: true,
+            sample: Some(Sampler::new(0.2, 42, SampleKey::Index)),
+            ..Default::default()
+        };
+
+        let first = mol2utils::table(input_files.clone(), output_filename, &options).unwrap();
+        let second = mol2utils::table(input_files, output_filename, &options).unwrap();
+
+        assert!(first == second);
+        assert!(first > 45 && first < 135);
+
+        // all poses of a molecule share the same fate when sampling by name
+        let sampler = Sampler::new(0.5, 42, SampleKey::Name);
+        assert!(sampler.keep("ZINC000004737119", 0) == sampler.keep("ZINC000004737119", 1));
+    }
+
+    #[test]
+    fn read_properties() {
+        /*
+        Tests whether DOCK header fields are parsed into properties
+        */
+
+        let mol = Mol2Reader::new("data/test0000.mol2.gz")
+            .unwrap()
+            .next()
+            .unwrap();
+
+        let properties = mol.get_properties();
+
+        assert!(properties.len() == 24);
+        assert!(properties[0] == ("Name".to_string(), "ZINC000004737119".to_string()));
+        assert!(properties[13] == ("Van der Waals".to_string(), "-24.655830".to_string()));
+        assert!(properties[20].1.parse::<f64>().unwrap() == mol.get_energy());
+    }
+
+    #[test]
+    fn run_table_long() {
+        /*
+        Tests whether the long table format writes one row per header field
+        */
+
+        let scratch = Scratch::new("run_table_long");
+
+        let input_files = vec!["data/test0000.mol2.gz".to_string()];
+        let output_filename = &scratch.path("test_table_long.tab.gz");
+
+        let options = TableOptions {
+            write_header: true,
+            long: true,
+            ..Default::default()
+        };
+
+        let num_written = mol2utils::table(input_files, output_filename, &options).unwrap();
+        assert!(num_written == 451);
+
+        let mut contents = String::new();
+        flate2::read::MultiGzDecoder::new(std::fs::File::open(output_filename).unwrap())
+            .read_to_string(&mut contents)
+            .unwrap();
+        let rows: Vec<&str> = contents.lines().collect();
+
+        assert!(rows.len() == 1 + 451 * 24);
+        assert!(rows[0] == "name\tpose_index\tfield\tvalue");
+        assert!(rows[12] == "ZINC000004737119\t0\telectrostatic\t-6.714393");
+    }
+
+    #[test]
+    fn run_table_file_column() {
+        /*
+        Tests whether every pose row records the input file it was read from
+        */
+
+        let scratch = Scratch::new("run_table_file_column");
+
+        let input_files = vec![
+            "data/test0000.mol2.gz".to_string(),
+            "data/test0001.mol2.gz".to_string()
+        ];
+        let output_filename = &scratch.path("test_table_file_column.tab.gz");
+
+        let options = TableOptions {
+            write_header: true,
+            file_column: true,
+            ..Default::default()
+        };
+
+        let num_written = mol2utils::table(input_files.clone(), output_filename, &options).unwrap();
+        let num_first = Mol2Reader::new(&input_files[0]).unwrap().count();
+
+        let mut contents = String::new();
+        flate2::read::MultiGzDecoder::new(std::fs::File::open(output_filename).unwrap())
+            .read_to_string(&mut contents)
+            .unwrap();
+        let rows: Vec<Vec<&str>> = contents.lines().map(|x| x.split('\t').collect()).collect();
+
+        assert!(rows.len() == 1 + num_written as usize);
+        assert!(rows[0] == vec!["ligand_id", "name", "energy", "file"]);
+        assert!(rows[1][3] == input_files[0]);
+        assert!(rows[num_first][3] == input_files[0]);
+        assert!(rows[num_first + 1][3] == input_files[1]);
+    }
+
+    #[test]
+    fn run_table_all_fields() {
+        /*
+        Tests whether every header field is written to its own column
+        */
+
+        let scratch = Scratch::new("run_table_all_fields");
+
+        let input_files = vec!["data/test0000.mol2.gz".to_string()];
+        let output_filename = &scratch.path("test_table_all_fields.tab.gz");
+
+        let options = TableOptions {
+            write_header: true,
+            all_fields: true,
+            ..Default::default()
+        };
+
+        let num_written = mol2utils::table(input_files, output_filename, &options).unwrap();
+        assert!(num_written == 451);
+
+        let mut contents = String::new();
+        flate2::read::MultiGzDecoder::new(std::fs::File::open(output_filename).unwrap())
+            .read_to_string(&mut contents)
+            .unwrap();
+        let rows: Vec<Vec<&str>> = contents.lines().map(|x| x.split('\t').collect()).collect();
+
+        assert!(rows.len() == 1 + 451);
+        assert!(rows[0].len() == 1 + 24);
+        assert!(rows[0][..3] == ["ligand_id", "name", "protonation"]);
+        assert!(rows[0][14] == "van_der_waals");
+        assert!(rows[1][1] == "ZINC000004737119");
+        assert!(rows[1][14] == "-24.655830");
+    }
+
+    #[test]
+    fn run_table_fields() {
+        /*
+        Tests whether the selected built-in columns and header fields are
+        written in the requested order
+        */
+
+        let scratch = Scratch::new("run_table_fields");
+
+        let input_files = vec!["data/test0000.mol2.gz".to_string()];
+        let output_filename = &scratch.path("test_table_fields.tab.gz");
+
+        let options = TableOptions {
+            write_header: true,
+            fields: Some(vec![
+                "van_der_waals".to_string(),
+                "name".to_string(),
+                "file".to_string(),
+                "energy".to_string(),
+                "missing_field".to_string()
+            ]),
+            ..Default::default()
+        };
+
+        mol2utils::table(input_files, output_filename, &options).unwrap();
+
+        let mut contents = String::new();
+        flate2::read::MultiGzDecoder::new(std::fs::File::open(output_filename).unwrap())
+            .read_to_string(&mut contents)
+            .unwrap();
+        let rows: Vec<&str> = contents.lines().collect();
+
+        assert!(rows[0] == "van_der_waals\tname\tfile\tenergy\tmissing_field");
+        assert!(rows[1] == "-24.655830\tZINC000004737119\tdata/test0000.mol2.gz\t-20.377613\t");
+    }
+
+    #[test]
+    fn run_table_limit() {
+        /*
+        Tests whether reading stops once the row limit is reached, whether a
+        pretty table is rendered without writing the output file, and whether
+        its columns are aligned
+        */
+
+        let scratch = Scratch::new("run_table_limit");
+
+        let input_files = vec![
+            "data/test0000.mol2.gz".to_string(),
+            "data/test0001.mol2.gz".to_string()
+        ];
+        let output_filename = &scratch.path("test_table_limit.tab.gz");
+
+        let options = TableOptions {
+            write_header: true,
+            pretty: true,
+            limit: Some(50),
+            ..Default::default()
+        };
+        let num_written = mol2utils::table(input_files.clone(), output_filename, &options).unwrap();
+        assert!(num_written == 50);
+        assert!(!std::path::Path::new(output_filename).exists());
+
+        // the limit applies to written tables as well
+        let options = TableOptions { pretty: false, limit: Some(5), ..options };
+        let num_written = mol2utils::table(input_files, output_filename, &options).unwrap();
+        assert!(num_written == 5);
+        let mut text = String::new();
+        flate2::read::MultiGzDecoder::new(std::fs::File::open(output_filename).unwrap())
+            .read_to_string(&mut text)
+            .unwrap();
+        let rows: Vec<Vec<String>> = text
+            .lines()
+            .filter(|x| !x.starts_with('#'))
+            .map(|x| x.split('\t').map(|c| c.to_string()).collect())
+            .collect();
+        assert!(rows.len() == 6);
+
+        // every column starts at the same offset on every line
+        let aligned = report::format_aligned(&rows);
+        let lines: Vec<&str> = aligned.lines().collect();
+        assert!(lines.len() == 6);
+        assert!(lines[0].starts_with("ligand_id  name"));
+        let name_offset = lines[0].find("name").unwrap();
+        let energy_offset = lines[0].find("energy").unwrap();
+        for (line, row) in lines.iter().zip(rows.iter()) {
+            assert!(line[name_offset..].starts_with(&row[1]));
+            assert!(line[energy_offset..] == row[2]);
+        }
+    }
+
+    #[test]
+    fn read_atoms() {
+        /*
+        Tests whether atom records are parsed from the ATOM section
+        */
+
+        let mol = Mol2Reader::new("data/test0000.mol2.gz")
+            .unwrap()
+            .next()
+            .unwrap();
+
+        let atoms = mol.atoms();
+        assert!(atoms.len() == 45);
+        assert!(atoms[0].atom_type == "N.am");
+        assert!(atoms[0].x == -23.7615);
+        assert!(atoms[44].z == -18.4569);
+        assert!(atoms[0].charge == -0.86);
+    }
+
+    #[test]
+    fn read_sections() {
+        /*
+        Tests whether molecules are delimited by their records rather than the
+        section counts (sections.mol2.gz has trailing SUBSTRUCTURE, SET, and CRYSIN
+        sections and a SUBSTRUCTURE placed before BOND)
+        */
+
+        let mols: Vec<Mol2> = Mol2Reader::new("data/sections.mol2.gz")
+            .unwrap()
+            .collect();
+        assert!(mols.len() == 3);
+        assert!(mols.iter().all(|x| !x.atoms().is_empty()));
+        assert!(mols[0].atoms().len() == 45);
+
+        // unknown sections are kept verbatim
+        let sections = mols[0].section_names();
+        assert!(sections.contains(&"SET"));
+        assert!(sections.contains(&"CRYSIN"));
+        assert!(mols[0].get_lines().contains("@<TRIPOS>CRYSIN"));
+
+        // substructures are exposed regardless of their position
+        assert!(mols[0].substructures().len() == 1);
+        assert!(mols[1].substructures().len() == 1);
+        assert!(mols[2].substructures().is_empty());
+        assert!(mols[1].get_name() == "ZINC000153919792");
+        assert!(mols[2].get_name() == "ZINC000153919692");
+    }
+
+    #[test]
+    fn read_final_molecule() {
+        /*
+        Tests whether the final molecule of a file is kept when the file ends without
+        a trailing newline or the molecule lacks its MOLECULE record
+        */
+
+        let molecules: Vec<Mol2> = Mol2Reader::new("data/eof_no_newline.mol2.gz").unwrap().collect();
+        assert!(molecules.len() == 3);
+        assert!(molecules[2].get_name() == "ZINC000257225218");
+        assert!(molecules.iter().all(|x| x.get_lines().ends_with('\n')));
+
+        let molecules: Vec<Mol2> = Mol2Reader::new("data/eof_no_record.mol2.gz").unwrap().collect();
+        assert!(molecules.len() == 3);
+        assert!(molecules[2].get_name() == "ZINC000257225218");
+        assert!(molecules[2].get_energy() == -25.511309);
+        assert!(molecules[2].atoms().len() == molecules[2].section("ATOM").count());
+        assert!(!molecules[2].atoms().is_empty());
+    }
+
+    #[test]
+    fn read_fallback_names() {
+        /*
+        Tests whether molecules without a Name header are named by their MOLECULE
+        title, or by file stem and record index when the title is blank or generic
+        */
+
+        let names: Vec<String> = Mol2Reader::new("data/unnamed.mol2.gz")
+            .unwrap()
+            .map(|x| x.get_name().to_string())
+            .collect();
+        assert!(names == vec!["unnamed#0", "unnamed#1", "ZINC000153919692"]);
+    }
+
+    #[test]
+    fn read_interleaved() {
+        /*
+        Tests whether many readers consumed in lockstep all progress
+        (more open readers than IO threads)
+        */
+
+        let mut readers: Vec<Mol2Reader> = (0..12)
+            .map(|_| Mol2Reader::new("data/test0001.mol2.gz").unwrap())
+            .collect();
+
+        let mut num_molecules = 0;
+        while readers.iter_mut().all(|r| r.next().is_some()) {
+            num_molecules += 1;
+        }
+        assert!(num_molecules == 2797);
+    }
+
+    #[test]
+    fn read_parallel_gzip() {
+        /*
+        Tests whether members of a multi-member gzip decompressed in parallel are
+        yielded in order (members.mol2.gz is test0002 split mid-molecule into 5
+        members of about 38 kB), reading a bounded window at a time
+        */
+
+        let data = std::fs::read("data/members.mol2.gz").unwrap();
+        let mut expected = Vec::new();
+        flate2::read::MultiGzDecoder::new(&data[..])
+            .read_to_end(&mut expected)
+            .unwrap();
+
+        let mut observed = Vec::new();
+        let mut decoder = gzip::ParallelGzDecoder::new(std::io::Cursor::new(data.clone()));
+        decoder.read_to_end(&mut observed).unwrap();
+        assert!(observed == expected);
+        assert!(decoder.num_members() == 5);
+
+        // members continuing past a window are carried over to the next
+        let mut observed = Vec::new();
+        let mut decoder = gzip::ParallelGzDecoder::with_window(std::io::Cursor::new(data.clone()), 50_000);
+        decoder.read_to_end(&mut observed).unwrap();
+        assert!(observed == expected);
+        assert!(decoder.num_members() == 5);
+        assert!(decoder.peak_window() < 100_000);
+
+        // a single member spanning many windows is decompressed sequentially
+        let mut observed = Vec::new();
+        let mut decoder = gzip::ParallelGzDecoder::with_window(std::io::Cursor::new(data), 1_000);
+        decoder.read_to_end(&mut observed).unwrap();
+        assert!(observed == expected);
+        assert!(decoder.peak_window() <= 5_000);
+
+        let num_molecules = Mol2Reader::new("data/members.mol2.gz")
+            .unwrap()
+            .count();
+        assert!(num_molecules == 238);
+    }
+
+    #[test]
+    fn read_parallel_gzip_false_member() {
+        /*
+        Tests whether a gzip magic number inside compressed data only splits off
+        the member holding it (stored blocks keep the bytes verbatim)
+        */
+
+        let payload = b"before\x1f\x8b\x08\x00after\n".to_vec();
+        let mut data = Vec::new();
+        for _ in 0..2 {
+            let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::none());
+            encoder.write_all(&payload).unwrap();
+            data.extend(encoder.finish().unwrap());
+        }
+
+        assert!(gzip::member_offsets(&data).len() > 2);
+
+        let mut decoder = gzip::ParallelGzDecoder::new(std::io::Cursor::new(data));
+        let mut observed = Vec::new();
+        decoder.read_to_end(&mut observed).unwrap();
+        assert!(observed == [payload.clone(), payload].concat());
+        assert!(decoder.num_members() == 2);
+    }
+
+    #[test]
+    fn read_parallel_gzip_windows() {
+        /*
+        Tests whether a false member header inside compressed data is resynced past
+        when members span windows, whether a member larger than the window limit is
+        decompressed sequentially, and whether only one file is decompressed in
+        parallel at a time
+        */
+
+        // stored members of about 1 kB each holding a gzip magic number mid-member
+        let payload = [vec![b'a'; 500], b"\x1f\x8b\x08\x00".to_vec(), vec![b'b'; 500]].concat();
+        let mut data = Vec::new();
+        for _ in 0..5 {
+            let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::none());
+            encoder.write_all(&payload).unwrap();
+            data.extend(encoder.finish().unwrap());
+        }
+        assert!(gzip::member_offsets(&data).len() == 10);
+
+        let mut decoder = gzip::ParallelGzDecoder::with_window(std::io::Cursor::new(data), 700);
+        let mut observed = Vec::new();
+        decoder.read_to_end(&mut observed).unwrap();
+        assert!(observed == payload.repeat(5));
+        assert!(decoder.num_members() == 5);
+        assert!(decoder.peak_window() < 2_800);
+
+        // a small member followed by one spanning more windows than allowed
+        let mut expected = Vec::new();
+        let mut data = Vec::new();
+        let mut molecules = Vec::new();
+        flate2::read::MultiGzDecoder::new(std::fs::File::open("data/test0002.mol2.gz").unwrap())
+            .read_to_end(&mut molecules)
+            .unwrap();
+        for contents in [b"header\n".to_vec(), molecules] {
+            let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(&contents).unwrap();
+            data.extend(encoder.finish().unwrap());
+            expected.extend(contents);
+        }
+
+        let mut decoder = gzip::ParallelGzDecoder::with_window(std::io::Cursor::new(data), 10_000);
+        let mut observed = Vec::new();
+        decoder.read_to_end(&mut observed).unwrap();
+        assert!(observed == expected);
+        assert!(decoder.num_members() == 1);
+        assert!(decoder.peak_window() <= 50_000);
+
+        // a second file opened while one is decompressed in parallel is refused
+        let first = gzip::ParallelGzDecoder::exclusive(std::io::empty()).ok().unwrap();
+        assert!(gzip::ParallelGzDecoder::exclusive(std::io::empty()).is_err());
+        drop(first);
+        assert!(gzip::ParallelGzDecoder::exclusive(std::io::empty()).is_ok());
+    }
+
+    #[cfg(feature = "plugins")]
+    #[test]
+    fn run_filter_plugin() {
+        /*
+        Tests whether a script plugin decides which molecules are kept
+        */
+
+        let scratch = Scratch::new("run_filter_plugin");
+
+        let input_files = vec!["data/test0000.mol2.gz".to_string()];
+        let output_filename = &scratch.path("test_filter_plugin.mol2.gz");
+
+        let options = FilterOptions {
+            plugin: Some(crate::plugin::load_plugin("data/keep_sulfur.rhai").unwrap().into()),
+            ..Default::default()
+        };
+        let num_passing = filter::filter(input_files, output_filename, &options).unwrap();
+
+        let expected = Mol2Reader::new("data/test0000.mol2.gz")
+            .unwrap()
+            .filter(|x| x.get_energy() < -20.0 && x.atoms().iter().any(|a| a.element() == "S"))
+            .count();
+        assert!(num_passing as usize == expected);
+        assert!(expected > 0);
+    }
+
+    #[cfg(feature = "plugins")]
+    #[test]
+    fn run_filter_plugin_error() {
+        /*
+        Tests whether a script failing at runtime fails the filter instead of panicking
+        */
+
+        let scratch = Scratch::new("run_filter_plugin_error");
+        let script = scratch.path("failing.rhai");
+        std::fs::write(&script, "fn keep(mol) {\n    mol.energy.missing()\n}\n").unwrap();
+
+        let options = FilterOptions {
+            plugin: Some(crate::plugin::load_plugin(&script).unwrap().into()),
+            ..Default::default()
+        };
+        let result = filter::filter(
+            vec!["data/test0000.mol2.gz".to_string()],
+            &scratch.path("filtered.mol2.gz"),
+            &options
+        );
+        assert!(result.unwrap_err().to_string().contains("Plugin failed"));
+    }
+
+    #[test]
+    fn read_geometry() {
+        /*
+        Tests whether the centroid and radius of gyration are computed from the atom coordinates
+        */
+
+        let mol = Mol2Reader::new("data/test0000.mol2.gz")
+            .unwrap()
+            .next()
+            .unwrap();
+        let atoms = mol.atoms();
+        let geometry = mol.geometry().unwrap();
+
+        let mean_x = atoms.iter().map(|a| a.x).sum::<f64>() / atoms.len() as f64;
+        assert!((geometry.centroid.0 - mean_x).abs() < 1e-9);
+        assert!(geometry.distance_to(geometry.centroid) == 0.0);
+
+        // no atom lies farther from the centroid than every other atom on average
+        let max_distance = atoms
+            .iter()
+            .map(|a| geometry.distance_to((a.x, a.y, a.z)))
+            .fold(0.0, f64::max);
+        assert!(geometry.radius_of_gyration > 0.0);
+        assert!(geometry.radius_of_gyration <= max_distance);
+    }
+
+    #[test]
+    fn run_filter_geometry() {
+        /*
+        Tests whether poses are filtered on radius of gyration and distance to a pocket center
+        */
+
+        let scratch = Scratch::new("run_filter_geometry");
+
+        let input_files = vec!["data/test0000.mol2.gz".to_string()];
+        let output_filename = &scratch.path("test_filter_geometry.mol2.gz");
+
+        let pocket = filter::Pocket::from_args("-18.0, 18.0, -20.0", 3.0).unwrap();
+        assert!(filter::Pocket::from_args("1,2", 4.0).is_none());
+
+        let options = FilterOptions {
+            max_radius_of_gyration: Some(4.5),
+            pocket: Some(pocket.clone()),
+            ..Default::default()
+        };
+        let num_passing = filter::filter(input_files, output_filename, &options).unwrap();
+
+        let expected = Mol2Reader::new("data/test0000.mol2.gz")
+            .unwrap()
+            .filter_map(|x| x.geometry())
+            .filter(|g| g.radius_of_gyration <= 4.5 && g.distance_to(pocket.center) <= 3.0)
+            .count();
+        assert!(num_passing as usize == expected);
+        assert!(expected > 0 && expected < 451);
+    }
+
+    #[test]
+    fn run_filter_pose_counts() {
+        /*
+        Tests whether ligands are kept according to their number of poses across the inputs
+        */
+
+        let scratch = Scratch::new("run_filter_pose_counts");
+
+        let input_files = vec![
+            "data/test0000.mol2.gz".to_string(),
+            "data/test0003.mol2.gz".to_string()
+        ];
+        let output_filename = &scratch.path("test_filter_pose_counts.mol2.gz");
+
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for x in input_files.iter() {
+            for mol in Mol2Reader::new(x).unwrap() {
+                *counts.entry(mol.get_name().to_string()).or_default() += 1;
+            }
+        }
+
+        // test0003 is a copy of test0000 so every ligand has an even number of poses
+        let options = FilterOptions {
+            min_poses: Some(4),
+            ..Default::default()
+        };
+        let num_passing = filter::filter(input_files.clone(), output_filename, &options).unwrap();
+        let expected: usize = counts.values().filter(|n| **n >= 4).sum();
+        assert!(num_passing as usize == expected);
+        assert!(Mol2Reader::new(output_filename).unwrap().all(|x| counts[x.get_name()] >= 4));
+
+        let options = FilterOptions {
+            max_poses: Some(2),
+            ..Default::default()
+        };
+        let num_passing = filter::filter(input_files, output_filename, &options).unwrap();
+        let expected: usize = counts.values().filter(|n| **n <= 2).sum();
+        assert!(num_passing as usize == expected);
+        assert!(expected > 0 && expected < 902);
+    }
+
+    #[test]
+    fn run_filter_exec() {
+        /*
+        Tests whether accepted molecules are piped into a command in batches
+        and whether the exit code of every batch is reported
+        */
+
+        let scratch = Scratch::new("run_filter_exec");
+
+        let input_files = vec!["data/test0000.mol2.gz".to_string()];
+        let piped_filename = &scratch.path("test_filter_exec.mol2");
+        let report_filename = &scratch.path("test_filter_exec.tsv");
+        std::fs::write(piped_filename, "").unwrap();
+
+        let options = FilterOptions {
+            top_percent: Some(10.0),
+            exec: Some(crate::exec::ExecOptions {
+                command: format!("cat >> {}; test $MOL2GREP_BATCH -ne 2", piped_filename),
+                batch_size: 10,
+                report: report_filename.to_string()
+            }),
+            ..Default::default()
+        };
+        let num_passing = filter::filter(input_files, &scratch.path("test_filter_exec.mol2.gz"), &options).unwrap();
+        assert!(num_passing == 46);
+
+        // every accepted molecule went through the command exactly once
+        assert!(Mol2Reader::new(piped_filename).unwrap().count() == 46);
+
+        let report = std::fs::read_to_string(report_filename).unwrap();
+        let rows: Vec<&str> = report.lines().collect();
+        assert!(rows.len() == 1 + 5);
+        assert!(rows[3] == "2\t10\t1");
+        assert!(rows[5] == "4\t6\t0");
+    }
+
+    #[test]
+    fn run_duplicates() {
+        /*
+        Tests whether geometrically identical poses are detected across files
+        (test0003 is a copy of test0000)
+        */
+
+        let scratch = Scratch::new("run_duplicates");
+
+        let input_files = vec![
+            "data/test0000.mol2.gz".to_string(),
+            "data/test0003.mol2.gz".to_string()
+        ];
+        let output_filename = &scratch.path("test_duplicates.tsv");
+
+        let num_redundant = qc::duplicates(input_files, output_filename, 3).unwrap();
+        assert!(num_redundant == 451);
+    }
+
+    #[test]
+    fn run_atom_census() {
+        /*
+        Tests whether atom types and elements are counted consistently
+        */
+
+        let scratch = Scratch::new("run_atom_census");
+
+        let input_files = vec!["data/test0000.mol2.gz".to_string()];
+        let output_filename = &scratch.path("test_atoms.tsv");
+
+        let census = qc::atom_census(input_files, output_filename).unwrap();
+
+        let num_atoms: u64 = Mol2Reader::new("data/test0000.mol2.gz")
+            .unwrap()
+            .map(|x| x.atoms().len() as u64)
+            .sum();
+
+        assert!(census.types.values().sum::<u64>() == num_atoms);
+        assert!(census.elements.values().sum::<u64>() == num_atoms);
+        assert!(census.types.contains_key("N.am"));
+        assert!(!census.elements.contains_key("N.am"));
+    }
+
+    #[test]
+    fn run_charge_stats() {
+        /*
+        Tests whether every molecule contributes once to the net charge distribution
+        */
+
+        let scratch = Scratch::new("run_charge_stats");
+
+        let input_files = vec!["data/test0000.mol2.gz".to_string()];
+
+        let summary = qc::charge_stats(
+            input_files,
+            &scratch.path("test_charges.tsv"),
+            &scratch.path("test_charges.flagged.tsv"),
+            0.01
+        ).unwrap();
+
+        assert!(summary.distribution.values().sum::<u64>() == 451);
+
+        let flagged = std::fs::read_to_string(scratch.path("test_charges.flagged.tsv")).unwrap();
+        assert!(flagged.lines().count() as u64 == summary.num_flagged + 1);
+    }
+
+    #[test]
+    fn run_reorder() {
+        /*
+        Tests whether molecules are written in the order of a ranked list
+        (names absent from the inputs are skipped and repeated names written once)
+        */
+
+        let scratch = Scratch::new("run_reorder");
+
+        let input_files = read_input_list("data/input_list.txt").unwrap();
+        let names = read_name_list("data/ranked_list.tsv").unwrap();
+        let output_filename = &scratch.path("test_reorder.mol2.gz");
+
+        let num_written = reorder::reorder(input_files, &names, output_filename).unwrap();
+        assert!(num_written == 8);
+
+        let observed: Vec<String> = Mol2Reader::new(output_filename)
+            .unwrap()
+            .map(|x| x.get_name().to_string())
+            .collect();
+        let mut expected = vec!["ZINC000067674651"; 2];
+        expected.extend(vec!["ZINC000002366042"; 4]);
+        expected.extend(vec!["ZINC000012505565"; 2]);
+        assert!(observed == expected);
+    }
+
+    #[test]
+    fn run_decoys() {
+        /*
+        Tests whether distinct decoys matching the net charge of each active are selected
+        */
+
+        let scratch = Scratch::new("run_decoys");
+
+        let input_files = read_input_list("data/input_list.txt").unwrap();
+        let actives = decoys::Actives::Names(read_name_list("data/zinc_list_subset.txt").unwrap());
+        let output_filename = &scratch.path("test_decoys.mol2.gz");
+        let pairs_filename = &scratch.path("test_decoys.tsv");
+
+        let num_decoys = decoys::decoys(input_files, &actives, 5, output_filename, pairs_filename).unwrap();
+        assert!(num_decoys == 10);
+
+        let pairs = std::fs::read_to_string(pairs_filename).unwrap();
+        let rows: Vec<Vec<&str>> = pairs
+            .lines()
+            .skip(1)
+            .map(|x| x.split('\t').collect())
+            .collect();
+        let decoy_names: std::collections::HashSet<&str> = rows.iter().map(|r| r[1]).collect();
+        assert!(decoy_names.len() == 10);
+        assert!(rows.iter().all(|r| r[0] != r[1] && !r[1].is_empty()));
+
+        let written: Vec<Mol2> = Mol2Reader::new(output_filename).unwrap().collect();
+        assert!(written.len() == 10);
+        assert!(written.iter().all(|x| decoy_names.contains(x.get_name())));
+    }
+
+    #[test]
+    fn run_enrichment() {
+        /*
+        Tests ROC AUC, logAUC, and enrichment factors on a hand-computed ranking
+        (a1 -10, d1 -9, a2 -8, d2 -7, d3 -6)
+        */
+
+        let scores = enrichment::read_score_table("data/enrichment_scores.tsv").unwrap();
+        let actives = read_name_list("data/enrichment_actives.txt")
+            .unwrap()
+            .into_iter()
+            .collect();
+
+        let result = enrichment::enrichment(&scores, &actives, &[20.0, 50.0]).unwrap();
+        assert!(result.num_actives == 2);
+        assert!(result.num_decoys == 3);
+        assert!((result.auc - 5.0 / 6.0).abs() < 1e-9);
+        assert!((result.log_auc - 0.579520).abs() < 1e-6);
+        assert!(result.enrichment_factors == vec![(20.0, 2.5), (50.0, 2.0 / 3.0 / 0.4)]);
+        assert!(result.roc.len() == 6);
+        assert!(result.roc[5].0 == 1.0 && result.roc[5].1 == 1.0);
+
+        // a ranking without decoys has no ROC curve
+        let only_actives = scores
+            .into_iter()
+            .filter(|(k, _)| actives.contains(k))
+            .collect();
+        assert!(enrichment::enrichment(&only_actives, &actives, &[1.0]).is_none());
+    }
+
+    #[cfg(feature = "plot")]
+    #[test]
+    fn run_plot() {
+        /*
+        Tests whether energy and ROC figures are rendered to SVG
+        */
+
+        let scratch = Scratch::new("run_plot");
+
+        let per_file = crate::plot::file_energies(&["data/test0002.mol2.gz".to_string()]);
+        assert!(per_file[0].1.len() == 238);
+
+        crate::plot::energy_histogram(&per_file[0].1, 20, &scratch.path("test_histogram.svg")).unwrap();
+        crate::plot::file_boxes(&per_file, &scratch.path("test_boxes.svg")).unwrap();
+
+        let scores = enrichment::read_score_table("data/enrichment_scores.tsv").unwrap();
+        let actives = read_name_list("data/enrichment_actives.txt").unwrap().into_iter().collect();
+        let result = enrichment::enrichment(&scores, &actives, &[1.0]).unwrap();
+        crate::plot::roc_curve(&result.roc, result.auc, &scratch.path("test_roc.svg")).unwrap();
+
+        for x in [&scratch.path("test_histogram.svg"), &scratch.path("test_boxes.svg"), &scratch.path("test_roc.svg")] {
+            let svg = std::fs::read_to_string(x).unwrap();
+            assert!(svg.starts_with("<svg") && svg.trim_end().ends_with("</svg>"));
+        }
+    }
+
+    #[test]
+    fn run_stats_per_ligand() {
+        /*
+        Tests whether poses are aggregated into one row per ligand
+        (test0003 is a copy of test0000 so every pose is seen twice)
+        */
+
+        let scratch = Scratch::new("run_stats_per_ligand");
+
+        let input_files = vec![
+            "data/test0000.mol2.gz".to_string(),
+            "data/test0003.mol2.gz".to_string()
+        ];
+        let output_filename = &scratch.path("test_stats.tsv");
+
+        let total = stats::stats(input_files, output_filename, true, &[]).unwrap();
+        assert!(total.count == 902);
+
+        let content = std::fs::read_to_string(output_filename).unwrap();
+        let rows: Vec<Vec<&str>> = content
+            .lines()
+            .skip(1)
+            .map(|x| x.split('\t').collect())
+            .collect();
+
+        let num_poses: u64 = rows.iter().map(|r| r[1].parse::<u64>().unwrap()).sum();
+        assert!(num_poses == 902);
+        assert!(rows[0][2].parse::<f64>().unwrap() == total.best);
+        assert!(rows.iter().all(|r| r[1].parse::<u64>().unwrap() % 2 == 0));
+        assert!(rows.iter().all(|r| r[6].parse::<f64>().unwrap() >= 0.0));
+    }
+
+    #[test]
+    fn run_stats_percentiles() {
+        /*
+        Tests whether the whole run summary reports the median and requested percentiles
+        */
+
+        let scratch = Scratch::new("run_stats_percentiles");
+
+        assert!(stats::percentile(&[1.0, 2.0, 3.0, 4.0], 50.0) == Some(2.5));
+        assert!(stats::percentile(&[1.0, 2.0, 3.0, 4.0], 0.0) == Some(1.0));
+        assert!(stats::percentile(&[1.0, 2.0, 3.0, 4.0], 100.0) == Some(4.0));
+        assert!(stats::percentile(&[], 50.0).is_none());
+
+        let input_files = vec!["data/test0000.mol2.gz".to_string()];
+        let output_filename = &scratch.path("test_stats_percentiles.tsv");
+        let total = stats::stats(input_files, output_filename, false, &[5.0, 95.0]).unwrap();
+
+        let content = std::fs::read_to_string(output_filename).unwrap();
+        let lines: Vec<Vec<&str>> = content.lines().map(|x| x.split('\t').collect()).collect();
+        assert!(lines[0] == vec!["poses", "ligands", "best", "worst", "mean", "std", "median", "p5", "p95"]);
+        let values: Vec<f64> = lines[1].iter().map(|x| x.parse::<f64>().unwrap()).collect();
+        assert!(values[0] as u64 == total.count);
+        assert!(values[2] <= values[7] && values[7] <= values[6]);
+        assert!(values[6] <= values[8] && values[8] <= values[3]);
+    }
+
+    #[test]
+    fn run_info() {
+        /*
+        Tests whether per-file summaries report counts, energy ranges, names, and truncation
+        */
+
+        let scratch = Scratch::new("run_info");
+
+        let input_files = vec!["data/test0002.mol2.gz".to_string(), "data/truncated.mol2.gz".to_string()];
+        let output_filename = &scratch.path("test_info.tsv");
+        let infos = report::info(input_files, Some(output_filename)).unwrap();
+
+        let molecules: Vec<Mol2> = Mol2Reader::new("data/test0002.mol2.gz").unwrap().collect();
+        let energies: Vec<f64> = molecules.iter().map(|x| x.get_energy()).collect();
+        assert!(infos[0].molecules == 238);
+        assert!(infos[0].best == energies.iter().copied().reduce(f64::min));
+        assert!(infos[0].worst == energies.iter().copied().reduce(f64::max));
+        assert!(infos[0].first_name.as_deref() == Some(molecules[0].get_name()));
+        assert!(infos[0].last_name.as_deref() == Some(molecules[237].get_name()));
+        assert!(infos[0].compressed_bytes < infos[0].uncompressed_bytes);
+        assert!(!infos[0].truncated);
+        assert!(infos[1].truncated);
+
+        let content = std::fs::read_to_string(output_filename).unwrap();
+        assert!(content.lines().count() == 3);
+        assert!(content.lines().nth(1).unwrap().starts_with("data/test0002.mol2.gz\t238\t"));
+    }
+
+    #[test]
+    fn run_count_cache() {
+        /*
+        Tests whether per-file summaries are served from the cache for unchanged
+        files and recomputed once a file changes
+        */
+
+        let scratch = Scratch::new("run_count_cache");
+
+        let copied = &scratch.path("test_cache_input.mol2.gz");
+        let cache_path = scratch.path("test_cache.tsv");
+        let cache_filename = std::path::Path::new(&cache_path);
+        std::fs::copy("data/test0000.mol2.gz", copied).unwrap();
+
+        let input_files = vec!["data/test0002.mol2.gz".to_string(), copied.to_string()];
+        let total = cache::count(input_files.clone(), &scratch.path("test_count.tsv"), Some(cache_filename)).unwrap();
+        assert!(total == 238 + 451);
+
+        let mut c = cache::MetadataCache::load(cache_filename);
+        let summaries = cache::summarize_files(&input_files, Some(&mut c)).unwrap();
+        assert!(c.num_hits() == 2);
+        assert!(summaries[1].molecules == 451);
+        assert!(summaries[1].fields.iter().any(|x| x == "Total Energy"));
+        assert!(summaries[1] == cache::summarize_file(copied).unwrap());
+
+        // a changed file is read again
+        std::fs::copy("data/test0002.mol2.gz", copied).unwrap();
+        let mut c = cache::MetadataCache::load(cache_filename);
+        let summaries = cache::summarize_files(&input_files, Some(&mut c)).unwrap();
+        assert!(c.num_hits() == 1);
+        assert!(summaries[1].molecules == 238);
+    }
+
+    #[test]
+    fn read_zinc_ids() {
+        /*
+        Tests whether ZINC identifiers of every generation are recognized and
+        whether molecules are tabulated and split by identifier component
+        */
+
+        let scratch = Scratch::new("read_zinc_ids");
+
+        let id = zinc::ZincId::parse("ZINC000004737119").unwrap();
+        assert!(id.generation == zinc::ZincGeneration::Zinc20);
+        assert!(id.tranche.is_none());
+        assert!(zinc::ZincId::parse("ZINC04737119").unwrap().generation == zinc::ZincGeneration::Zinc12);
+
+        let id = zinc::ZincId::parse("ZINCms000002NiP3").unwrap();
+        assert!(id.generation == zinc::ZincGeneration::Zinc22);
+        assert!(id.tranche.as_deref() == Some("ms"));
+        assert!(id.heavy_atoms == Some(22));
+        assert!(id.serial == "000002NiP3");
+        assert!(id.key(zinc::ZincComponent::HeavyAtoms) == "H22");
+
+        assert!(zinc::ZincId::parse("ZINC0004737119").is_none());
+        assert!(zinc::ZincId::parse("ZINC00000473711_").is_none());
+        assert!(zinc::ZincId::parse("CHEMBL25").is_none());
+
+        let input_files = vec!["data/test0000.mol2.gz".to_string()];
+        let options = zinc::ZincOptions {
+            component: zinc::ZincComponent::Generation,
+            split_prefix: Some(scratch.path("test_zinc"))
+        };
+        let num_invalid = zinc::zinc(input_files, &scratch.path("test_zinc.tsv"), &options).unwrap();
+        assert!(num_invalid == 0);
+
+        let table = std::fs::read_to_string(scratch.path("test_zinc.tsv")).unwrap();
+        let rows: Vec<&str> = table.lines().collect();
+        assert!(rows.len() == 2);
+        assert!(rows[1].starts_with("zinc20\t451\t"));
+        assert!(Mol2Reader::new(&scratch.path("test_zinc.zinc20.mol2.gz")).unwrap().count() == 451);
+    }
+
+    #[test]
+    fn read_query_chunks() {
+        /*
+        Tests whether query tables parsed in parallel chunks match a line by line
+        read, with repeated names keeping every score in file order
+        */
+
+        let scratch = Scratch::new("read_query_chunks");
+
+        let data = b"a\t1\nbb\t2\n\nccc\t3\nbb\t4\n";
+        for size in 0..data.len() + 2 {
+            let chunks = crate::query::line_chunks(data, size);
+            assert!(chunks.concat() == data.to_vec());
+            assert!(chunks.iter().all(|x| x.ends_with(b"\n")));
+        }
+
+        let filename = &scratch.path("test_query_chunks.tsv");
+        std::fs::write(filename, data).unwrap();
+        match QueryReader::new(filename).unwrap().load_queries().unwrap() {
+            QueryFormat::WithScore(t) => {
+                assert!(t.len() == 3);
+                let mut bb = Mol2::new();
+                bb.add_name("bb".to_string());
+                assert!(t[&bb].iter().map(|x| x.score).collect::<Vec<f64>>() == vec![2.0, 4.0]);
+            },
+            _ => panic!("expected a table with scores")
+        }
+
+        match QueryReader::new("data/zinc_list.txt").unwrap().load_queries().unwrap() {
+            QueryFormat::WithoutScore(t) => {
+                let expected: HashSet<String> = std::fs::read_to_string("data/zinc_list.txt")
+                    .unwrap()
+                    .lines()
+                    .map(|x| x.trim().to_string())
+                    .filter(|x| !x.is_empty())
+                    .collect();
+                assert!(t.len() == expected.len());
+                assert!(t.iter().all(|x| expected.contains(x.get_name())));
+            },
+            _ => panic!("expected a table without scores")
+        }
+    }
+
+    #[test]
+    fn read_memory_stats() {
+        /*
+        Tests whether channel high-water marks and byte formatting are tracked for memory summaries
+        */
+
+        let gauge = crate::memory::QueueGauge::default();
+        gauge.push(100);
+        gauge.push(50);
+        gauge.pop(100);
+        gauge.push(10);
+        gauge.pop(50);
+        assert!(gauge.high_water() == (2, 150));
+
+        assert!(crate::memory::format_bytes(512) == "512 B");
+        assert!(crate::memory::format_bytes(3 << 20) == "3.0 MB");
+        if cfg!(target_os = "linux") {
+            assert!(crate::memory::peak_rss_bytes().unwrap() > 0);
+        }
+
+        let table = QueryReader::new("data/zinc_list.txt").unwrap().load_queries().unwrap();
+        assert!(table.estimated_bytes() > 0);
+    }
+
+    #[test]
+    fn run_merge_concat() {
+        /*
+        Tests whether inputs are concatenated in order into a single recompressed output
+        */
+
+        let scratch = Scratch::new("run_merge_concat");
+
+        let input_files = vec!["data/test0002.mol2.gz".to_string(), "data/test0000.mol2.gz".to_string()];
+        let output_filename = &scratch.path("test_merge_concat.mol2.gz");
+        let num_written = merge::merge(input_files.clone(), output_filename, false, false).unwrap();
+        assert!(num_written == 238 + 451);
+
+        let expected: Vec<String> = input_files
+            .iter()
+            .flat_map(|x| Mol2Reader::new(x).unwrap())
+            .map(|x| x.get_lines().to_string())
+            .collect();
+        let merged: Vec<String> = Mol2Reader::new(output_filename)
+            .unwrap()
+            .map(|x| x.get_lines().to_string())
+            .collect();
+        assert!(merged == expected);
+    }
+
+    #[test]
+    fn run_merge_group_output() {
+        /*
+        Tests whether merging with grouped output writes all poses of a ligand
+        adjacently, keeping every molecule of the inputs, with and without sorting
+        */
+
+        let scratch = Scratch::new("run_merge_group_output");
+
+        let input_files = vec!["data/test0000.mol2.gz".to_string(), "data/test0000.mol2.gz".to_string()];
+        let output_filename = &scratch.path("test_merge_group_output.mol2.gz");
+
+        let mut expected: Vec<String> = input_files
+            .iter()
+            .flat_map(|x| Mol2Reader::new(x).unwrap())
+            .map(|x| x.get_lines().to_string())
+            .collect();
+        expected.sort_unstable();
+        let num_names = Mol2Reader::new(&input_files[0])
+            .unwrap()
+            .map(|x| x.get_name().to_string())
+            .collect::<HashSet<String>>()
+            .len();
+
+        for sorted in [false, true] {
+            let num_written = merge::merge(input_files.clone(), output_filename, sorted, true).unwrap();
+            assert!(num_written == 2 * 451);
+
+            let molecules: Vec<Mol2> = Mol2Reader::new(output_filename).unwrap().collect();
+            let mut merged: Vec<String> = molecules.iter().map(|x| x.get_lines().to_string()).collect();
+            merged.sort_unstable();
+            assert!(merged == expected);
+
+            // once consecutive duplicates are removed each name appears exactly once
+            let mut names: Vec<&str> = molecules.iter().map(|x| x.get_name()).collect();
+            names.dedup();
+            assert!(names.len() == num_names);
+        }
+    }
+
+    #[test]
+    fn run_interleave() {
+        /*
+        Tests whether inputs are merged one molecule of each in turn,
+        the longer input finishing the output
+        */
+
+        let scratch = Scratch::new("run_interleave");
+
+        let input_files = vec!["data/test0002.mol2.gz".to_string(), "data/test0000.mol2.gz".to_string()];
+        let output_filename = &scratch.path("test_interleave.mol2.gz");
+        let num_written = merge::interleave(input_files.clone(), output_filename).unwrap();
+        assert!(num_written == 238 + 451);
+
+        let read = |x: &str| -> Vec<String> {
+            Mol2Reader::new(x).unwrap().map(|m| m.get_lines().to_string()).collect()
+        };
+        let (a, b) = (read(&input_files[0]), read(&input_files[1]));
+        let mut expected = Vec::new();
+        for (idx, x) in b.iter().enumerate() {
+            expected.extend(a.get(idx).cloned());
+            expected.push(x.clone());
+        }
+        assert!(read(output_filename) == expected);
+    }
+
+    #[test]
+    fn run_merge_sorted() {
+        /*
+        Tests whether energy sorted shards are merged into a globally sorted output
+        */
+
+        let scratch = Scratch::new("run_merge_sorted");
+
+        // sorted shards of two inputs
+        let mut shards = Vec::new();
+        for (idx, x) in ["data/test0000.mol2.gz", "data/test0002.mol2.gz"].iter().enumerate() {
+            let mut molecules: Vec<Mol2> = Mol2Reader::new(x).unwrap().collect();
+            molecules.sort_by(|a, b| a.get_energy().total_cmp(&b.get_energy()));
+
+            let shard = scratch.path(&format!("test_merge_shard{}.mol2.gz", idx));
+            let mut writer = crate::file_io::writer(&shard).unwrap();
+            for mol in molecules.iter() {
+                writer.write_all(mol.get_lines().as_bytes()).unwrap();
+            }
+            shards.push(shard);
+        }
+
+        let output_filename = &scratch.path("test_merge_sorted.mol2.gz");
+        let num_written = merge::merge(shards, output_filename, true, false).unwrap();
+        assert!(num_written == 451 + 238);
+
+        let energies: Vec<f64> = Mol2Reader::new(output_filename)
+            .unwrap()
+            .map(|x| x.get_energy())
+            .collect();
+        assert!(energies.len() == 451 + 238);
+        assert!(energies.windows(2).all(|w| w[0] <= w[1]));
+    }
+
+    #[test]
+    fn run_diff() {
+        /*
+        Tests whether names only in either collection and shifted best energies are reported
+        (B holds test0002 and test0000 with every pose of one name shifted by 1.0)
+        */
+
+        let scratch = Scratch::new("run_diff");
+
+        let molecules: Vec<Mol2> = Mol2Reader::new("data/test0000.mol2.gz").unwrap().collect();
+        let shifted = molecules[0].get_name().to_string();
+        {
+            let mut writer = crate::file_io::writer(&scratch.path("test_diff_b.mol2.gz")).unwrap();
+            for mut mol in molecules.iter().cloned() {
+                if mol.get_name() == shifted {
+                    let energy = mol.get_energy() + 1.0;
+                    mol.set_energy(energy);
+                }
+                writer.write_all(mol.get_lines().as_bytes()).unwrap();
+            }
+        }
+
+        let names = |x: &str| -> HashSet<String> {
+            Mol2Reader::new(x).unwrap().map(|m| m.get_name().to_string()).collect()
+        };
+        let names_a = names("data/test0000.mol2.gz");
+        let names_b: HashSet<String> = names_a.union(&names("data/test0002.mol2.gz")).cloned().collect();
+
+        let input_a = vec!["data/test0000.mol2.gz".to_string()];
+        let input_b = vec![scratch.path("test_diff_b.mol2.gz"), "data/test0002.mol2.gz".to_string()];
+        let summary = compare::diff(&input_a, &input_b, &scratch.path("test_diff.tsv"), Some(0.5)).unwrap();
+        assert!(summary.shared == names_a.len());
+        assert!(summary.only_a == 0);
+        assert!(summary.only_b == names_b.len() - names_a.len());
+        assert!(summary.changed == 1);
+
+        let content = std::fs::read_to_string(scratch.path("test_diff.tsv")).unwrap();
+        let changed: Vec<&str> = content.lines().filter(|x| x.contains("\tchanged\t")).collect();
+        assert!(changed.len() == 1 && changed[0].starts_with(&shifted));
+
+        // names alone do not report energy changes
+        let summary = compare::diff(&input_b, &input_a, &scratch.path("test_diff.tsv"), None).unwrap();
+        assert!(summary.only_a == names_b.len() - names_a.len());
+        assert!(summary.changed == 0);
+    }
+
+    #[test]
+    fn run_set_operations() {
+        /*
+        Tests whether name-based intersect, subtract, and union keep the expected pose records
+        */
+
+        let scratch = Scratch::new("run_set_operations");
+
+        // the collections overlap on test0002
+        let input_a = vec!["data/test0000.mol2.gz".to_string(), "data/test0002.mol2.gz".to_string()];
+        let input_b = vec!["data/test0002.mol2.gz".to_string(), "data/test0001.mol2.gz".to_string()];
+        let poses = |x: &[String]| -> Vec<Mol2> {
+            x.iter().flat_map(|f| Mol2Reader::new(f).unwrap()).collect()
+        };
+        let (poses_a, poses_b) = (poses(&input_a), poses(&input_b));
+        let names_a: HashSet<&str> = poses_a.iter().map(|x| x.get_name()).collect();
+        let names_b: HashSet<&str> = poses_b.iter().map(|x| x.get_name()).collect();
+        let read = |x: &str| -> HashSet<String> {
+            Mol2Reader::new(x).unwrap().map(|m| m.get_lines().to_string()).collect()
+        };
+        let lines = |poses: &[Mol2], keep: &dyn Fn(&str) -> bool| -> HashSet<String> {
+            poses.iter().filter(|x| keep(x.get_name())).map(|x| x.get_lines().to_string()).collect()
+        };
+
+        compare::set_operation(input_a.clone(), input_b.clone(), compare::SetOperation::Intersect, &scratch.path("test_set.mol2.gz")).unwrap();
+        let expected = lines(&poses_a, &|x| names_b.contains(x));
+        assert!(!expected.is_empty());
+        assert!(read(&scratch.path("test_set.mol2.gz")) == expected);
+
+        compare::set_operation(input_a.clone(), input_b.clone(), compare::SetOperation::Subtract, &scratch.path("test_set.mol2.gz")).unwrap();
+        assert!(read(&scratch.path("test_set.mol2.gz")) == lines(&poses_a, &|x| !names_b.contains(x)));
+
+        let num_written = compare::set_operation(input_a, input_b, compare::SetOperation::Union, &scratch.path("test_set.mol2.gz")).unwrap();
+        let mut expected = lines(&poses_a, &|_| true);
+        expected.extend(lines(&poses_b, &|x| !names_a.contains(x)));
+        assert!(read(&scratch.path("test_set.mol2.gz")) == expected);
+        assert!(num_written as usize == poses_a.len() + poses_b.iter().filter(|x| !names_a.contains(x.get_name())).count());
+    }
+
+    #[test]
+    fn run_head_tail() {
+        /*
+        Tests whether the first and last molecules are taken across inputs in order
+        */
+
+        let scratch = Scratch::new("run_head_tail");
+
+        let input_files = vec!["data/test0002.mol2.gz".to_string(), "data/test0000.mol2.gz".to_string()];
+        let all: Vec<String> = input_files
+            .iter()
+            .flat_map(|x| Mol2Reader::new(x).unwrap())
+            .map(|x| x.get_lines().to_string())
+            .collect();
+        let read = |x: &str| -> Vec<String> {
+            Mol2Reader::new(x).unwrap().map(|m| m.get_lines().to_string()).collect()
+        };
+
+        // the head stops within the first input, the tail spans both
+        assert!(slice::head(input_files.clone(), 100, &scratch.path("test_head.mol2.gz")).unwrap() == 100);
+        assert!(read(&scratch.path("test_head.mol2.gz")) == all[..100]);
+        assert!(slice::tail(input_files.clone(), 500, &scratch.path("test_tail.mol2.gz")).unwrap() == 500);
+        assert!(read(&scratch.path("test_tail.mol2.gz")) == all[all.len() - 500..]);
+
+        assert!(slice::tail(input_files.clone(), 0, &scratch.path("test_tail.mol2.gz")).unwrap() == 0);
+        assert!(slice::head(input_files, 10_000, &scratch.path("test_head.mol2.gz")).unwrap() as usize == all.len());
+    }
+
+    #[test]
+    fn run_slice() {
+        /*
+        Tests whether molecules are selected by a range or by ordinals of their
+        position across inputs
+        */
+
+        let scratch = Scratch::new("run_slice");
+
+        let input_files = vec!["data/test0002.mol2.gz".to_string(), "data/test0000.mol2.gz".to_string()];
+        let all: Vec<String> = input_files
+            .iter()
+            .flat_map(|x| Mol2Reader::new(x).unwrap())
+            .map(|x| x.get_lines().to_string())
+            .collect();
+        let read = |x: &str| -> Vec<String> {
+            Mol2Reader::new(x).unwrap().map(|m| m.get_lines().to_string()).collect()
+        };
+
+        let range = slice::Selection::Range(200, Some(300));
+        assert!(slice::slice(input_files.clone(), &range, &scratch.path("test_slice.mol2.gz")).unwrap() == 100);
+        assert!(read(&scratch.path("test_slice.mol2.gz")) == all[200..300]);
+
+        let open = slice::Selection::Range(600, None);
+        slice::slice(input_files.clone(), &open, &scratch.path("test_slice.mol2.gz")).unwrap();
+        assert!(read(&scratch.path("test_slice.mol2.gz")) == all[600..]);
+
+        let ordinals = slice::Selection::Ordinals(std::collections::BTreeSet::from([5, 0, 400, 100_000]));
+        assert!(slice::slice(input_files, &ordinals, &scratch.path("test_slice.mol2.gz")).unwrap() == 3);
+        assert!(read(&scratch.path("test_slice.mol2.gz")) == vec![all[0].clone(), all[5].clone(), all[400].clone()]);
+    }
+
+    #[test]
+    fn run_sample_reservoir() {
+        /*
+        Tests whether a reservoir sample has the requested size, keeps input order,
+        and is reproducible for a given seed
+        */
+
+        let scratch = Scratch::new("run_sample_reservoir");
+
+        let input_files = vec!["data/test0000.mol2.gz".to_string(), "data/test0002.mol2.gz".to_string()];
+        let num_written = sample::sample(input_files.clone(), 50, 42, &scratch.path("test_sample_a.mol2.gz")).unwrap();
+        assert!(num_written == 50);
+        sample::sample(input_files.clone(), 50, 42, &scratch.path("test_sample_b.mol2.gz")).unwrap();
+        sample::sample(input_files.clone(), 50, 7, &scratch.path("test_sample_c.mol2.gz")).unwrap();
+
+        let read = |x: &str| -> Vec<String> {
+            Mol2Reader::new(x).unwrap().map(|m| m.get_lines().to_string()).collect()
+        };
+        let (a, b, c) = (read(&scratch.path("test_sample_a.mol2.gz")), read(&scratch.path("test_sample_b.mol2.gz")), read(&scratch.path("test_sample_c.mol2.gz")));
+        assert!(a == b);
+        assert!(a != c);
+
+        // sampled poses appear in input order
+        let all: Vec<String> = input_files.iter().flat_map(|x| read(x)).collect();
+        let positions: Vec<usize> = a.iter().map(|x| all.iter().position(|y| y == x).unwrap()).collect();
+        assert!(positions.windows(2).all(|w| w[0] < w[1]));
+
+        // asking for more poses than available keeps them all
+        let num_written = sample::sample(input_files, 10_000, 42, &scratch.path("test_sample_a.mol2.gz")).unwrap();
+        assert!(num_written as usize == all.len());
+    }
+
+    #[test]
+    fn run_shuffle() {
+        /*
+        Tests whether shuffling keeps every pose, depends only on the seed, and
+        gives the same order when spilling to temporary shards
+        */
+
+        let scratch = Scratch::new("run_shuffle");
+
+        let input_files = vec!["data/test0000.mol2.gz".to_string(), "data/test0002.mol2.gz".to_string()];
+        let tmp_dir = std::path::PathBuf::from(&scratch.path("test_shuffle_shards"));
+        let read = |x: &str| -> Vec<String> {
+            Mol2Reader::new(x).unwrap().map(|m| m.get_lines().to_string()).collect()
+        };
+
+        let in_memory = sample::ShuffleOptions { seed: 42, tmp_dir: tmp_dir.clone(), ..Default::default() };
+        let num_written = sample::shuffle(input_files.clone(), &scratch.path("test_shuffle_memory.mol2.gz"), &in_memory).unwrap();
+        assert!(num_written == 451 + 238);
+
+        // about 100 KB of molecules spills to shards
+        let spilled = sample::ShuffleOptions { seed: 42, buffer_bytes: 100 << 10, tmp_dir: tmp_dir.clone() };
+        sample::shuffle(input_files.clone(), &scratch.path("test_shuffle_spilled.mol2.gz"), &spilled).unwrap();
+        assert!(read(&scratch.path("test_shuffle_memory.mol2.gz")) == read(&scratch.path("test_shuffle_spilled.mol2.gz")));
+        assert!(std::fs::read_dir(&tmp_dir).map_or(true, |mut x| x.next().is_none()));
+
+        let reseeded = sample::ShuffleOptions { seed: 7, tmp_dir, ..Default::default() };
+        sample::shuffle(input_files.clone(), &scratch.path("test_shuffle_reseeded.mol2.gz"), &reseeded).unwrap();
+        let shuffled = read(&scratch.path("test_shuffle_memory.mol2.gz"));
+        assert!(shuffled != read(&scratch.path("test_shuffle_reseeded.mol2.gz")));
+
+        let original: Vec<String> = input_files.iter().flat_map(|x| read(x)).collect();
+        assert!(shuffled != original);
+        let mut sorted = shuffled.clone();
+        sorted.sort();
+        let mut expected = original;
+        expected.sort();
+        assert!(sorted == expected);
+    }
+
+    #[test]
+    fn run_dedup() {
+        /*
+        Tests whether a single pose is kept per name: the lowest energy one or the first seen
+        (test0003 is a copy of test0000, so its poses only tie)
+        */
+
+        let scratch = Scratch::new("run_dedup");
+
+        let input_files = vec!["data/test0000.mol2.gz".to_string(), "data/test0003.mol2.gz".to_string()];
+        let mut best: HashMap<String, f64> = HashMap::new();
+        let mut first: Vec<String> = Vec::new();
+        for mol in Mol2Reader::new("data/test0000.mol2.gz").unwrap() {
+            let e = best.entry(mol.get_name().to_string()).or_insert(f64::INFINITY);
+            if *e == f64::INFINITY {
+                first.push(mol.get_lines().to_string());
+            }
+            *e = e.min(mol.get_energy());
+        }
+
+        let num_written = dedup::dedup(input_files.clone(), &scratch.path("test_dedup_best.mol2.gz"), false).unwrap();
+        assert!(num_written as usize == best.len());
+        let kept: Vec<Mol2> = Mol2Reader::new(&scratch.path("test_dedup_best.mol2.gz")).unwrap().collect();
+        assert!(kept.len() == best.len());
+        assert!(kept.iter().all(|x| best[x.get_name()] == x.get_energy()));
+
+        let num_written = dedup::dedup(input_files, &scratch.path("test_dedup_first.mol2.gz"), true).unwrap();
+        assert!(num_written as usize == best.len());
+        let kept: Vec<String> = Mol2Reader::new(&scratch.path("test_dedup_first.mol2.gz"))
+            .unwrap()
+            .map(|x| x.get_lines().to_string())
+            .collect();
+        assert!(kept == first);
+    }
+
+    #[test]
+    fn run_sort_external() {
+        /*
+        Tests whether sorting through spilled runs gives the same order as sorting in memory
+        */
+
+        let scratch = Scratch::new("run_sort_external");
+
+        let input_files = vec!["data/test0000.mol2.gz".to_string(), "data/test0002.mol2.gz".to_string()];
+        let tmp_dir = std::path::PathBuf::from(&scratch.path("test_sort_runs"));
+
+        let in_memory = sort::SortOptions { tmp_dir: tmp_dir.clone(), ..Default::default() };
+        let num_written = sort::sort(input_files.clone(), &scratch.path("test_sort_memory.mol2.gz"), &in_memory).unwrap();
+        assert!(num_written == 451 + 238);
+
+        // about 100 KB per run spills several runs
+        let external = sort::SortOptions { buffer_bytes: 100 << 10, tmp_dir: tmp_dir.clone(), ..Default::default() };
+        sort::sort(input_files.clone(), &scratch.path("test_sort_external.mol2.gz"), &external).unwrap();
+
+        let memory: Vec<Mol2> = Mol2Reader::new(&scratch.path("test_sort_memory.mol2.gz")).unwrap().collect();
+        let spilled: Vec<Mol2> = Mol2Reader::new(&scratch.path("test_sort_external.mol2.gz")).unwrap().collect();
+        assert!(memory.len() == spilled.len());
+        assert!(memory.iter().zip(spilled.iter()).all(|(a, b)| a.get_lines() == b.get_lines()));
+        assert!(memory.windows(2).all(|w| w[0].get_energy() <= w[1].get_energy()));
+        assert!(std::fs::read_dir(&tmp_dir).map_or(true, |mut x| x.next().is_none()));
+
+        let descending = sort::SortOptions { descending: true, buffer_bytes: 100 << 10, tmp_dir };
+        sort::sort(input_files, &scratch.path("test_sort_descending.mol2.gz"), &descending).unwrap();
+        let energies: Vec<f64> = Mol2Reader::new(&scratch.path("test_sort_descending.mol2.gz")).unwrap().map(|x| x.get_energy()).collect();
+        assert!(energies.len() == 451 + 238);
+        assert!(energies.windows(2).all(|w| w[0] >= w[1]));
+    }
+
+    #[test]
+    fn run_top_streaming() {
+        /*
+        Tests whether the bounded heap keeps the same poses as a full sort
+        */
+
+        let scratch = Scratch::new("run_top_streaming");
+
+        let input_files = vec!["data/test0000.mol2.gz".to_string(), "data/test0002.mol2.gz".to_string()];
+        let mut expected: Vec<Mol2> = input_files
+            .iter()
+            .flat_map(|x| Mol2Reader::new(x).unwrap())
+            .collect();
+        expected.sort_by(|a, b| a.get_energy().total_cmp(&b.get_energy()));
+
+        let num_written = sort::top(input_files.clone(), Some(25), None, &scratch.path("test_top_streaming.mol2.gz")).unwrap();
+        assert!(num_written == 25);
+        let best: Vec<Mol2> = Mol2Reader::new(&scratch.path("test_top_streaming.mol2.gz")).unwrap().collect();
+        assert!(best.iter().zip(expected.iter()).all(|(a, b)| a.get_lines() == b.get_lines()));
+
+        let num_written = sort::top(input_files, Some(25), Some(-40.0), &scratch.path("test_top_streaming.mol2.gz")).unwrap();
+        let within = expected.iter().filter(|x| x.get_energy() <= -40.0).count().min(25);
+        assert!(num_written as usize == within);
+    }
+
+    #[test]
+    fn run_reassemble() {
+        /*
+        Tests whether numbered files are concatenated in natural numeric order
+        and whether missing numbers are detected
+        */
+
+        let scratch = Scratch::new("run_reassemble");
+
+        assert!(merge::file_number("out/split.0003.mol2.gz") == Some(3));
+        assert!(merge::file_number("chunk_12.mol2.gz") == Some(12));
+        assert!(merge::file_number("chunk_9.mol2") == Some(9));
+        assert!(merge::file_number("library.mol2.gz").is_none());
+
+        // shuffled and lexically misordered (9 before 10)
+        std::fs::copy("data/test0002.mol2.gz", scratch.path("test_reassemble_10.mol2.gz")).unwrap();
+        std::fs::copy("data/test0000.mol2.gz", scratch.path("test_reassemble_9.mol2.gz")).unwrap();
+        std::fs::copy("data/test0002.mol2.gz", scratch.path("test_reassemble_11.mol2.gz")).unwrap();
+        let input_files = vec![
+            scratch.path("test_reassemble_11.mol2.gz"),
+            scratch.path("test_reassemble_10.mol2.gz"),
+            scratch.path("test_reassemble_9.mol2.gz")
+        ];
+
+        let output_filename = &scratch.path("test_reassemble.mol2.gz");
+        let report_filename = &scratch.path("test_reassemble.tsv");
+        let num_written = merge::reassemble(input_files.clone(), output_filename, Some(report_filename), false).unwrap();
+        assert!(num_written == 451 + 238 + 238);
+
+        let first = Mol2Reader::new(output_filename).unwrap().next().unwrap();
+        let expected = Mol2Reader::new("data/test0000.mol2.gz").unwrap().next().unwrap();
+        assert!(first.get_name() == expected.get_name());
+
+        let report = std::fs::read_to_string(report_filename).unwrap();
+        let rows: Vec<&str> = report.lines().collect();
+        assert!(rows[1] == format!("9\t{}\t0\t451", input_files[2]));
+        assert!(rows[3] == format!("11\t{}\t689\t238", input_files[0]));
+
+        // a missing number fails unless gaps are allowed
+        let gapped = vec![input_files[0].clone(), input_files[2].clone()];
+        assert!(merge::reassemble(gapped.clone(), output_filename, None, false).is_err());
+        assert!(merge::reassemble(gapped, output_filename, None, true).unwrap() == 451 + 238);
+    }
+
+    #[test]
+    fn write_bgzf() {
+        /*
+        Tests whether BGZF output is made of size-tagged blocks of whole molecules
+        ending with the end of file marker, and is indexed one block per molecule
+        */
+
+        let scratch = Scratch::new("write_bgzf");
+
+        let output_filename = &scratch.path("test_bgzf.mol2.gz");
+        let molecules: Vec<Mol2> = Mol2Reader::new("data/test0001.mol2.gz").unwrap().collect();
+        {
+            let file = std::fs::File::create(output_filename).unwrap();
+            let mut writer = crate::file_io::BgzfWriter::new(file, flate2::Compression::default());
+            for mol in molecules.iter() {
+                writer.write_all(mol.get_lines().as_bytes()).unwrap();
+            }
+        }
+
+        // every block records its own size
+        let data = std::fs::read(output_filename).unwrap();
+        let mut offset = 0;
+        let mut num_blocks = 0;
+        while offset < data.len() {
+            assert!(data[offset..offset + 4] == [0x1f, 0x8b, 0x08, 0x04]);
+            assert!(data[offset + 12..offset + 14] == *b"BC");
+            offset += u16::from_le_bytes([data[offset + 16], data[offset + 17]]) as usize + 1;
+            num_blocks += 1;
+        }
+        assert!(offset == data.len());
+        assert!(num_blocks > 2);
+        assert!(data.len() >= 28 && data[data.len() - 28..data.len() - 24] == [0x1f, 0x8b, 0x08, 0x04]);
+
+        let written: Vec<Mol2> = Mol2Reader::new(output_filename).unwrap().collect();
+        assert!(written.len() == molecules.len());
+        assert!(written.iter().zip(molecules.iter()).all(|(a, b)| a.get_lines() == b.get_lines()));
+
+        // no molecule crosses a block, so the index keeps every block separate
+        index::build_index(vec![output_filename.to_string()], &scratch.path("test_bgzf.idx")).unwrap();
+        let (_, entries) = index::read_index(&scratch.path("test_bgzf.idx"), None, None).unwrap();
+        let blocks: HashSet<u64> = entries.iter().map(|e| e.block_offset).collect();
+        assert!(blocks.len() == num_blocks - 1);
+        assert!(entries.iter().all(|e| e.block_length <= 1 << 16));
+    }
+
+    #[test]
+    fn run_index_find_names() {
+        /*
+        Tests whether the poses of a name are located from the index alone
+        and read back from their recorded offsets
+        */
+
+        let scratch = Scratch::new("run_index_find_names");
+
+        let input_files = vec!["data/test0002.mol2.gz".to_string(), "data/members.mol2.gz".to_string()];
+        let index_filename = &scratch.path("test_index_names.idx");
+        index::build_index(input_files.clone(), index_filename).unwrap();
+
+        let molecules: Vec<Mol2> = input_files
+            .iter()
+            .flat_map(|x| Mol2Reader::new(x).unwrap())
+            .collect();
+        let name = molecules[17].get_name().to_string();
+        let expected: Vec<&str> = molecules
+            .iter()
+            .filter(|x| x.get_name() == name)
+            .map(|x| x.get_lines())
+            .collect();
+
+        let names: HashSet<String> = vec![name.clone(), "ZINC_ABSENT".to_string()].into_iter().collect();
+        let (files, entries) = index::find_names(index_filename, &names).unwrap();
+        assert!(entries.len() == expected.len());
+        assert!(entries.iter().all(|e| e.name == name));
+
+        let records = index::fetch_entries(&files, &entries).unwrap();
+        assert!(records == expected);
+
+        // names are looked up in the binary name table, or by scanning the index without it
+        let name_table = index::name_table_filename(index_filename);
+        assert!(std::path::Path::new(&name_table).exists());
+        std::fs::remove_file(&name_table).unwrap();
+        let (_, scanned) = index::find_names(index_filename, &names).unwrap();
+        assert!(index::fetch_entries(&files, &scanned).unwrap() == expected);
+        index::build_index(input_files.clone(), index_filename).unwrap();
+
+        let num_written = index::fetch(index_filename, &names, &scratch.path("test_index_fetch.mol2.gz")).unwrap();
+        assert!(num_written == expected.len());
+        let fetched: Vec<String> = Mol2Reader::new(&scratch.path("test_index_fetch.mol2.gz"))
+            .unwrap()
+            .map(|x| x.get_lines().to_string())
+            .collect();
+        assert!(fetched == expected);
+    }
+
+    #[test]
+    fn run_index_fetch() {
+        /*
+        Tests whether fetch writes every pose of the requested names across indexed
+        files in input order, skips names missing from the index, and refuses to
+        read an input changed since it was indexed
+        */
+
+        let scratch = Scratch::new("run_index_fetch");
+
+        let input_files = vec![scratch.path("test_fetch0.mol2.gz"), scratch.path("test_fetch1.mol2.gz")];
+        std::fs::copy("data/test0000.mol2.gz", &input_files[0]).unwrap();
+        std::fs::copy("data/test0002.mol2.gz", &input_files[1]).unwrap();
+        let index_filename = &scratch.path("test_fetch.idx");
+        index::build_index(input_files.clone(), index_filename).unwrap();
+
+        let molecules: Vec<Mol2> = input_files
+            .iter()
+            .flat_map(|x| Mol2Reader::new(x).unwrap())
+            .collect();
+        let names: HashSet<String> = vec![
+            molecules[3].get_name().to_string(),
+            molecules[molecules.len() - 1].get_name().to_string(),
+            "ZINC_ABSENT".to_string()
+        ].into_iter().collect();
+        let expected: Vec<&str> = molecules
+            .iter()
+            .filter(|x| names.contains(x.get_name()))
+            .map(|x| x.get_lines())
+            .collect();
+
+        let output_filename = &scratch.path("test_fetch.mol2.gz");
+        let num_written = index::fetch(index_filename, &names, output_filename).unwrap();
+        assert!(num_written == expected.len());
+        let fetched: Vec<String> = Mol2Reader::new(output_filename)
+            .unwrap()
+            .map(|x| x.get_lines().to_string())
+            .collect();
+        assert!(fetched == expected);
+
+        // names missing from the index write no molecules
+        let absent: HashSet<String> = vec!["ZINC_ABSENT".to_string()].into_iter().collect();
+        assert!(index::fetch(index_filename, &absent, output_filename).unwrap() == 0);
+        assert!(Mol2Reader::new(output_filename).unwrap().next().is_none());
+
+        // an input changed after indexing is refused
+        let mut file = std::fs::OpenOptions::new().append(true).open(&input_files[1]).unwrap();
+        file.write_all(b"\n").unwrap();
+        let error = index::fetch(index_filename, &names, output_filename).err().unwrap();
+        assert!(error.kind() == std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn run_index_name_table() {
+        /*
+        Tests whether names are found by binary search in the name table, including
+        the first and last names of the table, and whether names missing from the
+        table (between or outside its names) find nothing
+        */
+
+        let scratch = Scratch::new("run_index_name_table");
+
+        let input_files = vec!["data/test0000.mol2.gz".to_string()];
+        let index_filename = &scratch.path("test_index_table.idx");
+        index::build_index(input_files.clone(), index_filename).unwrap();
+
+        let molecules: Vec<Mol2> = Mol2Reader::new(&input_files[0]).unwrap().collect();
+        let mut sorted: Vec<&str> = molecules.iter().map(|x| x.get_name()).collect();
+        sorted.sort_unstable();
+        sorted.dedup();
+        let poses = |name: &str| molecules.iter().filter(|x| x.get_name() == name).count();
+
+        let find = |name: &str| {
+            let names: HashSet<String> = vec![name.to_string()].into_iter().collect();
+            let (_, entries) = index::find_names(index_filename, &names).unwrap();
+            assert!(entries.iter().all(|e| e.name == name));
+            entries.len()
+        };
+
+        // hits in the middle and at both ends of the table
+        let middle = sorted[sorted.len() / 2];
+        let first = sorted[0];
+        let last = sorted[sorted.len() - 1];
+        for name in [middle, first, last] {
+            assert!(find(name) > 0);
+            assert!(find(name) == poses(name));
+        }
+
+        // misses before the first name, after the last, and between two names
+        assert!(find("") == 0);
+        assert!(find("A") == 0);
+        assert!(find(&format!("{}~", last)) == 0);
+        assert!(find(&format!("{}0", middle)) == 0);
+        assert!(find(&middle[..middle.len() - 1]) == 0);
+    }
+
+    #[test]
+    fn run_index_top() {
+        /*
+        Tests whether the best poses are read back from an energy sorted index
+        (the concatenated file has two members, each decompressed only when needed)
+        */
+
+        let scratch = Scratch::new("run_index_top");
+
+        let mut data = std::fs::read("data/test0000.mol2.gz").unwrap();
+        data.extend(std::fs::read("data/test0002.mol2.gz").unwrap());
+        std::fs::write(scratch.path("test_index_concat.mol2.gz"), data).unwrap();
+
+        let input_files = vec![
+            scratch.path("test_index_concat.mol2.gz"),
+            "data/members.mol2.gz".to_string()
+        ];
+        let index_filename = &scratch.path("test_index.idx");
+        let num_indexed = index::build_index(input_files.clone(), index_filename).unwrap();
+        assert!(num_indexed == 451 + 238 + 238);
+
+        // members split mid-molecule are grouped into a single block
+        let (_, entries) = index::read_index(index_filename, None, None).unwrap();
+        let blocks: std::collections::HashSet<(usize, u64)> = entries
+            .iter()
+            .map(|e| (e.file_index, e.block_offset))
+            .collect();
+        assert!(blocks.len() == 3);
+
+        let mut expected: Vec<Mol2> = input_files
+            .iter()
+            .flat_map(|x| Mol2Reader::new(x).unwrap())
+            .collect();
+        expected.sort_by(|a, b| a.get_energy().total_cmp(&b.get_energy()));
+
+        let num_written = index::top(index_filename, Some(25), None, &scratch.path("test_index_top.mol2.gz")).unwrap();
+        assert!(num_written == 25);
+        let observed: Vec<Mol2> = Mol2Reader::new(&scratch.path("test_index_top.mol2.gz")).unwrap().collect();
+        for (o, e) in observed.iter().zip(expected.iter()) {
+            assert!(o.get_energy() == e.get_energy());
+        }
+        assert!(expected.iter().any(|e| e.get_lines() == observed[24].get_lines()));
+
+        let num_below = expected.iter().filter(|x| x.get_energy() <= -40.0).count();
+        let num_written = index::top(index_filename, None, Some(-40.0), &scratch.path("test_index_top.mol2.gz")).unwrap();
+        assert!(num_written == num_below);
+    }
+
+    #[test]
+    fn run_process_files() {
+        /*
+        Tests whether the visitor API counts, keeps, and stops as instructed
+        and reports files that fail to open
+        */
+
+        let scratch = Scratch::new("run_process_files");
+
+        let input_files = vec![
+            "data/test0000.mol2.gz".to_string(),
+            "data/test0002.mol2.gz".to_string(),
+            "data/missing.mol2.gz".to_string()
+        ];
+        let options = ProcessOptions {
+            num_threads: 2,
+            output: Some(scratch.path("test_process.mol2.gz"))
+        };
+        let summary = crate::process_files(&input_files, &options, |mol| {
+            if mol.get_energy() < -30.0 { Action::Keep } else { Action::Skip }
+        }).unwrap();
+
+        let expected = input_files[..2]
+            .iter()
+            .flat_map(|x| Mol2Reader::new(x).unwrap())
+            .filter(|x| x.get_energy() < -30.0)
+            .count();
+        assert!(summary.num_molecules == 451 + 238);
+        assert!(summary.num_kept as usize == expected);
+        assert!(Mol2Reader::new(&scratch.path("test_process.mol2.gz")).unwrap().count() == expected);
+        assert!(summary.failed_files.len() == 1 && summary.failed_files[0].0 == "data/missing.mol2.gz");
+        assert!(!summary.stopped);
+
+        // stopping at the first molecule visits one molecule per file at most
+        let summary = crate::process_files(&input_files[..1], &ProcessOptions::default(), |_| Action::Stop).unwrap();
+        assert!(summary.stopped && summary.num_molecules == 1);
+    }
+
+    #[test]
+    fn run_transform() {
+        /*
+        Tests whether energies are rescaled and written back into the headers
+        */
+
+        let scratch = Scratch::new("run_transform");
+
+        let input_files = vec!["data/test0000.mol2.gz".to_string()];
+        let output_filename = &scratch.path("test_transform.mol2.gz");
+
+        let options = TransformOptions {
+            scale: 2.0,
+            offset: 1.0,
+            zscore: false
+        };
+
+        let num_molecules = transform::transform(
+            input_files,
+            output_filename,
+            &options
+        ).unwrap();
+
+        assert!(num_molecules == 451);
+
+        let original = Mol2Reader::new("data/test0000.mol2.gz").unwrap();
+        let transformed = Mol2Reader::new(output_filename).unwrap();
+
+        original
+            .zip(transformed)
+            .for_each(|(a, b)| {
+                assert!(a.get_name() == b.get_name());
+                assert!((2.0 * a.get_energy() + 1.0 - b.get_energy()).abs() < 1e-5);
+            });
+    }
+
+    #[test]
+    fn run_filter_top_percent() {
+        /*
+        Tests whether only the best percentile of molecules is kept
+        */
+
+        let scratch = Scratch::new("run_filter_top_percent");
+
+        let input_files = vec!["data/test0000.mol2.gz".to_string()];
+        let output_filename = &scratch.path("test_filter_top_percent.mol2.gz");
+
+        let options = FilterOptions {
+            top_percent: Some(10.0),
+            ..Default::default()
+        };
+
+        let num_passing = filter::filter(
+            input_files,
+            output_filename,
+            &options
+        ).unwrap();
+
+        assert!(num_passing == 46);
+
+        let energies: Vec<f64> = Mol2Reader::new("data/test0000.mol2.gz")
+            .unwrap()
+            .map(|x| x.get_energy())
+            .collect();
+        let cutoff = filter::percentile_cutoff(energies.clone(), 10.0).unwrap();
+        let num_better = energies.iter().filter(|e| **e < cutoff).count();
+
+        Mol2Reader::new(output_filename)
+            .unwrap()
+            .for_each(|x| assert!(x.get_energy() <= cutoff));
+        assert!(num_better == 45);
+    }
+
+    #[test]
+    fn run_filter_energy_range() {
+        /*
+        Tests whether only molecules within the energy thresholds are kept
+        */
+
+        let scratch = Scratch::new("run_filter_energy_range");
+
+        let input_files = vec!["data/test0000.mol2.gz".to_string()];
+        let output_filename = &scratch.path("test_filter_energy_range.mol2.gz");
+
+        let options = FilterOptions {
+            max_energy: Some(-30.0),
+            min_energy: Some(-40.0),
+            ..Default::default()
+        };
+
+        let num_passing = filter::filter(
+            input_files,
+            output_filename,
+            &options
+        ).unwrap();
+
+        let num_within = Mol2Reader::new("data/test0000.mol2.gz")
+            .unwrap()
+            .filter(|x| (-40.0..=-30.0).contains(&x.get_energy()))
+            .count();
+        assert!(num_within > 0);
+        assert!(num_passing as usize == num_within);
+        Mol2Reader::new(output_filename)
+            .unwrap()
+            .for_each(|x| assert!(x.get_energy() <= -30.0 && x.get_energy() >= -40.0));
+    }
+
+    #[test]
+    fn run_grep_budget() {
+        /*
+        Tests whether no new file is started once the molecule budget is spent
+        while the files in flight are finished
+        */
+
+        let scratch = Scratch::new("run_grep_budget");
+
+        let input_files = read_input_list("data/input_list.txt").unwrap();
+        let output_filename = &scratch.path("test_grep_budget.mol2.gz");
+        let report_filename = &scratch.path("test_grep_budget.tsv");
+
+        let options = GrepOptions {
+            file_report: Some(report_filename.to_string()),
+            budget: Budget {
+                max_seconds: None,
+                max_molecules: Some(1)
+            },
+            ..Default::default()
+        };
+        mol2utils::grep(input_files.clone(), &["data/zinc_list.txt"], output_filename, 0.0, &options).unwrap();
+
+        // every processed file is complete
+        let content = std::fs::read_to_string(report_filename).unwrap();
+        let rows: Vec<Vec<&str>> = content
+            .lines()
+            .skip(1)
+            .map(|x| x.split('\t').collect())
+            .collect();
+        assert!(!rows.is_empty() && rows.len() < input_files.len());
+        for r in rows.iter() {
+            let expected = Mol2Reader::new(r[0]).unwrap().count();
+            assert!(r[2].parse::<usize>().unwrap() == expected);
+        }
+    }
+
+    #[test]
+    fn read_rate_limit() {
+        /*
+        Tests whether the token bucket holds readers to the requested rate
+        */
+
+        let limiter = crate::file_io::RateLimiter::new(20.0);
+        let start = std::time::Instant::now();
+        for _ in 0..4 {
+            limiter.acquire(500_000);
+        }
+
+        // 2 MB at 20 MB/s from an empty bucket
+        assert!(start.elapsed().as_secs_f64() >= 0.095);
+    }
+
+    #[test]
+    fn writer_errors() {
+        /*
+        Tests whether outputs that cannot be created are reported as errors
+        rather than panics
+        */
+
+        let scratch = Scratch::new("writer_errors");
+        assert!(crate::file_io::writer(&scratch.path("missing_dir/out.mol2.gz")).is_err());
+
+        if !cfg!(feature = "zstd") {
+            let error = crate::file_io::writer(&scratch.path("out.mol2.zst")).err().unwrap();
+            assert!(error.kind() == std::io::ErrorKind::Unsupported);
+        }
+    }
+
+    #[test]
+    fn run_split_formats() {
+        /*
+        Tests whether templated shard names choose the compression of each shard
+        and whether the shards are read back
+        */
+
+        let scratch = Scratch::new("run_split_formats");
+
+        let input_files = vec!["data/test0002.mol2.gz".to_string()];
+        let mut formats: Vec<(String, &[u8])> = vec![
+            (scratch.path("test_split_format.{}.mol2"), b"#@"),
+            (scratch.path("test_split_format.{}.mol2.gz"), b"\x1f\x8b")
+        ];
+        if cfg!(feature = "zstd") {
+            formats.push((scratch.path("test_split_format.{}.mol2.zst"), b"\x28\xb5\x2f\xfd"));
+        }
+        for (template, magic) in formats {
+
+            let count_vec = mol2utils::split(input_files.clone(), &template, 2, &SplitOptions::default()).unwrap();
+            assert!(count_vec == vec![119, 119]);
+
+            for i in 0..2 {
+                let filename = mol2utils::split_filename(&template, i);
+                assert!(std::fs::read(&filename).unwrap().starts_with(magic));
+                assert!(Mol2Reader::new(&filename).unwrap().count() == 119);
+            }
+        }
+
+        assert!(mol2utils::per_query_filename("hits.mol2.zst", "data/kinase.tsv") == "hits.kinase.mol2.zst");
+        assert!(mol2utils::per_query_filename("hits", "data/kinase.tsv") == "hits.kinase.mol2.gz");
+    }
+
+    #[test]
+    fn run_split_archive() {
+        /*
+        Tests whether shards are written as the entries of a single tar.gz archive
+        */
+
+        let scratch = Scratch::new("run_split_archive");
+
+        let input_files = vec!["data/test0002.mol2.gz".to_string()];
+        let options = SplitOptions {
+            archive: Some(scratch.path("test_split_archive.tar.gz")),
+            ..Default::default()
+        };
+        let count_vec = mol2utils::split(input_files, "shard", 3, &options).unwrap();
+        assert!(count_vec == vec![80, 79, 79]);
+
+        let file = std::fs::File::open(scratch.path("test_split_archive.tar.gz")).unwrap();
+        let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(file));
+        let mut names = Vec::new();
+        for entry in archive.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let name = entry.path().unwrap().to_str().unwrap().to_string();
+
+            let mut data = Vec::new();
+            entry.read_to_end(&mut data).unwrap();
+            let mut text = String::new();
+            flate2::read::GzDecoder::new(&data[..]).read_to_string(&mut text).unwrap();
+            assert!(text.matches("@<TRIPOS>MOLECULE").count() as u32 == count_vec[names.len()]);
+
+            names.push(name);
+        }
+        assert!(names == vec!["shard.0000.mol2.gz", "shard.0001.mol2.gz", "shard.0002.mol2.gz"]);
+        assert!(!std::path::Path::new("shard.0000.mol2.gz").exists());
+    }
+
+    #[test]
+    fn write_archive_spills() {
+        /*
+        Tests whether archive entries are written to disk as they are filled rather
+        than held in memory, and whether the spill files are removed once archived
+        */
+
+        let scratch = Scratch::new("write_archive_spills");
+        let archive = scratch.path("outputs.tar");
+        let names = vec!["a.mol2".to_string(), "b.mol2".to_string()];
+        let (outputs, mut writers) = crate::file_io::OutputSet::open(&names, Some(&archive)).unwrap();
+
+        let payload = "x".repeat(1 << 20);
+        for _ in 0..4 {
+            for w in writers.iter_mut() {
+                w.write_all(payload.as_bytes()).unwrap();
+            }
+        }
+        writers.iter_mut().for_each(|w| w.flush().unwrap());
+        let spilled: u64 = std::fs::read_dir(&scratch.0)
+            .unwrap()
+            .map(|x| x.unwrap())
+            .filter(|x| x.file_name().to_str().unwrap().ends_with(".part"))
+            .map(|x| x.metadata().unwrap().len())
+            .sum();
+        assert!(spilled == 8 << 20);
+
+        outputs.finish(writers).unwrap();
+        let remaining: Vec<_> = std::fs::read_dir(&scratch.0).unwrap().collect();
+        assert!(remaining.len() == 1);
+
+        let mut archive = tar::Archive::new(std::fs::File::open(&archive).unwrap());
+        let sizes: Vec<(String, u64)> = archive
+            .entries()
+            .unwrap()
+            .map(|x| x.unwrap())
+            .map(|x| (x.path().unwrap().to_str().unwrap().to_string(), x.size()))
+            .collect();
+        assert!(sizes == vec![("a.mol2".to_string(), 4 << 20), ("b.mol2".to_string(), 4 << 20)]);
+    }
+
+    #[test]
+    fn run_split() {
+
+        let scratch = Scratch::new("run_split");
 
         let input_list = "data/input_list.txt";
         let input_files = read_input_list(input_list)
             .expect("Error: Failed Reading Input List");
-        let prefix = "split";
+        let prefix = &scratch.path("split");
         let num_files = 10;
 
         let count_vec = mol2utils::split(
             input_files,
             prefix,
-            num_files
+            num_files,
+            &SplitOptions::default()
         ).unwrap();
 
         let expected = vec![
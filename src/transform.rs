@@ -0,0 +1,101 @@
+
+use std::io::{Error, ErrorKind};
+use std::io::prelude::*;
+
+use crate::mol2utils::stream_molecules;
+use crate::file_io::{is_stdin, writer};
+
+// Options describing the energy transformation: e' = scale * e + offset
+pub struct TransformOptions {
+    pub scale: f64,
+    pub offset: f64,
+    pub zscore: bool
+}
+impl Default for TransformOptions {
+    fn default() -> Self {
+        TransformOptions {
+            scale: 1.0,
+            offset: 0.0,
+            zscore: false
+        }
+    }
+}
+
+// Running mean and variance of energies (Welford's algorithm)
+#[derive(Default)]
+struct RunningMoments {
+    count: u64,
+    mean: f64,
+    m2: f64
+}
+impl RunningMoments {
+
+    // Adds an observation to the running moments
+    fn push(&mut self, x: f64) {
+        self.count += 1;
+        let delta = x - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (x - self.mean);
+    }
+
+    // Returns the population standard deviation
+    fn std(&self) -> f64 {
+        if self.count > 0 {
+            (self.m2 / self.count as f64).sqrt()
+        } else {
+            0.0
+        }
+    }
+}
+
+// Computes the mean and standard deviation of energies across all inputs
+fn energy_moments(input_files: Vec<String>) -> (f64, f64) {
+    let mut moments = RunningMoments::default();
+    for mol in stream_molecules(input_files) {
+        moments.push(mol.get_energy());
+    }
+    (moments.mean, moments.std())
+}
+
+// implements transform subcommand
+pub fn transform(
+        input_files: Vec<String>,
+        output_filename: &str,
+        options: &TransformOptions) -> Result<u32, Error> {
+
+    if options.zscore && input_files.iter().any(|x| is_stdin(x)) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "--zscore reads the inputs twice and cannot read the standard input"
+        ))
+    }
+
+    // first pass computing the normalization if requested
+    let (mean, std) = if options.zscore {
+        let (mean, std) = energy_moments(input_files.clone());
+        println!(">>> Energy Mean: {:.6}", mean);
+        println!(">>> Energy Std: {:.6}", std);
+        (mean, if std > 0.0 { std } else { 1.0 })
+    } else {
+        (0.0, 1.0)
+    };
+
+    let mut writer = writer(output_filename)?;
+
+    let mut num_molecules = 0;
+    for mut mol in stream_molecules(input_files) {
+
+        let energy = (mol.get_energy() - mean) / std;
+        mol.set_energy(options.scale * energy + options.offset);
+
+        writer
+            .write_all(mol.get_lines().as_bytes())
+            .expect("Error in writing to output file");
+
+        num_molecules += 1;
+    }
+
+    println!(">>> Number of Molecules Transformed: {}", num_molecules);
+
+    Ok(num_molecules)
+}
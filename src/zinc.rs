@@ -0,0 +1,181 @@
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufWriter, Error};
+use std::io::prelude::*;
+
+use crate::mol2utils::stream_molecules;
+use crate::file_io::writer;
+
+// Generation of the ZINC database an identifier follows
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ZincGeneration {
+    // ZINC + 8 digits
+    Zinc12,
+    // ZINC + 12 digits, shared by ZINC15 and ZINC20
+    Zinc20,
+    // ZINC + 2 tranche characters + 10 base62 characters
+    Zinc22
+}
+impl ZincGeneration {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ZincGeneration::Zinc12 => "zinc12",
+            ZincGeneration::Zinc20 => "zinc20",
+            ZincGeneration::Zinc22 => "zinc22"
+        }
+    }
+}
+
+// Struct describing the components of a ZINC identifier
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ZincId {
+    pub generation: ZincGeneration,
+    // tranche characters of ZINC22 identifiers (heavy atom count and logP class)
+    pub tranche: Option<String>,
+    // heavy atom count encoded by the first ZINC22 tranche character
+    pub heavy_atoms: Option<u32>,
+    pub serial: String
+}
+impl ZincId {
+
+    // Parses a ZINC identifier, returning None if the name follows no known format
+    pub fn parse(name: &str) -> Option<Self> {
+        let body = name.strip_prefix("ZINC")?;
+        if !body.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return None
+        }
+        let all_digits = body.chars().all(|c| c.is_ascii_digit());
+        match body.len() {
+            8 if all_digits => Some(ZincId {
+                generation: ZincGeneration::Zinc12,
+                tranche: None,
+                heavy_atoms: None,
+                serial: body.to_string()
+            }),
+            12 if all_digits => Some(ZincId {
+                generation: ZincGeneration::Zinc20,
+                tranche: None,
+                heavy_atoms: None,
+                serial: body.to_string()
+            }),
+            12 => Some(ZincId {
+                generation: ZincGeneration::Zinc22,
+                tranche: Some(body[..2].to_string()),
+                heavy_atoms: base62_digit(body.as_bytes()[0]),
+                serial: body[2..].to_string()
+            }),
+            _ => None
+        }
+    }
+
+    // Returns the value of a component used to group identifiers
+    pub fn key(&self, component: ZincComponent) -> String {
+        match component {
+            ZincComponent::Generation => self.generation.as_str().to_string(),
+            ZincComponent::Tranche => self.tranche.clone().unwrap_or_else(|| "none".to_string()),
+            ZincComponent::HeavyAtoms => self.heavy_atoms
+                .map(|x| format!("H{:02}", x))
+                .unwrap_or_else(|| "none".to_string())
+        }
+    }
+}
+
+// Returns the value of a base62 digit (0-9, a-z, A-Z)
+fn base62_digit(c: u8) -> Option<u32> {
+    match c {
+        b'0'..=b'9' => Some((c - b'0') as u32),
+        b'a'..=b'z' => Some((c - b'a') as u32 + 10),
+        b'A'..=b'Z' => Some((c - b'A') as u32 + 36),
+        _ => None
+    }
+}
+
+// Component of ZINC identifiers molecules are grouped by
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ZincComponent {
+    Generation,
+    Tranche,
+    HeavyAtoms
+}
+impl ZincComponent {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "generation" => Some(ZincComponent::Generation),
+            "tranche" => Some(ZincComponent::Tranche),
+            "heavy-atoms" => Some(ZincComponent::HeavyAtoms),
+            _ => None
+        }
+    }
+
+    // Returns the name the component is selected by
+    pub fn name(&self) -> &'static str {
+        match self {
+            ZincComponent::Generation => "generation",
+            ZincComponent::Tranche => "tranche",
+            ZincComponent::HeavyAtoms => "heavy-atoms"
+        }
+    }
+}
+
+// Returns the key a molecule name is grouped under ("invalid" for non ZINC names)
+fn group_key(name: &str, component: ZincComponent) -> String {
+    ZincId::parse(name)
+        .map(|x| x.key(component))
+        .unwrap_or_else(|| "invalid".to_string())
+}
+
+// Options of the zinc subcommand
+#[derive(Clone, Debug)]
+pub struct ZincOptions {
+    pub component: ZincComponent,
+    // prefix of mol2 files molecules are split into by component
+    pub split_prefix: Option<String>
+}
+
+// implements zinc subcommand: validates names and tabulates molecules by an identifier component
+// returns the number of molecules whose name is not a valid ZINC identifier
+//
+// Writes `key molecules ligands` counts and, with a split prefix, the molecules
+// of every key to `prefix.key.mol2.gz`.
+pub fn zinc(
+        input_files: Vec<String>,
+        output_filename: &str,
+        options: &ZincOptions) -> Result<u64, Error> {
+
+    let mut counts: BTreeMap<String, (u64, HashSet<String>)> = BTreeMap::new();
+    let mut writers: HashMap<String, Box<dyn Write>> = HashMap::new();
+
+    for mol in stream_molecules(input_files) {
+        let key = group_key(mol.get_name(), options.component);
+
+        if let Some(ref prefix) = options.split_prefix {
+            if !writers.contains_key(&key) {
+                writers.insert(key.clone(), writer(&format!("{}.{}.mol2.gz", prefix, key))?);
+            }
+            let w = writers.get_mut(&key).unwrap();
+            w.write_all(mol.get_lines().as_bytes())
+                .expect("Error: Error writing to output file");
+        }
+
+        let entry = counts.entry(key).or_default();
+        entry.0 += 1;
+        entry.1.insert(mol.get_name().to_string());
+    }
+    for w in writers.values_mut() {
+        w.flush()?;
+    }
+
+    let mut output = BufWriter::new(File::create(output_filename)?);
+    output.write_all(b"key\tmolecules\tligands\n")?;
+    for (key, (molecules, ligands)) in counts.iter() {
+        writeln!(output, "{}\t{}\t{}", key, molecules, ligands.len())?;
+    }
+
+    let num_invalid = counts.get("invalid").map_or(0, |x| x.0);
+    println!(">>> Number of Groups: {}", counts.len());
+    println!(">>> Number of Molecules with Invalid ZINC Names: {}", num_invalid);
+    println!(">>> Counts Written to: {}", output_filename);
+
+    Ok(num_invalid)
+}